@@ -147,15 +147,8 @@ impl CommonOpt {
             .context("binding endpoint")?;
 
         let socket_state = UdpSocketState::new((&socket).into())?;
-        socket_state
-            .set_send_buffer_size((&socket).into(), self.send_buffer_size as usize)
-            .context("send buffer size")?;
-        socket_state
-            .set_recv_buffer_size((&socket).into(), self.recv_buffer_size as usize)
-            .context("recv buffer size")?;
-
         let buf_size = socket_state
-            .send_buffer_size((&socket).into())
+            .set_send_buffer_size((&socket).into(), self.send_buffer_size as usize)
             .context("send buffer size")?;
         if buf_size < self.send_buffer_size as usize {
             warn!(
@@ -165,7 +158,7 @@ impl CommonOpt {
         }
 
         let buf_size = socket_state
-            .recv_buffer_size((&socket).into())
+            .set_recv_buffer_size((&socket).into(), self.recv_buffer_size as usize)
             .context("recv buffer size")?;
         if buf_size < self.recv_buffer_size as usize {
             warn!(
@@ -0,0 +1,197 @@
+//! This example drives a QUIC handshake between a client and a server entirely in memory.
+//!
+//! No sockets, threads or async runtime are involved: the example plays the part of the I/O layer,
+//! which is what an integration with a custom network stack has to do. Each loop iteration
+//!
+//! 1. drains outgoing datagrams from every `Connection` with `poll_transmit()`,
+//! 2. delivers datagrams to the receiving `Endpoint` with `handle()`, routing the resulting events
+//!    to the right `Connection`, and
+//! 3. advances a simulated clock, firing timers with `handle_timeout()` when nothing is in flight.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    mem,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytes::BytesMut;
+use quinn_proto::{
+    ClientConfig, Connection, ConnectionHandle, DatagramEvent, Endpoint, EndpointConfig, Event,
+    ServerConfig,
+};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+
+/// One-way delay of the simulated network
+const LATENCY: Duration = Duration::from_millis(10);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let key = PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der());
+    let cert = CertificateDer::from(cert.cert);
+    let server_config = ServerConfig::with_single_cert(vec![cert.clone()], key.into())?;
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert)?;
+    let client_config = ClientConfig::with_root_certificates(Arc::new(roots))?;
+
+    let endpoint_config = Arc::new(EndpointConfig::default());
+    let mut server = Node::new(
+        4433,
+        Endpoint::new(endpoint_config.clone(), Some(Arc::new(server_config)), true),
+    );
+    let mut client = Node::new(5000, Endpoint::new(endpoint_config, None, true));
+
+    let start = Instant::now();
+    let mut now = start;
+    let (ch, conn) = client
+        .endpoint
+        .connect(now, client_config, server.addr, "localhost")?;
+    client.connections.insert(ch, conn);
+
+    let mut in_flight = Vec::new();
+    while !(client.connected && server.connected) {
+        client.drive(now, &mut in_flight);
+        server.drive(now, &mut in_flight);
+
+        if in_flight.is_empty() {
+            // Nothing to deliver, so skip ahead to the next timer
+            now = [client.next_timeout(), server.next_timeout()]
+                .into_iter()
+                .flatten()
+                .min()
+                .ok_or("connections went idle before completing the handshake")?;
+            continue;
+        }
+
+        now += LATENCY;
+        for datagram in mem::take(&mut in_flight) {
+            let node = match datagram.destination == client.addr {
+                true => &mut client,
+                false => &mut server,
+            };
+            node.receive(now, datagram, &mut in_flight);
+        }
+    }
+
+    println!("handshake complete after {:?}", now - start);
+    Ok(())
+}
+
+/// An endpoint together with the I/O state a real integration would keep around it
+struct Node {
+    addr: SocketAddr,
+    endpoint: Endpoint,
+    connections: HashMap<ConnectionHandle, Connection>,
+    connected: bool,
+    /// Reusable buffer for outgoing datagrams
+    buf: Vec<u8>,
+}
+
+impl Node {
+    fn new(port: u16, endpoint: Endpoint) -> Self {
+        Self {
+            addr: SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), port),
+            endpoint,
+            connections: HashMap::new(),
+            connected: false,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Feed a datagram received from the network into the endpoint
+    fn receive(&mut self, now: Instant, datagram: Datagram, out: &mut Vec<Datagram>) {
+        self.buf.clear();
+        let event = self.endpoint.handle(
+            now,
+            datagram.source,
+            None,
+            None,
+            datagram.contents,
+            &mut self.buf,
+        );
+        match event {
+            Some(DatagramEvent::ConnectionEvent(handle, event)) => {
+                self.connections
+                    .get_mut(&handle)
+                    .unwrap()
+                    .handle_event(event);
+            }
+            Some(DatagramEvent::NewConnection(incoming)) => {
+                // A real server might instead `refuse()`, `retry()` or `ignore()` the connection
+                match self.endpoint.accept(incoming, now, &mut self.buf, None) {
+                    Ok((handle, connection)) => {
+                        self.connections.insert(handle, connection);
+                    }
+                    Err(e) => {
+                        if let Some(transmit) = e.response {
+                            out.push(Datagram::new(self.addr, transmit, &self.buf));
+                        }
+                    }
+                }
+            }
+            // Stateless responses such as version negotiation or stateless resets
+            Some(DatagramEvent::Response(transmit)) => {
+                out.push(Datagram::new(self.addr, transmit, &self.buf));
+            }
+            None => {}
+        }
+    }
+
+    /// Let every connection react to the passage of time and collect its outgoing datagrams
+    fn drive(&mut self, now: Instant, out: &mut Vec<Datagram>) {
+        for (&handle, connection) in &mut self.connections {
+            if connection
+                .poll_timeout()
+                .is_some_and(|timeout| timeout <= now)
+            {
+                connection.handle_timeout(now);
+            }
+
+            // Connections and their endpoint must exchange events to coordinate connection IDs
+            while let Some(event) = connection.poll_endpoint_events() {
+                if let Some(event) = self.endpoint.handle_event(handle, event) {
+                    connection.handle_event(event);
+                }
+            }
+
+            while let Some(event) = connection.poll() {
+                println!("{:?}: {event:?}", connection.side());
+                if let Event::Connected = event {
+                    self.connected = true;
+                }
+            }
+
+            // Without segmentation offload, each `Transmit` holds a single datagram
+            self.buf.clear();
+            while let Some(transmit) = connection.poll_transmit(now, 1, &mut self.buf) {
+                out.push(Datagram::new(self.addr, transmit, &self.buf));
+                self.buf.clear();
+            }
+        }
+    }
+
+    fn next_timeout(&mut self) -> Option<Instant> {
+        self.connections
+            .values_mut()
+            .filter_map(|connection| connection.poll_timeout())
+            .min()
+    }
+}
+
+struct Datagram {
+    source: SocketAddr,
+    destination: SocketAddr,
+    contents: BytesMut,
+}
+
+impl Datagram {
+    fn new(source: SocketAddr, transmit: quinn_proto::Transmit, buf: &[u8]) -> Self {
+        Self {
+            source,
+            destination: transmit.destination,
+            contents: BytesMut::from(&buf[..transmit.size]),
+        }
+    }
+}
@@ -17,6 +17,40 @@ pub trait ConnectionIdGenerator: Send + Sync {
     /// or cryptographic-grade random data.
     fn generate_cid(&mut self) -> ConnectionId;
 
+    /// Generates a new CID, drawing randomness from `rng`
+    ///
+    /// Endpoints call this with their own RNG, which is seeded by
+    /// [`EndpointConfig::rng_seed`](crate::EndpointConfig::rng_seed) if set, allowing the generated
+    /// CIDs to be reproduced. The same requirements as for [`generate_cid`](Self::generate_cid)
+    /// apply. Defaults to calling `generate_cid`, ignoring `rng`.
+    fn generate_cid_from_rng(&mut self, rng: &mut dyn RngCore) -> ConnectionId {
+        let _ = rng;
+        self.generate_cid()
+    }
+
+    /// Generates the source CID of a Retry packet, drawing randomness from `rng`
+    ///
+    /// `orig_dst_cid` is the destination CID of the client's first Initial packet. The client
+    /// addresses its next Initial to the returned CID, which is not used afterwards, since the
+    /// connection's own CIDs are generated once it's accepted. Defaults to calling
+    /// [`generate_cid_from_rng`](Self::generate_cid_from_rng).
+    ///
+    /// In a cluster of servers behind a load balancer routing packets by their destination CID,
+    /// the first Initial of a connection may reach any node, as its destination CID is chosen by
+    /// the client. The node which sends the Retry can steer the follow-up Initial to a particular
+    /// node by encoding it in the returned CID, e.g. one derived from `orig_dst_cid`. Any node can
+    /// validate the Retry token, without state shared beyond the
+    /// [`ServerConfig::token_key`](crate::ServerConfig::token_key), as long as the nodes' clocks
+    /// agree to within the [`retry_token_lifetime`](crate::ServerConfig::retry_token_lifetime).
+    fn generate_retry_cid(
+        &mut self,
+        rng: &mut dyn RngCore,
+        orig_dst_cid: ConnectionId,
+    ) -> ConnectionId {
+        let _ = orig_dst_cid;
+        self.generate_cid_from_rng(rng)
+    }
+
     /// Quickly determine whether `cid` could have been generated by this generator
     ///
     /// False positives are permitted, but increase the cost of handling invalid packets.
@@ -76,8 +110,12 @@ impl RandomConnectionIdGenerator {
 
 impl ConnectionIdGenerator for RandomConnectionIdGenerator {
     fn generate_cid(&mut self) -> ConnectionId {
+        self.generate_cid_from_rng(&mut rand::rng())
+    }
+
+    fn generate_cid_from_rng(&mut self, rng: &mut dyn RngCore) -> ConnectionId {
         let mut bytes_arr = [0; MAX_CID_SIZE];
-        rand::rng().fill_bytes(&mut bytes_arr[..self.cid_len]);
+        rng.fill_bytes(&mut bytes_arr[..self.cid_len]);
 
         ConnectionId::new(&bytes_arr[..self.cid_len])
     }
@@ -134,8 +172,12 @@ impl Default for HashedConnectionIdGenerator {
 
 impl ConnectionIdGenerator for HashedConnectionIdGenerator {
     fn generate_cid(&mut self) -> ConnectionId {
+        self.generate_cid_from_rng(&mut rand::rng())
+    }
+
+    fn generate_cid_from_rng(&mut self, rng: &mut dyn RngCore) -> ConnectionId {
         let mut bytes_arr = [0; NONCE_LEN + SIGNATURE_LEN];
-        rand::rng().fill_bytes(&mut bytes_arr[..NONCE_LEN]);
+        rng.fill_bytes(&mut bytes_arr[..NONCE_LEN]);
         let mut hasher = rustc_hash::FxHasher::default();
         hasher.write_u64(self.key);
         hasher.write(&bytes_arr[..NONCE_LEN]);
@@ -1,6 +1,6 @@
 use std::{
     fmt,
-    net::{SocketAddrV4, SocketAddrV6},
+    net::{SocketAddr, SocketAddrV4, SocketAddrV6},
     num::TryFromIntError,
     sync::Arc,
 };
@@ -16,19 +16,23 @@ use crate::BloomTokenLog;
 #[cfg(not(feature = "bloom"))]
 use crate::NoneTokenLog;
 #[cfg(any(feature = "rustls-aws-lc-rs", feature = "rustls-ring"))]
-use crate::crypto::rustls::{QuicServerConfig, configured_provider};
+use crate::crypto::rustls::{NoInitialCipherSuite, QuicServerConfig, configured_provider};
 use crate::{
-    DEFAULT_SUPPORTED_VERSIONS, Duration, MAX_CID_SIZE, RandomConnectionIdGenerator, SystemTime,
-    TokenLog, TokenMemoryCache, TokenStore, VarInt, VarIntBoundsExceeded,
+    DEFAULT_SUPPORTED_VERSIONS, DropReason, Duration, MAX_CID_SIZE, RandomConnectionIdGenerator,
+    SystemTime, TokenLog, TokenMemoryCache, TokenStore, VarInt, VarIntBoundsExceeded,
     cid_generator::{ConnectionIdGenerator, HashedConnectionIdGenerator},
     crypto::{self, HandshakeTokenKey, HmacKey},
     shared::ConnectionId,
+    transport_parameters::TransportParameters,
 };
 
 mod transport;
 #[cfg(feature = "qlog")]
 pub use transport::QlogConfig;
-pub use transport::{AckFrequencyConfig, IdleTimeout, MtuDiscoveryConfig, TransportConfig};
+pub use transport::{
+    AckFrequencyConfig, IdleTimeout, MtuDiscoveryConfig, PacketDirection, PacketInfo,
+    PacketObserver, TransportConfig,
+};
 
 /// Global configuration for the endpoint, affecting all connections
 ///
@@ -48,6 +52,9 @@ pub struct EndpointConfig {
     pub(crate) min_reset_interval: Duration,
     /// Optional seed to be used internally for random number generation
     pub(crate) rng_seed: Option<[u8; 32]>,
+    pub(crate) max_aggregate_send_rate: Option<u64>,
+    pub(crate) max_handshakes_per_second: Option<u32>,
+    pub(crate) on_packet_drop: Option<Arc<dyn Fn(DropReason, SocketAddr) + Send + Sync>>,
 }
 
 impl EndpointConfig {
@@ -63,6 +70,9 @@ impl EndpointConfig {
             grease_quic_bit: true,
             min_reset_interval: Duration::from_millis(20),
             rng_seed: None,
+            max_aggregate_send_rate: None,
+            max_handshakes_per_second: None,
+            on_packet_drop: None,
         }
     }
 
@@ -82,6 +92,33 @@ impl EndpointConfig {
         self
     }
 
+    /// Issue local connection IDs of exactly `len` bytes
+    ///
+    /// Replaces any generator supplied to [`cid_generator`](Self::cid_generator): an 8 byte
+    /// length uses a [`HashedConnectionIdGenerator`], and any other length a
+    /// [`RandomConnectionIdGenerator`]. Every CID issued by the endpoint, including those in the
+    /// handshake and in `NEW_CONNECTION_ID` frames, has this length, and short-header packets are
+    /// parsed assuming it. This suits middleboxes such as hardware load balancers that can't parse
+    /// variable-length CIDs.
+    ///
+    /// `len` must be between 1 and 20 inclusive. Stateless reset tokens are derived from the CIDs
+    /// they are issued with, which requires a fixed, nonzero CID length (RFC 9000 §10.3.1); a
+    /// client-only endpoint that wants zero-length CIDs can configure a
+    /// [`RandomConnectionIdGenerator`] directly. The length only constrains CIDs chosen by this
+    /// endpoint: peers may use CIDs of any length, and load balancers routing on CIDs must account
+    /// for clients' initial destination CIDs, which are chosen by the client and at least 8 bytes.
+    pub fn connection_id_length(&mut self, len: usize) -> Result<&mut Self, ConfigError> {
+        if !(1..=MAX_CID_SIZE).contains(&len) {
+            return Err(ConfigError::OutOfBounds);
+        }
+
+        self.connection_id_generator_factory = match len {
+            8 => Arc::new(|| Box::<HashedConnectionIdGenerator>::default()),
+            _ => Arc::new(move || Box::new(RandomConnectionIdGenerator::new(len))),
+        };
+        Ok(self)
+    }
+
     /// Private key used to send authenticated connection resets to peers who were
     /// communicating with a previous instance of this endpoint.
     pub fn reset_key(&mut self, key: Arc<dyn HmacKey>) -> &mut Self {
@@ -91,7 +128,8 @@ impl EndpointConfig {
 
     /// Maximum UDP payload size accepted from peers (excluding UDP and IP overhead).
     ///
-    /// Must be greater or equal than 1200.
+    /// Advertised to peers as the `max_udp_payload_size` transport parameter, which forbids them
+    /// from sending larger packets. Must be greater or equal than 1200.
     ///
     /// Defaults to 1472, which is the largest UDP payload that can be transmitted in the typical
     /// 1500 byte Ethernet MTU. Deployments on links with larger MTUs (e.g. loopback or Ethernet
@@ -118,7 +156,21 @@ impl EndpointConfig {
         self.max_udp_payload_size.into()
     }
 
-    /// Override supported QUIC versions
+    /// Override supported QUIC versions, in order of preference
+    ///
+    /// Defaults to [`DEFAULT_SUPPORTED_VERSIONS`]. Servers answer Initial packets for any other
+    /// version with a Version Negotiation packet listing these. A client that receives such a
+    /// packet restarts its handshake once, using the first version in this list that the server
+    /// also offered, and fails with [`ConnectionError::VersionMismatch`] if there is none.
+    ///
+    /// Compatible version negotiation (RFC 9368) is not performed, so a client always completes
+    /// the handshake using the version in its [`ClientConfig`] or the one selected above.
+    ///
+    /// The defaults don't include [`QUIC_V2`](crate::QUIC_V2): RFC 9368 expects peers supporting
+    /// it to exchange the version information transport parameter, which isn't implemented. Add
+    /// it here to accept or initiate QUIC v2 connections regardless.
+    ///
+    /// [`ConnectionError::VersionMismatch`]: crate::ConnectionError::VersionMismatch
     pub fn supported_versions(&mut self, supported_versions: Vec<u32>) -> &mut Self {
         self.supported_versions = supported_versions;
         self
@@ -155,10 +207,108 @@ impl EndpointConfig {
     /// However, you can seed the rng yourself through this method (e.g. if you need to run quinn
     /// deterministically or if you are using quinn in an environment that doesn't have a source of
     /// entropy available).
+    ///
+    /// The endpoint's rng is a cryptographically secure [`StdRng`](rand::rngs::StdRng), which is
+    /// used for local connection IDs (see
+    /// [`ConnectionIdGenerator::generate_cid_from_rng`]), stateless reset padding, address
+    /// validation tokens, and to seed the rng of each connection. Endpoints with the same seed
+    /// and inputs therefore produce the same sequence of CIDs, which is useful for tests and fuzzing
+    /// but must not be relied upon in production, where a fixed seed makes CIDs predictable.
+    ///
+    /// Key material is unaffected: TLS keys are derived by the crypto provider, while the
+    /// [`reset_key`](Self::reset_key) and the key of a [`HashedConnectionIdGenerator`] are chosen
+    /// independently. CIDs from a default `HashedConnectionIdGenerator` are hence only reproducible
+    /// if it is constructed with [`HashedConnectionIdGenerator::from_key`].
     pub fn rng_seed(&mut self, seed: Option<[u8; 32]>) -> &mut Self {
         self.rng_seed = seed;
         self
     }
+
+    /// Maximum rate, in bytes per second, at which all connections of the endpoint together may
+    /// send UDP payload
+    ///
+    /// Caps the endpoint's aggregate outbound traffic, e.g. to stay within the capacity of a shared
+    /// uplink when serving many connections that each pace themselves correctly. The budget is
+    /// handed out in the order connections ask for it, one transmit at a time: a connection whose
+    /// transmit doesn't fit holds it until its reserved share of the budget becomes available, and
+    /// doesn't queue further transmits in the meantime. Connections with data to send hence take
+    /// turns, each receiving a roughly equal share of the budget, while idle connections don't
+    /// consume any. Short bursts of up to 2ms worth of budget are permitted. Packets the endpoint
+    /// sends on its own behalf rather than a connection's, i.e. stateless resets, Version
+    /// Negotiation and Retry packets and refusals of incoming connections, are exempt and don't
+    /// consume any budget: they are small and, being answers to received packets, bounded by the
+    /// incoming traffic.
+    ///
+    /// Held transmits delay acknowledgements and raise the RTT measured by each connection, so
+    /// congestion controllers see the cap like a bottleneck link and adapt their sending rate to
+    /// their share of it.
+    ///
+    /// The limit is enforced by the I/O layer driving the connections, such as the `quinn` crate,
+    /// which reads it through [`get_max_aggregate_send_rate`](Self::get_max_aggregate_send_rate).
+    /// Must be nonzero. Defaults to `None`, which leaves the endpoint's sending rate unlimited.
+    pub fn max_aggregate_send_rate(
+        &mut self,
+        value: Option<u64>,
+    ) -> Result<&mut Self, ConfigError> {
+        if value == Some(0) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.max_aggregate_send_rate = value;
+        Ok(self)
+    }
+
+    /// Get the current value of [`max_aggregate_send_rate`](Self::max_aggregate_send_rate)
+    pub fn get_max_aggregate_send_rate(&self) -> Option<u64> {
+        self.max_aggregate_send_rate
+    }
+
+    /// Maximum rate at which the endpoint starts handshakes for incoming connection attempts
+    ///
+    /// Protects the server's CPU from floods of Initial packets, each of which would otherwise
+    /// cost a cryptographic handshake once accepted. The rate is measured over a sliding window of
+    /// one second. Connection attempts beyond it are answered with a Retry packet if the client's
+    /// address hasn't been validated yet, which is cheap and stateless and stops floods from
+    /// spoofed addresses. Attempts from validated addresses are dropped instead, and the client
+    /// retransmits its Initial later.
+    ///
+    /// Throttled attempts never become an [`Incoming`](crate::Incoming), so they don't count
+    /// towards [`ServerConfig::max_incoming`], which keeps bounding the number of attempts awaiting
+    /// a decision by the application. Neither limit caps the number of established connections.
+    ///
+    /// Must be nonzero. Defaults to `None`, which leaves the handshake rate unlimited.
+    pub fn max_handshakes_per_second(
+        &mut self,
+        value: Option<u32>,
+    ) -> Result<&mut Self, ConfigError> {
+        if value == Some(0) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.max_handshakes_per_second = value;
+        Ok(self)
+    }
+
+    /// Callback invoked for every received packet the endpoint or its connections drop
+    ///
+    /// Called with the reason the packet was dropped and the address it was received from, e.g.
+    /// to feed security monitoring. See [`DropReason`] for the circumstances reported. The
+    /// callback runs synchronously in the receive path, so it should return quickly.
+    ///
+    /// Packets which are processed without effect, such as duplicates, aren't reported. Defaults
+    /// to `None`, which costs nothing.
+    pub fn on_packet_drop(
+        &mut self,
+        callback: Arc<dyn Fn(DropReason, SocketAddr) + Send + Sync>,
+    ) -> &mut Self {
+        self.on_packet_drop = Some(callback);
+        self
+    }
+
+    /// Report a dropped packet to the [`on_packet_drop`](Self::on_packet_drop) callback
+    pub(crate) fn packet_dropped(&self, reason: DropReason, remote: SocketAddr) {
+        if let Some(callback) = &self.on_packet_drop {
+            callback(reason, remote);
+        }
+    }
 }
 
 impl fmt::Debug for EndpointConfig {
@@ -170,6 +320,9 @@ impl fmt::Debug for EndpointConfig {
             .field("supported_versions", &self.supported_versions)
             .field("grease_quic_bit", &self.grease_quic_bit)
             .field("rng_seed", &self.rng_seed)
+            .field("max_aggregate_send_rate", &self.max_aggregate_send_rate)
+            .field("max_handshakes_per_second", &self.max_handshakes_per_second)
+            // on_packet_drop not debug
             .finish_non_exhaustive()
     }
 }
@@ -226,8 +379,16 @@ pub struct ServerConfig {
     pub(crate) incoming_buffer_size_total: u64,
 
     pub(crate) time_source: Arc<dyn TimeSource>,
+
+    pub(crate) transport_parameters_validator: Option<Arc<TransportParametersValidator>>,
 }
 
+/// Decides whether to proceed with a handshake given the client's transport parameters
+///
+/// See [`ServerConfig::transport_parameters_validator`].
+pub type TransportParametersValidator =
+    dyn Fn(&TransportParameters) -> Result<(), String> + Send + Sync;
+
 impl ServerConfig {
     /// Create a default config with a particular handshake token key
     pub fn new(
@@ -253,6 +414,8 @@ impl ServerConfig {
             incoming_buffer_size_total: 100 << 20,
 
             time_source: Arc::new(StdSystemTime),
+
+            transport_parameters_validator: None,
         }
     }
 
@@ -272,6 +435,10 @@ impl ServerConfig {
     }
 
     /// Private key used to authenticate data included in handshake tokens
+    ///
+    /// Servers sharing a key accept each other's tokens, so a cluster of servers can validate
+    /// Retry tokens regardless of which node issued them; see
+    /// [`ConnectionIdGenerator::generate_retry_cid`].
     pub fn token_key(&mut self, value: Arc<dyn HandshakeTokenKey>) -> &mut Self {
         self.token_key = value;
         self
@@ -368,6 +535,28 @@ impl ServerConfig {
         self
     }
 
+    /// Inspect each client's transport parameters before completing its handshake
+    ///
+    /// Called once per connection, as soon as the client's transport parameters have been
+    /// received in its ClientHello and authenticated. Returning `Err` with a reason closes the
+    /// connection with a `CONNECTION_REFUSED` error carrying that reason, so the handshake never
+    /// completes.
+    ///
+    /// Every parameter exposed by [`TransportParameters`] is available, e.g. flow control limits
+    /// such as [`initial_max_data`](TransportParameters::initial_max_data) and datagram support
+    /// via [`max_datagram_frame_size`](TransportParameters::max_datagram_frame_size).
+    /// Connection IDs and other parameters only meaningful to the QUIC implementation itself are
+    /// not exposed.
+    ///
+    /// Defaults to `None`, accepting any parameters that are valid per the QUIC specification.
+    pub fn transport_parameters_validator(
+        &mut self,
+        validator: Option<Arc<TransportParametersValidator>>,
+    ) -> &mut Self {
+        self.transport_parameters_validator = validator;
+        self
+    }
+
     pub(crate) fn has_preferred_address(&self) -> bool {
         self.preferred_address_v4.is_some() || self.preferred_address_v6.is_some()
     }
@@ -386,6 +575,20 @@ impl ServerConfig {
             cert_chain, key,
         )?)))
     }
+
+    /// Create a server config from a shared [`rustls::ServerConfig`]
+    ///
+    /// Building a `rustls::ServerConfig` can be expensive, so it may be built once and shared by
+    /// any number of endpoints. Only the TLS configuration is shared: the transport parameters sent
+    /// to clients are derived from each `ServerConfig`'s own [`TransportConfig`], and each gets a
+    /// randomized handshake token key.
+    ///
+    /// See [`QuicServerConfig`] for the requirements the TLS configuration must satisfy.
+    pub fn with_rustls(crypto: Arc<rustls::ServerConfig>) -> Result<Self, NoInitialCipherSuite> {
+        Ok(Self::with_crypto(Arc::new(QuicServerConfig::try_from(
+            crypto,
+        )?)))
+    }
 }
 
 #[cfg(any(feature = "aws-lc-rs", feature = "ring"))]
@@ -427,6 +630,7 @@ impl fmt::Debug for ServerConfig {
                 &self.incoming_buffer_size_total,
             )
             // system_time_clock not debug
+            // transport_parameters_validator not debug
             .finish_non_exhaustive()
     }
 }
@@ -482,6 +686,7 @@ pub struct ValidationTokenConfig {
     pub(crate) lifetime: Duration,
     pub(crate) log: Arc<dyn TokenLog>,
     pub(crate) sent: u32,
+    pub(crate) lift_amplification_limit: bool,
 }
 
 impl ValidationTokenConfig {
@@ -519,6 +724,24 @@ impl ValidationTokenConfig {
         self.sent = value;
         self
     }
+
+    /// Whether a valid address validation token validates the client's address immediately
+    ///
+    /// When enabled, a client presenting a validation token it was issued for its IP address
+    /// isn't subject to the anti-amplification limit. This is safe because a token is only sent
+    /// to an address after it was validated, cannot be forged without the server's token key,
+    /// expires after [`lifetime`](Self::lifetime), and is only accepted once by the
+    /// [`TokenLog`]. An attacker spoofing the client's address would have to have observed a token
+    /// sent to it, so cannot use the server to reflect traffic to arbitrary addresses.
+    ///
+    /// When disabled, validation tokens are ignored, and clients are validated through the
+    /// handshake as if they presented no token. Retry tokens are not affected.
+    ///
+    /// Defaults to `true`.
+    pub fn lift_amplification_limit(&mut self, value: bool) -> &mut Self {
+        self.lift_amplification_limit = value;
+        self
+    }
 }
 
 impl Default for ValidationTokenConfig {
@@ -531,6 +754,7 @@ impl Default for ValidationTokenConfig {
             lifetime: Duration::from_secs(2 * 7 * 24 * 60 * 60),
             log,
             sent: if cfg!(feature = "bloom") { 2 } else { 0 },
+            lift_amplification_limit: true,
         }
     }
 }
@@ -541,6 +765,7 @@ impl fmt::Debug for ValidationTokenConfig {
             .field("lifetime", &self.lifetime)
             // log not debug
             .field("sent", &self.sent)
+            .field("lift_amplification_limit", &self.lift_amplification_limit)
             .finish_non_exhaustive()
     }
 }
@@ -603,6 +828,16 @@ impl ClientConfig {
         self
     }
 
+    /// Modify the [`TransportConfig`] used by this config
+    ///
+    /// If the transport config is shared with other `ClientConfig`s (e.g. because this one was
+    /// cloned from an endpoint's default), it's copied first, so only connections made with this
+    /// `ClientConfig` are affected. This allows overriding e.g. the congestion controller for a
+    /// single connection without rebuilding the rest of the configuration.
+    pub fn transport_config_mut(&mut self) -> &mut TransportConfig {
+        Arc::make_mut(&mut self.transport)
+    }
+
     /// Set a custom [`TokenStore`]
     ///
     /// Defaults to [`TokenMemoryCache`], which is suitable for most internet applications.
@@ -612,10 +847,28 @@ impl ClientConfig {
     }
 
     /// Set the QUIC version to use
+    ///
+    /// Must be one of the endpoint's [`supported_versions`](EndpointConfig::supported_versions).
+    /// Defaults to QUIC v1. If the server doesn't support it, the version may be renegotiated.
+    /// Setting [`QUIC_V2`](crate::QUIC_V2), which must first be added to the supported versions,
+    /// prefers QUIC v2 while still falling back to v1 with servers which only support that.
     pub fn version(&mut self, version: u32) -> &mut Self {
         self.version = version;
         self
     }
+
+    /// Whether a connection to `server_name` made now with this configuration could send 0-RTT
+    /// data
+    ///
+    /// True if the crypto configuration holds a resumption ticket for `server_name` which permits
+    /// early data and hasn't expired. Doesn't consume the ticket. Even then, the server may still
+    /// reject the early data, e.g. if it was restarted and lost its ticket keys; see
+    /// [`Connection::accepted_0rtt`](crate::Connection::accepted_0rtt).
+    ///
+    /// See [`crypto::ClientConfig::can_resume_0rtt`].
+    pub fn can_resume_0rtt(&self, server_name: &str) -> bool {
+        self.crypto.can_resume_0rtt(server_name)
+    }
 }
 
 #[cfg(any(feature = "rustls-aws-lc-rs", feature = "rustls-ring"))]
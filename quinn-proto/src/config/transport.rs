@@ -1,4 +1,4 @@
-use std::{fmt, sync::Arc};
+use std::{fmt, ops::RangeInclusive, sync::Arc};
 #[cfg(feature = "qlog")]
 use std::{io, sync::Mutex, time::Instant};
 
@@ -8,8 +8,9 @@ use qlog::streamer::QlogStreamer;
 #[cfg(feature = "qlog")]
 use crate::QlogStream;
 use crate::{
-    Duration, INITIAL_MTU, MAX_UDP_PAYLOAD, VarInt, VarIntBoundsExceeded, congestion,
-    connection::qlog::QlogSink,
+    ConfigError, Duration, INITIAL_MTU, MAX_UDP_PAYLOAD, MIN_INITIAL_SIZE, VarInt,
+    VarIntBoundsExceeded, cid_queue::CidQueue, congestion, connection::qlog::QlogSink,
+    packet::SpaceId,
 };
 
 /// Parameters governing the core QUIC state machine
@@ -24,38 +25,63 @@ use crate::{
 /// for higher bandwidths and latencies increases worst-case memory consumption, but does not impair
 /// performance at lower bandwidths and latencies. The default configuration is tuned for a 100Mbps
 /// link with a 100ms round trip time.
+#[derive(Clone)]
 pub struct TransportConfig {
     pub(crate) max_concurrent_bidi_streams: VarInt,
     pub(crate) max_concurrent_uni_streams: VarInt,
+    pub(crate) max_unhandled_streams: Option<VarInt>,
     pub(crate) max_idle_timeout: Option<VarInt>,
     pub(crate) stream_receive_window: VarInt,
+    pub(crate) stream_receive_window_bidi_local: Option<VarInt>,
+    pub(crate) stream_receive_window_bidi_remote: Option<VarInt>,
+    pub(crate) stream_receive_window_uni: Option<VarInt>,
+    pub(crate) max_stream_out_of_order_bytes: Option<VarInt>,
     pub(crate) receive_window: VarInt,
+    pub(crate) max_receive_window: Option<VarInt>,
     pub(crate) send_window: u64,
     pub(crate) send_fairness: bool,
+    pub(crate) weighted_priorities: bool,
+    pub(crate) stream_starvation_threshold: u32,
+    pub(crate) amplification_factor: u8,
 
     pub(crate) packet_threshold: u32,
     pub(crate) time_threshold: f32,
+    pub(crate) max_time_threshold: Option<f32>,
     pub(crate) initial_rtt: Duration,
     pub(crate) initial_mtu: u16,
     pub(crate) min_mtu: u16,
     pub(crate) mtu_discovery_config: Option<MtuDiscoveryConfig>,
     pub(crate) pad_to_mtu: bool,
+    pub(crate) initial_padding: RangeInclusive<u16>,
     pub(crate) ack_frequency_config: Option<AckFrequencyConfig>,
+    pub(crate) adaptive_ack_eliciting_threshold: Option<VarInt>,
+    pub(crate) max_ack_delay: VarInt,
 
     pub(crate) persistent_congestion_threshold: u32,
+    pub(crate) max_pto_count: u32,
+    pub(crate) pto_probe_count: u8,
     pub(crate) keep_alive_interval: Option<Duration>,
+    pub(crate) nat_keepalive: Option<Duration>,
     pub(crate) crypto_buffer_size: usize,
     pub(crate) allow_spin: bool,
+    pub(crate) allow_migration: bool,
+    pub(crate) migration_cooldown: Duration,
+    pub(crate) migrate_to_preferred_address: bool,
+    pub(crate) connection_id_rotation_interval: Option<Duration>,
+    pub(crate) active_connection_id_limit: u32,
     pub(crate) datagram_receive_buffer_size: Option<usize>,
     pub(crate) datagram_send_buffer_size: usize,
+    pub(crate) partial_reliability: bool,
     #[cfg(test)]
     pub(crate) deterministic_packet_numbers: bool,
 
     pub(crate) congestion_controller_factory: Arc<dyn congestion::ControllerFactory + Send + Sync>,
+    pub(crate) max_congestion_window: u64,
 
     pub(crate) enable_segmentation_offload: bool,
 
     pub(crate) qlog_sink: QlogSink,
+    pub(crate) on_packet: Option<Arc<PacketObserver>>,
 }
 
 impl TransportConfig {
@@ -76,6 +102,23 @@ impl TransportConfig {
         self
     }
 
+    /// Maximum number of accepted incoming streams of each directionality that the application
+    /// may be handling before the peer is prevented from opening more
+    ///
+    /// A remotely initiated stream is considered unhandled from the moment it is accepted until
+    /// the application releases it with [`RecvStream::handled`](crate::RecvStream::handled),
+    /// regardless of whether all of its data has been read. While this many streams are
+    /// unhandled, no further `MAX_STREAMS` credit is issued to the peer, so the rate at which it
+    /// can open streams is tied to the rate at which the application processes them. Credit that
+    /// was already issued is unaffected, so the peer may still open up to
+    /// `max_concurrent_bidi_streams` or `max_concurrent_uni_streams` streams in the meantime.
+    ///
+    /// `None` disables this limit. Defaults to `None`.
+    pub fn max_unhandled_streams(&mut self, value: Option<VarInt>) -> &mut Self {
+        self.max_unhandled_streams = value;
+        self
+    }
+
     /// Maximum duration of inactivity to accept before timing out the connection.
     ///
     /// The true idle timeout is the minimum of this and the peer's own max idle timeout. `None`
@@ -111,11 +154,65 @@ impl TransportConfig {
     /// stream doesn't monopolize receive buffers, which may otherwise occur if the application
     /// chooses not to read from a large stream for a time while still requiring data on other
     /// streams.
+    ///
+    /// Applies to every kind of stream, unless overridden by
+    /// [`stream_receive_window_bidi_local`](Self::stream_receive_window_bidi_local),
+    /// [`stream_receive_window_bidi_remote`](Self::stream_receive_window_bidi_remote), or
+    /// [`stream_receive_window_uni`](Self::stream_receive_window_uni).
     pub fn stream_receive_window(&mut self, value: VarInt) -> &mut Self {
         self.stream_receive_window = value;
         self
     }
 
+    /// Override [`stream_receive_window`](Self::stream_receive_window) for bidirectional streams
+    /// opened locally
+    ///
+    /// Advertised as the `initial_max_stream_data_bidi_local` transport parameter, and maintained
+    /// as the streams are read. Useful for asymmetric workloads, e.g. a client which uploads on the
+    /// streams it opens and only receives small responses can set this lower than
+    /// [`stream_receive_window_bidi_remote`](Self::stream_receive_window_bidi_remote).
+    pub fn stream_receive_window_bidi_local(&mut self, value: VarInt) -> &mut Self {
+        self.stream_receive_window_bidi_local = Some(value);
+        self
+    }
+
+    /// Override [`stream_receive_window`](Self::stream_receive_window) for bidirectional streams
+    /// opened by the peer
+    ///
+    /// Advertised as the `initial_max_stream_data_bidi_remote` transport parameter, and
+    /// maintained as the streams are read.
+    pub fn stream_receive_window_bidi_remote(&mut self, value: VarInt) -> &mut Self {
+        self.stream_receive_window_bidi_remote = Some(value);
+        self
+    }
+
+    /// Override [`stream_receive_window`](Self::stream_receive_window) for unidirectional streams
+    ///
+    /// Only the peer can send on unidirectional streams it opened, so this covers all the
+    /// unidirectional streams we receive. Advertised as the `initial_max_stream_data_uni`
+    /// transport parameter, and maintained as the streams are read.
+    pub fn stream_receive_window_uni(&mut self, value: VarInt) -> &mut Self {
+        self.stream_receive_window_uni = Some(value);
+        self
+    }
+
+    /// Maximum distance by which received data on any one stream may extend past the first gap
+    ///
+    /// Data that arrives ahead of a missing range must be buffered until the gap is filled. Without
+    /// this limit, a peer may deliberately withhold the start of a stream while sending data at
+    /// far-off offsets, forcing up to `stream_receive_window` bytes per stream to be held in many
+    /// small, sparsely populated buffers. A STREAM frame which would end more than this many bytes
+    /// past the lowest offset not yet received closes the connection with a
+    /// `FLOW_CONTROL_ERROR`.
+    ///
+    /// Legitimate peers routinely send out of order data when packets are lost, so this should be
+    /// no smaller than the expected bandwidth-delay product. `None` disables this limit, leaving
+    /// only `stream_receive_window` to bound buffering. Defaults to `None`.
+    pub fn max_stream_out_of_order_bytes(&mut self, value: Option<VarInt>) -> &mut Self {
+        self.max_stream_out_of_order_bytes = value;
+        self
+    }
+
     /// Maximum number of bytes the peer may transmit across all streams of a connection before
     /// becoming blocked.
     ///
@@ -127,6 +224,23 @@ impl TransportConfig {
         self
     }
 
+    /// Upper bound to which the connection-level receive window may grow automatically
+    ///
+    /// A connection can stall even though every stream has flow control credit to spare, if the
+    /// peer exhausts the connection-level [`receive_window`](Self::receive_window) before the
+    /// resulting `MAX_DATA` update reaches it. When set, the window starts at `receive_window` and
+    /// doubles, up to this limit, whenever updates have to be issued less than two round trips
+    /// apart, as the application is then reading the data about as fast as the path delivers it.
+    /// The window never shrinks again on its own. The current window is reported in
+    /// [`FlowControlStats::receive_window`](crate::FlowControlStats::receive_window).
+    ///
+    /// Has no effect unless larger than `receive_window`. Defaults to `None`, keeping the window
+    /// fixed.
+    pub fn max_receive_window(&mut self, value: Option<VarInt>) -> &mut Self {
+        self.max_receive_window = value;
+        self
+    }
+
     /// Maximum number of bytes to transmit to a peer without acknowledgment
     ///
     /// Provides an upper bound on memory when communicating with peers that issue large amounts of
@@ -153,6 +267,59 @@ impl TransportConfig {
         self
     }
 
+    /// Whether stream priorities act as weights rather than strict precedence
+    ///
+    /// By default, data from a stream is only sent once no stream of higher priority has data
+    /// pending, so a busy high priority stream can starve all others indefinitely. When enabled,
+    /// streams instead share the connection in proportion to their priority: a stream with
+    /// priority 4 gets roughly twice the bytes of one with priority 2 and four times those of one
+    /// with priority 1, while all of them make progress. Priorities below 1, including the
+    /// default of 0, all count as a weight of 1.
+    ///
+    /// This is implemented as deficit round-robin: streams with pending data take turns in
+    /// order, and each turn allows a stream to send up to its weight times a fixed quantum of
+    /// about one packet's worth of data. A turn may span several packets. Since every stream
+    /// takes turns, [`send_fairness()`](Self::send_fairness) has no effect when this is enabled.
+    pub fn weighted_priorities(&mut self, value: bool) -> &mut Self {
+        self.weighted_priorities = value;
+        self
+    }
+
+    /// Number of packets a send stream may wait for before it's counted as starved
+    ///
+    /// A stream waits from the moment it has data to send, or last sent some, until it's next
+    /// scheduled. The wait is measured in packets carrying data of other streams, so time spent
+    /// blocked by congestion or flow control doesn't count. Waits reaching the threshold are
+    /// counted by [`ConnectionStats::starved_streams`](crate::ConnectionStats::starved_streams).
+    /// Streams are checked every `value` packets, so a wait is counted once it's lasted between
+    /// one and two times the threshold.
+    ///
+    /// Even with fair scheduling, a stream waits for its turn behind every other stream of the
+    /// same priority, so the threshold should exceed the number of streams expected to send
+    /// concurrently. 0 disables the measurement. Defaults to 1000.
+    pub fn stream_starvation_threshold(&mut self, value: u32) -> &mut Self {
+        self.stream_starvation_threshold = value;
+        self
+    }
+
+    /// Multiple of the data received from an unvalidated peer address that may be sent to it
+    ///
+    /// RFC 9000 §8 requires that, until a peer's address is validated, an endpoint sends no more
+    /// than three times the amount of data it has received from that address, to prevent QUIC from
+    /// being used to amplify attacks against spoofed addresses. This applies to servers during the
+    /// handshake, and after a client migrates to a new address. Smaller factors further reduce the
+    /// potential for amplification, at the cost of more round trips when a large server
+    /// certificate chain doesn't fit within the budget.
+    ///
+    /// Must be between 1 and 3 inclusive. Defaults to 3.
+    pub fn amplification_factor(&mut self, value: u8) -> Result<&mut Self, ConfigError> {
+        if !(1..=3).contains(&value) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.amplification_factor = value;
+        Ok(self)
+    }
+
     /// Maximum reordering in packet number space before FACK style loss detection considers a
     /// packet lost. Should not be less than 3, per RFC5681.
     pub fn packet_threshold(&mut self, value: u32) -> &mut Self {
@@ -167,6 +334,72 @@ impl TransportConfig {
         self
     }
 
+    /// Set both loss detection thresholds at once, rejecting nonsensical values
+    ///
+    /// `packet_threshold` is the reordering threshold in packets (see
+    /// [`packet_threshold`](Self::packet_threshold)) and must be nonzero. `time_threshold_factor`
+    /// is the reordering threshold as a multiple of RTT (see
+    /// [`time_threshold`](Self::time_threshold)) and must be finite and at least 1. The defaults
+    /// are 3 and 9/8 respectively, as recommended by RFC 9002 §6.1.
+    ///
+    /// Raising either threshold makes loss detection more tolerant of reordering on the path at
+    /// the cost of detecting genuine losses later. Lowering them below the RFC 9002
+    /// recommendations risks declaring packets lost while they are merely delayed, which causes
+    /// spurious retransmissions and needlessly shrinks the congestion window.
+    pub fn loss_detection(
+        &mut self,
+        packet_threshold: u32,
+        time_threshold_factor: f32,
+    ) -> Result<&mut Self, ConfigError> {
+        if packet_threshold == 0
+            || !time_threshold_factor.is_finite()
+            || time_threshold_factor < 1.0
+        {
+            return Err(ConfigError::OutOfBounds);
+        }
+
+        self.packet_threshold = packet_threshold;
+        self.time_threshold = time_threshold_factor;
+        Ok(self)
+    }
+
+    /// Let the time threshold of loss detection widen when reordering is observed, up to this
+    /// multiple of the RTT
+    ///
+    /// Follows the reordering window adaptation of RACK (RFC 8985 §6.2). The current window is
+    /// reported in [`PathStats::reordering_window`](crate::PathStats::reordering_window). Must be
+    /// finite and at least 1. Defaults to `None`, which keeps the thresholds fixed.
+    pub fn max_time_threshold(&mut self, value: Option<f32>) -> Result<&mut Self, ConfigError> {
+        if value.is_some_and(|x| !x.is_finite() || x < 1.0) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.max_time_threshold = value;
+        Ok(self)
+    }
+
+    /// Maximum time we may delay acknowledging an ack-eliciting packet
+    ///
+    /// Advertised to the peer in the `max_ack_delay` transport parameter, which it includes in its
+    /// RTT and probe timeout computations (RFC 9002 §5.3, §6.2.1), and used locally to arm the
+    /// timer that forces out a delayed ACK. Larger values reduce the number of ACKs sent at the
+    /// cost of slower loss recovery by the peer.
+    ///
+    /// Must be at least 1ms, since we advertise support for delays as short as that, and less than
+    /// 2^14ms, per RFC 9000 §18.2. Sub-millisecond precision is discarded. Defaults to 25ms.
+    ///
+    /// If the peer supports QUIC Acknowledgement Frequency, it may request a different delay with
+    /// an ACK_FREQUENCY frame, which then takes precedence over this value for the rest of the
+    /// connection.
+    pub fn max_ack_delay(&mut self, value: Duration) -> Result<&mut Self, ConfigError> {
+        let millis = value.as_millis();
+        if !(1..1 << 14).contains(&millis) {
+            return Err(ConfigError::OutOfBounds);
+        }
+
+        self.max_ack_delay = VarInt(millis as u64);
+        Ok(self)
+    }
+
     /// The RTT used before an RTT sample is taken
     pub fn initial_rtt(&mut self, value: Duration) -> &mut Self {
         self.initial_rtt = value;
@@ -190,6 +423,17 @@ impl TransportConfig {
         self.initial_mtu.max(self.min_mtu)
     }
 
+    /// Receive windows of locally opened bidirectional, remotely opened bidirectional, and
+    /// unidirectional streams, in that order
+    pub(crate) fn stream_receive_windows(&self) -> (VarInt, VarInt, VarInt) {
+        let default = self.stream_receive_window;
+        (
+            self.stream_receive_window_bidi_local.unwrap_or(default),
+            self.stream_receive_window_bidi_remote.unwrap_or(default),
+            self.stream_receive_window_uni.unwrap_or(default),
+        )
+    }
+
     /// The maximum UDP payload size guaranteed to be supported by the network.
     ///
     /// Must be at least 1200, which is the default, and lower than or equal to
@@ -230,6 +474,23 @@ impl TransportConfig {
         self
     }
 
+    /// Range of sizes to pad datagrams carrying Initial packets to
+    ///
+    /// Each datagram that must be padded to 1200 bytes, including those carrying PATH_CHALLENGE or
+    /// PATH_RESPONSE frames, is padded to a size drawn uniformly at random from this range, but
+    /// never beyond the space available. Fails if the range starts below 1200 or is empty.
+    /// Defaults to `1200..=1200`.
+    pub fn initial_padding(
+        &mut self,
+        value: RangeInclusive<u16>,
+    ) -> Result<&mut Self, ConfigError> {
+        if *value.start() < MIN_INITIAL_SIZE || value.is_empty() {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.initial_padding = value;
+        Ok(self)
+    }
+
     /// Specifies the ACK frequency config (see [`AckFrequencyConfig`] for details)
     ///
     /// The provided configuration will be ignored if the peer does not support the acknowledgement
@@ -243,12 +504,56 @@ impl TransportConfig {
         self
     }
 
+    /// Acknowledge less often while no packets are lost, up to this threshold
+    ///
+    /// Each streak of 64 packets received without loss doubles the number of ack-eliciting packets
+    /// covered by each ACK; loss reverts to acknowledging every other packet. A threshold requested
+    /// by the peer through the acknowledgement frequency extension takes precedence. Defaults to
+    /// `None`, which keeps acknowledging every other ack-eliciting packet.
+    pub fn adaptive_ack_eliciting_threshold(&mut self, value: Option<VarInt>) -> &mut Self {
+        self.adaptive_ack_eliciting_threshold = value;
+        self
+    }
+
     /// Number of consecutive PTOs after which network is considered to be experiencing persistent congestion.
     pub fn persistent_congestion_threshold(&mut self, value: u32) -> &mut Self {
         self.persistent_congestion_threshold = value;
         self
     }
 
+    /// Number of consecutive PTOs after which the connection is closed with
+    /// [`ConnectionError::TimedOut`](crate::ConnectionError::TimedOut)
+    ///
+    /// An ACK that newly acknowledges packets resets the count. Must be at least 2, so that at
+    /// least one round of probes is sent. Defaults to `u32::MAX`, leaving the idle timeout as the
+    /// only bound.
+    pub fn max_pto_count(&mut self, value: u32) -> Result<&mut Self, ConfigError> {
+        if value < 2 {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.max_pto_count = value;
+        Ok(self)
+    }
+
+    /// Number of probe packets sent when a PTO expires with ack-eliciting packets in flight
+    ///
+    /// Probes elicit an acknowledgement which lets loss detection resume, so they're sent even if
+    /// the congestion window is full. Sending more than one makes it less likely that all of them
+    /// are lost and recovery has to wait for the next, exponentially longer, PTO; each additional
+    /// probe costs a packet's worth of bandwidth on a path that's likely congested already.
+    /// RFC 9002 §6.2.4 permits up to two. A PTO that expires without ack-eliciting packets in
+    /// flight, to prevent a deadlock while the handshake is incomplete, always sends a single
+    /// probe.
+    ///
+    /// Fails if not 1 or 2. Defaults to 2.
+    pub fn pto_probe_count(&mut self, value: u8) -> Result<&mut Self, ConfigError> {
+        if !(1..=2).contains(&value) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.pto_probe_count = value;
+        Ok(self)
+    }
+
     /// Period of inactivity before sending a keep-alive packet
     ///
     /// Keep-alive packets prevent an inactive but otherwise healthy connection from timing out.
@@ -261,6 +566,16 @@ impl TransportConfig {
         self
     }
 
+    /// Period without outgoing traffic before sending a minimal packet to refresh NAT bindings
+    ///
+    /// Unlike [`keep_alive_interval`](Self::keep_alive_interval), this restarts whenever we send a
+    /// packet, and sends a single unpadded PING when it fires. `None` to disable, which is the
+    /// default.
+    pub fn nat_keepalive(&mut self, value: Option<Duration>) -> &mut Self {
+        self.nat_keepalive = value;
+        self
+    }
+
     /// Maximum quantity of out-of-order crypto layer data to buffer
     pub fn crypto_buffer_size(&mut self, value: usize) -> &mut Self {
         self.crypto_buffer_size = value;
@@ -270,12 +585,80 @@ impl TransportConfig {
     /// Whether the implementation is permitted to set the spin bit on this connection
     ///
     /// This allows passive observers to easily judge the round trip time of a connection, which can
-    /// be useful for network administration but sacrifices a small amount of privacy.
+    /// be useful for network administration but sacrifices a small amount of privacy. The spin bit
+    /// is toggled as described in RFC 9000 §17.4, flipping once per round trip on 1-RTT packets.
+    ///
+    /// Even when permitted, the spin bit is disabled at random on 1 in 8 network paths, as RFC 9000
+    /// requires of at least 1 in 16 so that endpoints which never use it don't stand out. Packets
+    /// sent while it's disabled carry a random spin bit value. Defaults to `true`.
     pub fn allow_spin(&mut self, value: bool) -> &mut Self {
         self.allow_spin = value;
         self
     }
 
+    /// Whether to allow the peer to actively migrate to a new address
+    ///
+    /// When disabled, the `disable_active_migration` transport parameter is sent, and packets from
+    /// any address other than the one the handshake completed on are dropped. Defaults to `true`.
+    pub fn allow_migration(&mut self, value: bool) -> &mut Self {
+        self.allow_migration = value;
+        self
+    }
+
+    /// Minimum time between following the peer to new addresses
+    ///
+    /// Packets from other addresses during the cooldown are processed without migrating, unless
+    /// nothing was received on the current path for three PTOs. Only affects migrations initiated
+    /// by the peer. Defaults to zero, i.e. no cooldown.
+    pub fn migration_cooldown(&mut self, value: Duration) -> &mut Self {
+        self.migration_cooldown = value;
+        self
+    }
+
+    /// Whether a client automatically migrates to the server's preferred address once the
+    /// handshake is confirmed
+    ///
+    /// See [`Connection::migrate_to_preferred_address`] for how the migration proceeds. Has no
+    /// effect on servers. Defaults to `false`.
+    ///
+    /// [`Connection::migrate_to_preferred_address`]: crate::Connection::migrate_to_preferred_address
+    pub fn migrate_to_preferred_address(&mut self, value: bool) -> &mut Self {
+        self.migrate_to_preferred_address = value;
+        self
+    }
+
+    /// Period after which connection IDs are replaced, or `None` to keep them until migration
+    ///
+    /// Limits how long an observer can link packets by their connection ID. Both the connection
+    /// IDs we issue and the one of the peer's we use are replaced at this interval. Has no effect
+    /// with zero-length connection IDs. Defaults to `None`.
+    pub fn connection_id_rotation_interval(&mut self, value: Option<Duration>) -> &mut Self {
+        self.connection_id_rotation_interval = value;
+        self
+    }
+
+    /// Maximum number of the peer's connection IDs to store at once
+    ///
+    /// Advertised to the peer as the `active_connection_id_limit` transport parameter. The peer
+    /// must not issue more connection IDs than this that haven't been retired, and we likewise
+    /// issue no more than the peer's limit, and never more than 8. Each side uses the spare
+    /// connection IDs it holds to switch to an unlinkable one on migration or rotation, retiring
+    /// the previous one; a new one can only be issued once the retirement reaches the issuer.
+    ///
+    /// Lower limits save a little state at the cost of being more likely to run out of spare
+    /// connection IDs when switching often, in which case the current connection ID keeps being
+    /// used. Such events are counted in [`ConnectionStats::cids`](crate::ConnectionStats::cids).
+    /// Ignored when the endpoint uses zero-length connection IDs.
+    ///
+    /// Must be between 2 and 5 inclusive. Defaults to 5.
+    pub fn active_connection_id_limit(&mut self, value: u32) -> Result<&mut Self, ConfigError> {
+        if !(2..=CidQueue::LEN as u32).contains(&value) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.active_connection_id_limit = value;
+        Ok(self)
+    }
+
     /// Maximum number of incoming application datagram bytes to buffer, or None to disable
     /// incoming datagrams
     ///
@@ -298,6 +681,19 @@ impl TransportConfig {
         self
     }
 
+    /// Whether to accept partially reliable streams from the peer
+    ///
+    /// Partial reliability is a Quinn extension which lets a sender abandon stale stream data, see
+    /// [`SendStream::set_partial_reliability`](crate::SendStream::set_partial_reliability). When
+    /// enabled, support is advertised to the peer in a private transport parameter, and the peer
+    /// may then tell us to skip over parts of a stream it won't retransmit. The skipped ranges show
+    /// up as gaps in the offsets of [`Chunk`](crate::Chunk)s read from the stream. Other QUIC
+    /// implementations don't support this, and never skip data. Defaults to `false`.
+    pub fn partial_reliability(&mut self, value: bool) -> &mut Self {
+        self.partial_reliability = value;
+        self
+    }
+
     /// Whether to force every packet number to be used
     ///
     /// By default, packet numbers are occasionally skipped to ensure peers aren't ACKing packets
@@ -327,6 +723,29 @@ impl TransportConfig {
         self
     }
 
+    /// Upper bound on the congestion window, in bytes
+    ///
+    /// Limits the amount of ack-eliciting data in flight, and the pacing rate derived from it,
+    /// whatever window the congestion controller computes. On paths with large buffers,
+    /// loss-based controllers keep growing the window until the buffer overflows, adding
+    /// queueing delay proportional to the excess. Capping the window at around the path's
+    /// bandwidth-delay product bounds that delay, at the cost of throughput if the cap is below
+    /// what the path could sustain.
+    ///
+    /// The controller itself is unaware of the cap. Loss-based controllers such as
+    /// [`Cubic`](congestion::Cubic) see no congestion while capped, so their internal window may
+    /// keep growing, and the first reduction after a loss may leave it above the cap. Model-based
+    /// controllers such as [`Bbr`](congestion::Bbr) size the window from their bandwidth and RTT
+    /// estimates, which the cap holds down only once it's below their own window, typically at
+    /// about twice the bandwidth-delay product.
+    ///
+    /// Defaults to `u64::MAX`, i.e. no cap. Values below the controller's minimum window, a few
+    /// packets, may stall the connection's throughput.
+    pub fn max_congestion_window(&mut self, value: u64) -> &mut Self {
+        self.max_congestion_window = value;
+        self
+    }
+
     /// Whether to use "Generic Segmentation Offload" to accelerate transmits, when supported by the
     /// environment
     ///
@@ -348,6 +767,22 @@ impl TransportConfig {
         self.qlog_sink = stream.into();
         self
     }
+
+    /// Callback invoked for every QUIC packet sent or received, or `None` to disable
+    ///
+    /// Packets are reported individually, even when coalesced into a single UDP datagram. Sent
+    /// packets are reported as they're encrypted, and received packets once they've been
+    /// authenticated and processed; packets which can't be decrypted or are duplicates aren't
+    /// reported.
+    ///
+    /// The callback runs synchronously on the connection's hot path, with the connection's state
+    /// locked, so it must be fast and must not block. Expensive work should be deferred, e.g. by
+    /// updating atomic counters or sending the [`PacketInfo`] to another task. Defaults to `None`,
+    /// which adds no overhead.
+    pub fn on_packet(&mut self, observer: Option<Arc<PacketObserver>>) -> &mut Self {
+        self.on_packet = observer;
+        self
+    }
 }
 
 impl Default for TransportConfig {
@@ -361,36 +796,60 @@ impl Default for TransportConfig {
         Self {
             max_concurrent_bidi_streams: 100u32.into(),
             max_concurrent_uni_streams: 100u32.into(),
+            max_unhandled_streams: None,
             // 30 second default recommended by RFC 9308 § 3.2
             max_idle_timeout: Some(VarInt(30_000)),
             stream_receive_window: STREAM_RWND.into(),
+            stream_receive_window_bidi_local: None,
+            stream_receive_window_bidi_remote: None,
+            stream_receive_window_uni: None,
+            max_stream_out_of_order_bytes: None,
             receive_window: VarInt::MAX,
+            max_receive_window: None,
             send_window: (8 * STREAM_RWND).into(),
             send_fairness: true,
+            weighted_priorities: false,
+            stream_starvation_threshold: 1000,
+            amplification_factor: 3,
 
             packet_threshold: 3,
             time_threshold: 9.0 / 8.0,
+            max_time_threshold: None,
             initial_rtt: Duration::from_millis(333), // per spec, intentionally distinct from EXPECTED_RTT
             initial_mtu: INITIAL_MTU,
             min_mtu: INITIAL_MTU,
             mtu_discovery_config: Some(MtuDiscoveryConfig::default()),
             pad_to_mtu: false,
+            initial_padding: MIN_INITIAL_SIZE..=MIN_INITIAL_SIZE,
             ack_frequency_config: None,
+            adaptive_ack_eliciting_threshold: None,
+            max_ack_delay: VarInt(25),
 
             persistent_congestion_threshold: 3,
+            max_pto_count: u32::MAX,
+            pto_probe_count: 2,
             keep_alive_interval: None,
+            nat_keepalive: None,
             crypto_buffer_size: 16 * 1024,
             allow_spin: true,
+            allow_migration: true,
+            migration_cooldown: Duration::ZERO,
+            migrate_to_preferred_address: false,
+            connection_id_rotation_interval: None,
+            active_connection_id_limit: CidQueue::LEN as u32,
             datagram_receive_buffer_size: Some(STREAM_RWND as usize),
             datagram_send_buffer_size: 1024 * 1024,
+            partial_reliability: false,
             #[cfg(test)]
             deterministic_packet_numbers: false,
 
             congestion_controller_factory: Arc::new(congestion::CubicConfig::default()),
+            max_congestion_window: u64::MAX,
 
             enable_segmentation_offload: true,
 
             qlog_sink: QlogSink::default(),
+            on_packet: None,
         }
     }
 }
@@ -400,67 +859,160 @@ impl fmt::Debug for TransportConfig {
         let Self {
             max_concurrent_bidi_streams,
             max_concurrent_uni_streams,
+            max_unhandled_streams,
             max_idle_timeout,
             stream_receive_window,
+            stream_receive_window_bidi_local,
+            stream_receive_window_bidi_remote,
+            stream_receive_window_uni,
+            max_stream_out_of_order_bytes,
             receive_window,
+            max_receive_window,
             send_window,
             send_fairness,
+            weighted_priorities,
+            stream_starvation_threshold,
+            amplification_factor,
             packet_threshold,
             time_threshold,
+            max_time_threshold,
             initial_rtt,
             initial_mtu,
             min_mtu,
             mtu_discovery_config,
             pad_to_mtu,
+            initial_padding,
             ack_frequency_config,
+            adaptive_ack_eliciting_threshold,
+            max_ack_delay,
             persistent_congestion_threshold,
+            max_pto_count,
+            pto_probe_count,
             keep_alive_interval,
+            nat_keepalive,
             crypto_buffer_size,
             allow_spin,
+            allow_migration,
+            migration_cooldown,
+            migrate_to_preferred_address,
+            connection_id_rotation_interval,
+            active_connection_id_limit,
             datagram_receive_buffer_size,
             datagram_send_buffer_size,
+            partial_reliability,
             #[cfg(test)]
                 deterministic_packet_numbers: _,
             congestion_controller_factory: _,
+            max_congestion_window,
             enable_segmentation_offload,
             qlog_sink,
+            on_packet,
         } = self;
         let mut s = fmt.debug_struct("TransportConfig");
 
         s.field("max_concurrent_bidi_streams", max_concurrent_bidi_streams)
             .field("max_concurrent_uni_streams", max_concurrent_uni_streams)
+            .field("max_unhandled_streams", max_unhandled_streams)
             .field("max_idle_timeout", max_idle_timeout)
             .field("stream_receive_window", stream_receive_window)
+            .field(
+                "stream_receive_window_bidi_local",
+                stream_receive_window_bidi_local,
+            )
+            .field(
+                "stream_receive_window_bidi_remote",
+                stream_receive_window_bidi_remote,
+            )
+            .field("stream_receive_window_uni", stream_receive_window_uni)
+            .field(
+                "max_stream_out_of_order_bytes",
+                max_stream_out_of_order_bytes,
+            )
             .field("receive_window", receive_window)
+            .field("max_receive_window", max_receive_window)
             .field("send_window", send_window)
             .field("send_fairness", send_fairness)
+            .field("weighted_priorities", weighted_priorities)
+            .field("stream_starvation_threshold", stream_starvation_threshold)
+            .field("amplification_factor", amplification_factor)
             .field("packet_threshold", packet_threshold)
             .field("time_threshold", time_threshold)
+            .field("max_time_threshold", max_time_threshold)
             .field("initial_rtt", initial_rtt)
             .field("initial_mtu", initial_mtu)
             .field("min_mtu", min_mtu)
             .field("mtu_discovery_config", mtu_discovery_config)
             .field("pad_to_mtu", pad_to_mtu)
+            .field("initial_padding", initial_padding)
             .field("ack_frequency_config", ack_frequency_config)
+            .field(
+                "adaptive_ack_eliciting_threshold",
+                adaptive_ack_eliciting_threshold,
+            )
+            .field("max_ack_delay", max_ack_delay)
             .field(
                 "persistent_congestion_threshold",
                 persistent_congestion_threshold,
             )
+            .field("max_pto_count", max_pto_count)
+            .field("pto_probe_count", pto_probe_count)
             .field("keep_alive_interval", keep_alive_interval)
+            .field("nat_keepalive", nat_keepalive)
             .field("crypto_buffer_size", crypto_buffer_size)
             .field("allow_spin", allow_spin)
+            .field("allow_migration", allow_migration)
+            .field("migration_cooldown", migration_cooldown)
+            .field("migrate_to_preferred_address", migrate_to_preferred_address)
+            .field(
+                "connection_id_rotation_interval",
+                connection_id_rotation_interval,
+            )
+            .field("active_connection_id_limit", active_connection_id_limit)
             .field("datagram_receive_buffer_size", datagram_receive_buffer_size)
             .field("datagram_send_buffer_size", datagram_send_buffer_size)
+            .field("partial_reliability", partial_reliability)
             // congestion_controller_factory not debug
+            .field("max_congestion_window", max_congestion_window)
             .field("enable_segmentation_offload", enable_segmentation_offload);
         if cfg!(feature = "qlog") {
             s.field("qlog_stream", &qlog_sink.is_enabled());
         }
+        s.field("on_packet", &on_packet.is_some());
 
         s.finish_non_exhaustive()
     }
 }
 
+/// Callback invoked for every packet sent or received
+///
+/// See [`TransportConfig::on_packet`].
+pub type PacketObserver = dyn Fn(PacketInfo) + Send + Sync;
+
+/// Details of a single QUIC packet, passed to a [`PacketObserver`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct PacketInfo {
+    /// Whether the packet was sent or received
+    pub direction: PacketDirection,
+    /// Packet number space the packet belongs to
+    pub space: SpaceId,
+    /// Packet number
+    pub number: u64,
+    /// Size of the encrypted packet in bytes, including its header
+    pub size: usize,
+    /// Whether the packet contained frames that require acknowledgement
+    pub ack_eliciting: bool,
+}
+
+/// Whether a packet was sent or received
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PacketDirection {
+    /// The packet was sent by this endpoint
+    Sent,
+    /// The packet was received from the peer
+    Received,
+}
+
 /// Parameters for controlling the peer's acknowledgement frequency
 ///
 /// The parameters provided in this config will be sent to the peer at the beginning of the
@@ -689,6 +1241,7 @@ pub struct MtuDiscoveryConfig {
     pub(crate) upper_bound: u16,
     pub(crate) minimum_change: u16,
     pub(crate) black_hole_cooldown: Duration,
+    pub(crate) packet_too_big: bool,
 }
 
 impl MtuDiscoveryConfig {
@@ -730,6 +1283,25 @@ impl MtuDiscoveryConfig {
         self.minimum_change = value;
         self
     }
+
+    /// Specifies whether ICMP "packet too big" messages may lower the MTU. Defaults to `false`.
+    ///
+    /// When enabled, messages passed to
+    /// [`Endpoint::handle_packet_too_big`](crate::Endpoint::handle_packet_too_big) reduce the MTU
+    /// immediately rather than waiting for black hole detection to notice the loss, and MTU
+    /// discovery then waits for [`black_hole_cooldown`](Self::black_hole_cooldown) before probing
+    /// again.
+    ///
+    /// ICMP messages are not authenticated, so an off-path attacker could forge them to degrade
+    /// throughput. Following [RFC 8899 §4.6](https://www.rfc-editor.org/rfc/rfc8899#section-4.6),
+    /// a message is only acted upon if the datagram it quotes was sent to the current path's
+    /// remote address and carries a connection ID the peer issued to us, and the reported size is
+    /// below the current MTU but not below the minimum MTU. The MTU can therefore never be lowered
+    /// beneath what the connection would fall back to after a black hole.
+    pub fn packet_too_big(&mut self, value: bool) -> &mut Self {
+        self.packet_too_big = value;
+        self
+    }
 }
 
 impl Default for MtuDiscoveryConfig {
@@ -739,6 +1311,7 @@ impl Default for MtuDiscoveryConfig {
             upper_bound: 1452,
             black_hole_cooldown: Duration::from_secs(60),
             minimum_change: 20,
+            packet_too_big: false,
         }
     }
 }
@@ -10,6 +10,7 @@ mod bbr_max;
 mod brutal;
 mod brutal_bbr;
 mod cubic;
+mod hystart;
 mod new_reno;
 
 pub use bbr::{Bbr, BbrConfig};
@@ -40,6 +41,13 @@ pub trait Controller: Send + Sync {
     ) {
     }
 
+    /// A fresh RTT sample was taken from an acknowledgement and incorporated into `rtt`
+    ///
+    /// Called at most once per batch of acknowledgements, after [`on_ack`](Self::on_ack) and
+    /// before [`on_end_acks`](Self::on_end_acks).
+    #[allow(unused_variables)]
+    fn on_rtt_sample(&mut self, now: Instant, rtt: &RttEstimator) {}
+
     /// Packets are acked in batches, all with the same `now` argument. This indicates one of those batches has completed.
     #[allow(unused_variables)]
     fn on_end_acks(
@@ -111,7 +119,8 @@ pub trait Controller: Send + Sync {
 }
 
 /// Common congestion controller metrics
-#[derive(Default)]
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[non_exhaustive]
 pub struct ControllerMetrics {
     /// Congestion window (bytes)
@@ -133,6 +142,8 @@ pub struct ControllerMetrics {
     /// Delivery success rate (0.0–1.0) from sliding-window ack/loss tracking.
     /// Only populated by controllers that implement ack-rate estimation.
     pub ack_rate: Option<f64>,
+    /// Number of times HyStart++ ended slow start because the RTT increased
+    pub hystart_exits: Option<u64>,
 }
 
 /// Constructs controllers on demand
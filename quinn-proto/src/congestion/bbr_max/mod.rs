@@ -661,6 +661,7 @@ impl Controller for BbrMax {
             is_at_full_bandwidth: Some(self.is_at_full_bandwidth),
             round_count: Some(self.round_count),
             ack_rate: Some(self.ack_rate),
+            hystart_exits: None,
         }
     }
 
@@ -513,6 +513,7 @@ impl Controller for BrutalBbr {
             is_at_full_bandwidth: Some(self.is_at_full_bandwidth),
             round_count: Some(self.round_count),
             ack_rate: None,
+            hystart_exits: None,
         }
     }
 
@@ -2,6 +2,7 @@ use std::any::Any;
 use std::cmp;
 use std::sync::Arc;
 
+use super::hystart::{CSS_GROWTH_DIVISOR, HyStart};
 use super::{BASE_DATAGRAM_SIZE, Controller, ControllerFactory};
 use crate::connection::RttEstimator;
 use crate::{Duration, Instant};
@@ -77,6 +78,7 @@ pub struct Cubic {
     config: Arc<CubicConfig>,
     current_mtu: u64,
     state: State,
+    hystart: HyStart,
     /// Copy of the controller state to restore when a spurious congestion event is detected.
     pre_congestion_state: Option<State>,
 }
@@ -91,6 +93,7 @@ impl Cubic {
                 ..Default::default()
             },
             current_mtu: current_mtu as u64,
+            hystart: HyStart::default(),
             pre_congestion_state: None,
             config,
         }
@@ -99,9 +102,18 @@ impl Cubic {
     fn minimum_window(&self) -> u64 {
         2 * self.current_mtu
     }
+
+    /// Whether HyStart++ governs slow start, which is only the case for the initial slow start
+    fn hystart_active(&self) -> bool {
+        self.config.hystart && self.state.ssthresh == u64::MAX
+    }
 }
 
 impl Controller for Cubic {
+    fn on_sent(&mut self, _now: Instant, _bytes: u64, last_packet_number: u64) {
+        self.hystart.on_sent(last_packet_number);
+    }
+
     fn on_ack(
         &mut self,
         now: Instant,
@@ -122,7 +134,11 @@ impl Controller for Cubic {
 
         if self.state.window < self.state.ssthresh {
             // Slow start
-            self.state.window += bytes;
+            if self.hystart_active() && self.hystart.in_css() {
+                self.state.window += bytes / CSS_GROWTH_DIVISOR;
+            } else {
+                self.state.window += bytes;
+            }
         } else {
             // Congestion avoidance.
             let ca_start_time;
@@ -174,6 +190,25 @@ impl Controller for Cubic {
         }
     }
 
+    fn on_rtt_sample(&mut self, _now: Instant, rtt: &RttEstimator) {
+        if self.hystart_active() {
+            self.hystart.on_rtt_sample(rtt.latest());
+        }
+    }
+
+    fn on_end_acks(
+        &mut self,
+        _now: Instant,
+        _in_flight: u64,
+        _app_limited: bool,
+        largest_packet_num_acked: Option<u64>,
+    ) {
+        if self.hystart_active() && self.hystart.on_end_acks(largest_packet_num_acked) {
+            // Conservative slow start is over, move on to congestion avoidance
+            self.state.ssthresh = self.state.window;
+        }
+    }
+
     fn on_congestion_event(
         &mut self,
         now: Instant,
@@ -252,6 +287,7 @@ impl Controller for Cubic {
             congestion_window: self.window(),
             ssthresh: Some(self.state.ssthresh),
             pacing_rate: None,
+            hystart_exits: Some(self.hystart.exits()),
             ..Default::default()
         }
     }
@@ -273,6 +309,7 @@ impl Controller for Cubic {
 #[derive(Debug, Clone)]
 pub struct CubicConfig {
     initial_window: u64,
+    hystart: bool,
 }
 
 impl CubicConfig {
@@ -283,12 +320,25 @@ impl CubicConfig {
         self.initial_window = value;
         self
     }
+
+    /// Whether to use HyStart++ (RFC9406) to leave the initial slow start
+    ///
+    /// HyStart++ ends slow start once the round-trip time starts increasing, as the bottleneck
+    /// queue fills up, rather than waiting for the queue to overflow. This avoids a burst of losses
+    /// when slow start ends, which is especially costly on lossy links such as wireless networks.
+    ///
+    /// Enabled by default, as in modern TCP implementations.
+    pub fn hystart(&mut self, value: bool) -> &mut Self {
+        self.hystart = value;
+        self
+    }
 }
 
 impl Default for CubicConfig {
     fn default() -> Self {
         Self {
             initial_window: 14720.clamp(2 * BASE_DATAGRAM_SIZE, 10 * BASE_DATAGRAM_SIZE),
+            hystart: true,
         }
     }
 }
@@ -298,3 +348,45 @@ impl ControllerFactory for CubicConfig {
         Box::new(Cubic::new(self, now, current_mtu))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Only acknowledgements that yield a fresh RTT sample may count towards HyStart++
+    #[test]
+    fn hystart_ignores_acks_without_rtt_sample() {
+        let now = Instant::now();
+        let mut cubic = Cubic::new(Arc::new(CubicConfig::default()), now, 1200);
+        let mut rtt = RttEstimator::new(Duration::from_millis(100));
+        let mut ack = |cubic: &mut Cubic, pn: u64, sample: Option<Duration>| {
+            cubic.on_ack(now, now, 1200, false, &rtt);
+            if let Some(sample) = sample {
+                rtt.update(Duration::ZERO, sample);
+                cubic.on_rtt_sample(now, &rtt);
+            }
+            cubic.on_end_acks(now, 0, false, Some(pn));
+        };
+
+        // Establish a minimum RTT of 100ms for the previous round
+        let base = Duration::from_millis(100);
+        cubic.on_sent(now, 12_000, 9);
+        ack(&mut cubic, 0, Some(base));
+        cubic.on_sent(now, 24_000, 29);
+        ack(&mut cubic, 9, Some(base));
+
+        // A single increased sample followed by acknowledgements without samples
+        let increased = Duration::from_millis(200);
+        ack(&mut cubic, 10, Some(increased));
+        for pn in 11..18 {
+            ack(&mut cubic, pn, None);
+        }
+        assert_eq!(cubic.metrics().hystart_exits, Some(0));
+
+        // Enough fresh samples in the same round
+        for pn in 18..25 {
+            ack(&mut cubic, pn, Some(increased));
+        }
+        assert_eq!(cubic.metrics().hystart_exits, Some(1));
+    }
+}
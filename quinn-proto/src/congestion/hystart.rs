@@ -0,0 +1,112 @@
+use crate::Duration;
+
+/// HyStart++ Constants.
+///
+/// These are the recommended values in RFC9406.
+const MIN_RTT_THRESH: Duration = Duration::from_millis(4);
+const MAX_RTT_THRESH: Duration = Duration::from_millis(16);
+const MIN_RTT_DIVISOR: u32 = 8;
+const N_RTT_SAMPLE: u32 = 8;
+pub(super) const CSS_GROWTH_DIVISOR: u64 = 4;
+const CSS_ROUNDS: u32 = 5;
+
+/// HyStart++ slow start exit, as described in RFC9406
+///
+/// Watches the minimum RTT of each round of slow start. Once it increases noticeably, the
+/// bottleneck queue is filling up, and slow start is replaced by Conservative Slow Start (CSS),
+/// which grows the window more carefully until either the increase turns out to be spurious or
+/// `CSS_ROUNDS` rounds have passed.
+///
+/// The limit `L` on the window increase per ACK is not implemented, as it's only needed for
+/// non-paced senders.
+#[derive(Debug, Default, Clone)]
+pub(super) struct HyStart {
+    /// Largest packet number sent so far
+    last_sent_packet: u64,
+    /// Packet number whose acknowledgement ends the current round, if a round has started
+    window_end: Option<u64>,
+    /// Minimum RTT of the previous round
+    last_round_min_rtt: Option<Duration>,
+    /// Minimum RTT of the current round
+    current_round_min_rtt: Option<Duration>,
+    /// Number of RTT samples taken in the current round
+    rtt_sample_count: u32,
+    /// Minimum RTT of the round in which CSS was entered, while in CSS
+    css_baseline_min_rtt: Option<Duration>,
+    /// Number of rounds completed in CSS
+    css_rounds: u32,
+    /// Number of times an RTT increase ended standard slow start
+    exits: u64,
+}
+
+impl HyStart {
+    pub(super) fn on_sent(&mut self, last_packet_number: u64) {
+        self.last_sent_packet = self.last_sent_packet.max(last_packet_number);
+    }
+
+    /// Track the end of rounds after a batch of ACKs, returning whether slow start should end
+    pub(super) fn on_end_acks(&mut self, largest_packet_num_acked: Option<u64>) -> bool {
+        let round_ended = match (self.window_end, largest_packet_num_acked) {
+            (None, _) => true,
+            (Some(end), Some(largest)) => largest >= end,
+            (Some(_), None) => false,
+        };
+        if !round_ended {
+            return false;
+        }
+
+        if self.in_css() {
+            self.css_rounds += 1;
+            if self.css_rounds >= CSS_ROUNDS {
+                return true;
+            }
+        }
+
+        self.last_round_min_rtt = self.current_round_min_rtt.take();
+        self.rtt_sample_count = 0;
+        self.window_end = Some(self.last_sent_packet);
+        false
+    }
+
+    /// Whether Conservative Slow Start is in progress
+    pub(super) fn in_css(&self) -> bool {
+        self.css_baseline_min_rtt.is_some()
+    }
+
+    /// Number of times an RTT increase ended standard slow start
+    pub(super) fn exits(&self) -> u64 {
+        self.exits
+    }
+
+    pub(super) fn on_rtt_sample(&mut self, rtt: Duration) {
+        let current = match self.current_round_min_rtt {
+            Some(min) => min.min(rtt),
+            None => rtt,
+        };
+        self.current_round_min_rtt = Some(current);
+        self.rtt_sample_count += 1;
+        if self.rtt_sample_count < N_RTT_SAMPLE {
+            return;
+        }
+
+        match self.css_baseline_min_rtt {
+            // The RTT increase was spurious, so resume standard slow start
+            Some(baseline) if current < baseline => {
+                self.css_baseline_min_rtt = None;
+                self.css_rounds = 0;
+            }
+            Some(_) => {}
+            None => {
+                let Some(last) = self.last_round_min_rtt else {
+                    return;
+                };
+                let rtt_thresh = (last / MIN_RTT_DIVISOR).clamp(MIN_RTT_THRESH, MAX_RTT_THRESH);
+                if current >= last + rtt_thresh {
+                    self.css_baseline_min_rtt = Some(current);
+                    self.css_rounds = 0;
+                    self.exits += 1;
+                }
+            }
+        }
+    }
+}
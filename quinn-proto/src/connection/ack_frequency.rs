@@ -21,14 +21,14 @@ pub(super) struct AckFrequencyState {
 }
 
 impl AckFrequencyState {
-    pub(super) fn new(default_max_ack_delay: Duration) -> Self {
+    pub(super) fn new(max_ack_delay: Duration, default_peer_max_ack_delay: Duration) -> Self {
         Self {
             in_flight_ack_frequency_frame: None,
             next_outgoing_sequence_number: VarInt(0),
-            peer_max_ack_delay: default_max_ack_delay,
+            peer_max_ack_delay: default_peer_max_ack_delay,
 
             last_ack_frequency_frame: None,
-            max_ack_delay: default_max_ack_delay,
+            max_ack_delay,
         }
     }
 
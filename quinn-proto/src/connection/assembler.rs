@@ -22,6 +22,9 @@ pub(super) struct Assembler {
     /// aka the stream offset.
     bytes_read: u64,
     end: u64,
+    /// Offset below which the peer abandoned any data it didn't deliver, so that ordered reads
+    /// pass over gaps
+    skipped: u64,
 }
 
 impl Assembler {
@@ -52,6 +55,7 @@ impl Assembler {
                 recvd.insert(chunk.offset..chunk.offset + chunk.bytes.len() as u64);
             }
             self.state = State::Unordered { recvd };
+            self.skip_to(self.skipped);
         }
         Ok(())
     }
@@ -59,9 +63,19 @@ impl Assembler {
     /// Get the the next chunk
     pub(super) fn read(&mut self, max_length: usize, ordered: bool) -> Option<Chunk> {
         loop {
-            let mut chunk = self.data.peek_mut()?;
+            let Some(mut chunk) = self.data.peek_mut() else {
+                if ordered {
+                    // Pass over a trailing gap the peer abandoned
+                    self.bytes_read = self.bytes_read.max(self.skipped);
+                }
+                return None;
+            };
 
             if ordered {
+                if chunk.offset > self.bytes_read {
+                    // Pass over a gap the peer abandoned, if any
+                    self.bytes_read = self.bytes_read.max(chunk.offset.min(self.skipped));
+                }
                 if chunk.offset > self.bytes_read {
                     // Next chunk is after current read index
                     return None;
@@ -98,6 +112,35 @@ impl Assembler {
         }
     }
 
+    /// Copy buffered data starting at `offset` into `buf` without consuming it
+    ///
+    /// Returns the number of contiguous bytes copied, which is zero if the data at `offset` has
+    /// not been received yet or was already read.
+    pub(super) fn peek(&self, mut offset: u64, buf: &mut [u8]) -> usize {
+        if self.state.is_ordered() && offset < self.bytes_read {
+            // Stale chunks may still hold data before the read index
+            return 0;
+        }
+
+        let mut copied = 0;
+        'search: while copied < buf.len() {
+            for chunk in &self.data {
+                let end = chunk.offset + chunk.bytes.len() as u64;
+                if chunk.offset > offset || end <= offset {
+                    continue;
+                }
+                let start = (offset - chunk.offset) as usize;
+                let len = (chunk.bytes.len() - start).min(buf.len() - copied);
+                buf[copied..copied + len].copy_from_slice(&chunk.bytes[start..start + len]);
+                copied += len;
+                offset += len as u64;
+                continue 'search;
+            }
+            break;
+        }
+        copied
+    }
+
     /// Copy fragmented chunk data to new chunks backed by a single buffer
     ///
     /// This makes sure we're not unnecessarily holding on to many larger allocations.
@@ -208,6 +251,26 @@ impl Assembler {
         }
     }
 
+    /// Stop waiting for any data below `offset` that hasn't been received, as the peer abandoned it
+    ///
+    /// Data received below `offset` remains readable. In unordered mode, returns the number of
+    /// bytes passed over, which count as read. In ordered mode, gaps are only passed over, and
+    /// counted in [`bytes_read`](Self::bytes_read), as reads reach them, and this returns zero.
+    pub(super) fn skip_to(&mut self, offset: u64) -> u64 {
+        self.end = self.end.max(offset);
+        self.skipped = self.skipped.max(offset);
+        let State::Unordered { ref mut recvd } = self.state else {
+            return 0;
+        };
+        let received = recvd
+            .replace(0..offset)
+            .map(|x| x.end - x.start)
+            .sum::<u64>();
+        let skipped = offset - received;
+        self.bytes_read += skipped;
+        skipped
+    }
+
     /// Number of bytes consumed by the application
     pub(super) fn bytes_read(&self) -> u64 {
         self.bytes_read
@@ -218,6 +281,7 @@ impl Assembler {
         self.data.clear();
         self.buffered = 0;
         self.allocated = 0;
+        self.skipped = 0;
     }
 }
 
@@ -368,6 +432,31 @@ mod test {
         assert_matches!(next(&mut x, 32), None);
     }
 
+    #[test]
+    fn skip_gaps() {
+        let mut x = Assembler::new();
+        x.insert(3, Bytes::from_static(b"456"), 3);
+        x.insert(9, Bytes::from_static(b"10"), 2);
+        assert_eq!(x.skip_to(8), 0);
+        // Received data is still read, while gaps below the skipped offset are passed over
+        assert_matches!(x.read(32, true), Some(ref y) if y.offset == 3 && &y.bytes[..] == b"456");
+        assert_matches!(x.read(32, true), None);
+        assert_eq!(x.bytes_read(), 8);
+        x.insert(8, Bytes::from_static(b"9"), 1);
+        assert_matches!(next(&mut x, 32), Some(ref y) if &y[..] == b"9");
+        assert_matches!(next(&mut x, 32), Some(ref y) if &y[..] == b"10");
+    }
+
+    #[test]
+    fn skip_gaps_unordered() {
+        let mut x = Assembler::new();
+        x.ensure_ordering(false).unwrap();
+        x.insert(3, Bytes::from_static(b"456"), 3);
+        assert_eq!(x.skip_to(8), 5);
+        assert_eq!(next_unordered(&mut x).offset, 3);
+        assert_eq!(x.bytes_read(), 8);
+    }
+
     #[test]
     fn assemble_duplicate() {
         let mut x = Assembler::new();
@@ -648,6 +737,26 @@ mod test {
         assert_eq!(x.read(3, false), None);
     }
 
+    #[test]
+    fn peek_out_of_order() {
+        let mut x = Assembler::new();
+        let mut buf = [0; 8];
+        x.insert(3, Bytes::from_static(b"def"), 3);
+        assert_eq!(x.peek(0, &mut buf), 0);
+        assert_eq!(x.peek(4, &mut buf), 2);
+        assert_eq!(&buf[..2], b"ef");
+        x.insert(0, Bytes::from_static(b"abcd"), 4);
+        x.insert(8, Bytes::from_static(b"ij"), 2);
+        assert_eq!(x.peek(1, &mut buf), 5);
+        assert_eq!(&buf[..5], b"bcdef");
+        assert_eq!(x.peek(8, &mut buf[..1]), 1);
+        assert_eq!(&buf[..1], b"i");
+        assert_eq!(next(&mut x, 2).as_deref(), Some(&b"ab"[..]));
+        assert_eq!(x.peek(0, &mut buf), 0);
+        assert_eq!(x.peek(2, &mut buf), 4);
+        assert_eq!(&buf[..4], b"cdef");
+    }
+
     fn next_unordered(x: &mut Assembler) -> Chunk {
         x.read(usize::MAX, false).unwrap()
     }
@@ -88,13 +88,18 @@ impl CidState {
         });
     }
 
+    /// Whether the peer hasn't yet retired all the CIDs we last asked it to
+    ///
+    /// Until it does, we can't ask it to retire more, and so can't issue replacements.
+    pub(crate) fn retirement_pending(&self) -> bool {
+        (self.prev_retire_seq..self.retire_seq).any(|seq| self.active_seq.contains(&seq))
+    }
+
     /// Update local CID state when previously issued CID is retired
     ///
     /// Return whether a new CID needs to be pushed that notifies remote peer to respond `RETIRE_CONNECTION_ID`
     pub(crate) fn on_cid_timeout(&mut self) -> bool {
-        // Whether the peer hasn't retired all the CIDs we asked it to yet
-        let unretired_ids_found =
-            (self.prev_retire_seq..self.retire_seq).any(|seq| self.active_seq.contains(&seq));
+        let unretired_ids_found = self.retirement_pending();
 
         let current_retire_prior_to = self.retire_seq;
         let next_retire_sequence = self
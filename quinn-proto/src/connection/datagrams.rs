@@ -4,7 +4,7 @@ use bytes::Bytes;
 use thiserror::Error;
 use tracing::{debug, trace};
 
-use super::Connection;
+use super::{Connection, DatagramDropStats};
 use crate::{
     TransportError,
     frame::{Datagram, FrameStruct},
@@ -87,6 +87,34 @@ impl Datagrams<'_> {
         self.conn.datagrams.recv()
     }
 
+    /// Discard datagrams on arrival until [`resume_recv`](Self::resume_recv) is called
+    ///
+    /// For applications that temporarily can't keep up with incoming datagrams. Without pausing,
+    /// datagrams are buffered up to
+    /// [`TransportConfig::datagram_receive_buffer_size`](crate::TransportConfig::datagram_receive_buffer_size),
+    /// beyond which the oldest are dropped; while paused, new datagrams are dropped immediately
+    /// instead, so what is eventually read isn't stale. Datagrams buffered before pausing can still
+    /// be read. Drops are counted in [`ConnectionStats::datagram_drops`].
+    ///
+    /// QUIC datagrams are inherently unreliable, so the peer isn't told about dropped datagrams,
+    /// and the application protocol must tolerate their loss like any other. `backpressure`
+    /// selects whether the peer is additionally asked to slow down.
+    ///
+    /// [`ConnectionStats::datagram_drops`]: crate::ConnectionStats::datagram_drops
+    pub fn pause_recv(&mut self, backpressure: DatagramBackpressure) {
+        self.conn.datagrams.recv_paused = Some(backpressure);
+    }
+
+    /// Resume buffering received datagrams after [`pause_recv`](Self::pause_recv)
+    pub fn resume_recv(&mut self) {
+        self.conn.datagrams.recv_paused = None;
+    }
+
+    /// Whether received datagrams are currently discarded due to [`pause_recv`](Self::pause_recv)
+    pub fn is_recv_paused(&self) -> bool {
+        self.conn.datagrams.recv_paused.is_some()
+    }
+
     /// Bytes available in the outgoing datagram buffer
     ///
     /// When greater than zero, [`send`](Self::send)ing a datagram of at most this size is
@@ -108,6 +136,8 @@ pub(super) struct DatagramState {
     pub(super) outgoing: VecDeque<Datagram>,
     pub(super) outgoing_total: usize,
     pub(super) send_blocked: bool,
+    /// Set by [`Datagrams::pause_recv`]
+    pub(super) recv_paused: Option<DatagramBackpressure>,
 }
 
 impl DatagramState {
@@ -115,6 +145,7 @@ impl DatagramState {
         &mut self,
         datagram: Datagram,
         window: &Option<usize>,
+        drops: &mut DatagramDropStats,
     ) -> Result<bool, TransportError> {
         let window = match window {
             None => {
@@ -129,10 +160,19 @@ impl DatagramState {
             return Err(TransportError::PROTOCOL_VIOLATION("oversized datagram"));
         }
 
+        if self.recv_paused.is_some() {
+            trace!(len = datagram.data.len(), "dropping datagram while paused");
+            drops.paused += 1;
+            drops.bytes += datagram.data.len() as u64;
+            return Ok(false);
+        }
+
         let was_empty = self.recv_buffered == 0;
         while datagram.data.len() + self.recv_buffered > window {
             debug!("dropping stale datagram");
-            self.recv();
+            let stale = self.recv().expect("datagrams.recv_buffered desynchronized");
+            drops.buffer_full += 1;
+            drops.bytes += stale.len() as u64;
         }
 
         self.recv_buffered += datagram.data.len();
@@ -194,6 +234,24 @@ impl DatagramState {
     }
 }
 
+/// How the peer is informed that received datagrams are being discarded
+///
+/// See [`Datagrams::pause_recv`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DatagramBackpressure {
+    /// Silently discard datagrams
+    Drop,
+    /// Discard datagrams, and report the packets carrying them as congestion-marked
+    ///
+    /// Packets are reported with the ECN Congestion Experienced codepoint, so the peer's
+    /// congestion controller reduces its sending rate as if a router on the path were congested.
+    /// Only effective on paths where the peer uses ECN; otherwise behaves like `Drop`.
+    ///
+    /// This misreports the ECN marking of the path to the peer: the CE marks it counts weren't set
+    /// by the network, and the packets may well have arrived unmarked or marked ECT.
+    SignalCongestion,
+}
+
 /// Errors that can arise when sending a datagram
 #[derive(Debug, Error, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum SendDatagramError {
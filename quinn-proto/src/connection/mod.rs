@@ -15,24 +15,24 @@ use thiserror::Error;
 use tracing::{debug, error, trace, trace_span, warn};
 
 use crate::{
-    Dir, Duration, EndpointConfig, Frame, INITIAL_MTU, Instant, MAX_CID_SIZE, MAX_STREAM_COUNT,
-    MIN_INITIAL_SIZE, Side, StreamId, TIMER_GRANULARITY, TokenStore, Transmit, TransportError,
-    TransportErrorCode, VarInt,
+    Dir, DropReason, Duration, EndpointConfig, Frame, INITIAL_MTU, Instant, MAX_CID_SIZE,
+    MAX_STREAM_COUNT, Side, StreamId, TokenStore, Transmit, TransportError, TransportErrorCode,
+    VarInt,
     cid_generator::ConnectionIdGenerator,
     cid_queue::CidQueue,
     coding::BufMutExt,
-    config::{ServerConfig, TransportConfig},
+    config::{PacketDirection, PacketInfo, ServerConfig, TransportConfig},
     connection::spaces::LostPacket,
     crypto::{self, KeyPair, Keys, PacketKey},
     frame::{self, Close, Datagram, FrameStruct, NewConnectionId, NewToken},
     packet::{
-        FixedLengthConnectionIdParser, Header, InitialHeader, InitialPacket, LongType, Packet,
-        PacketNumber, PartialDecode, SpaceId,
+        FixedLengthConnectionIdParser, Header, InitialHeader, InitialPacket, LONG_HEADER_FORM,
+        LongType, Packet, PacketNumber, PartialDecode, SpaceId,
     },
     range_set::ArrayRangeSet,
     shared::{
         ConnectionEvent, ConnectionEventInner, ConnectionId, DatagramConnectionEvent, EcnCodepoint,
-        EndpointEvent, EndpointEventInner,
+        EndpointEvent, EndpointEventInner, PacketTooBigEvent,
     },
     token::{ResetToken, Token, TokenPayload},
     transport_parameters::TransportParameters,
@@ -49,7 +49,7 @@ use cid_state::CidState;
 
 mod datagrams;
 use datagrams::DatagramState;
-pub use datagrams::{Datagrams, SendDatagramError};
+pub use datagrams::{DatagramBackpressure, Datagrams, SendDatagramError};
 
 mod mtud;
 mod pacing;
@@ -64,6 +64,9 @@ mod paths;
 pub use paths::RttEstimator;
 use paths::{PathData, PathResponses};
 
+mod pings;
+use pings::PingTracker;
+
 pub(crate) mod qlog;
 
 mod send_buffer;
@@ -76,7 +79,11 @@ use spaces::Retransmits;
 use spaces::{PacketNumberFilter, PacketSpace, SendableFrames, SentPacket, ThinRetransmits};
 
 mod stats;
-pub use stats::{ConnectionStats, FlowControlStats, FrameStats, PathStats, UdpStats};
+pub use stats::{
+    ConnectionDebug, ConnectionIdStats, ConnectionStats, DatagramDropStats, DroppedPacketStats,
+    FlowControlStats, FrameStats, MtuSearchState, PathStats, RecoveryStats, SpaceRecoveryStats,
+    UdpStats,
+};
 
 mod streams;
 #[cfg(fuzzing)]
@@ -84,8 +91,8 @@ pub use streams::StreamsState;
 #[cfg(not(fuzzing))]
 use streams::StreamsState;
 pub use streams::{
-    Chunks, ClosedStream, FinishError, ReadError, ReadableError, RecvStream, SendStream,
-    ShouldTransmit, StreamEvent, Streams, WriteError, Written,
+    Chunks, ClosedStream, FinishError, PartialReliability, ReadError, ReadableError, RecvStream,
+    SendStream, ShouldTransmit, StreamEvent, Streams, WriteError, WriteStallReason, Written,
 };
 
 mod timer;
@@ -148,8 +155,12 @@ pub struct Connection {
     ///
     /// Stored separately from `path.generation` to account for aborted migrations
     path_counter: u64,
+    /// When we last migrated to follow the peer to a new address
+    last_peer_migration: Option<Instant>,
     /// Whether MTU detection is supported in this environment
     allow_mtud: bool,
+    /// Whether we're a server that accepted the peer's address as validated by a token
+    address_prevalidated: bool,
     prev_path: Option<(ConnectionId, PathData)>,
     state: State,
     side: ConnectionSide,
@@ -162,6 +173,9 @@ pub struct Connection {
     key_phase_size: u64,
     /// Transport parameters set by the peer
     peer_params: TransportParameters,
+    /// Whether `peer_params` came from the peer in this handshake, rather than being defaults or
+    /// remembered from a previous session
+    peer_params_received: bool,
     /// Source ConnectionId of the first packet received from the peer
     orig_rem_cid: ConnectionId,
     /// Destination ConnectionId sent by the client on the first Initial
@@ -171,10 +185,22 @@ pub struct Connection {
     retry_src_cid: Option<ConnectionId>,
     events: VecDeque<Event>,
     endpoint_events: VecDeque<EndpointEventInner>,
-    /// Whether the spin bit is in use for this connection
+    /// Whether the spin bit is in use on the current path
+    ///
+    /// Randomly disabled on 1 in 8 paths, so that observers can't tell which endpoints never use
+    /// it (RFC 9000 §17.4.1).
     spin_enabled: bool,
     /// Outgoing spin bit state
     spin: bool,
+    /// Whether the packet currently being processed contained an ack-eliciting frame
+    ///
+    /// Only used to report received packets to [`TransportConfig::on_packet`].
+    rx_ack_eliciting: bool,
+    /// ECN codepoint of the packet currently being processed
+    ///
+    /// Taken when the packet is reported as congestion-marked instead, see
+    /// [`DatagramBackpressure::SignalCongestion`].
+    rx_ecn: Option<EcnCodepoint>,
     /// Packet number spaces: initial, handshake, 1-RTT
     spaces: [PacketSpace; 3],
     /// Highest usable packet number space
@@ -211,6 +237,9 @@ pub struct Connection {
     //
     ack_frequency: AckFrequencyState,
 
+    /// PINGs requested by the application
+    pings: PingTracker,
+
     //
     // Loss Detection
     //
@@ -227,6 +256,10 @@ pub struct Connection {
     /// Whether the last `poll_transmit` call yielded no data because there was
     /// no outgoing application data.
     app_limited: bool,
+    /// Whether multiple datagrams may be batched into a single transmit
+    ///
+    /// See [`Connection::set_segmentation_enabled`].
+    segmentation_enabled: bool,
 
     streams: StreamsState,
     /// Surplus remote CIDs for future use on new paths
@@ -237,6 +270,8 @@ pub struct Connection {
     datagrams: DatagramState,
     /// Connection level statistics
     stats: ConnectionStats,
+    /// When the connection was created, from which the handshake duration is measured
+    created: Instant,
     /// QUIC version used for the connection.
     version: u32,
 }
@@ -272,6 +307,14 @@ impl Connection {
             client_hello: None,
         });
         let mut rng = StdRng::from_seed(rng_seed);
+        // Zero-length CIDs can't be rotated
+        let rotation_interval = config
+            .connection_id_rotation_interval
+            .filter(|_| cid_gen.cid_len() > 0);
+        let cid_lifetime = match (cid_gen.cid_lifetime(), rotation_interval) {
+            (Some(x), Some(y)) => Some(x.min(y)),
+            (x, y) => x.or(y),
+        };
         let mut this = Self {
             endpoint_config,
             crypto,
@@ -279,13 +322,15 @@ impl Connection {
             rem_handshake_cid: rem_cid,
             local_cid_state: CidState::new(
                 cid_gen.cid_len(),
-                cid_gen.cid_lifetime(),
+                cid_lifetime,
                 now,
                 if pref_addr_cid.is_some() { 2 } else { 1 },
             ),
             path: PathData::new(remote, allow_mtud, None, 0, now, &config),
             path_counter: 0,
+            last_peer_migration: None,
             allow_mtud,
+            address_prevalidated: side.is_server() && path_validated,
             local_ip,
             prev_path: None,
             state,
@@ -301,6 +346,7 @@ impl Connection {
             // at the 100th short-header packet.
             key_phase_size: rng.random_range(10..1000),
             peer_params: TransportParameters::default(),
+            peer_params_received: false,
             orig_rem_cid: rem_cid,
             initial_dst_cid: init_cid,
             retry_src_cid: None,
@@ -308,6 +354,8 @@ impl Connection {
             endpoint_events: VecDeque::new(),
             spin_enabled: config.allow_spin && rng.random_ratio(7, 8),
             spin: false,
+            rx_ack_eliciting: false,
+            rx_ecn: None,
             spaces: [initial_space, PacketSpace::new(now), PacketSpace::new(now)],
             highest_space: SpaceId::Initial,
             prev_crypto: None,
@@ -332,13 +380,16 @@ impl Connection {
             path_responses: PathResponses::default(),
             close: false,
 
-            ack_frequency: AckFrequencyState::new(get_max_ack_delay(
-                &TransportParameters::default(),
-            )),
+            ack_frequency: AckFrequencyState::new(
+                Duration::from_millis(config.max_ack_delay.0),
+                get_max_ack_delay(&TransportParameters::default()),
+            ),
+            pings: PingTracker::default(),
 
             pto_count: 0,
 
             app_limited: false,
+            segmentation_enabled: config.enable_segmentation_offload,
             receiving_ecn: false,
             total_authed_packets: 0,
 
@@ -355,8 +406,26 @@ impl Connection {
             rem_cids: CidQueue::new(rem_cid),
             rng,
             stats: ConnectionStats::default(),
+            created: now,
             version,
         };
+        this.streams
+            .set_max_unhandled(this.config.max_unhandled_streams);
+        this.streams
+            .set_max_out_of_order(this.config.max_stream_out_of_order_bytes);
+        this.streams
+            .set_max_receive_window(this.config.max_receive_window);
+        this.streams
+            .set_stream_receive_windows(this.config.stream_receive_windows());
+        this.streams
+            .set_weighted_priorities(this.config.weighted_priorities);
+        this.streams
+            .set_starvation_threshold(this.config.stream_starvation_threshold);
+        if let Some(max) = this.config.adaptive_ack_eliciting_threshold {
+            this.spaces[SpaceId::Data]
+                .pending_acks
+                .set_adaptive_threshold_max(max.into_inner());
+        }
         if path_validated {
             this.on_path_validated();
         }
@@ -377,6 +446,10 @@ impl Connection {
     /// - a call was made to `handle_timeout`
     #[must_use]
     pub fn poll_timeout(&mut self) -> Option<Instant> {
+        match self.streams.next_deadline() {
+            Some(deadline) => self.timers.set(Timer::StreamDeadline, deadline),
+            None => self.timers.stop(Timer::StreamDeadline),
+        }
         self.timers.next_timeout()
     }
 
@@ -457,7 +530,7 @@ impl Connection {
         buf: &mut Vec<u8>,
     ) -> Option<Transmit> {
         assert!(max_datagrams != 0);
-        let max_datagrams = match self.config.enable_segmentation_offload {
+        let max_datagrams = match self.segmentation_enabled {
             false => 1,
             true => max_datagrams,
         };
@@ -580,14 +653,13 @@ impl Connection {
 
                 // Anti-amplification is only based on `total_sent`, which gets
                 // updated at the end of this method. Therefore we pass the amount
-                // of bytes for datagrams that are already created, as well as 1 byte
-                // for starting another datagram. If there is any anti-amplification
-                // budget left, we always allow a full MTU to be sent
-                // (see https://github.com/quinn-rs/quinn/issues/1082)
-                if self
-                    .path
-                    .anti_amplification_blocked(segment_size as u64 * (num_datagrams as u64) + 1)
-                {
+                // of bytes for datagrams that are already created, as well as the
+                // smallest packet worth starting another datagram for. The datagram
+                // itself is shrunk to fit the remaining budget below.
+                let min_datagram_size = (MIN_PACKET_SPACE + tag_len) as u64;
+                if self.path.anti_amplification_blocked(
+                    segment_size as u64 * (num_datagrams as u64) + min_datagram_size,
+                ) {
                     trace!("blocked by anti-amplification");
                     break;
                 }
@@ -604,7 +676,7 @@ impl Connection {
                     debug_assert!(untracked_bytes <= segment_size as u64);
 
                     let bytes_to_send = segment_size as u64 + untracked_bytes;
-                    if self.path.in_flight.bytes + bytes_to_send >= self.path.congestion.window() {
+                    if self.path.in_flight.bytes + bytes_to_send >= self.path.congestion_window() {
                         space_idx += 1;
                         congestion_blocked = true;
                         // We continue instead of breaking here in order to avoid
@@ -619,7 +691,7 @@ impl Connection {
                         smoothed_rtt,
                         bytes_to_send,
                         self.path.current_mtu(),
-                        self.path.congestion.pacing_window(),
+                        self.path.pacing_window(),
                         now,
                     ) {
                         self.timers.set(Timer::Pacing, delay);
@@ -634,7 +706,7 @@ impl Connection {
                 // Finish current packet
                 if let Some(mut builder) = builder_storage.take() {
                     if pad_datagram {
-                        builder.pad_to(MIN_INITIAL_SIZE);
+                        builder.pad_to(self.initial_padding());
                     }
 
                     if num_datagrams > 1 || pad_datagram_to_mtu {
@@ -702,7 +774,7 @@ impl Connection {
                 }
 
                 // Allocate space for another datagram
-                let next_datagram_size_limit = match self.spaces[space_id].loss_probes {
+                let mut next_datagram_size_limit = match self.spaces[space_id].loss_probes {
                     0 => segment_size,
                     _ => {
                         self.spaces[space_id].loss_probes -= 1;
@@ -712,6 +784,13 @@ impl Connection {
                         std::cmp::min(segment_size, usize::from(INITIAL_MTU))
                     }
                 };
+                // Never exceed the anti-amplification limit, even if that means sending a
+                // datagram smaller than the MTU
+                if let Some(credit) = self.path.anti_amplification_credit() {
+                    let credit = credit.saturating_sub(buf_capacity as u64);
+                    next_datagram_size_limit =
+                        cmp::min(next_datagram_size_limit as u64, credit) as usize;
+                }
                 buf_capacity += next_datagram_size_limit;
                 if buf.capacity() < buf_capacity {
                     // We reserve the maximum space for sending `max_datagrams` upfront
@@ -857,7 +936,8 @@ impl Connection {
                     buf.write(frame::FrameType::PATH_RESPONSE);
                     buf.write(token);
                     self.stats.frame_tx.path_response += 1;
-                    builder.pad_to(MIN_INITIAL_SIZE);
+                    let padding = self.path_validation_padding();
+                    builder.pad_to(padding);
                     builder.finish_and_track(
                         now,
                         self,
@@ -912,7 +992,7 @@ impl Connection {
         // Finish the last packet
         if let Some(mut builder) = builder_storage {
             if pad_datagram {
-                builder.pad_to(MIN_INITIAL_SIZE);
+                builder.pad_to(self.initial_padding());
             }
 
             // If this datagram is a loss probe and `segment_size` is larger than `INITIAL_MTU`,
@@ -991,6 +1071,7 @@ impl Connection {
 
         trace!("sending {} bytes in {} datagrams", buf.len(), num_datagrams);
         self.path.total_sent = self.path.total_sent.saturating_add(buf.len() as u64);
+        self.reset_nat_keepalive(now);
 
         self.stats.udp_tx.on_sent(num_datagrams as u64, buf.len());
 
@@ -1021,12 +1102,14 @@ impl Connection {
             .challenge
             .expect("previous path challenge pending without token");
         let destination = prev_path.remote;
+        let prev_cid = *prev_cid;
         debug_assert_eq!(
             self.highest_space,
             SpaceId::Data,
             "PATH_CHALLENGE queued without 1-RTT keys"
         );
-        buf.reserve(MIN_INITIAL_SIZE as usize);
+        let padding = self.path_validation_padding();
+        buf.reserve(padding as usize);
 
         let buf_capacity = buf.capacity();
 
@@ -1038,7 +1121,7 @@ impl Connection {
         let mut builder = PacketBuilder::new(
             now,
             SpaceId::Data,
-            *prev_cid,
+            prev_cid,
             buf,
             buf_capacity,
             0,
@@ -1054,7 +1137,7 @@ impl Connection {
         // to at least the smallest allowed maximum datagram size of 1200 bytes,
         // unless the anti-amplification limit for the path does not permit
         // sending a datagram of this size
-        builder.pad_to(MIN_INITIAL_SIZE);
+        builder.pad_to(padding);
 
         builder.finish(self, now, buf);
         self.stats.udp_tx.on_sent(1, buf.len());
@@ -1103,8 +1186,10 @@ impl Connection {
                 // If this packet could initiate a migration and we're a client or a server that
                 // forbids migration, drop the datagram. This could be relaxed to heuristically
                 // permit NAT-rebinding-like migration.
-                if remote != self.path.remote && !self.side.remote_may_migrate() {
+                if remote != self.path.remote && !self.remote_may_migrate() {
                     trace!("discarding packet from unrecognized peer {}", remote);
+                    self.endpoint_config
+                        .packet_dropped(DropReason::MigrationRejected, remote);
                     return;
                 }
 
@@ -1113,6 +1198,7 @@ impl Connection {
                 self.stats.udp_rx.datagrams += 1;
                 self.stats.udp_rx.bytes += first_decode.len() as u64;
                 let data_len = first_decode.len();
+                let dst_cid = first_decode.dst_cid();
 
                 self.handle_decode(now, remote, ecn, first_decode);
                 // The current `path` might have changed inside `handle_decode`,
@@ -1123,7 +1209,7 @@ impl Connection {
 
                 if let Some(data) = remaining {
                     self.stats.udp_rx.bytes += data.len() as u64;
-                    self.handle_coalesced(now, remote, ecn, data);
+                    self.handle_coalesced(now, remote, ecn, dst_cid, data);
                 }
 
                 self.config.qlog_sink.emit_recovery_metrics(
@@ -1154,9 +1240,51 @@ impl Connection {
                     self.reset_cid_retirement();
                 }
             }
+            PacketTooBig(PacketTooBigEvent {
+                now,
+                remote,
+                mtu,
+                quoted,
+            }) => {
+                // ICMP messages can be forged by anyone, so only trust those quoting a packet we
+                // could have sent on the current path
+                if remote != self.path.remote || !self.quotes_own_packet(&quoted) {
+                    trace!("ignoring packet too big message for unrecognized packet");
+                    return;
+                }
+                if !self.path.mtud.on_packet_too_big(now, mtu) {
+                    return;
+                }
+                debug!(mtu, "path MTU lowered by packet too big message");
+                self.stats.path.icmp_mtu_reductions += 1;
+                self.path
+                    .congestion
+                    .on_mtu_update(self.path.mtud.current_mtu());
+                if let Some(max_datagram_size) = self.datagrams().max_size() {
+                    if self.datagrams.drop_oversized(max_datagram_size)
+                        && self.datagrams.send_blocked
+                    {
+                        self.datagrams.send_blocked = false;
+                        self.events.push_back(Event::DatagramsUnblocked);
+                    }
+                }
+            }
         }
     }
 
+    /// Whether `quoted` begins with the header of a packet sent to the peer's active CID
+    fn quotes_own_packet(&self, quoted: &[u8]) -> bool {
+        let cid = self.rem_cids.active();
+        let dst_cid = match quoted.first() {
+            Some(first) if first & LONG_HEADER_FORM != 0 => quoted
+                .get(5)
+                .and_then(|&len| quoted.get(6..6 + len as usize)),
+            Some(_) => quoted.get(1..1 + cid.len()),
+            None => None,
+        };
+        dst_cid == Some(&cid[..])
+    }
+
     /// Process timer expirations
     ///
     /// Executes protocol logic, potentially preparing signals (including application `Event`s,
@@ -1183,7 +1311,11 @@ impl Connection {
                     self.kill(ConnectionError::TimedOut);
                 }
                 Timer::KeepAlive => {
-                    self.ping();
+                    self.queue_ping();
+                }
+                Timer::NatKeepAlive => {
+                    trace!("sending NAT keep-alive");
+                    self.queue_ping();
                 }
                 Timer::LossDetection => {
                     self.on_loss_detection_timeout(now);
@@ -1209,6 +1341,10 @@ impl Connection {
                 }
                 Timer::Pacing => trace!("pacing timer expired"),
                 Timer::PushNewCid => {
+                    if self.local_cid_state.retirement_pending() {
+                        // The peer holds as many CIDs as it may until it retires earlier ones
+                        debug!("peer hasn't retired previous CIDs; deferring CID rotation");
+                    }
                     // Update `retire_prior_to` field in NEW_CONNECTION_ID frame
                     let num_new_cid = self.local_cid_state.on_cid_timeout().into();
                     if !self.state.is_closed() {
@@ -1218,6 +1354,12 @@ impl Connection {
                         );
                         self.endpoint_events
                             .push_back(EndpointEventInner::NeedIdentifiers(now, num_new_cid));
+                        // Stop using the peer's CID in step with our own
+                        if self.config.connection_id_rotation_interval.is_some()
+                            && self.state.is_established()
+                        {
+                            self.update_rem_cid();
+                        }
                     }
                 }
                 Timer::MaxAckDelay => {
@@ -1227,6 +1369,10 @@ impl Connection {
                         .pending_acks
                         .on_max_ack_delay_timeout()
                 }
+                Timer::StreamDeadline => {
+                    self.streams
+                        .expire_deadlines(now, &mut self.spaces[SpaceId::Data].pending);
+                }
             }
         }
     }
@@ -1270,9 +1416,21 @@ impl Connection {
 
         let mut stats = self.stats;
         stats.path.rtt = self.path.rtt.get();
-        stats.path.cwnd = self.path.congestion.window();
+        stats.path.min_rtt = self.path.rtt.min();
+        stats.path.rtt_variance = self.path.rtt.variance();
+        stats.path.latest_rtt = self.path.rtt.latest();
+        stats.path.cwnd = self.path.congestion_window();
         stats.path.bytes_in_flight = self.path.in_flight.bytes;
+        stats.path.bytes_pending_retransmit = self.streams.retransmit_pending();
         stats.path.current_mtu = self.path.mtud.current_mtu();
+        stats.path.mtu_search_state = self.path.mtud.search_state();
+        stats.path.anti_amplification_credit = self.path.anti_amplification_credit();
+        stats.path.hystart_exits = self.path.congestion.metrics().hystart_exits.unwrap_or(0);
+        stats.path.reordering_window = self.path.reordering.window(&self.path.rtt);
+        stats.ack_eliciting_threshold = self.spaces[SpaceId::Data]
+            .pending_acks
+            .ack_eliciting_threshold();
+        stats.starved_streams = self.streams.starved_streams();
         let (data_sent, max_data, unacked_data, send_window) =
             self.streams.flow_control_snapshot();
         stats.flow_control = FlowControlStats {
@@ -1280,15 +1438,123 @@ impl Connection {
             max_data,
             unacked_data,
             send_window,
+            receive_window: self.streams.receive_window(),
         };
 
         stats
     }
 
+    /// Returns a snapshot of the connection's internal state for debugging
+    ///
+    /// Aggregates [`stats()`](Self::stats), the congestion controller's metrics, stream counts,
+    /// flow control limits and the timers pending at `now`. See [`ConnectionDebug`] for caveats.
+    pub fn debug_snapshot(&mut self, now: Instant) -> ConnectionDebug {
+        let state = match self.state {
+            State::Handshake(_) => "handshake",
+            State::Established => "established",
+            State::Closed(_) => "closed",
+            State::Draining => "draining",
+            State::Drained => "drained",
+        };
+        let (data_received, local_max_data) = self.streams.receive_flow_control_snapshot();
+        let streams = self.streams();
+        let remote_open_streams = [
+            streams.remote_open_streams(Dir::Bi),
+            streams.remote_open_streams(Dir::Uni),
+        ];
+        let send_streams = streams.send_streams();
+        ConnectionDebug {
+            side: self.side(),
+            state,
+            remote_address: self.path.remote,
+            local_ip: self.local_ip,
+            stats: self.stats(),
+            congestion: self.path.congestion.metrics(),
+            pto_count: self.pto_count,
+            idle_timeout: self.idle_timeout,
+            send_streams,
+            remote_open_streams,
+            data_received,
+            local_max_data,
+            pending_timers: Timer::VALUES
+                .iter()
+                .filter_map(|&timer| {
+                    let time = self.timers.get(timer)?;
+                    Some((timer.name(), time.saturating_duration_since(now)))
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns connection statistics, then restarts cumulative counters from zero
+    ///
+    /// Resets the counts of UDP datagrams and frames sent and received, the packet and byte
+    /// counters in [`PathStats`] (such as `sent_packets`, `lost_packets` and `congestion_events`),
+    /// the counts of dropped and coalesced packets, and `starved_streams`. Values describing the
+    /// connection's current state, such as the RTT estimates, the congestion window, the MTU and
+    /// [`FlowControlStats`], are preserved, as are the facts recorded once during the handshake:
+    /// `time_to_first_flight`, `handshake_duration`, `used_0rtt` and `zero_rtt_accepted`.
+    /// [`PathStats::hystart_exits`] is reported by the congestion controller, and isn't reset
+    /// either.
+    ///
+    /// The statistics are returned as they were immediately before the reset, so that periodic
+    /// reporting can read the counters for each period without losing any updates in between.
+    pub fn reset_stats(&mut self) -> ConnectionStats {
+        let stats = self.stats();
+        let ConnectionStats {
+            time_to_first_flight,
+            handshake_duration,
+            used_0rtt,
+            zero_rtt_accepted,
+            ..
+        } = self.stats;
+        self.stats = ConnectionStats {
+            time_to_first_flight,
+            handshake_duration,
+            used_0rtt,
+            zero_rtt_accepted,
+            ..ConnectionStats::default()
+        };
+        self.streams.reset_starved_streams();
+        stats
+    }
+
     /// Ping the remote endpoint
     ///
-    /// Causes an ACK-eliciting packet to be transmitted.
+    /// Causes an ACK-eliciting packet to be transmitted. The call is answered once the peer
+    /// acknowledges a PING sent after it, as reported by [`pings_acknowledged()`]. Any number of
+    /// pings may be outstanding at once.
+    ///
+    /// [`pings_acknowledged()`]: Self::pings_acknowledged
     pub fn ping(&mut self) {
+        self.pings.on_requested();
+        self.queue_ping();
+    }
+
+    /// Number of calls to [`ping()`](Self::ping) answered by the peer so far
+    ///
+    /// A call is answered by the acknowledgement of any PING sent after it was made, so a single
+    /// acknowledgement may answer several calls.
+    pub fn pings_acknowledged(&self) -> u64 {
+        self.pings.acknowledged()
+    }
+
+    /// Ask the peer to acknowledge the next packet without delay
+    ///
+    /// Useful after sending a small response, so that the peer's ACK promptly frees up the
+    /// congestion window rather than being held back by its acknowledgement delay. The request is
+    /// carried in an IMMEDIATE_ACK frame, which is only available if the peer supports the
+    /// acknowledgement frequency extension, as all Quinn endpoints do. Otherwise, this falls back
+    /// to sending a PING, which the peer must still acknowledge, but possibly only after its
+    /// `max_ack_delay`.
+    pub fn request_immediate_ack(&mut self) {
+        match self.highest_space == SpaceId::Data && self.peer_supports_ack_frequency() {
+            true => self.immediate_ack(),
+            false => self.queue_ping(),
+        }
+    }
+
+    fn queue_ping(&mut self) {
         self.spaces[self.highest_space].ping_pending = true;
     }
 
@@ -1353,6 +1619,26 @@ impl Connection {
         self.zero_rtt_enabled
     }
 
+    /// Transport parameters advertised by the peer during the handshake
+    ///
+    /// These are the peer's own values, which limit what *we* may do: `initial_max_data` and the
+    /// `initial_max_stream_data_*` parameters bound how much we can initially send, the
+    /// `initial_max_streams_*` parameters how many streams we can initially open, and
+    /// `max_udp_payload_size` how large our datagrams may be. Stream-related names are from the
+    /// peer's perspective, so `initial_max_stream_data_bidi_local` applies to bidirectional
+    /// streams the peer opens. Flow control limits only describe the initial state and are
+    /// raised by later `MAX_DATA`/`MAX_STREAM_DATA`/`MAX_STREAMS` frames.
+    ///
+    /// Where both sides contribute to an effective value, it is not reflected here: the idle
+    /// timeout in use is the minimum of both sides' non-zero `max_idle_timeout`, and the MTU is
+    /// bounded by our own configuration as well as the peer's `max_udp_payload_size`.
+    ///
+    /// `None` until the peer's parameters have been received, including while a client is
+    /// sending 0-RTT data based on parameters remembered from a previous connection.
+    pub fn peer_transport_parameters(&self) -> Option<TransportParameters> {
+        self.peer_params_received.then_some(self.peer_params)
+    }
+
     /// Whether there are any pending retransmits
     pub fn has_pending_retransmits(&self) -> bool {
         !self.spaces[SpaceId::Data].pending.is_empty(&self.streams)
@@ -1363,6 +1649,30 @@ impl Connection {
         self.side.side()
     }
 
+    /// The QUIC version in use, which may differ from the configured one after version
+    /// negotiation
+    ///
+    /// `0x0000_0001` is QUIC v1 as specified in RFC 9000, [`QUIC_V2`](crate::QUIC_V2) is QUIC v2
+    /// as specified in RFC 9369, and `0xff00_00XX` is IETF draft `XX`, e.g. `0xff00_001d` for
+    /// draft-29. The versions supported by default are listed in
+    /// [`DEFAULT_SUPPORTED_VERSIONS`](crate::DEFAULT_SUPPORTED_VERSIONS). For clients, this is
+    /// only final once the handshake completes, since a version negotiation packet may still
+    /// switch it.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Whether the peer's address was validated by a token before the handshake began
+    ///
+    /// A server considers a client's address validated without a round trip when the client
+    /// presents a valid retry token, or an address validation token from a previous connection
+    /// (see [`ValidationTokenConfig`](crate::ValidationTokenConfig)). The anti-amplification limit
+    /// then doesn't apply to the connection, so the server may send its whole first flight and any
+    /// 0.5-RTT data immediately. Always `false` for clients.
+    pub fn address_prevalidated(&self) -> bool {
+        self.address_prevalidated
+    }
+
     /// The latest socket address for this connection's peer
     pub fn remote_address(&self) -> SocketAddr {
         self.path.remote
@@ -1386,6 +1696,25 @@ impl Connection {
         self.path.rtt.get()
     }
 
+    /// Bytes of packets containing retransmittable frames that have been sent but not yet
+    /// acknowledged or declared lost
+    ///
+    /// An instantaneous gauge that rises with each packet sent and falls as packets are
+    /// acknowledged or lost. New packets other than ACKs may only be sent while this is below the
+    /// congestion window. Also reported as [`PathStats::bytes_in_flight`].
+    pub fn bytes_in_flight(&self) -> u64 {
+        self.path.in_flight.bytes
+    }
+
+    /// Bytes of stream data deemed lost and waiting to be retransmitted
+    ///
+    /// An instantaneous gauge that rises when packets carrying stream data are declared lost and
+    /// falls as the data is sent again. Data of streams that were reset no longer counts. Also
+    /// reported as [`PathStats::bytes_pending_retransmit`].
+    pub fn bytes_pending_retransmit(&self) -> u64 {
+        self.streams.retransmit_pending()
+    }
+
     /// Current state of this connection's congestion controller, for debugging purposes
     pub fn congestion_state(&self) -> &dyn Controller {
         self.path.congestion.as_ref()
@@ -1409,6 +1738,11 @@ impl Connection {
     ///
     /// No streams may be opened by the peer unless fewer than `count` are already open. Large
     /// `count`s increase both minimum and worst-case memory consumption.
+    ///
+    /// Raising the limit promptly sends the peer a `MAX_STREAMS` frame granting the additional
+    /// streams. Stream credit that has already been granted can never be retracted, so lowering
+    /// the limit only restricts streams opened in the future: no new credit is issued as remotely
+    /// initiated streams close until fewer than `count` are open.
     pub fn set_max_concurrent_streams(&mut self, dir: Dir, count: VarInt) {
         self.streams.set_max_concurrent(dir, count);
         // If the limit was reduced, then a flow control update previously deemed insignificant may
@@ -1426,6 +1760,50 @@ impl Connection {
         self.streams.max_concurrent(dir)
     }
 
+    /// Abandon every currently open stream
+    ///
+    /// Equivalent to calling [`SendStream::reset`] on every open send stream and
+    /// [`RecvStream::stop`] on every open receive stream with `error_code`, queueing `RESET_STREAM`
+    /// and `STOP_SENDING` frames respectively. Streams which were already reset or stopped are
+    /// left alone, as are streams opened after this call.
+    ///
+    /// The frames are only delivered if the connection stays open long enough to transmit them.
+    /// To inform the peer of `error_code` on each stream before shutting down, call this first and
+    /// give the connection a chance to transmit before calling [`close`](Self::close); streams are
+    /// abandoned implicitly by `close`, so calling this afterwards has no effect.
+    pub fn reset_all_streams(&mut self, error_code: VarInt) {
+        let send = self.streams.open_send_streams().collect::<Vec<_>>();
+        let recv = self.streams.open_recv_streams().collect::<Vec<_>>();
+        for id in send {
+            // Fails only for streams that were already reset
+            let _ = self.send_stream(id).reset(error_code);
+        }
+        for id in recv {
+            // Fails only for streams that were already stopped
+            let _ = self.recv_stream(id).stop(error_code);
+        }
+    }
+
+    /// Finish every currently open send stream
+    ///
+    /// Equivalent to calling [`SendStream::finish`] on every open send stream. Streams stopped by
+    /// the peer can't be finished, so they are reset with the error code the peer supplied instead.
+    /// Streams which were already finished or reset are left alone, as are streams opened after
+    /// this call.
+    ///
+    /// Once the peer has acknowledged all outstanding stream data, [`Streams::send_streams`]
+    /// drops to zero; this can be used to delay [`close`](Self::close) until then.
+    pub fn finish_all_streams(&mut self) {
+        let send = self.streams.open_send_streams().collect::<Vec<_>>();
+        for id in send {
+            let mut stream = self.send_stream(id);
+            if let Err(FinishError::Stopped(error_code)) = stream.finish() {
+                // Fails only for streams that were already reset
+                let _ = stream.reset(error_code);
+            }
+        }
+    }
+
     /// See [`TransportConfig::send_window()`]
     pub fn set_send_window(&mut self, send_window: u64) {
         self.streams.set_send_window(send_window);
@@ -1438,6 +1816,15 @@ impl Connection {
         }
     }
 
+    /// Whether to batch multiple datagrams into a single transmit using segmentation offload
+    ///
+    /// Overrides [`TransportConfig::enable_segmentation_offload()`] for this connection only,
+    /// independent of whether the socket supports GSO. When disabled, every [`Transmit`] carries a
+    /// single datagram, which may help on paths that mishandle large coalesced sends.
+    pub fn set_segmentation_enabled(&mut self, enabled: bool) {
+        self.segmentation_enabled = enabled;
+    }
+
     fn on_ack_received(
         &mut self,
         now: Instant,
@@ -1507,17 +1894,12 @@ impl Connection {
                 // Notify ack frequency that a packet was acked, because it might contain an ACK_FREQUENCY frame
                 self.ack_frequency.on_acked(packet);
 
+                self.pings.on_acked(space, packet);
+
                 self.on_packet_acked(now, info);
             }
         }
 
-        self.path.congestion.on_end_acks(
-            now,
-            self.path.in_flight.bytes,
-            self.app_limited,
-            self.spaces[space].largest_acked_packet,
-        );
-
         if new_largest && ack_eliciting_acked {
             let ack_delay = if space != SpaceId::Data {
                 Duration::from_micros(0)
@@ -1529,12 +1911,20 @@ impl Connection {
             };
             let rtt = now.saturating_duration_since(self.spaces[space].largest_acked_packet_sent);
             self.path.rtt.update(ack_delay, rtt);
+            self.path.congestion.on_rtt_sample(now, &self.path.rtt);
             if self.path.first_packet_after_rtt_sample.is_none() {
                 self.path.first_packet_after_rtt_sample =
                     Some((space, self.spaces[space].next_packet_number));
             }
         }
 
+        self.path.congestion.on_end_acks(
+            now,
+            self.path.in_flight.bytes,
+            self.app_limited,
+            self.spaces[space].largest_acked_packet,
+        );
+
         // Must be called before crypto/pto_count are clobbered
         self.detect_lost_packets(now, space, true);
 
@@ -1571,6 +1961,7 @@ impl Connection {
             return false;
         }
 
+        let mut any_spurious = false;
         for range in ack.iter() {
             let spurious_losses: Vec<u64> = lost_packets
                 .range(range.clone())
@@ -1580,9 +1971,14 @@ impl Connection {
 
             for pn in spurious_losses {
                 lost_packets.remove(&pn);
+                any_spurious = true;
             }
         }
 
+        if any_spurious {
+            self.path.reordering.on_spurious_loss();
+        }
+
         // If this ACK frame acknowledged all deemed lost packets,
         // then we have raised a spurious congestion event in the past.
         // We cannot conclude when there are remaining packets,
@@ -1649,6 +2045,9 @@ impl Connection {
             for (id, _) in retransmits.reset_stream.iter() {
                 self.streams.reset_acked(*id);
             }
+            for &(id, offset) in retransmits.stream_skip.iter() {
+                self.streams.stream_skip_acked(id, offset);
+            }
         }
 
         for frame in info.stream_frames {
@@ -1699,10 +2098,19 @@ impl Connection {
                 }
                 1
             }
-            _ => 2,
+            _ => self.config.pto_probe_count.into(),
         };
-        self.spaces[space].loss_probes = self.spaces[space].loss_probes.saturating_add(count);
         self.pto_count = self.pto_count.saturating_add(1);
+        self.stats.recovery.space_mut(space).ptos += 1;
+        if self.pto_count >= self.config.max_pto_count {
+            debug!(
+                count = self.pto_count,
+                "too many consecutive PTOs, giving up"
+            );
+            self.kill(ConnectionError::TimedOut);
+            return;
+        }
+        self.spaces[space].loss_probes = self.spaces[space].loss_probes.saturating_add(count);
         self.set_loss_detection_timer(now);
     }
 
@@ -1710,12 +2118,16 @@ impl Connection {
         let mut lost_packets = Vec::<u64>::new();
         let mut lost_mtu_probe = None;
         let in_flight_mtu_probe = self.path.mtud.in_flight_mtu_probe();
-        let rtt = self.path.rtt.conservative();
-        let loss_delay = cmp::max(rtt.mul_f32(self.config.time_threshold), TIMER_GRANULARITY);
+        let loss_delay = self.path.reordering.window(&self.path.rtt);
 
         let largest_acked_packet = self.spaces[pn_space].largest_acked_packet.unwrap();
-        let packet_threshold = self.config.packet_threshold as u64;
+        let packet_threshold = if self.path.reordering.use_packet_threshold() {
+            self.config.packet_threshold as u64
+        } else {
+            u64::MAX
+        };
         let mut size_of_lost_packets = 0u64;
+        let mut lost_by_packet_threshold = 0u64;
 
         // InPersistentCongestion: Determine if all packets in the time period before the newest
         // lost packet, including the edges, are marked lost. PTO computation must always
@@ -1740,7 +2152,8 @@ impl Connection {
             // However, we avoid this subtraction as it can panic and there's no
             // saturating equivalent of this substraction operation with a Duration.
             let packet_too_old = now.saturating_duration_since(info.time_sent) >= loss_delay;
-            if packet_too_old || largest_acked_packet >= packet + packet_threshold {
+            let packet_reordered = largest_acked_packet >= packet.saturating_add(packet_threshold);
+            if packet_too_old || packet_reordered {
                 if Some(packet) == in_flight_mtu_probe {
                     // Lost MTU probes are not included in `lost_packets`, because they should not
                     // trigger a congestion control response
@@ -1748,6 +2161,9 @@ impl Connection {
                 } else {
                     lost_packets.push(packet);
                     size_of_lost_packets += info.size as u64;
+                    if packet_reordered {
+                        lost_by_packet_threshold += 1;
+                    }
                     if info.ack_eliciting && due_to_ack {
                         match persistent_congestion_start {
                             // Two ACK-eliciting packets lost more than congestion_period apart, with no
@@ -1786,7 +2202,14 @@ impl Connection {
         if let Some(largest_lost) = lost_packets.last().cloned() {
             let old_bytes_in_flight = self.path.in_flight.bytes;
             let largest_lost_sent = self.spaces[pn_space].sent_packets[&largest_lost].time_sent;
+            self.path.reordering.on_loss();
+            self.spaces[SpaceId::Data].pending_acks.on_packets_lost();
             self.stats.path.lost_packets += lost_packets.len() as u64;
+            let recovery_stats = self.stats.recovery.space_mut(pn_space);
+            recovery_stats.lost_packets += lost_packets.len() as u64;
+            recovery_stats.lost_by_packet_threshold += lost_by_packet_threshold;
+            recovery_stats.lost_by_time_threshold +=
+                lost_packets.len() as u64 - lost_by_packet_threshold;
             self.stats.path.lost_bytes += size_of_lost_packets;
             trace!(
                 "packets lost: {:?}, bytes lost: {}",
@@ -1810,6 +2233,9 @@ impl Connection {
                 }
                 self.spaces[pn_space].pending |= info.retransmits;
                 self.path.mtud.on_non_probe_lost(packet, info.size);
+                if self.pings.on_lost(pn_space, packet) {
+                    self.queue_ping();
+                }
 
                 self.spaces[pn_space].lost_packets.insert(
                     packet,
@@ -1977,6 +2403,13 @@ impl Connection {
         self.reset_idle_timeout(now, space_id);
         self.permit_idle_reset = true;
         self.receiving_ecn |= ecn.is_some();
+        let stats = &mut self.stats.path;
+        match ecn {
+            Some(EcnCodepoint::Ect0) => stats.received_ect0 += 1,
+            Some(EcnCodepoint::Ect1) => stats.received_ect1 += 1,
+            Some(EcnCodepoint::Ce) => stats.received_ce += 1,
+            None => stats.received_not_ect += 1,
+        }
         if let Some(x) = ecn {
             let space = &mut self.spaces[space_id];
             space.ecn_counters += x;
@@ -2003,8 +2436,11 @@ impl Connection {
         space.pending_acks.insert_one(packet, now);
         if packet >= space.rx_packet {
             space.rx_packet = packet;
-            // Update outgoing spin bit, inverting iff we're the client
-            self.spin = self.side.is_client() ^ spin;
+            // Update outgoing spin bit, inverting iff we're the client. Only 1-RTT packets carry a
+            // spin bit (RFC 9000 §17.4).
+            if is_1rtt {
+                self.spin = self.side.is_client() ^ spin;
+            }
         }
 
         self.config.qlog_sink.emit_packet_received(
@@ -2036,6 +2472,14 @@ impl Connection {
         self.timers.set(Timer::KeepAlive, now + interval);
     }
 
+    fn reset_nat_keepalive(&mut self, now: Instant) {
+        let interval = match self.config.nat_keepalive {
+            Some(x) if self.state.is_established() => x,
+            _ => return,
+        };
+        self.timers.set(Timer::NatKeepAlive, now + interval);
+    }
+
     fn reset_cid_retirement(&mut self) {
         if let Some(t) = self.local_cid_state.next_timeout() {
             self.timers.set(Timer::PushNewCid, t);
@@ -2051,6 +2495,7 @@ impl Connection {
         now: Instant,
         remote: SocketAddr,
         ecn: Option<EcnCodepoint>,
+        datagram_len: usize,
         packet_number: u64,
         packet: InitialPacket,
         remaining: Option<BytesMut>,
@@ -2058,8 +2503,7 @@ impl Connection {
         let span = trace_span!("first recv");
         let _guard = span.enter();
         debug_assert!(self.side.is_server());
-        let len = packet.header_data.len() + packet.payload.len();
-        self.path.total_recvd = len as u64;
+        self.path.total_recvd = datagram_len as u64;
 
         match self.state {
             State::Handshake(ref mut state) => {
@@ -2077,9 +2521,14 @@ impl Connection {
             false,
         );
 
-        self.process_decrypted_packet(now, remote, Some(packet_number), packet.into())?;
+        let size = datagram_len - remaining.as_ref().map_or(0, |data| data.len());
+        let dst_cid = packet.header.dst_cid;
+        self.rx_ack_eliciting = false;
+        let result = self.process_decrypted_packet(now, remote, Some(packet_number), packet.into());
+        self.report_received(SpaceId::Initial, packet_number, size);
+        result?;
         if let Some(data) = remaining {
-            self.handle_coalesced(now, remote, ecn, data);
+            self.handle_coalesced(now, remote, ecn, dst_cid, data);
         }
 
         self.config.qlog_sink.emit_recovery_metrics(
@@ -2215,6 +2664,71 @@ impl Connection {
         }
     }
 
+    /// Start the handshake over under `version`, after the server rejected ours
+    fn restart_with_version(
+        &mut self,
+        now: Instant,
+        version: u32,
+        restart: Box<ClientRestart>,
+    ) -> Result<(), ConnectionError> {
+        let ConnectionSide::Client {
+            ref token,
+            ref server_name,
+            ..
+        } = self.side
+        else {
+            unreachable!("only clients restart the handshake");
+        };
+        // Any address validation token remains valid for the new attempt, but `discard_space`
+        // would drop it
+        let token = token.clone();
+        self.crypto = restart
+            .crypto
+            .start_session_with_remote(version, server_name, self.path.remote, &restart.params)
+            .map_err(|e| {
+                debug!("failed to restart handshake with version {version:#x}: {e}");
+                ConnectionError::VersionMismatch
+            })?;
+        trace!("restarting handshake with version {:#x}", version);
+        self.version = version;
+
+        self.discard_space(now, SpaceId::Initial);
+        self.spaces[SpaceId::Initial] = PacketSpace {
+            crypto: Some(
+                self.crypto
+                    .initial_keys(self.rem_cids.active(), self.side.side()),
+            ),
+            next_packet_number: self.spaces[SpaceId::Initial].next_packet_number,
+            ..PacketSpace::new(now)
+        };
+        if let ConnectionSide::Client {
+            token: ref mut t, ..
+        } = self.side
+        {
+            *t = token;
+        }
+
+        // 0-RTT data was protected under the old version's keys; resend it if the new session
+        // permits 0-RTT at all
+        let zero_rtt = mem::take(&mut self.spaces[SpaceId::Data].sent_packets);
+        for info in zero_rtt.into_values() {
+            self.remove_in_flight(&info);
+            self.spaces[SpaceId::Data].pending |= info.retransmits;
+        }
+        self.streams.retransmit_all_for_0rtt();
+        self.zero_rtt_enabled = false;
+        self.zero_rtt_crypto = None;
+
+        self.state = State::Handshake(state::Handshake {
+            expected_token: Bytes::new(),
+            rem_cid_set: false,
+            client_hello: None,
+        });
+        self.write_crypto();
+        self.init_0rtt();
+        Ok(())
+    }
+
     /// Switch to stronger cryptography during handshake
     fn upgrade_crypto(&mut self, space: SpaceId, crypto: Keys) {
         debug_assert!(
@@ -2257,19 +2771,27 @@ impl Connection {
         for packet in sent_packets.into_values() {
             self.remove_in_flight(&packet);
         }
+        if self.pings.on_space_discarded(space_id) {
+            self.queue_ping();
+        }
         self.set_loss_detection_timer(now)
     }
 
+    /// Process the packets following the first one in a datagram
+    ///
+    /// `dst_cid` is the destination connection ID of the first packet.
     fn handle_coalesced(
         &mut self,
         now: Instant,
         remote: SocketAddr,
         ecn: Option<EcnCodepoint>,
+        dst_cid: ConnectionId,
         data: BytesMut,
     ) {
         self.path.total_recvd = self.path.total_recvd.saturating_add(data.len() as u64);
         let mut remaining = Some(data);
         while let Some(data) = remaining {
+            let len = data.len();
             match PartialDecode::new(
                 data,
                 &FixedLengthConnectionIdParser::new(self.local_cid_state.cid_len()),
@@ -2278,10 +2800,19 @@ impl Connection {
             ) {
                 Ok((partial_decode, rest)) => {
                     remaining = rest;
+                    // RFC9000 §12.2: packets coalesced into one datagram must share the same
+                    // destination connection ID
+                    if partial_decode.dst_cid() != dst_cid {
+                        trace!("discarding coalesced packet with mismatched destination CID");
+                        continue;
+                    }
+                    self.stats.coalesced_packets_received += 1;
                     self.handle_decode(now, remote, ecn, partial_decode);
                 }
                 Err(e) => {
-                    trace!("malformed header: {}", e);
+                    // Senders may pad datagrams after the last packet, e.g. to reach the minimum
+                    // size for Initial packets, so this is not necessarily an error
+                    trace!(len, "ignoring trailing data in datagram: {}", e);
                     return;
                 }
             }
@@ -2300,8 +2831,12 @@ impl Connection {
             &self.spaces,
             self.zero_rtt_crypto.as_ref(),
             self.peer_params.stateless_reset_token,
+            &mut self.stats.header_protection_failures,
         ) {
             self.handle_packet(now, remote, ecn, decoded.packet, decoded.stateless_reset);
+        } else {
+            self.endpoint_config
+                .packet_dropped(DropReason::DecryptionFailed, remote);
         }
     }
 
@@ -2326,12 +2861,18 @@ impl Connection {
 
         if self.is_handshaking() && remote != self.path.remote {
             debug!("discarding packet with unexpected remote during handshake");
+            self.endpoint_config
+                .packet_dropped(DropReason::MigrationRejected, remote);
             return;
         }
 
         let was_closed = self.state.is_closed();
         let was_drained = self.state.is_drained();
 
+        let space = packet.as_ref().map(|packet| packet.header.space());
+        let size = packet
+            .as_ref()
+            .map_or(0, |packet| packet.header_data.len() + packet.payload.len());
         let decrypted = match packet {
             None => Err(None),
             Some(mut packet) => self
@@ -2349,6 +2890,11 @@ impl Connection {
             }
             Err(None) => {
                 debug!("failed to authenticate packet");
+                self.endpoint_config
+                    .packet_dropped(DropReason::DecryptionFailed, remote);
+                if let Some(space) = space {
+                    self.stats.decryption_failures.record(space);
+                }
                 self.authentication_failures += 1;
                 let integrity_limit = self.spaces[self.highest_space]
                     .crypto
@@ -2406,7 +2952,14 @@ impl Connection {
                         );
                     }
 
-                    self.process_decrypted_packet(now, remote, number, packet)
+                    let space = packet.header.space();
+                    self.rx_ack_eliciting = false;
+                    self.rx_ecn = ecn;
+                    let result = self.process_decrypted_packet(now, remote, number, packet);
+                    if let Some(number) = number {
+                        self.report_received(space, number, size);
+                    }
+                    result
                 }
             }
         };
@@ -2458,6 +3011,38 @@ impl Connection {
         }
     }
 
+    /// Report the packet being processed to the peer as if it were marked Congestion Experienced
+    ///
+    /// Does nothing if the packet wasn't ECN-capable, or was already reported.
+    fn report_congestion_experienced(&mut self) {
+        let Some(ecn) = self.rx_ecn.take() else {
+            return;
+        };
+        // The packet's codepoint was already counted, but can't have been acknowledged yet
+        let space = &mut self.spaces[SpaceId::Data];
+        match ecn {
+            EcnCodepoint::Ect0 => space.ecn_counters.ect0 -= 1,
+            EcnCodepoint::Ect1 => space.ecn_counters.ect1 -= 1,
+            EcnCodepoint::Ce => return,
+        }
+        trace!("reporting congestion to apply datagram backpressure");
+        space.ecn_counters.ce += 1;
+        space.pending_acks.set_immediate_ack_required();
+    }
+
+    /// Report a processed incoming packet to the application's observer, if any
+    fn report_received(&self, space: SpaceId, number: u64, size: usize) {
+        if let Some(ref observer) = self.config.on_packet {
+            observer(PacketInfo {
+                direction: PacketDirection::Received,
+                space,
+                number,
+                size,
+                ack_eliciting: self.rx_ack_eliciting,
+            });
+        }
+    }
+
     fn process_decrypted_packet(
         &mut self,
         now: Instant,
@@ -2645,6 +3230,11 @@ impl Connection {
                     trace!("handshake confirmed");
                 }
 
+                self.stats.handshake_duration = Some(now.saturating_duration_since(self.created));
+                self.stats.zero_rtt_accepted = match self.side.is_client() {
+                    true => self.accepted_0rtt,
+                    false => self.zero_rtt_enabled,
+                };
                 self.events.push_back(Event::Connected);
                 self.state = State::Established;
                 trace!("established");
@@ -2682,6 +3272,14 @@ impl Connection {
                             "transport parameters missing".to_owned(),
                         )
                     })?;
+                    if let ConnectionSide::Server { ref server_config } = self.side {
+                        if let Some(ref validator) = server_config.transport_parameters_validator {
+                            validator(&params).map_err(|reason| {
+                                debug!("rejecting client transport parameters: {}", reason);
+                                TransportError::CONNECTION_REFUSED(reason)
+                            })?;
+                        }
+                    }
                     self.handle_peer_params(params)?;
                     self.issue_first_cids(now);
                     self.init_0rtt();
@@ -2692,6 +3290,7 @@ impl Connection {
                 ty: LongType::ZeroRtt,
                 ..
             } => {
+                self.stats.used_0rtt = true;
                 self.process_payload(now, remote, number.unwrap(), packet)?;
                 Ok(())
             }
@@ -2699,18 +3298,37 @@ impl Connection {
                 if self.total_authed_packets > 1 {
                     return Ok(());
                 }
-                let supported = packet
+                let offered = packet
                     .payload
-                    .chunks(4)
-                    .any(|x| match <[u8; 4]>::try_from(x) {
-                        Ok(version) => self.version == u32::from_be_bytes(version),
-                        Err(_) => false,
-                    });
-                if supported {
+                    .chunks_exact(4)
+                    .map(|x| u32::from_be_bytes(x.try_into().unwrap()))
+                    .collect::<Vec<_>>();
+                if offered.contains(&self.version) {
                     return Ok(());
                 }
-                debug!("remote doesn't support our version");
-                Err(ConnectionError::VersionMismatch)
+                let ConnectionSide::Client {
+                    ref mut restart, ..
+                } = self.side
+                else {
+                    return Ok(());
+                };
+                let Some(restart) = restart.take() else {
+                    // A client must not act on more than one Version Negotiation packet, or an
+                    // attacker could repeatedly force it onto worse versions
+                    return Ok(());
+                };
+                // Prefer versions in the order we list them
+                let Some(version) = self
+                    .endpoint_config
+                    .supported_versions
+                    .iter()
+                    .copied()
+                    .find(|v| offered.contains(v))
+                else {
+                    debug!("remote doesn't support our version");
+                    return Err(ConnectionError::VersionMismatch);
+                };
+                self.restart_with_version(now, version, restart)
             }
             Header::Short { .. } => unreachable!(
                 "short packets received during handshake are discarded in handle_packet"
@@ -2762,6 +3380,7 @@ impl Connection {
             }
         }
 
+        self.rx_ack_eliciting = ack_eliciting;
         if ack_eliciting {
             // In the initial and handshake spaces, ACKs must be sent immediately
             self.spaces[packet.header.space()]
@@ -2779,18 +3398,40 @@ impl Connection {
         remote: SocketAddr,
         number: u64,
         packet: Packet,
+    ) -> Result<(), TransportError> {
+        let mut frame_type = None;
+        self.process_payload_frames(now, remote, number, packet, &mut frame_type)
+            .map_err(|mut e| {
+                // Attribute the error to the frame being processed when it occurred
+                e.frame = e.frame.or(frame_type);
+                e
+            })
+    }
+
+    /// Process the frames in `packet`, keeping `frame_type` set to the frame being processed
+    fn process_payload_frames(
+        &mut self,
+        now: Instant,
+        remote: SocketAddr,
+        number: u64,
+        packet: Packet,
+        frame_type: &mut Option<frame::FrameType>,
     ) -> Result<(), TransportError> {
         let payload = packet.payload.freeze();
         let mut is_probing_packet = true;
         let mut close = None;
+        let mut handshake_confirmed = false;
         let payload_len = payload.len();
         let mut ack_eliciting = false;
         for result in frame::Iter::new(payload)? {
+            // Decoding errors carry the type of the malformed frame themselves, if known
+            *frame_type = None;
             let frame = result?;
             let span = match frame {
                 Frame::Padding => continue,
                 _ => Some(trace_span!("frame", ty = %frame.ty())),
             };
+            *frame_type = Some(frame.ty());
 
             self.stats.frame_rx.record(&frame);
             // Crypto, Stream and Datagram frames are special cased in order no pollute
@@ -2856,13 +3497,14 @@ impl Connection {
                         // attack. Send a non-probing packet to recover the active path.
                         match self.peer_supports_ack_frequency() {
                             true => self.immediate_ack(),
-                            false => self.ping(),
+                            false => self.queue_ping(),
                         }
                     }
                 }
                 Frame::PathResponse(token) => {
                     if self.path.challenge == Some(token) && remote == self.path.remote {
                         trace!("new path validated");
+                        self.stats.path.migration_successes += 1;
                         self.timers.stop(Timer::PathValidation);
                         self.path.challenge = None;
                         self.path.validated = true;
@@ -2888,6 +3530,16 @@ impl Connection {
                         self.spaces[SpaceId::Data].pending.max_data = true;
                     }
                 }
+                Frame::StreamSkip(frame) => {
+                    if !self.config.partial_reliability {
+                        return Err(TransportError::PROTOCOL_VIOLATION(
+                            "partial reliability not enabled",
+                        ));
+                    }
+                    if self.streams.received_stream_skip(frame)?.should_transmit() {
+                        self.spaces[SpaceId::Data].pending.max_data = true;
+                    }
+                }
                 Frame::DataBlocked { offset } => {
                     debug!(offset, "peer claims to be blocked at connection level");
                 }
@@ -2933,6 +3585,10 @@ impl Connection {
                     let allow_more_cids = self
                         .local_cid_state
                         .on_cid_retirement(sequence, self.peer_params.issue_cids_limit())?;
+                    if !allow_more_cids {
+                        debug!("peer's active_connection_id_limit reached; not issuing a new CID");
+                        self.stats.cids.local_limit_reached += 1;
+                    }
                     self.endpoint_events
                         .push_back(EndpointEventInner::RetireConnectionId(
                             now,
@@ -2956,6 +3612,13 @@ impl Connection {
                             "NEW_CONNECTION_ID retiring unissued CIDs",
                         ));
                     }
+                    // `rem_cids` can hold more than the limit we advertised
+                    let first_active = frame.retire_prior_to.max(self.rem_cids.active_seq());
+                    if frame.sequence
+                        >= first_active + u64::from(self.config.active_connection_id_limit)
+                    {
+                        return Err(TransportError::CONNECTION_ID_LIMIT_ERROR(""));
+                    }
 
                     use crate::cid_queue::InsertError;
                     match self.rem_cids.insert(frame) {
@@ -3017,12 +3680,17 @@ impl Connection {
                     token_store.insert(server_name, token);
                 }
                 Frame::Datagram(datagram) => {
-                    if self
-                        .datagrams
-                        .received(datagram, &self.config.datagram_receive_buffer_size)?
-                    {
+                    let paused = self.datagrams.recv_paused;
+                    if self.datagrams.received(
+                        datagram,
+                        &self.config.datagram_receive_buffer_size,
+                        &mut self.stats.datagram_drops,
+                    )? {
                         self.events.push_back(Event::DatagramReceived);
                     }
+                    if paused == Some(DatagramBackpressure::SignalCongestion) {
+                        self.report_congestion_experienced();
+                    }
                 }
                 Frame::AckFrequency(ack_frequency) => {
                     // This frame can only be sent in the Data space
@@ -3062,10 +3730,13 @@ impl Connection {
                     }
                     self.events.push_back(Event::HandshakeConfirmed);
                     trace!("handshake confirmed");
+                    handshake_confirmed = true;
                 }
             }
         }
+        *frame_type = None;
 
+        self.rx_ack_eliciting = ack_eliciting;
         let space = &mut self.spaces[SpaceId::Data];
         if space
             .pending_acks
@@ -3088,28 +3759,57 @@ impl Connection {
             self.close = true;
         }
 
-        if remote != self.path.remote
-            && !is_probing_packet
+        if remote == self.path.remote {
+            self.path.last_rx = now;
+        } else if !is_probing_packet
             && number == self.spaces[SpaceId::Data].rx_packet
+            && !self.migration_cooling_down(now)
         {
             let ConnectionSide::Server { ref server_config } = self.side else {
                 panic!("packets from unknown remote should be dropped by clients");
             };
             debug_assert!(
-                server_config.migration,
+                server_config.migration && self.config.allow_migration,
                 "migration-initiating packets should have been dropped immediately"
             );
             self.migrate(now, remote);
+            self.last_peer_migration = Some(now);
             // Break linkability, if possible
             self.update_rem_cid();
             self.spin = false;
+            // The spin bit must be disabled on at least one in every 16 network paths
+            self.spin_enabled = self.config.allow_spin && self.rng.random_ratio(7, 8);
+        }
+
+        // Deferred until here, since the migration changes the remote address checked above
+        if handshake_confirmed && self.config.migrate_to_preferred_address {
+            self.migrate_to_preferred_address(now);
         }
 
         Ok(())
     }
 
+    /// Whether the peer's latest change of address should be ignored
+    ///
+    /// See [`TransportConfig::migration_cooldown`].
+    fn migration_cooling_down(&self, now: Instant) -> bool {
+        let Some(last) = self.last_peer_migration else {
+            return false;
+        };
+        if now.saturating_duration_since(last) >= self.config.migration_cooldown {
+            return false;
+        }
+        // Follow the peer anyway if the current path seems to have failed
+        let failed = now.saturating_duration_since(self.path.last_rx) > 3 * self.pto(SpaceId::Data);
+        if !failed {
+            debug!("ignoring peer address change during migration cooldown");
+        }
+        !failed
+    }
+
     fn migrate(&mut self, now: Instant, remote: SocketAddr) {
         trace!(%remote, "migration initiated");
+        self.stats.path.migration_attempts += 1;
         self.path_counter = self.path_counter.wrapping_add(1);
         // Reset rtt/congestion state for new path unless it looks like a NAT rebinding.
         // Note that the congestion window will not grow until validation terminates. Helps mitigate
@@ -3149,15 +3849,86 @@ impl Connection {
         );
     }
 
+    /// Whether packets from a new remote address may initiate a migration
+    fn remote_may_migrate(&self) -> bool {
+        self.config.allow_migration && self.side.remote_may_migrate()
+    }
+
     /// Handle a change in the local address, i.e. an active migration
+    ///
+    /// If the peer disabled active migration, this does nothing: packets keep flowing from the new
+    /// address as if after a NAT rebinding, which the peer may or may not tolerate.
     pub fn local_address_changed(&mut self) {
+        if self.peer_params.disable_active_migration {
+            debug!("peer disabled active migration; not switching connection ID");
+            return;
+        }
+        self.update_rem_cid();
+        self.queue_ping();
+    }
+
+    /// The preferred address advertised by the server, if any
+    ///
+    /// Of the addresses the server may advertise, this is the one matching the address family of
+    /// [`remote_address()`](Self::remote_address). Always `None` on servers and before the
+    /// server's transport parameters have been received.
+    pub fn peer_preferred_address(&self) -> Option<SocketAddr> {
+        if self.side.is_server() || !self.peer_params_received {
+            return None;
+        }
+        let preferred = self.peer_params.preferred_address.as_ref()?;
+        match self.path.remote {
+            SocketAddr::V4(_) => preferred.address_v4.map(SocketAddr::V4),
+            SocketAddr::V6(remote) if remote.ip().to_ipv4_mapped().is_some() => {
+                let v4 = preferred.address_v4?;
+                Some(SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port()))
+            }
+            SocketAddr::V6(_) => preferred.address_v6.map(SocketAddr::V6),
+        }
+    }
+
+    /// Start migrating to the server's preferred address
+    ///
+    /// Returns the address being migrated to, or `None` if there's nothing to do: the server
+    /// didn't advertise a preferred address, the handshake isn't confirmed yet, a migration is
+    /// already being validated, or the connection already uses the preferred address.
+    ///
+    /// Packets are sent to the new address right away, using the next unused connection ID the
+    /// server issued, and carry a PATH_CHALLENGE. That's the connection ID sent along with the
+    /// preferred address unless it has already been switched to or past. If no matching
+    /// PATH_RESPONSE arrives within three PTOs, the connection falls back to its previous
+    /// address. This is permitted even if the server disabled active migration. See also
+    /// [`TransportConfig::migrate_to_preferred_address`].
+    pub fn migrate_to_preferred_address(&mut self, now: Instant) -> Option<SocketAddr> {
+        let remote = self.peer_preferred_address()?;
+        if remote == self.path.remote
+            || !self.state.is_established()
+            || self.spaces[SpaceId::Handshake].crypto.is_some()
+            || self.path.challenge.is_some()
+        {
+            return None;
+        }
+        debug!(%remote, "migrating to preferred address");
+        self.migrate(now, remote);
+        // Anti-amplification limits don't apply to an address the server gave us itself. The
+        // challenge still determines whether the new path is kept.
+        self.path.validated = true;
+        // The previous path is ours and known to work; only the new one needs validating
+        if let Some((_, ref mut prev_path)) = self.prev_path {
+            prev_path.challenge = None;
+            prev_path.challenge_pending = false;
+        }
         self.update_rem_cid();
-        self.ping();
+        Some(remote)
     }
 
     /// Switch to a previously unused remote connection ID, if possible
     fn update_rem_cid(&mut self) {
         let Some((reset_token, retired)) = self.rem_cids.next() else {
+            if !self.rem_cids.active().is_empty() {
+                debug!("no unused remote CID available; keeping the current one");
+                self.stats.cids.remote_exhausted += 1;
+            }
             return;
         };
 
@@ -3224,6 +3995,7 @@ impl Connection {
             buf.write(frame::FrameType::PING);
             sent.non_retransmits = true;
             self.stats.frame_tx.ping += 1;
+            self.pings.on_sent(space_id, pn);
         }
 
         // IMMEDIATE_ACK
@@ -3351,6 +4123,8 @@ impl Connection {
                 &mut sent.retransmits,
                 &mut self.stats.frame_tx,
                 max_size,
+                now,
+                self.path.rtt.get(),
             );
         }
 
@@ -3447,7 +4221,7 @@ impl Connection {
         if space_id == SpaceId::Data {
             sent.stream_frames =
                 self.streams
-                    .write_stream_frames(buf, max_size, self.config.send_fairness);
+                    .write_stream_frames(buf, max_size, self.config.send_fairness, now);
             self.stats.frame_tx.stream += sent.stream_frames.len() as u64;
         }
 
@@ -3518,6 +4292,7 @@ impl Connection {
         }
 
         self.set_peer_params(params);
+        self.peer_params_received = true;
 
         Ok(())
     }
@@ -3610,6 +4385,22 @@ impl Connection {
         self.peer_params.min_ack_delay.is_some()
     }
 
+    /// Size to pad a datagram that must meet the minimum for Initial packets to
+    fn initial_padding(&mut self) -> u16 {
+        let range = self.config.initial_padding.clone();
+        match range.start() == range.end() {
+            true => *range.start(),
+            false => self.rng.random_range(range),
+        }
+    }
+
+    /// Size to pad a datagram validating a path other than the current one to
+    ///
+    /// The MTU of such a path is unknown, so this never exceeds the initial MTU.
+    fn path_validation_padding(&mut self) -> u16 {
+        self.initial_padding().min(self.config.get_initial_mtu())
+    }
+
     /// Send an IMMEDIATE_ACK frame to the remote endpoint
     ///
     /// According to the spec, this will result in an error if the remote endpoint does not support
@@ -3639,6 +4430,7 @@ impl Connection {
             &self.spaces,
             self.zero_rtt_crypto.as_ref(),
             self.peer_params.stateless_reset_token,
+            &mut DroppedPacketStats::default(),
         )?;
 
         let mut packet = decrypted_header.packet?;
@@ -3655,19 +4447,11 @@ impl Connection {
         Some(packet.payload.to_vec())
     }
 
-    /// The number of bytes of packets containing retransmittable frames that have not been
-    /// acknowledged or declared lost.
-    #[cfg(test)]
-    pub(crate) fn bytes_in_flight(&self) -> u64 {
-        self.path.in_flight.bytes
-    }
-
     /// Number of bytes worth of non-ack-only packets that may be sent
     #[cfg(test)]
     pub(crate) fn congestion_window(&self) -> u64 {
         self.path
-            .congestion
-            .window()
+            .congestion_window()
             .saturating_sub(self.path.in_flight.bytes)
     }
 
@@ -3676,7 +4460,12 @@ impl Connection {
     pub(crate) fn is_idle(&self) -> bool {
         Timer::VALUES
             .iter()
-            .filter(|&&t| !matches!(t, Timer::KeepAlive | Timer::PushNewCid | Timer::KeyDiscard))
+            .filter(|&&t| {
+                !matches!(
+                    t,
+                    Timer::KeepAlive | Timer::NatKeepAlive | Timer::PushNewCid | Timer::KeyDiscard
+                )
+            })
             .filter_map(|&t| Some((t, self.timers.get(t)?)))
             .min_by_key(|&(_, time)| time)
             .map_or(true, |(timer, _)| timer == Timer::Idle)
@@ -3694,6 +4483,12 @@ impl Connection {
         self.path.total_recvd
     }
 
+    /// Whether the spin bit is in use on the current path
+    #[cfg(test)]
+    pub(crate) fn spin_enabled(&self) -> bool {
+        self.spin_enabled
+    }
+
     #[cfg(test)]
     pub(crate) fn active_local_cid_seq(&self) -> (u64, u64) {
         self.local_cid_state.active_seq()
@@ -3708,6 +4503,24 @@ impl Connection {
             .push_back(EndpointEventInner::NeedIdentifiers(now, n));
     }
 
+    /// Disregard the flow control limits set by the peer, so as to violate them
+    #[cfg(test)]
+    pub(crate) fn ignore_peer_flow_control(&mut self) {
+        self.streams.set_params(&TransportParameters {
+            initial_max_data: VarInt::MAX,
+            initial_max_stream_data_uni: VarInt::MAX,
+            initial_max_stream_data_bidi_local: VarInt::MAX,
+            initial_max_stream_data_bidi_remote: VarInt::MAX,
+            ..self.peer_params
+        });
+    }
+
+    /// The remote CID currently used to address the peer
+    #[cfg(test)]
+    pub(crate) fn active_rem_cid(&self) -> ConnectionId {
+        self.rem_cids.active()
+    }
+
     /// Check the current active remote CID sequence
     #[cfg(test)]
     pub(crate) fn active_rem_cid_seq(&self) -> u64 {
@@ -3766,6 +4579,21 @@ impl Connection {
         self.path.current_mtu()
     }
 
+    /// Largest UDP payload this connection may ever send on the current path
+    ///
+    /// The smaller of the peer's `max_udp_payload_size` transport parameter and the largest size
+    /// local configuration permits, i.e. [`MtuDiscoveryConfig::upper_bound`] or, if MTU discovery
+    /// is disabled, [`TransportConfig::initial_mtu`]. [`current_mtu()`](Self::current_mtu) never
+    /// exceeds this. Until the peer's transport parameters arrive, its limit is taken to be the
+    /// protocol default of 65527 bytes. Our own limit is advertised to the peer through
+    /// [`EndpointConfig::max_udp_payload_size`](crate::EndpointConfig::max_udp_payload_size).
+    ///
+    /// [`MtuDiscoveryConfig::upper_bound`]: crate::MtuDiscoveryConfig::upper_bound
+    /// [`TransportConfig::initial_mtu`]: crate::TransportConfig::initial_mtu
+    pub fn max_mtu(&self) -> u16 {
+        self.path.mtud.max_mtu()
+    }
+
     /// Size of non-frame data for a 1-RTT packet
     ///
     /// Quantifies space consumed by the QUIC header and AEAD tag. All other bytes in a packet are
@@ -3827,12 +4655,22 @@ enum ConnectionSide {
         token: Bytes,
         token_store: Arc<dyn TokenStore>,
         server_name: String,
+        /// Needed to restart the handshake in response to a Version Negotiation packet
+        ///
+        /// Taken when that happens, since a client acts on at most one such packet.
+        restart: Option<Box<ClientRestart>>,
     },
     Server {
         server_config: Arc<ServerConfig>,
     },
 }
 
+/// What a client needs to start a fresh crypto session under a different QUIC version
+struct ClientRestart {
+    crypto: Arc<dyn crypto::ClientConfig>,
+    params: TransportParameters,
+}
+
 impl ConnectionSide {
     fn remote_may_migrate(&self) -> bool {
         match self {
@@ -3863,10 +4701,16 @@ impl From<SideArgs> for ConnectionSide {
             SideArgs::Client {
                 token_store,
                 server_name,
+                crypto,
+                params,
             } => Self::Client {
                 token: token_store.take(&server_name).unwrap_or_default(),
                 token_store,
                 server_name,
+                restart: Some(Box::new(ClientRestart {
+                    crypto,
+                    params: *params,
+                })),
             },
             SideArgs::Server {
                 server_config,
@@ -3882,6 +4726,8 @@ pub(crate) enum SideArgs {
     Client {
         token_store: Arc<dyn TokenStore>,
         server_name: String,
+        crypto: Arc<dyn crypto::ClientConfig>,
+        params: Box<TransportParameters>,
     },
     Server {
         server_config: Arc<ServerConfig>,
@@ -3920,6 +4766,9 @@ pub enum ConnectionError {
     #[error("peer doesn't implement any supported version")]
     VersionMismatch,
     /// The peer violated the QUIC specification as understood by this implementation
+    ///
+    /// The [`TransportError`] holds the error `code`, the type of the offending `frame` if
+    /// known, and a human-readable `reason`.
     #[error(transparent)]
     TransportError(#[from] TransportError),
     /// The peer's QUIC stack aborted the connection automatically
@@ -3948,6 +4797,34 @@ pub enum ConnectionError {
     CidsExhausted,
 }
 
+impl ConnectionError {
+    /// The QUIC transport error code the connection was closed with, if any
+    ///
+    /// Set both when this endpoint detected a violation of the protocol
+    /// ([`TransportError`](Self::TransportError)), and when the peer reported one
+    /// ([`ConnectionClosed`](Self::ConnectionClosed)). Compare against the [`TransportErrorCode`]
+    /// constants to react to specific errors.
+    pub fn transport_error_code(&self) -> Option<TransportErrorCode> {
+        match self {
+            Self::TransportError(e) => Some(e.code),
+            Self::ConnectionClosed(close) => Some(close.error_code),
+            _ => None,
+        }
+    }
+
+    /// Type of the frame that caused a transport error, if known
+    ///
+    /// Only ever set alongside [`transport_error_code`](Self::transport_error_code), and only when
+    /// the error was caused by a particular frame.
+    pub fn frame_type(&self) -> Option<frame::FrameType> {
+        match self {
+            Self::TransportError(e) => e.frame,
+            Self::ConnectionClosed(close) => close.frame_type,
+            _ => None,
+        }
+    }
+}
+
 impl From<Close> for ConnectionError {
     fn from(x: Close) -> Self {
         match x {
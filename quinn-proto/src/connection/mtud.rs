@@ -1,4 +1,6 @@
-use crate::{Instant, MAX_UDP_PAYLOAD, MtuDiscoveryConfig, packet::SpaceId};
+use crate::{
+    Instant, MAX_UDP_PAYLOAD, MtuDiscoveryConfig, connection::MtuSearchState, packet::SpaceId,
+};
 use std::cmp;
 use tracing::trace;
 
@@ -9,6 +11,8 @@ use tracing::trace;
 pub(crate) struct MtuDiscovery {
     /// Detected MTU for the path
     current_mtu: u16,
+    /// The peer's `max_udp_payload_size` transport parameter, or its default if not yet known
+    peer_max_udp_payload_size: u16,
     /// The state of the MTU discovery, if enabled
     state: Option<EnabledMtuDiscovery>,
     /// The state of the black hole detector
@@ -43,14 +47,24 @@ impl MtuDiscovery {
         mtud
     }
 
-    /// MTU discovery will be disabled and the current MTU will be fixed to the provided value
-    pub(crate) fn disabled(plpmtu: u16, min_mtu: u16) -> Self {
-        Self::with_state(plpmtu, min_mtu, None)
+    /// MTU discovery will be disabled and the current MTU will be fixed to the provided value, or
+    /// the peer's `max_udp_payload_size` if that is smaller
+    pub(crate) fn disabled(
+        plpmtu: u16,
+        min_mtu: u16,
+        peer_max_udp_payload_size: Option<u16>,
+    ) -> Self {
+        let mut mtud = Self::with_state(plpmtu, min_mtu, None);
+        if let Some(peer_max_udp_payload_size) = peer_max_udp_payload_size {
+            mtud.on_peer_max_udp_payload_size_received(peer_max_udp_payload_size);
+        }
+        mtud
     }
 
     fn with_state(current_mtu: u16, min_mtu: u16, state: Option<EnabledMtuDiscovery>) -> Self {
         Self {
             current_mtu,
+            peer_max_udp_payload_size: MAX_UDP_PAYLOAD,
             state,
             black_hole_detector: BlackHoleDetector::new(min_mtu),
         }
@@ -60,8 +74,8 @@ impl MtuDiscovery {
         self.current_mtu = current_mtu;
         if let Some(state) = self.state.take() {
             self.state = Some(EnabledMtuDiscovery::new(state.config));
-            self.on_peer_max_udp_payload_size_received(state.peer_max_udp_payload_size);
         }
+        self.on_peer_max_udp_payload_size_received(self.peer_max_udp_payload_size);
         self.black_hole_detector = BlackHoleDetector::new(min_mtu);
     }
 
@@ -70,6 +84,31 @@ impl MtuDiscovery {
         self.current_mtu
     }
 
+    /// Returns the largest MTU that may ever be used on this path
+    ///
+    /// This is the smaller of the peer's `max_udp_payload_size` and the upper bound of MTU
+    /// discovery, or the current MTU if discovery is disabled.
+    pub(crate) fn max_mtu(&self) -> u16 {
+        self.state
+            .as_ref()
+            .map_or(self.current_mtu, |state| {
+                state.config.upper_bound.max(self.current_mtu)
+            })
+            .min(self.peer_max_udp_payload_size)
+    }
+
+    /// Returns the state of the search for a higher MTU
+    pub(crate) fn search_state(&self) -> MtuSearchState {
+        match &self.state {
+            None => MtuSearchState::Disabled,
+            Some(state) => match state.phase {
+                Phase::Initial | Phase::Searching(_) => MtuSearchState::Searching,
+                Phase::Complete(_) if state.black_hole => MtuSearchState::BlackHole,
+                Phase::Complete(_) => MtuSearchState::Done,
+            },
+        }
+    }
+
     /// Returns the amount of bytes that should be sent as an MTU probe, if any
     pub(crate) fn poll_transmit(&mut self, now: Instant, next_pn: u64) -> Option<u16> {
         self.state
@@ -81,6 +120,7 @@ impl MtuDiscovery {
     /// been received
     pub(crate) fn on_peer_max_udp_payload_size_received(&mut self, peer_max_udp_payload_size: u16) {
         self.current_mtu = self.current_mtu.min(peer_max_udp_payload_size);
+        self.peer_max_udp_payload_size = peer_max_udp_payload_size;
 
         if let Some(state) = self.state.as_mut() {
             // It is possible for black hole detection to trigger before the connection has been
@@ -165,12 +205,35 @@ impl MtuDiscovery {
 
         true
     }
+
+    /// Returns true if an ICMP "packet too big" message lowered the current MTU to `mtu`
+    ///
+    /// The message is ignored unless enabled by [`MtuDiscoveryConfig::packet_too_big`], or if the
+    /// reported size is outside the range between `min_mtu` and the current MTU.
+    pub(crate) fn on_packet_too_big(&mut self, now: Instant, mtu: u16) -> bool {
+        let Some(state) = &mut self.state else {
+            return false;
+        };
+        if !state.config.packet_too_big
+            || mtu < self.black_hole_detector.min_mtu
+            || mtu >= self.current_mtu
+        {
+            return false;
+        }
+
+        trace!(current_mtu = mtu, "MTU lowered by ICMP packet too big");
+        self.current_mtu = mtu;
+        state.on_packet_too_big(now);
+        true
+    }
 }
 
 /// Additional state for enabled MTU discovery
 #[derive(Debug, Clone)]
 struct EnabledMtuDiscovery {
     phase: Phase,
+    /// Whether the current `Phase::Complete` was entered due to a black hole
+    black_hole: bool,
     peer_max_udp_payload_size: u16,
     config: MtuDiscoveryConfig,
 }
@@ -179,6 +242,7 @@ impl EnabledMtuDiscovery {
     fn new(config: MtuDiscoveryConfig) -> Self {
         Self {
             phase: Phase::Initial,
+            black_hole: false,
             peer_max_udp_payload_size: MAX_UDP_PAYLOAD,
             config,
         }
@@ -199,6 +263,7 @@ impl EnabledMtuDiscovery {
             }
 
             // Start a new search (we have reached the next activation time)
+            self.black_hole = false;
             self.phase = Phase::Searching(SearchState::new(
                 current_mtu,
                 self.peer_max_udp_payload_size,
@@ -268,6 +333,15 @@ impl EnabledMtuDiscovery {
         // Stop searching, if applicable, and reset the timer
         let next_mtud_activation = now + self.config.black_hole_cooldown;
         self.phase = Phase::Complete(next_mtud_activation);
+        self.black_hole = true;
+    }
+
+    /// Called when an ICMP "packet too big" message lowered the MTU
+    fn on_packet_too_big(&mut self, now: Instant) {
+        // The reported MTU is likely accurate, so searching above it is pointless for a while
+        let next_mtud_activation = now + self.config.black_hole_cooldown;
+        self.phase = Phase::Complete(next_mtud_activation);
+        self.black_hole = false;
     }
 }
 
@@ -601,14 +675,14 @@ mod tests {
 
     #[test]
     fn mtu_discovery_disabled_does_nothing() {
-        let mut mtud = MtuDiscovery::disabled(1_200, 1_200);
+        let mut mtud = MtuDiscovery::disabled(1_200, 1_200, None);
         let probe_size = mtud.poll_transmit(Instant::now(), 0);
         assert_eq!(probe_size, None);
     }
 
     #[test]
     fn mtu_discovery_disabled_lost_four_packet_bursts_triggers_black_hole_detection() {
-        let mut mtud = MtuDiscovery::disabled(1_400, 1_250);
+        let mut mtud = MtuDiscovery::disabled(1_400, 1_250, None);
         let now = Instant::now();
 
         for i in 0..4 {
@@ -651,6 +725,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn packet_too_big_lowers_mtu_within_bounds() {
+        let mut config = MtuDiscoveryConfig::default();
+        config.packet_too_big(true);
+        let mut mtud = MtuDiscovery::new(1_200, 1_200, None, config);
+        let now = Instant::now();
+        drive_to_completion(&mut mtud, now, 1_500);
+        assert_eq!(mtud.current_mtu, 1_452);
+
+        // Sizes at or above the current MTU, or below the minimum, are ignored
+        assert!(!mtud.on_packet_too_big(now, 1_452));
+        assert!(!mtud.on_packet_too_big(now, 1_199));
+        assert_eq!(mtud.current_mtu, 1_452);
+
+        let later = now + Duration::from_secs(1);
+        assert!(mtud.on_packet_too_big(later, 1_300));
+        assert_eq!(mtud.current_mtu, 1_300);
+        assert_eq!(mtud.search_state(), MtuSearchState::Done);
+        assert_matches!(
+            mtud.state.as_ref().unwrap().phase,
+            Phase::Complete(t) if t == later + Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn packet_too_big_ignored_unless_enabled() {
+        let mut mtud = default_mtud();
+        let now = Instant::now();
+        drive_to_completion(&mut mtud, now, 1_500);
+        assert!(!mtud.on_packet_too_big(now, 1_300));
+        assert_eq!(mtud.current_mtu, 1_452);
+
+        let mut mtud = MtuDiscovery::disabled(1_400, 1_200, None);
+        assert!(!mtud.on_packet_too_big(now, 1_300));
+        assert_eq!(mtud.current_mtu, 1_400);
+    }
+
     #[test]
     fn mtu_discovery_after_complete_reactivates_when_interval_elapsed() {
         let mut config = MtuDiscoveryConfig::default();
@@ -736,6 +847,32 @@ mod tests {
         assert!(completed(&mtud));
     }
 
+    #[test]
+    fn max_mtu_respects_peer_max_udp_payload_size() {
+        // Until the peer's limit is known, only the configured upper bound applies
+        let mut mtud = default_mtud();
+        assert_eq!(mtud.max_mtu(), 1452);
+
+        // The default value of the transport parameter doesn't constrain anything
+        mtud.on_peer_max_udp_payload_size_received(MAX_UDP_PAYLOAD);
+        assert_eq!(mtud.max_mtu(), 1452);
+
+        mtud.on_peer_max_udp_payload_size_received(1300);
+        assert_eq!(mtud.max_mtu(), 1300);
+
+        // Without MTU discovery, the initial MTU is the limit
+        let mtud = MtuDiscovery::disabled(1400, 1_200, Some(1300));
+        assert_eq!(mtud.current_mtu, 1300);
+        assert_eq!(mtud.max_mtu(), 1300);
+        let mut mtud = MtuDiscovery::disabled(1400, 1_200, None);
+        assert_eq!(mtud.max_mtu(), 1400);
+
+        // The peer's limit survives a reset
+        mtud.on_peer_max_udp_payload_size_received(1300);
+        mtud.reset(1400, 1_200);
+        assert_eq!(mtud.current_mtu, 1300);
+    }
+
     #[cfg(debug_assertions)]
     #[test]
     #[should_panic(expected = "Transport parameters received after MTU probing started")]
@@ -5,6 +5,7 @@ use tracing::{debug, trace, trace_span};
 use super::{Connection, SentFrames, spaces::SentPacket};
 use crate::{
     ConnectionId, Instant, TransportError, TransportErrorCode,
+    config::{PacketDirection, PacketInfo},
     connection::ConnectionSide,
     frame::{self, Close},
     packet::{FIXED_BIT, Header, InitialHeader, LongType, PacketNumber, PartialEncode, SpaceId},
@@ -171,13 +172,19 @@ impl PacketBuilder {
 
     /// Append the minimum amount of padding to the packet such that, after encryption, the
     /// enclosing datagram will occupy at least `min_size` bytes
+    ///
+    /// Padding never grows the datagram beyond the buffer capacity it was built with, which may be
+    /// smaller than `min_size` when limited by anti-amplification (RFC 9000 §8.2.1).
     pub(super) fn pad_to(&mut self, min_size: u16) {
         // The datagram might already have a larger minimum size than the caller is requesting, if
         // e.g. we're coalescing packets and have populated more than `min_size` bytes with packets
         // already.
         self.min_size = Ord::max(
             self.min_size,
-            self.datagram_start + (min_size as usize) - self.tag_len,
+            Ord::min(
+                self.datagram_start + (min_size as usize) - self.tag_len,
+                self.max_size,
+            ),
         );
     }
 
@@ -214,6 +221,12 @@ impl PacketBuilder {
         conn.path
             .sent(exact_number, packet, &mut conn.spaces[space_id]);
         conn.stats.path.sent_packets += 1;
+        if conn.stats.time_to_first_flight.is_none() {
+            conn.stats.time_to_first_flight = Some(now.saturating_duration_since(conn.created));
+        }
+        if space_id == SpaceId::Data && conn.spaces[space_id].crypto.is_none() {
+            conn.stats.used_0rtt = true;
+        }
         conn.reset_keep_alive(now);
         if size != 0 {
             if ack_eliciting {
@@ -275,6 +288,15 @@ impl PacketBuilder {
             now,
             conn.orig_rem_cid,
         );
+        if let Some(ref observer) = conn.config.on_packet {
+            observer(PacketInfo {
+                direction: PacketDirection::Sent,
+                space: self.space,
+                number: self.exact_number,
+                size: len,
+                ack_eliciting: self.ack_eliciting,
+            });
+        }
 
         (len, pad)
     }
@@ -1,7 +1,31 @@
+//! Removal of packet protection from incoming packets
+//!
+//! Everything in this module handles attacker-controlled input: anyone able to inject datagrams
+//! on the path, or to spoof the peer's address, can feed packets bearing one of our connection IDs
+//! through here. Such packets must be dropped without panicking, without changing connection state,
+//! and without leaking secrets through timing:
+//!
+//! - Header protection is removed before the packet is authenticated, so the recovered packet
+//!   number, key phase and reserved bits are not yet trustworthy. Reserved bits are therefore only
+//!   checked once AEAD decryption has succeeded, lest they become an oracle for the header
+//!   protection key (RFC 9001 §9.5).
+//! - The keys for the next key phase are derived ahead of time, so that packets with a flipped key
+//!   phase bit take the same path as any other packet failing authentication.
+//! - The trailing bytes of every packet that fails to decode are compared against the peer's
+//!   stateless reset token, which must not be revealed to an off-path attacker probing for it, so
+//!   the comparison is performed in constant time (RFC 9000 §10.3.1).
+//!
+//! Dropped packets are counted in [`ConnectionStats::header_protection_failures`] and
+//! [`ConnectionStats::decryption_failures`].
+//!
+//! [`ConnectionStats::header_protection_failures`]: crate::ConnectionStats::header_protection_failures
+//! [`ConnectionStats::decryption_failures`]: crate::ConnectionStats::decryption_failures
+
 use tracing::{debug, trace};
 
 use crate::Instant;
 use crate::connection::spaces::PacketSpace;
+use crate::connection::stats::DroppedPacketStats;
 use crate::crypto::{HeaderKey, KeyPair, PacketKey};
 use crate::packet::{Packet, PartialDecode, SpaceId};
 use crate::token::ResetToken;
@@ -13,6 +37,7 @@ pub(super) fn unprotect_header(
     spaces: &[PacketSpace; 3],
     zero_rtt_crypto: Option<&ZeroRttCrypto>,
     stateless_reset_token: Option<ResetToken>,
+    failures: &mut DroppedPacketStats,
 ) -> Option<UnprotectHeaderResult> {
     let header_crypto = if partial_decode.is_0rtt() {
         if let Some(crypto) = zero_rtt_crypto {
@@ -37,9 +62,12 @@ pub(super) fn unprotect_header(
         None
     };
 
+    let space = partial_decode.space();
     let packet = partial_decode.data();
     let stateless_reset = packet.len() >= RESET_TOKEN_SIZE + 5
-        && stateless_reset_token.as_deref() == Some(&packet[packet.len() - RESET_TOKEN_SIZE..]);
+        && stateless_reset_token.is_some_and(|token| {
+            crate::constant_time::eq(&token, &packet[packet.len() - RESET_TOKEN_SIZE..])
+        });
 
     match partial_decode.finish(header_crypto) {
         Ok(packet) => Some(UnprotectHeaderResult {
@@ -52,6 +80,9 @@ pub(super) fn unprotect_header(
         }),
         Err(e) => {
             trace!("unable to complete packet decoding: {}", e);
+            if let Some(space) = space {
+                failures.record(space);
+            }
             None
         }
     }
@@ -20,6 +20,8 @@ pub(super) struct PathData {
     pub(super) sending_ecn: bool,
     /// Congestion controller state
     pub(super) congestion: Box<dyn congestion::Controller>,
+    /// Upper bound on the congestion controller's window
+    max_congestion_window: u64,
     /// Pacing state
     pub(super) pacing: Pacer,
     pub(super) challenge: Option<u64>,
@@ -33,6 +35,12 @@ pub(super) struct PathData {
     pub(super) total_sent: u64,
     /// Total size of all UDP datagrams received on this path
     pub(super) total_recvd: u64,
+    /// When an authenticated 1-RTT packet was last received on this path
+    ///
+    /// Initially the time the path was created.
+    pub(super) last_rx: Instant,
+    /// Multiple of `total_recvd` we may send before the path is validated
+    amplification_factor: u64,
     /// The state of the MTU discovery process
     pub(super) mtud: MtuDiscovery,
     /// Packet number of the first packet sent after an RTT sample was collected on this path
@@ -40,6 +48,8 @@ pub(super) struct PathData {
     /// Used in persistent congestion determination.
     pub(super) first_packet_after_rtt_sample: Option<(SpaceId, u64)>,
     pub(super) in_flight: InFlight,
+    /// Time threshold used to declare packets lost, adapted to observed reordering
+    pub(super) reordering: ReorderingWindow,
     /// Number of the first packet sent on this path
     ///
     /// Used to determine whether a packet was sent on an earlier path. Insufficient to determine if
@@ -73,22 +83,33 @@ impl PathData {
             sending_ecn: true,
             pacing: Pacer::new(
                 config.initial_rtt,
-                congestion.initial_window(),
+                congestion
+                    .initial_window()
+                    .min(config.max_congestion_window),
                 config.get_initial_mtu(),
                 now,
             ),
             congestion,
+            max_congestion_window: config.max_congestion_window,
             challenge: None,
             challenge_pending: false,
             validated: false,
             total_sent: 0,
             total_recvd: 0,
+            last_rx: now,
+            amplification_factor: config.amplification_factor.into(),
             mtud: config
                 .mtu_discovery_config
                 .as_ref()
                 .filter(|_| allow_mtud)
                 .map_or_else(
-                    || MtuDiscovery::disabled(config.get_initial_mtu(), config.min_mtu),
+                    || {
+                        MtuDiscovery::disabled(
+                            config.get_initial_mtu(),
+                            config.min_mtu,
+                            peer_max_udp_payload_size,
+                        )
+                    },
                     |mtud_config| {
                         MtuDiscovery::new(
                             config.get_initial_mtu(),
@@ -100,6 +121,7 @@ impl PathData {
                 ),
             first_packet_after_rtt_sample: None,
             in_flight: InFlight::new(),
+            reordering: ReorderingWindow::new(config),
             first_packet: None,
             #[cfg(feature = "qlog")]
             recovery_metrics: RecoveryMetrics::default(),
@@ -118,17 +140,26 @@ impl PathData {
         Self {
             remote,
             rtt: prev.rtt,
-            pacing: Pacer::new(smoothed_rtt, congestion.pacing_window(), prev.current_mtu(), now),
+            pacing: Pacer::new(
+                smoothed_rtt,
+                congestion.pacing_window().min(prev.max_congestion_window),
+                prev.current_mtu(),
+                now,
+            ),
             sending_ecn: true,
             congestion,
+            max_congestion_window: prev.max_congestion_window,
             challenge: None,
             challenge_pending: false,
             validated: false,
             total_sent: 0,
             total_recvd: 0,
+            last_rx: now,
+            amplification_factor: prev.amplification_factor,
             mtud: prev.mtud.clone(),
             first_packet_after_rtt_sample: prev.first_packet_after_rtt_sample,
             in_flight: InFlight::new(),
+            reordering: prev.reordering,
             first_packet: None,
             #[cfg(feature = "qlog")]
             recovery_metrics: prev.recovery_metrics.clone(),
@@ -146,12 +177,29 @@ impl PathData {
             .clone()
             .build(now, config.get_initial_mtu());
         self.mtud.reset(config.get_initial_mtu(), config.min_mtu);
+        self.reordering = ReorderingWindow::new(config);
     }
 
     /// Indicates whether we're a server that hasn't validated the peer's address and hasn't
     /// received enough data from the peer to permit sending `bytes_to_send` additional bytes
     pub(super) fn anti_amplification_blocked(&self, bytes_to_send: u64) -> bool {
-        !self.validated && self.total_recvd * 30 < self.total_sent + bytes_to_send
+        self.anti_amplification_credit()
+            .is_some_and(|credit| credit < bytes_to_send)
+    }
+
+    /// Number of bytes we may send before the peer's address is validated, if it isn't yet
+    ///
+    /// RFC 9000 §8.1 forbids sending more than three times the amount of data received from an
+    /// unvalidated address.
+    pub(super) fn anti_amplification_credit(&self) -> Option<u64> {
+        if self.validated {
+            return None;
+        }
+        Some(
+            self.total_recvd
+                .saturating_mul(self.amplification_factor)
+                .saturating_sub(self.total_sent),
+        )
     }
 
     /// Returns the path's current MTU
@@ -193,7 +241,11 @@ impl PathData {
             bytes_in_flight: Some(self.in_flight.bytes),
             packets_in_flight: Some(self.in_flight.ack_eliciting),
 
-            congestion_window: Some(controller_metrics.congestion_window),
+            congestion_window: Some(
+                controller_metrics
+                    .congestion_window
+                    .min(self.max_congestion_window),
+            ),
             ssthresh: controller_metrics.ssthresh,
             pacing_rate: controller_metrics.pacing_rate,
         };
@@ -203,6 +255,18 @@ impl PathData {
         event
     }
 
+    /// Number of ack-eliciting bytes that may be in flight
+    pub(super) fn congestion_window(&self) -> u64 {
+        self.congestion.window().min(self.max_congestion_window)
+    }
+
+    /// Window from which the pacing rate is derived
+    pub(super) fn pacing_window(&self) -> u64 {
+        self.congestion
+            .pacing_window()
+            .min(self.max_congestion_window)
+    }
+
     pub(super) fn generation(&self) -> u64 {
         self.generation
     }
@@ -294,7 +358,7 @@ pub struct RttEstimator {
 }
 
 impl RttEstimator {
-    fn new(initial_rtt: Duration) -> Self {
+    pub(crate) fn new(initial_rtt: Duration) -> Self {
         Self {
             latest: initial_rtt,
             smoothed: None,
@@ -316,11 +380,21 @@ impl RttEstimator {
         self.get().max(self.latest)
     }
 
+    /// Most recent RTT sample, without any smoothing or adjustment for ack delay
+    pub fn latest(&self) -> Duration {
+        self.latest
+    }
+
     /// Minimum RTT registered so far for this estimator.
     pub fn min(&self) -> Duration {
         self.min
     }
 
+    /// Mean deviation of RTT samples from the smoothed RTT, as described in RFC6298
+    pub fn variance(&self) -> Duration {
+        self.var
+    }
+
     // PTO computed as described in RFC9002#6.2.1
     pub(crate) fn pto_base(&self) -> Duration {
         self.get() + cmp::max(4 * self.var, TIMER_GRANULARITY)
@@ -454,3 +528,73 @@ impl InFlight {
         self.ack_eliciting = self.ack_eliciting.saturating_sub(u64::from(packet.ack_eliciting));
     }
 }
+
+/// Time threshold for loss detection, widened when packets are found to have been reordered
+///
+/// Modeled after the reordering window of RACK (RFC 8985 §6.2).
+#[derive(Copy, Clone)]
+pub(super) struct ReorderingWindow {
+    /// Configured time threshold, used when no reordering has been observed
+    base: f32,
+    /// Upper bound for the time threshold, or `None` if it doesn't adapt
+    max: Option<f32>,
+    /// Current time threshold, as a multiple of the RTT
+    threshold: f32,
+    /// Number of loss detection episodes until the threshold is restored to `base`
+    ///
+    /// Nonzero if and only if reordering has been observed recently.
+    persist: u8,
+}
+
+impl ReorderingWindow {
+    /// Number of loss detection episodes a widened threshold persists for
+    const PERSIST: u8 = 16;
+    /// Amount by which the threshold grows on each spurious loss, as a multiple of the RTT
+    const STEP: f32 = 0.25;
+
+    fn new(config: &TransportConfig) -> Self {
+        Self {
+            base: config.time_threshold,
+            max: config.max_time_threshold,
+            threshold: config.time_threshold,
+            persist: 0,
+        }
+    }
+
+    /// Whether packets should be declared lost based on the packet threshold
+    ///
+    /// Counting packets is unreliable on a path known to reorder, so only the time threshold is
+    /// used until the reordering window is restored.
+    pub(super) fn use_packet_threshold(&self) -> bool {
+        self.persist == 0
+    }
+
+    /// Length of the window after which a packet is considered lost
+    pub(super) fn window(&self, rtt: &RttEstimator) -> Duration {
+        cmp::max(
+            rtt.conservative().mul_f32(self.threshold),
+            TIMER_GRANULARITY,
+        )
+    }
+
+    /// Account for a packet declared lost later being acknowledged
+    pub(super) fn on_spurious_loss(&mut self) {
+        let Some(max) = self.max else {
+            return;
+        };
+        self.threshold = (self.threshold + Self::STEP).min(max.max(self.base));
+        self.persist = Self::PERSIST;
+        trace!(threshold = self.threshold, "reordering detected");
+    }
+
+    /// Account for packets being declared lost
+    pub(super) fn on_loss(&mut self) {
+        if self.persist == 0 {
+            return;
+        }
+        self.persist -= 1;
+        if self.persist == 0 {
+            self.threshold = self.base;
+        }
+    }
+}
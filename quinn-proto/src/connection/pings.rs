@@ -0,0 +1,69 @@
+use crate::packet::SpaceId;
+
+/// Tracks PINGs requested by the application until they are acknowledged
+///
+/// A request is answered by the acknowledgement of any PING sent after it was made, so a single
+/// PING can answer many requests, and internally generated PINGs (e.g. keep-alives) answer
+/// requests too.
+#[derive(Debug, Default)]
+pub(super) struct PingTracker {
+    /// Number of PINGs requested so far
+    requested: u64,
+    /// Number of requests answered so far
+    acknowledged: u64,
+    /// Unacknowledged packets carrying a PING, with the number of requests made before each was
+    /// sent
+    in_flight: Vec<(SpaceId, u64, u64)>,
+}
+
+impl PingTracker {
+    pub(super) fn on_requested(&mut self) {
+        self.requested += 1;
+    }
+
+    /// Record that a PING was sent in packet `pn`
+    pub(super) fn on_sent(&mut self, space: SpaceId, pn: u64) {
+        if self.requested > self.acknowledged {
+            self.in_flight.push((space, pn, self.requested));
+        }
+    }
+
+    /// Number of requests answered so far
+    pub(super) fn acknowledged(&self) -> u64 {
+        self.acknowledged
+    }
+
+    /// Record that packet `pn` was acknowledged
+    pub(super) fn on_acked(&mut self, space: SpaceId, pn: u64) {
+        let Some(answered) = self.take(space, pn) else {
+            return;
+        };
+        if answered <= self.acknowledged {
+            return;
+        }
+        self.acknowledged = answered;
+        self.in_flight.retain(|&(_, _, count)| count > answered);
+    }
+
+    /// Returns whether another PING must be sent because `pn` was lost
+    pub(super) fn on_lost(&mut self, space: SpaceId, pn: u64) -> bool {
+        self.take(space, pn)
+            .is_some_and(|answered| answered > self.acknowledged)
+    }
+
+    /// Returns whether another PING must be sent because PINGs in `space` can no longer be
+    /// acknowledged
+    pub(super) fn on_space_discarded(&mut self, space: SpaceId) -> bool {
+        let before = self.in_flight.len();
+        self.in_flight.retain(|&(s, _, _)| s != space);
+        before != self.in_flight.len() && self.in_flight.is_empty()
+    }
+
+    fn take(&mut self, space: SpaceId, pn: u64) -> Option<u64> {
+        let index = self
+            .in_flight
+            .iter()
+            .position(|&(s, p, _)| s == space && p == pn)?;
+        Some(self.in_flight.swap_remove(index).2)
+    }
+}
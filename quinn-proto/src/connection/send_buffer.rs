@@ -79,14 +79,14 @@ impl SendBuffer {
     /// `max_len` here includes the space which is available to transmit the
     /// offset and length of the data to send. The caller has to guarantee that
     /// there is at least enough space available to write maximum-sized metadata
-    /// (8 byte offset + 8 byte length).
+    /// (8 byte offset + 8 byte length). New data is only sent below offset `end`.
     ///
     /// The method returns a tuple:
     /// - The first return value indicates the range of data to send
     /// - The second return value indicates whether the length needs to be encoded
     ///   in the STREAM frames metadata (`true`), or whether it can be omitted
     ///   since the selected range will fill the whole packet.
-    pub(super) fn poll_transmit(&mut self, mut max_len: usize) -> (Range<u64>, bool) {
+    pub(super) fn poll_transmit(&mut self, mut max_len: usize, end: u64) -> (Range<u64>, bool) {
         debug_assert!(max_len >= 8 + 8);
         let mut encode_length = false;
 
@@ -117,14 +117,13 @@ impl SendBuffer {
         if self.unsent != 0 {
             max_len -= VarInt::size(unsafe { VarInt::from_u64_unchecked(self.unsent) });
         }
-        if self.offset - self.unsent < max_len as u64 {
+        let end = end.clamp(self.unsent, self.offset);
+        if end - self.unsent < max_len as u64 {
             encode_length = true;
             max_len -= 8;
         }
 
-        let end = self
-            .offset
-            .min((max_len as u64).saturating_add(self.unsent));
+        let end = end.min((max_len as u64).saturating_add(self.unsent));
         let result = self.unsent..end;
         self.unsent = end;
         (result, encode_length)
@@ -156,9 +155,32 @@ impl SendBuffer {
     }
 
     /// Queue a range of sent but unacknowledged data to be retransmitted
-    pub(super) fn retransmit(&mut self, range: Range<u64>) {
+    pub(super) fn retransmit(&mut self, mut range: Range<u64>) {
         debug_assert!(range.end <= self.unsent, "unsent data can't be lost");
-        self.retransmits.insert(range);
+        // Data below the acknowledged prefix may have been abandoned by `skip_to`
+        range.start = range.start.max(self.acked_prefix());
+        if range.start < range.end {
+            self.retransmits.insert(range);
+        }
+    }
+
+    /// Abandon all data below `offset`, so that it's neither sent nor retransmitted
+    ///
+    /// The abandoned data is treated as though it had been acknowledged.
+    pub(super) fn skip_to(&mut self, offset: u64) {
+        let offset = offset.min(self.offset);
+        while let Some(range) = self.retransmits.peek_min() {
+            if range.start >= offset {
+                break;
+            }
+            self.retransmits.pop_min();
+            if range.end > offset {
+                self.retransmits.insert(offset..range.end);
+                break;
+            }
+        }
+        self.unsent = self.unsent.max(offset);
+        self.ack(0..offset);
     }
 
     pub(super) fn retransmit_all_for_0rtt(&mut self) {
@@ -172,22 +194,42 @@ impl SendBuffer {
         self.offset
     }
 
+    /// Amount of data that has been transmitted at least once
+    pub(super) fn sent(&self) -> u64 {
+        self.unsent
+    }
+
+    /// Amount of data that has been acknowledged, counting each byte once
+    pub(super) fn acked(&self) -> u64 {
+        self.offset - self.unacked()
+    }
+
+    /// The offset below which all data has been acknowledged
+    pub(super) fn acked_prefix(&self) -> u64 {
+        self.offset - self.unacked_len as u64
+    }
+
     /// Whether all sent data has been acknowledged
     pub(super) fn is_fully_acked(&self) -> bool {
         self.unacked_len == 0
     }
 
-    /// Whether there's data to send
+    /// Whether there's data to send, considering new data only below offset `end`
     ///
     /// There may be sent unacknowledged data even when this is false.
-    pub(super) fn has_unsent_data(&self) -> bool {
-        self.unsent != self.offset || !self.retransmits.is_empty()
+    pub(super) fn has_unsent_data(&self, end: u64) -> bool {
+        self.unsent < end.min(self.offset) || !self.retransmits.is_empty()
     }
 
     /// Compute the amount of data that hasn't been acknowledged
     pub(super) fn unacked(&self) -> u64 {
         self.unacked_len as u64 - self.acks.iter().map(|x| x.end - x.start).sum::<u64>()
     }
+
+    /// Amount of sent data deemed lost and not yet retransmitted
+    pub(super) fn retransmit_pending(&self) -> u64 {
+        self.retransmits.iter().map(|x| x.end - x.start).sum()
+    }
 }
 
 #[cfg(test)]
@@ -201,13 +243,13 @@ mod tests {
         buf.write(MSG.into());
         // 0 byte offset => 19 bytes left => 13 byte data isn't enough
         // with 8 bytes reserved for length 11 payload bytes will fit
-        assert_eq!(buf.poll_transmit(19), (0..11, true));
+        assert_eq!(buf.poll_transmit(19, u64::MAX), (0..11, true));
         assert_eq!(
-            buf.poll_transmit(MSG.len() + 16 - 11),
+            buf.poll_transmit(MSG.len() + 16 - 11, u64::MAX),
             (11..MSG.len() as u64, true)
         );
         assert_eq!(
-            buf.poll_transmit(58),
+            buf.poll_transmit(58, u64::MAX),
             (MSG.len() as u64..MSG.len() as u64, true)
         );
     }
@@ -218,13 +260,13 @@ mod tests {
         const MSG: &[u8] = b"Hello, world with some extra data!";
         buf.write(MSG.into());
         // 0 byte offset => 19 bytes left => can be filled by 34 bytes payload
-        assert_eq!(buf.poll_transmit(19), (0..19, false));
+        assert_eq!(buf.poll_transmit(19, u64::MAX), (0..19, false));
         assert_eq!(
-            buf.poll_transmit(MSG.len() - 19 + 1),
+            buf.poll_transmit(MSG.len() - 19 + 1, u64::MAX),
             (19..MSG.len() as u64, false)
         );
         assert_eq!(
-            buf.poll_transmit(58),
+            buf.poll_transmit(58, u64::MAX),
             (MSG.len() as u64..MSG.len() as u64, true)
         );
     }
@@ -244,55 +286,55 @@ mod tests {
         const SIZE3: u64 = 1024 * 1024 * 1024;
 
         // Offset 0 requires no space
-        assert_eq!(buf.poll_transmit(16), (0..16, false));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..16, false));
         buf.retransmit(0..16);
-        assert_eq!(buf.poll_transmit(16), (0..16, false));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..16, false));
         let mut transmitted = 16u64;
 
         // Offset 16 requires 1 byte
         assert_eq!(
-            buf.poll_transmit((SIZE1 - transmitted + 1) as usize),
+            buf.poll_transmit((SIZE1 - transmitted + 1) as usize, u64::MAX),
             (transmitted..SIZE1, false)
         );
         buf.retransmit(transmitted..SIZE1);
         assert_eq!(
-            buf.poll_transmit((SIZE1 - transmitted + 1) as usize),
+            buf.poll_transmit((SIZE1 - transmitted + 1) as usize, u64::MAX),
             (transmitted..SIZE1, false)
         );
         transmitted = SIZE1;
 
         // Offset 64 requires 2 bytes
         assert_eq!(
-            buf.poll_transmit((SIZE2 - transmitted + 2) as usize),
+            buf.poll_transmit((SIZE2 - transmitted + 2) as usize, u64::MAX),
             (transmitted..SIZE2, false)
         );
         buf.retransmit(transmitted..SIZE2);
         assert_eq!(
-            buf.poll_transmit((SIZE2 - transmitted + 2) as usize),
+            buf.poll_transmit((SIZE2 - transmitted + 2) as usize, u64::MAX),
             (transmitted..SIZE2, false)
         );
         transmitted = SIZE2;
 
         // Offset 16384 requires requires 4 bytes
         assert_eq!(
-            buf.poll_transmit((SIZE3 - transmitted + 4) as usize),
+            buf.poll_transmit((SIZE3 - transmitted + 4) as usize, u64::MAX),
             (transmitted..SIZE3, false)
         );
         buf.retransmit(transmitted..SIZE3);
         assert_eq!(
-            buf.poll_transmit((SIZE3 - transmitted + 4) as usize),
+            buf.poll_transmit((SIZE3 - transmitted + 4) as usize, u64::MAX),
             (transmitted..SIZE3, false)
         );
         transmitted = SIZE3;
 
         // Offset 1GB requires 8 bytes
         assert_eq!(
-            buf.poll_transmit(chunk.len() + 8),
+            buf.poll_transmit(chunk.len() + 8, u64::MAX),
             (transmitted..transmitted + chunk.len() as u64, false)
         );
         buf.retransmit(transmitted..transmitted + chunk.len() as u64);
         assert_eq!(
-            buf.poll_transmit(chunk.len() + 8),
+            buf.poll_transmit(chunk.len() + 8, u64::MAX),
             (transmitted..transmitted + chunk.len() as u64, false)
         );
     }
@@ -316,16 +358,16 @@ mod tests {
 
         assert_eq!(aggregate_unacked(&buf), MSG);
 
-        assert_eq!(buf.poll_transmit(16), (0..8, true));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..8, true));
         assert_eq!(buf.get(0..5), SEG1);
         assert_eq!(buf.get(2..8), SEG2);
         assert_eq!(buf.get(6..8), SEG3);
 
-        assert_eq!(buf.poll_transmit(16), (8..MSG_LEN, true));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (8..MSG_LEN, true));
         assert_eq!(buf.get(8..MSG_LEN), SEG4);
         assert_eq!(buf.get(9..MSG_LEN), SEG5);
 
-        assert_eq!(buf.poll_transmit(42), (MSG_LEN..MSG_LEN, true));
+        assert_eq!(buf.poll_transmit(42, u64::MAX), (MSG_LEN..MSG_LEN, true));
 
         // Now drain the segments
         buf.ack(0..1);
@@ -348,16 +390,39 @@ mod tests {
         const MSG: &[u8] = b"Hello, world with extra data!";
         buf.write(MSG.into());
         // Transmit two frames
-        assert_eq!(buf.poll_transmit(16), (0..16, false));
-        assert_eq!(buf.poll_transmit(16), (16..23, true));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..16, false));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (16..23, true));
         // Lose the first, but not the second
         buf.retransmit(0..16);
         // Ensure we only retransmit the lost frame, then continue sending fresh data
-        assert_eq!(buf.poll_transmit(16), (0..16, false));
-        assert_eq!(buf.poll_transmit(16), (23..MSG.len() as u64, true));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..16, false));
+        assert_eq!(
+            buf.poll_transmit(16, u64::MAX),
+            (23..MSG.len() as u64, true)
+        );
         // Lose the second frame
         buf.retransmit(16..23);
-        assert_eq!(buf.poll_transmit(16), (16..23, true));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (16..23, true));
+    }
+
+    #[test]
+    fn skip() {
+        let mut buf = SendBuffer::new();
+        const MSG: &[u8] = b"Hello, world with extra data!";
+        buf.write(MSG.into());
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..16, false));
+        buf.retransmit(0..16);
+        // Abandoning data drops it from retransmissions and skips unsent data
+        buf.skip_to(20);
+        assert_eq!(buf.acked_prefix(), 20);
+        assert_eq!(aggregate_unacked(&buf), &MSG[20..]);
+        assert_eq!(
+            buf.poll_transmit(24, u64::MAX),
+            (20..MSG.len() as u64, true)
+        );
+        // Losses of abandoned data are ignored
+        buf.retransmit(0..16);
+        assert!(!buf.has_unsent_data(MSG.len() as u64));
     }
 
     #[test]
@@ -365,7 +430,7 @@ mod tests {
         let mut buf = SendBuffer::new();
         const MSG: &[u8] = b"Hello, world!";
         buf.write(MSG.into());
-        assert_eq!(buf.poll_transmit(16), (0..8, true));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..8, true));
         buf.ack(0..8);
         assert_eq!(aggregate_unacked(&buf), &MSG[8..]);
     }
@@ -375,8 +440,8 @@ mod tests {
         let mut buf = SendBuffer::new();
         const MSG: &[u8] = b"Hello, world with extra data!";
         buf.write(MSG.into());
-        assert_eq!(buf.poll_transmit(16), (0..16, false));
-        assert_eq!(buf.poll_transmit(16), (16..23, true));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (0..16, false));
+        assert_eq!(buf.poll_transmit(16, u64::MAX), (16..23, true));
         buf.ack(16..23);
         assert_eq!(aggregate_unacked(&buf), MSG);
         buf.ack(0..16);
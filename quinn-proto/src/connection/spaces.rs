@@ -322,6 +322,10 @@ pub struct Retransmits {
     pub(super) reset_stream: Vec<(StreamId, VarInt)>,
     pub(super) stop_sending: Vec<frame::StopSending>,
     pub(super) max_stream_data: FxHashSet<StreamId>,
+    /// Offsets below which partially reliable streams' data was abandoned
+    ///
+    /// An entry may be superseded by a later one for the same stream with a higher offset.
+    pub(super) stream_skip: Vec<(StreamId, u64)>,
     pub(super) crypto: VecDeque<frame::Crypto>,
     pub(super) new_cids: Vec<IssuedCid>,
     pub(super) retire_cids: Vec<u64>,
@@ -356,6 +360,7 @@ impl Retransmits {
                 .max_stream_data
                 .iter()
                 .all(|&id| !streams.can_send_flow_control(id))
+            && self.stream_skip.is_empty()
             && self.crypto.is_empty()
             && self.new_cids.is_empty()
             && self.retire_cids.is_empty()
@@ -377,6 +382,7 @@ impl ::std::ops::BitOrAssign for Retransmits {
         self.reset_stream.extend_from_slice(&rhs.reset_stream);
         self.stop_sending.extend_from_slice(&rhs.stop_sending);
         self.max_stream_data.extend(&rhs.max_stream_data);
+        self.stream_skip.extend_from_slice(&rhs.stream_skip);
         for crypto in rhs.crypto.into_iter().rev() {
             self.crypto.push_front(crypto);
         }
@@ -559,6 +565,19 @@ impl Dedup {
         }
     }
 
+    /// Whether `packet` is below the highest packet number authenticated, but wasn't itself
+    ///
+    /// Packets left of the window are assumed to have been authenticated.
+    fn is_missing(&self, packet: u64) -> bool {
+        let Some(offset) = self.highest().checked_sub(packet) else {
+            return false;
+        };
+        match offset.checked_sub(1) {
+            Some(bit) if bit < WINDOW_SIZE - 1 => self.window & (1 << bit) == 0,
+            _ => false,
+        }
+    }
+
     /// Returns true if there are any missing packets between the provided interval
     ///
     /// The provided packet numbers must have been received before calling this function
@@ -603,6 +622,17 @@ pub(super) struct PendingAcks {
     ack_eliciting_since_last_ack_sent: u64,
     non_ack_eliciting_since_last_ack_sent: u64,
     ack_eliciting_threshold: u64,
+    /// Largest `ack_eliciting_threshold` to back off to while no packets are lost, or 0 if the
+    /// threshold isn't adapted
+    ///
+    /// Disabled once the peer requests a threshold of its own through ACK_FREQUENCY.
+    adaptive_threshold_max: u64,
+    /// Ack-eliciting packets received since `ack_eliciting_threshold` last changed
+    loss_free_streak: u64,
+    /// Packet numbers below this were checked for gaps that persist beyond the reordering tolerance
+    gaps_checked: u64,
+    /// Whether the current loss-free streak already tolerated a persisting gap
+    gap_in_streak: bool,
     /// The reordering threshold, controlling how we respond to out-of-order ack-eliciting packets
     ///
     /// Different values enable different behavior:
@@ -633,6 +663,10 @@ impl PendingAcks {
             ack_eliciting_since_last_ack_sent: 0,
             non_ack_eliciting_since_last_ack_sent: 0,
             ack_eliciting_threshold: 1,
+            adaptive_threshold_max: 0,
+            loss_free_streak: 0,
+            gaps_checked: 0,
+            gap_in_streak: false,
             reordering_threshold: 1,
             earliest_ack_eliciting_since_last_ack_sent: None,
             ranges: ArrayRangeSet::default(),
@@ -645,6 +679,26 @@ impl PendingAcks {
     pub(super) fn set_ack_frequency_params(&mut self, frame: &frame::AckFrequency) {
         self.ack_eliciting_threshold = frame.ack_eliciting_threshold.into_inner();
         self.reordering_threshold = frame.reordering_threshold.into_inner();
+        self.adaptive_threshold_max = 0;
+    }
+
+    /// Raise the ack-eliciting threshold up to `max` while no packets are lost
+    pub(super) fn set_adaptive_threshold_max(&mut self, max: u64) {
+        self.adaptive_threshold_max = max;
+    }
+
+    /// Revert to acknowledging every other ack-eliciting packet after packets were declared lost
+    pub(super) fn on_packets_lost(&mut self) {
+        if self.adaptive_threshold_max > 0 {
+            self.ack_eliciting_threshold = 1;
+            self.loss_free_streak = 0;
+            self.gap_in_streak = false;
+        }
+    }
+
+    /// Number of ack-eliciting packets that may be received without sending an ACK immediately
+    pub(super) fn ack_eliciting_threshold(&self) -> u64 {
+        self.ack_eliciting_threshold
     }
 
     pub(super) fn set_immediate_ack_required(&mut self) {
@@ -681,6 +735,10 @@ impl PendingAcks {
         ack_eliciting: bool,
         dedup: &Dedup,
     ) -> bool {
+        if self.adaptive_threshold_max > 0 {
+            self.check_gaps(dedup);
+        }
+
         if !ack_eliciting {
             self.non_ack_eliciting_since_last_ack_sent += 1;
             return false;
@@ -694,6 +752,10 @@ impl PendingAcks {
             .map(|pn| pn.max(packet_number))
             .or(Some(packet_number));
 
+        if self.adaptive_threshold_max > 0 {
+            self.extend_loss_free_streak();
+        }
+
         // Handle ack_eliciting_threshold
         self.ack_eliciting_since_last_ack_sent += 1;
         self.immediate_ack_required |=
@@ -712,6 +774,48 @@ impl PendingAcks {
         false
     }
 
+    /// Acknowledge less often after a streak of packets received without any loss
+    ///
+    /// Each streak of [`LOSS_FREE_STREAK`] packets doubles the number of ack-eliciting packets
+    /// covered by one ACK, i.e. the threshold goes 1, 3, 7, 15... up to `adaptive_threshold_max`.
+    /// The `MaxAckDelay` timer still bounds how long an ACK is delayed.
+    ///
+    /// Gaps in the received packet numbers are acknowledged immediately, but the first one that
+    /// persists in a streak doesn't break it, as the peer may skip packet numbers on purpose. See
+    /// [`Self::check_gaps()`].
+    fn extend_loss_free_streak(&mut self) {
+        self.loss_free_streak += 1;
+        if self.loss_free_streak >= LOSS_FREE_STREAK {
+            self.loss_free_streak = 0;
+            self.gap_in_streak = false;
+            self.ack_eliciting_threshold =
+                (self.ack_eliciting_threshold * 2 + 1).min(self.adaptive_threshold_max);
+        }
+    }
+
+    /// Treat packets still missing once [`REORDERING_TOLERANCE`] later packets arrived as lost
+    ///
+    /// Peers only skip packet numbers once in a while, so a second gap in the same streak
+    /// reverts to acknowledging every other packet, like losses detected by our own sender do.
+    fn check_gaps(&mut self, dedup: &Dedup) {
+        let tolerance = self.reordering_threshold.max(REORDERING_TOLERANCE);
+        let end = dedup.next.saturating_sub(tolerance);
+        // Packets left of the window are assumed to have been received
+        let start = self.gaps_checked.max(end.saturating_sub(WINDOW_SIZE));
+        for packet in start..end {
+            if !dedup.is_missing(packet) {
+                continue;
+            }
+            if !self.gap_in_streak {
+                self.gap_in_streak = true;
+                continue;
+            }
+            trace!(packet, "received packet persistently missing");
+            self.on_packets_lost();
+        }
+        self.gaps_checked = self.gaps_checked.max(end);
+    }
+
     fn is_out_of_order(
         &self,
         packet_number: u64,
@@ -902,6 +1006,13 @@ impl PacketNumberFilter {
 /// Ensures we can always fit all our ACKs in a single minimum-MTU packet with room to spare
 const MAX_ACK_BLOCKS: usize = 64;
 
+/// Number of ack-eliciting packets received without loss after which ACKs are sent less often
+const LOSS_FREE_STREAK: u64 = 64;
+
+/// Number of later packets after which a missing packet is considered lost, following the packet
+/// threshold of RFC 9002 §6.1.1
+const REORDERING_TOLERANCE: u64 = 3;
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1057,6 +1168,86 @@ mod test {
         assert!(acks.can_send());
     }
 
+    #[test]
+    fn pending_acks_adaptive_threshold() {
+        let mut acks = PendingAcks::new();
+        let mut dedup = Dedup::new();
+        acks.set_adaptive_threshold_max(10);
+        let now = Instant::now();
+        let mut receive = |acks: &mut PendingAcks, pn: u64| {
+            dedup.insert(pn);
+            acks.insert_one(pn, now);
+            acks.packet_received(now, pn, true, &dedup);
+            let ack = acks.can_send();
+            if ack {
+                acks.acks_sent();
+            }
+            ack
+        };
+
+        for pn in 0..LOSS_FREE_STREAK {
+            assert_eq!(acks.ack_eliciting_threshold(), 1);
+            receive(&mut acks, pn);
+        }
+        assert_eq!(acks.ack_eliciting_threshold(), 3);
+        let acks_sent = (LOSS_FREE_STREAK..3 * LOSS_FREE_STREAK)
+            .filter(|&pn| receive(&mut acks, pn))
+            .count();
+        assert!(acks_sent < LOSS_FREE_STREAK as usize / 2);
+        // Capped at the configured maximum
+        assert_eq!(acks.ack_eliciting_threshold(), 10);
+
+        // A gap, e.g. a skipped packet number, is acknowledged right away, but isn't a loss even
+        // once it persists
+        assert!(receive(&mut acks, 3 * LOSS_FREE_STREAK + 1));
+        for pn in 3 * LOSS_FREE_STREAK + 2..3 * LOSS_FREE_STREAK + 10 {
+            receive(&mut acks, pn);
+        }
+        assert_eq!(acks.ack_eliciting_threshold(), 10);
+
+        // Loss reverts to acknowledging every other packet
+        acks.on_packets_lost();
+        assert_eq!(acks.ack_eliciting_threshold(), 1);
+        // Packets already waiting for an ACK are acknowledged along with the next one
+        assert!(receive(&mut acks, 3 * LOSS_FREE_STREAK + 10));
+        assert!(!receive(&mut acks, 3 * LOSS_FREE_STREAK + 11));
+        assert!(receive(&mut acks, 3 * LOSS_FREE_STREAK + 12));
+    }
+
+    #[test]
+    fn pending_acks_adaptive_threshold_receive_loss() {
+        let mut acks = PendingAcks::new();
+        let mut dedup = Dedup::new();
+        acks.set_adaptive_threshold_max(10);
+        let now = Instant::now();
+        let mut receive = |acks: &mut PendingAcks, pn: u64| {
+            dedup.insert(pn);
+            acks.insert_one(pn, now);
+            acks.packet_received(now, pn, true, &dedup);
+            if acks.can_send() {
+                acks.acks_sent();
+            }
+        };
+
+        for pn in 0..LOSS_FREE_STREAK {
+            receive(&mut acks, pn);
+        }
+        assert_eq!(acks.ack_eliciting_threshold(), 3);
+
+        // Two packets go missing, but may still be reordered
+        let lost = [LOSS_FREE_STREAK, LOSS_FREE_STREAK + 2];
+        for pn in LOSS_FREE_STREAK + 1..lost[1] + REORDERING_TOLERANCE {
+            if !lost.contains(&pn) {
+                receive(&mut acks, pn);
+            }
+        }
+        assert_eq!(acks.ack_eliciting_threshold(), 3);
+
+        // Once they're beyond the reordering tolerance, they're considered lost
+        receive(&mut acks, lost[1] + REORDERING_TOLERANCE);
+        assert_eq!(acks.ack_eliciting_threshold(), 1);
+    }
+
     #[test]
     fn pending_acks_ack_delay() {
         let mut acks = PendingAcks::new();
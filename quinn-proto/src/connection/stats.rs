@@ -1,11 +1,14 @@
 //! Connection statistics
 
-use crate::{Dir, Duration, frame::Frame};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{Dir, Duration, Side, congestion::ControllerMetrics, frame::Frame, packet::SpaceId};
 
 /// Statistics about UDP datagrams transmitted or received on a connection
 ///
 /// All QUIC packets are carried by UDP datagrams. Hence, these statistics cover all traffic on a connection.
 #[derive(Default, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[non_exhaustive]
 pub struct UdpStats {
     /// The amount of UDP datagrams observed
@@ -28,6 +31,7 @@ impl UdpStats {
 
 /// Number of frames transmitted or received of each frame type
 #[derive(Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[non_exhaustive]
 #[allow(missing_docs)]
 pub struct FrameStats {
@@ -55,6 +59,7 @@ pub struct FrameStats {
     pub streams_blocked_uni: u64,
     pub stop_sending: u64,
     pub stream: u64,
+    pub stream_skip: u64,
 }
 
 impl FrameStats {
@@ -94,6 +99,7 @@ impl FrameStats {
             Frame::Close(_) => self.connection_close += 1,
             Frame::AckFrequency(_) => self.ack_frequency += 1,
             Frame::ImmediateAck => self.immediate_ack += 1,
+            Frame::StreamSkip(_) => self.stream_skip += 1,
             Frame::HandshakeDone => self.handshake_done = self.handshake_done.saturating_add(1),
         }
     }
@@ -126,20 +132,43 @@ impl std::fmt::Debug for FrameStats {
             .field("STREAMS_BLOCKED_UNI", &self.streams_blocked_uni)
             .field("STOP_SENDING", &self.stop_sending)
             .field("STREAM", &self.stream)
+            .field("STREAM_SKIP", &self.stream_skip)
             .finish()
     }
 }
 
 /// Statistics related to a transmission path
 #[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[non_exhaustive]
 pub struct PathStats {
     /// Current best estimate of this connection's latency (round-trip-time)
     pub rtt: Duration,
+    /// Smallest round-trip time measured on this path
+    ///
+    /// Taken over every sample since the path was established, rather than a sliding window, so it
+    /// never increases. Samples aren't adjusted for the peer's acknowledgement delay. Migrating to
+    /// a new path starts a fresh measurement unless the new path looks like a NAT rebinding.
+    pub min_rtt: Duration,
+    /// Variation of the round-trip time, i.e. `rttvar` as described in RFC 9002
+    ///
+    /// An exponentially weighted mean of how far each sample deviated from the smoothed
+    /// [`rtt`](Self::rtt), useful as a measure of jitter.
+    pub rtt_variance: Duration,
+    /// Most recent round-trip time sample, without smoothing or adjustment for ack delay
+    pub latest_rtt: Duration,
     /// Current congestion window of the connection
     pub cwnd: u64,
     /// Bytes of packets in flight (sent but not yet acknowledged or declared lost)
+    ///
+    /// An instantaneous gauge as of the time the statistics were taken, rather than a cumulative
+    /// counter. See [`Connection::bytes_in_flight`](crate::Connection::bytes_in_flight).
     pub bytes_in_flight: u64,
+    /// Bytes of stream data deemed lost and waiting to be retransmitted
+    ///
+    /// An instantaneous gauge like [`bytes_in_flight`](Self::bytes_in_flight). See
+    /// [`Connection::bytes_pending_retransmit`](crate::Connection::bytes_pending_retransmit).
+    pub bytes_pending_retransmit: u64,
     /// Congestion events on the connection
     pub congestion_events: u64,
     /// The amount of packets lost on this path
@@ -155,12 +184,86 @@ pub struct PathStats {
     pub lost_plpmtud_probes: u64,
     /// The number of times a black hole was detected in the path
     pub black_holes_detected: u64,
+    /// The number of times an ICMP "packet too big" message lowered the MTU of the path
+    ///
+    /// See [`MtuDiscoveryConfig::packet_too_big`](crate::MtuDiscoveryConfig::packet_too_big).
+    pub icmp_mtu_reductions: u64,
+    /// Number of migrations to a new remote address
+    ///
+    /// Counts both the peer moving to a new address, and a client moving to the server's
+    /// preferred address (see
+    /// [`Connection::migrate_to_preferred_address`](crate::Connection::migrate_to_preferred_address)).
+    /// Changes of our own address, reported with
+    /// [`Connection::local_address_changed`](crate::Connection::local_address_changed), aren't
+    /// counted, as the peer sees those as migrations instead and validates the new path.
+    ///
+    /// Migrations initiated by the peer can be rate-limited with
+    /// [`TransportConfig::migration_cooldown`](crate::TransportConfig::migration_cooldown).
+    pub migration_attempts: u64,
+    /// Number of migrations whose new path was validated
+    ///
+    /// Migrations that fail validation fall back to the previous path.
+    pub migration_successes: u64,
     /// Largest UDP payload size the path currently supports
+    ///
+    /// Measured in bytes of UDP payload, i.e. excluding IP and UDP headers. This is the largest
+    /// size confirmed by path MTU discovery, or the initial MTU if none was confirmed yet.
     pub current_mtu: u16,
+    /// State of path MTU discovery on this path
+    pub mtu_search_state: MtuSearchState,
+    /// Bytes that may be sent before the peer's address is validated, if it isn't yet
+    ///
+    /// See [`TransportConfig::amplification_factor`](crate::TransportConfig::amplification_factor).
+    pub anti_amplification_credit: Option<u64>,
+    /// Number of times HyStart++ ended slow start because the round-trip time started increasing
+    ///
+    /// Only reported by congestion controllers that implement HyStart++, such as
+    /// [`Cubic`](crate::congestion::Cubic) with [`CubicConfig::hystart`] enabled.
+    ///
+    /// [`CubicConfig::hystart`]: crate::congestion::CubicConfig::hystart
+    pub hystart_exits: u64,
+    /// Time after which an unacknowledged packet is declared lost once a later one is acknowledged
+    ///
+    /// Derived from the RTT and the time threshold, which widens in response to reordering if
+    /// [`TransportConfig::max_time_threshold`](crate::TransportConfig::max_time_threshold) is set.
+    pub reordering_window: Duration,
+    /// Number of received packets marked ECT(0)
+    ///
+    /// The ECN codepoints of received packets are counted as reported by the socket, once a packet
+    /// is authenticated, so these counters are unaffected by whether the peer's ECN usage passed
+    /// validation or by how codepoints are reported back to it. Packets whose codepoint the
+    /// platform can't read are counted as Not-ECT.
+    pub received_ect0: u64,
+    /// Number of received packets marked ECT(1)
+    pub received_ect1: u64,
+    /// Number of received packets marked Congestion Experienced (CE)
+    pub received_ce: u64,
+    /// Number of received packets not marked ECN-capable (Not-ECT)
+    pub received_not_ect: u64,
+}
+
+/// State of Datagram Packetization Layer Path MTU Discovery (DPLPMTUD) on a path
+///
+/// See [`MtuDiscoveryConfig`](crate::MtuDiscoveryConfig).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum MtuSearchState {
+    /// MTU discovery is disabled, so the MTU only changes when a black hole is detected
+    #[default]
+    Disabled,
+    /// Probing for a larger MTU, or about to start doing so
+    Searching,
+    /// The search completed, and will be repeated after the configured interval
+    Done,
+    /// A black hole was detected, so the MTU was reset to the minimum, and searching will resume
+    /// after the configured cooldown
+    BlackHole,
 }
 
 /// Connection-level flow control diagnostics
 #[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[non_exhaustive]
 pub struct FlowControlStats {
     /// Total stream data bytes sent (cumulative, never decreases)
@@ -171,10 +274,131 @@ pub struct FlowControlStats {
     pub unacked_data: u64,
     /// Local congestion send window (mirrors cwnd)
     pub send_window: u64,
+    /// Current connection-level receive window
+    ///
+    /// Starts out as [`TransportConfig::receive_window`](crate::TransportConfig::receive_window),
+    /// and may grow up to
+    /// [`TransportConfig::max_receive_window`](crate::TransportConfig::max_receive_window).
+    pub receive_window: u64,
+}
+
+/// Number of received packets dropped in each packet number space
+///
+/// 0-RTT packets are counted in `data`.
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct DroppedPacketStats {
+    /// Packets in the Initial space
+    pub initial: u64,
+    /// Packets in the Handshake space
+    pub handshake: u64,
+    /// 0-RTT and 1-RTT packets
+    pub data: u64,
+}
+
+impl DroppedPacketStats {
+    pub(crate) fn record(&mut self, space: SpaceId) {
+        match space {
+            SpaceId::Initial => self.initial += 1,
+            SpaceId::Handshake => self.handshake += 1,
+            SpaceId::Data => self.data += 1,
+        }
+    }
+}
+
+/// Events where connection IDs ran short
+///
+/// Each side issues the other connection IDs up to the peer's `active_connection_id_limit`
+/// transport parameter (see [`TransportConfig::active_connection_id_limit`]), and switches to an
+/// unused one of the peer's when migrating or rotating connection IDs. Running short is harmless:
+/// the connection keeps using the connection IDs it has until more become available, but
+/// frequent occurrences mean migrations and rotations may be linkable.
+///
+/// [`TransportConfig::active_connection_id_limit`]: crate::TransportConfig::active_connection_id_limit
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct ConnectionIdStats {
+    /// Times a new connection ID couldn't be issued because the peer's limit was reached
+    ///
+    /// Issuing resumes once the peer retires one of the connection IDs it holds.
+    pub local_limit_reached: u64,
+    /// Times a fresh connection ID of the peer's was needed, but none was available
+    ///
+    /// The current connection ID is then kept until the peer issues more.
+    pub remote_exhausted: u64,
+}
+
+/// Received application datagrams which were discarded before the application read them
+///
+/// See [`Datagrams::pause_recv`](crate::Datagrams::pause_recv).
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct DatagramDropStats {
+    /// Datagrams discarded to make room for newer ones in a full receive buffer
+    ///
+    /// See [`TransportConfig::datagram_receive_buffer_size`](crate::TransportConfig::datagram_receive_buffer_size).
+    pub buffer_full: u64,
+    /// Datagrams discarded on arrival because receiving was paused
+    pub paused: u64,
+    /// Total payload size of all discarded datagrams, in bytes
+    pub bytes: u64,
+}
+
+/// Loss recovery activity in a single packet number space
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct SpaceRecoveryStats {
+    /// Packets declared lost, whose frames are then retransmitted as needed
+    pub lost_packets: u64,
+    /// Packets declared lost because enough later packets were acknowledged
+    ///
+    /// See [`TransportConfig::packet_threshold`](crate::TransportConfig::packet_threshold).
+    /// Included in `lost_packets`.
+    pub lost_by_packet_threshold: u64,
+    /// Packets declared lost because a later packet was acknowledged long enough after they were
+    /// sent
+    ///
+    /// See [`TransportConfig::time_threshold`](crate::TransportConfig::time_threshold). Packets
+    /// that also meet the packet threshold are counted by `lost_by_packet_threshold` instead.
+    /// Included in `lost_packets`.
+    pub lost_by_time_threshold: u64,
+    /// Probe timeouts that fired for this space
+    pub ptos: u64,
+}
+
+/// Loss recovery activity in each packet number space
+///
+/// Losses in the Initial and Handshake spaces delay connection establishment, so they are counted
+/// separately from those affecting application data. 0-RTT packets are counted in `data`.
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct RecoveryStats {
+    /// Packets in the Initial space
+    pub initial: SpaceRecoveryStats,
+    /// Packets in the Handshake space
+    pub handshake: SpaceRecoveryStats,
+    /// 0-RTT and 1-RTT packets
+    pub data: SpaceRecoveryStats,
+}
+
+impl RecoveryStats {
+    pub(crate) fn space_mut(&mut self, space: SpaceId) -> &mut SpaceRecoveryStats {
+        match space {
+            SpaceId::Initial => &mut self.initial,
+            SpaceId::Handshake => &mut self.handshake,
+            SpaceId::Data => &mut self.data,
+        }
+    }
 }
 
 /// Connection statistics
 #[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[non_exhaustive]
 pub struct ConnectionStats {
     /// Statistics about UDP datagrams transmitted on a connection
@@ -189,4 +413,110 @@ pub struct ConnectionStats {
     pub path: PathStats,
     /// Connection-level flow control diagnostics
     pub flow_control: FlowControlStats,
+    /// Received packets dropped because their header protection could not be removed
+    ///
+    /// Includes packets that are truncated or otherwise too malformed to decode.
+    pub header_protection_failures: DroppedPacketStats,
+    /// Received packets dropped because they failed AEAD authentication
+    ///
+    /// Persistent failures may indicate corruption on the network path, or a peer using the wrong
+    /// keys. Such packets are otherwise silently ignored, unless enough accumulate to exceed the
+    /// integrity limit of the cipher suite in use.
+    pub decryption_failures: DroppedPacketStats,
+    /// Packet losses and probe timeouts in each packet number space
+    ///
+    /// Losses are also counted by [`PathStats::lost_packets`], regardless of space.
+    pub recovery: RecoveryStats,
+    /// Events where connection IDs ran short
+    pub cids: ConnectionIdStats,
+    /// Received application datagrams discarded before the application read them
+    pub datagram_drops: DatagramDropStats,
+    /// Packets received in the same UDP datagram as a preceding packet
+    ///
+    /// Senders coalesce packets into one datagram to save overhead, most commonly during the
+    /// handshake. Counts every packet after the first of a datagram whose header could be parsed,
+    /// regardless of whether it could then be decrypted.
+    pub coalesced_packets_received: u64,
+    /// Times a send stream with data pending waited too long to be scheduled
+    ///
+    /// A wait is measured in packets carrying data of other streams, and counted once when it
+    /// reaches [`TransportConfig::stream_starvation_threshold`]. Under strict priorities, a stream
+    /// waits for as long as any stream of higher priority has data pending, so a busy stream of
+    /// higher priority readily starves the others. With [`TransportConfig::weighted_priorities`]
+    /// or among streams of the same priority, it only rises when more streams compete than the
+    /// threshold allows for.
+    ///
+    /// [`TransportConfig::stream_starvation_threshold`]: crate::TransportConfig::stream_starvation_threshold
+    /// [`TransportConfig::weighted_priorities`]: crate::TransportConfig::weighted_priorities
+    pub starved_streams: u64,
+    /// Number of ack-eliciting packets we currently receive before acknowledging them immediately
+    ///
+    /// 1 by default, i.e. every other ack-eliciting packet is acknowledged. Raised while no packets
+    /// are lost if [`TransportConfig::adaptive_ack_eliciting_threshold`] is set, or set
+    /// by the peer through the acknowledgement frequency extension.
+    ///
+    /// [`TransportConfig::adaptive_ack_eliciting_threshold`]: crate::TransportConfig::adaptive_ack_eliciting_threshold
+    pub ack_eliciting_threshold: u64,
+    /// Time from the creation of the connection until it sent its first packet
+    ///
+    /// For servers, this includes the time taken to accept the incoming connection and produce
+    /// the first flight of the handshake. `None` until the first packet is sent. Set once, and
+    /// stable afterward.
+    pub time_to_first_flight: Option<Duration>,
+    /// Time from the creation of the connection until its handshake completed
+    ///
+    /// `None` while the handshake is in progress. Set once when the handshake completes, and
+    /// stable afterward.
+    pub handshake_duration: Option<Duration>,
+    /// Whether 0-RTT packets were sent (for clients) or received (for servers) during the
+    /// handshake
+    ///
+    /// Only ever changes from `false` to `true` before the handshake completes.
+    pub used_0rtt: bool,
+    /// Whether 0-RTT was accepted by the server
+    ///
+    /// Meaningless until the handshake completes, after which it is stable. May be `true` even if
+    /// no 0-RTT packets were exchanged; see `used_0rtt`.
+    pub zero_rtt_accepted: bool,
+}
+
+/// Snapshot of a connection's internal state for debugging
+///
+/// Returned by [`Connection::debug_snapshot()`](crate::Connection::debug_snapshot). Intended to be
+/// logged through its `Debug` implementation, or serialized with the `serde` feature. Taking a
+/// snapshot doesn't visit individual streams or packets, so it's cheap enough to do on error
+/// paths.
+///
+/// The set of fields and their meaning are not part of the stable API, and may change in any
+/// release.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct ConnectionDebug {
+    /// Whether we are the client or the server
+    pub side: Side,
+    /// Lifecycle state: `"handshake"`, `"established"`, `"closed"`, `"draining"` or `"drained"`
+    pub state: &'static str,
+    /// The peer's current address
+    pub remote_address: SocketAddr,
+    /// The local IP address used for the connection, if known
+    pub local_ip: Option<IpAddr>,
+    /// Statistics as returned by [`Connection::stats()`](crate::Connection::stats)
+    pub stats: ConnectionStats,
+    /// Metrics reported by the congestion controller
+    pub congestion: ControllerMetrics,
+    /// Probe timeouts since the last acknowledgement was received
+    pub pto_count: u32,
+    /// Idle timeout negotiated with the peer
+    pub idle_timeout: Option<Duration>,
+    /// Streams that may have unacknowledged data
+    pub send_streams: usize,
+    /// Open streams initiated by the peer, bidirectional and unidirectional
+    pub remote_open_streams: [u64; 2],
+    /// Stream data received from the peer
+    pub data_received: u64,
+    /// Connection-level flow control limit advertised to the peer (our MAX_DATA)
+    pub local_max_data: u64,
+    /// Armed timers, with the time remaining until each expires
+    pub pending_timers: Vec<(&'static str, Duration)>,
 }
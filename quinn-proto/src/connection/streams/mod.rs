@@ -9,7 +9,7 @@ use tracing::trace;
 
 use super::spaces::{Retransmits, ThinRetransmits};
 use crate::{
-    Dir, StreamId, VarInt,
+    Dir, Instant, StreamId, VarInt,
     connection::streams::state::{get_or_insert_recv, get_or_insert_send},
     frame,
 };
@@ -21,7 +21,7 @@ pub use recv::{Chunks, ReadError, ReadableError};
 mod send;
 pub(crate) use send::{ByteSlice, BytesArray};
 use send::{BytesSource, Send, SendState};
-pub use send::{FinishError, WriteError, Written};
+pub use send::{FinishError, PartialReliability, WriteError, WriteStallReason, Written};
 
 mod state;
 #[allow(unreachable_pub)] // fuzzing only
@@ -60,6 +60,18 @@ impl<'a> Streams<'a> {
         Some(id)
     }
 
+    /// Number of streams of a certain directionality that [`open`](Self::open) could open now
+    ///
+    /// This is the credit left under the peer's most recent `MAX_STREAMS` grant, or 0 if the
+    /// connection is closed. The peer grants more credit as streams it accepted are finished,
+    /// so this is only a snapshot.
+    pub fn available(&self, dir: Dir) -> u64 {
+        if self.conn_state.is_closed() {
+            return 0;
+        }
+        self.state.max[dir as usize].saturating_sub(self.state.next[dir as usize])
+    }
+
     /// Accept a remotely initiated stream of a certain directionality, if possible
     ///
     /// Returns `None` if there are no new incoming streams for this connection.
@@ -75,7 +87,11 @@ impl<'a> Streams<'a> {
             self.state.send_streams += 1;
         }
 
-        Some(StreamId::new(!self.state.side, dir, x))
+        let id = StreamId::new(!self.state.side, dir, x);
+        if self.state.max_unhandled.is_some() {
+            self.state.unhandled.insert(id);
+        }
+        Some(id)
     }
 
     #[cfg(fuzzing)]
@@ -134,11 +150,12 @@ impl RecvStream<'_> {
     /// Discards unread data and notifies the peer to stop transmitting. Once stopped, further
     /// attempts to operate on a stream will yield `ClosedStream` errors.
     pub fn stop(&mut self, error_code: VarInt) -> Result<(), ClosedStream> {
+        let window = self.state.stream_receive_window(self.id);
         let mut entry = match self.state.recv.entry(self.id) {
             hash_map::Entry::Occupied(s) => s,
             hash_map::Entry::Vacant(_) => return Err(ClosedStream { _private: () }),
         };
-        let stream = get_or_insert_recv(self.state.stream_receive_window)(entry.get_mut());
+        let stream = get_or_insert_recv(window)(entry.get_mut());
 
         let (read_credits, stop_sending) = stream.stop()?;
         if stop_sending.should_transmit() {
@@ -163,6 +180,17 @@ impl RecvStream<'_> {
         Ok(())
     }
 
+    /// Indicate that the application has finished handling this stream
+    ///
+    /// Only meaningful for accepted streams when
+    /// [`TransportConfig::max_unhandled_streams`](crate::TransportConfig::max_unhandled_streams) is
+    /// set, in which case it may allow the peer to open another stream. Has no effect if called
+    /// more than once.
+    pub fn handled(&mut self) {
+        self.state.stream_handled(self.id);
+        self.state.queue_max_stream_id(self.pending);
+    }
+
     /// Check whether this stream has been reset by the peer, returning the reset error code if so
     ///
     /// After returning `Ok(Some(_))` once, stream state will be discarded and all future calls will
@@ -190,6 +218,54 @@ impl RecvStream<'_> {
 
         Ok(Some(code))
     }
+
+    /// Number of bytes of stream data received from the peer
+    ///
+    /// Counts application data, excluding framing and packet overhead, up to the highest offset
+    /// received so far, which is also what the stream consumes of the connection's flow control
+    /// window. Data received more than once is only counted once, but data still missing
+    /// before that offset due to loss or reordering is included.
+    pub fn bytes_received(&self) -> Result<u64, ClosedStream> {
+        let stream = self
+            .state
+            .recv
+            .get(&self.id)
+            .ok_or(ClosedStream { _private: () })?;
+
+        Ok(stream
+            .as_ref()
+            .and_then(|s| s.as_open_recv())
+            .map_or(0, |s| s.end))
+    }
+
+    /// Copy received data starting at `offset` into `buf` without consuming it
+    ///
+    /// Yields the number of contiguous bytes copied, or `None` if the data at `offset` hasn't been
+    /// received yet. Data that was already read through [`read`](Self::read), or discarded because
+    /// the stream was reset, is no longer available and also yields `None`. Copying stops at the
+    /// first gap in the received data or when `buf` is full.
+    ///
+    /// This doesn't advance the stream or issue flow control credit. Received data stays buffered
+    /// until it is consumed by `read`, so an application relying on `read_at` must still read the
+    /// stream to keep the peer from being blocked by the stream receive window.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<Option<usize>, ClosedStream> {
+        let stream = self
+            .state
+            .recv
+            .get(&self.id)
+            .ok_or(ClosedStream { _private: () })?;
+        let Some(stream) = stream.as_ref().and_then(|s| s.as_open_recv()) else {
+            return Ok(None);
+        };
+        if stream.stopped {
+            return Err(ClosedStream { _private: () });
+        }
+
+        match stream.assembler.peek(offset, buf) {
+            0 if !buf.is_empty() => Ok(None),
+            n => Ok(Some(n)),
+        }
+    }
 }
 
 /// Access to streams
@@ -224,6 +300,32 @@ impl<'a> SendStream<'a> {
         Ok(self.write_source(&mut ByteSlice::from_slice(data))?.bytes)
     }
 
+    /// Send data on the given stream, giving up on it if it isn't acknowledged by `deadline`
+    ///
+    /// Behaves like [`write`](Self::write), except that if the written data and everything before
+    /// it hasn't been acknowledged by the peer when `deadline` passes, the stream is reset with
+    /// `error_code` rather than retransmitting it further. This trades the stream's reliability
+    /// for timeliness, which suits e.g. real-time media where late data is useless: the peer
+    /// receives a RESET_STREAM, and any data it hasn't yet read is lost.
+    ///
+    /// Returns the number of bytes successfully written, all of which are subject to the deadline.
+    pub fn write_with_deadline(
+        &mut self,
+        data: &[u8],
+        deadline: Instant,
+        error_code: VarInt,
+    ) -> Result<usize, WriteError> {
+        let written = self.write(data)?;
+        if let Some(Some(stream)) = self.state.send.get_mut(&self.id) {
+            stream.set_deadline(deadline, error_code);
+            self.state.next_deadline = Some(match self.state.next_deadline {
+                Some(next) => next.min(deadline),
+                None => deadline,
+            });
+        }
+        Ok(written)
+    }
+
     /// Send data on the given stream
     ///
     /// Returns the number of bytes and chunks successfully written.
@@ -234,6 +336,20 @@ impl<'a> SendStream<'a> {
         self.write_source(&mut BytesArray::from_chunks(data))
     }
 
+    /// Send data on the given stream without copying it
+    ///
+    /// The stream keeps a reference to `data`'s underlying buffer, rather than copying it into an
+    /// internal buffer. Both the initial transmission and any retransmissions are read from that
+    /// buffer, and the reference is dropped once the peer has acknowledged everything it covers.
+    /// Until then, the whole allocation backing `data` stays alive, even if only a small part of
+    /// it remains unacknowledged, so large buffers may be held for several round trips.
+    ///
+    /// Returns the number of leading bytes of `data` accepted under flow control. The rest can be
+    /// written later with e.g. `data.slice(n..)`, which also doesn't copy.
+    pub fn write_owned(&mut self, data: Bytes) -> Result<usize, WriteError> {
+        Ok(self.write_chunks(&mut [data])?.bytes)
+    }
+
     fn write_source<B: BytesSource>(&mut self, source: &mut B) -> Result<Written, WriteError> {
         if self.conn_state.is_closed() {
             trace!(%self.id, "write blocked; connection draining");
@@ -273,12 +389,189 @@ impl<'a> SendStream<'a> {
         self.state.data_sent += written.bytes as u64;
         self.state.unacked_data += written.bytes as u64;
         trace!(stream = %self.id, "wrote {} bytes", written.bytes);
-        if !was_pending {
+        if self.state.peer_partial_reliability {
+            if let Some(offset) = stream.stale_offset(None) {
+                trace!(stream = %self.id, offset, "abandoning stale data");
+                self.state.unacked_data -= stream.skip_to(offset);
+                self.pending.stream_skip.push((self.id, offset));
+            }
+        }
+        if !was_pending && stream.is_pending() {
             self.state.pending.push_pending(self.id, stream.priority);
         }
         Ok(written)
     }
 
+    /// Coalesce small writes, holding back written data until `threshold` bytes have accumulated
+    ///
+    /// By default, data is framed and sent as soon as the connection is next polled for
+    /// transmission, so that many small writes in quick succession may each produce a separate
+    /// STREAM frame. While coalescing, written data is instead held back until at least
+    /// `threshold` bytes are waiting, or [`flush`](Self::flush) or [`finish`](Self::finish) is
+    /// called, so that it's sent in fewer, larger frames. Passing `None` flushes the stream and
+    /// restores the default behavior.
+    ///
+    /// This trades latency for framing overhead: held back data waits for the application to
+    /// flush it, however long that takes. Retransmissions of lost data and data written before
+    /// the last flush are never held back, and never carry held back data along with them.
+    pub fn set_write_coalescing(&mut self, threshold: Option<u64>) -> Result<(), ClosedStream> {
+        let max_send_data = self.state.max_send_data(self.id);
+        let stream = self
+            .state
+            .send
+            .get_mut(&self.id)
+            .map(get_or_insert_send(max_send_data))
+            .ok_or(ClosedStream { _private: () })?;
+
+        let was_pending = stream.is_pending();
+        stream.set_coalesce_threshold(threshold);
+        if !was_pending && stream.is_pending() {
+            self.state.pending.push_pending(self.id, stream.priority);
+        }
+        Ok(())
+    }
+
+    /// Make the stream partially reliable, abandoning data rather than retransmitting it once it's
+    /// stale according to `limit`
+    ///
+    /// Real-time applications such as media streaming may prefer losing old data to waiting for
+    /// it. When data goes stale, it's no longer sent or retransmitted, and the peer is told to
+    /// skip over it, so it may keep reading newer data without stalling. The skipped ranges show
+    /// up as gaps in the offsets of [`Chunk`](crate::Chunk)s read from the stream; reads that copy
+    /// data into a buffer silently leave them out. Passing `None` keeps all data written from now
+    /// on until it's acknowledged.
+    ///
+    /// This is a Quinn extension, which only takes effect if the peer enabled
+    /// [`TransportConfig::partial_reliability`](crate::TransportConfig::partial_reliability),
+    /// as reported by [`TransportParameters::partial_reliability`] once the peer's parameters are
+    /// known. Otherwise, the stream remains fully reliable.
+    ///
+    /// [`TransportParameters::partial_reliability`]: crate::transport_parameters::TransportParameters::partial_reliability
+    pub fn set_partial_reliability(
+        &mut self,
+        limit: Option<PartialReliability>,
+    ) -> Result<(), ClosedStream> {
+        let max_send_data = self.state.max_send_data(self.id);
+        let stream = self
+            .state
+            .send
+            .get_mut(&self.id)
+            .map(get_or_insert_send(max_send_data))
+            .ok_or(ClosedStream { _private: () })?;
+
+        stream.set_partial_reliability(limit);
+        Ok(())
+    }
+
+    /// Send all data held back by [`set_write_coalescing`](Self::set_write_coalescing)
+    ///
+    /// Has no effect if writes aren't being coalesced.
+    pub fn flush(&mut self) -> Result<(), ClosedStream> {
+        let stream = match self.state.send.get_mut(&self.id) {
+            Some(Some(stream)) => stream,
+            Some(None) => return Ok(()),
+            None => return Err(ClosedStream { _private: () }),
+        };
+
+        let was_pending = stream.is_pending();
+        stream.flush();
+        if !was_pending && stream.is_pending() {
+            self.state.pending.push_pending(self.id, stream.priority);
+        }
+        Ok(())
+    }
+
+    /// Set thresholds on the amount of data written to the stream but not yet acknowledged
+    ///
+    /// Once at least `high` bytes are unacknowledged, [`writable`](Self::writable) fails with
+    /// [`WriteError::Blocked`] until no more than `low` bytes are, at which point a
+    /// [`StreamEvent::Writable`] event is emitted. This lets a producer pause generating data while
+    /// the peer catches up, and resume before the stream runs dry.
+    ///
+    /// Watermarks are purely advisory: writes are never refused because of them, and remain
+    /// subject to flow and congestion control regardless. Because unacknowledged data includes
+    /// data blocked by congestion control, `high` is reached sooner on a congested path. Conversely,
+    /// if `high` exceeds the data flow control permits to be outstanding, writes block before it
+    /// is ever reached.
+    ///
+    /// # Panics
+    /// - when `low` is greater than `high`
+    ///
+    /// [`StreamEvent::Writable`]: crate::StreamEvent::Writable
+    pub fn set_watermarks(&mut self, low: u64, high: u64) -> Result<(), ClosedStream> {
+        assert!(low <= high, "low watermark exceeds high watermark");
+        let max_send_data = self.state.max_send_data(self.id);
+        let stream = self
+            .state
+            .send
+            .get_mut(&self.id)
+            .map(get_or_insert_send(max_send_data))
+            .ok_or(ClosedStream { _private: () })?;
+
+        stream.set_watermarks(low, high);
+        Ok(())
+    }
+
+    /// Check whether the application should write more data, according to the watermarks
+    ///
+    /// Fails with [`WriteError::Blocked`] while the watermarks set by
+    /// [`set_watermarks`](Self::set_watermarks) call for a pause, and otherwise with the error a
+    /// write would currently fail with, if any.
+    pub fn writable(&self) -> Result<(), WriteError> {
+        let stream = match self.state.send.get(&self.id) {
+            Some(Some(stream)) => stream,
+            Some(None) => return Ok(()),
+            None => return Err(WriteError::ClosedStream),
+        };
+        if !stream.is_writable() {
+            return Err(WriteError::ClosedStream);
+        }
+        if let Some(error_code) = stream.stop_reason {
+            return Err(WriteError::Stopped(error_code));
+        }
+        if stream.watermark_reached() {
+            return Err(WriteError::Blocked);
+        }
+        Ok(())
+    }
+
+    /// The limit that currently prevents writing to this stream, if any
+    ///
+    /// Explains why [`write`](Self::write) fails with [`WriteError::Blocked`], to help diagnose
+    /// throughput bottlenecks. When several limits are reached, the one checked first by `write`
+    /// is reported, in the order the [`WriteStallReason`] variants are declared. Returns `None`
+    /// if a write would make progress, or fail for another reason such as the stream being
+    /// stopped.
+    ///
+    /// The result is a snapshot: acknowledgements and flow control updates from the peer lift
+    /// limits, and writes reach them, so it may be out of date as soon as the connection is
+    /// driven again.
+    pub fn write_stall_reason(&self) -> Option<WriteStallReason> {
+        if self.conn_state.is_closed() {
+            return None;
+        }
+        let stream_budget = match self.state.send.get(&self.id) {
+            Some(Some(stream)) => {
+                if !stream.is_writable() || stream.stop_reason.is_some() {
+                    return None;
+                }
+                stream.max_data - stream.pending.offset()
+            }
+            Some(None) => self.state.max_send_data(self.id).into(),
+            None => return None,
+        };
+
+        if self.state.data_sent >= self.state.max_data {
+            Some(WriteStallReason::ConnectionFlowControl)
+        } else if self.state.unacked_data >= self.state.send_window {
+            Some(WriteStallReason::SendWindow)
+        } else if stream_budget == 0 {
+            Some(WriteStallReason::StreamFlowControl)
+        } else {
+            None
+        }
+    }
+
     /// Check if this stream was stopped, get the reason if it was
     pub fn stopped(&self) -> Result<Option<VarInt>, ClosedStream> {
         match self.state.send.get(&self.id).as_ref() {
@@ -342,6 +635,19 @@ impl<'a> SendStream<'a> {
 
     /// Set the priority of a stream
     ///
+    /// Any `i32` is valid, and streams start out with priority 0. Larger values are more urgent:
+    /// pending data of a stream is only sent once no stream of strictly higher priority has data
+    /// pending. Streams of equal priority take turns in round-robin order, unless
+    /// [`TransportConfig::send_fairness`](crate::TransportConfig::send_fairness) is disabled, in
+    /// which case each finishes its pending data before the next starts. Ties are broken by the
+    /// order in which streams became pending, so the schedule is deterministic.
+    ///
+    /// A stream that already has data queued keeps its previous priority for its next
+    /// transmission, and is queued with the new priority after that.
+    ///
+    /// With [`TransportConfig::weighted_priorities`](crate::TransportConfig::weighted_priorities)
+    /// enabled, priorities are weights rather than strict precedence.
+    ///
     /// # Panics
     /// - when applied to a receive stream
     pub fn set_priority(&mut self, priority: i32) -> Result<(), ClosedStream> {
@@ -359,6 +665,9 @@ impl<'a> SendStream<'a> {
 
     /// Get the priority of a stream
     ///
+    /// Returns the value most recently passed to [`set_priority`](Self::set_priority), or 0 if
+    /// it was never called.
+    ///
     /// # Panics
     /// - when applied to a receive stream
     pub fn priority(&self) -> Result<i32, ClosedStream> {
@@ -370,6 +679,40 @@ impl<'a> SendStream<'a> {
 
         Ok(stream.as_ref().map(|s| s.priority).unwrap_or_default())
     }
+
+    /// Number of bytes of stream data transmitted at least once
+    ///
+    /// Counts application data, excluding framing and packet overhead. Retransmissions of lost
+    /// data aren't counted again, so this never exceeds the amount written.
+    ///
+    /// Fails with [`ClosedStream`] once the stream has been reset, or finished and fully
+    /// acknowledged, as its state has then been discarded.
+    pub fn bytes_sent(&self) -> Result<u64, ClosedStream> {
+        let stream = self
+            .state
+            .send
+            .get(&self.id)
+            .ok_or(ClosedStream { _private: () })?;
+
+        Ok(stream.as_ref().map_or(0, |s| s.pending.sent()))
+    }
+
+    /// Number of bytes of stream data acknowledged by the peer
+    ///
+    /// Counts application data, excluding framing and packet overhead. Each byte is counted once,
+    /// however many times it was transmitted or acknowledged, so this never exceeds
+    /// [`bytes_sent()`](Self::bytes_sent).
+    ///
+    /// Fails with [`ClosedStream`] under the same conditions as `bytes_sent()`.
+    pub fn bytes_acked(&self) -> Result<u64, ClosedStream> {
+        let stream = self
+            .state
+            .send
+            .get(&self.id)
+            .ok_or(ClosedStream { _private: () })?;
+
+        Ok(stream.as_ref().map_or(0, |s| s.pending.acked()))
+    }
 }
 
 /// A queue of streams with pending outgoing data, sorted by priority
@@ -381,6 +724,16 @@ struct PendingStreamsQueue {
     /// A monotonically decreasing counter, used to implement round-robin scheduling for streams of the same priority.
     /// Underflowing is not a practical concern, as it is initialized to u64::MAX and only decremented by 1 in `push_pending`
     recency: u64,
+    /// Whether priorities are weights rather than strict precedence. If so, all streams are
+    /// queued with the same priority, and `StreamsState::write_stream_frames` limits how much each
+    /// may send per turn instead.
+    weighted: bool,
+    /// Number of packets which carried stream data, used to measure how long queued streams wait
+    packets: u64,
+    /// Packets a queued stream may wait for before it's considered starved, or 0 to not check
+    starvation_threshold: u64,
+    /// Number of times a queued stream waited for `starvation_threshold` packets
+    starved: u64,
 }
 
 impl PendingStreamsQueue {
@@ -389,6 +742,10 @@ impl PendingStreamsQueue {
             streams: BinaryHeap::new(),
             next: None,
             recency: u64::MAX,
+            weighted: false,
+            packets: 0,
+            starvation_threshold: 0,
+            starved: 0,
         }
     }
 
@@ -397,9 +754,10 @@ impl PendingStreamsQueue {
         assert!(self.next.is_none());
 
         self.next = Some(PendingStream {
-            priority,
+            priority: self.queued_priority(priority),
             recency: self.recency, // the value here doesn't really matter
             id,
+            queued_at: self.packets,
         });
     }
 
@@ -415,12 +773,38 @@ impl PendingStreamsQueue {
         // as in that case they are removed from the `BinaryHeap`, handled, and then immediately reinserted.
         self.recency -= 1;
         self.streams.push(PendingStream {
-            priority,
+            priority: self.queued_priority(priority),
             recency: self.recency,
             id,
+            queued_at: self.packets,
         });
     }
 
+    /// Account for a packet which carried stream data, checking whether queued streams starve
+    fn on_packet(&mut self) {
+        self.packets += 1;
+        let threshold = self.starvation_threshold;
+        if threshold == 0 || self.packets % threshold != 0 {
+            return;
+        }
+        // Streams are requeued whenever they send, so the time since they were queued is how long
+        // they have been waiting. Checking every `threshold` packets sees each wait cross the
+        // threshold exactly once.
+        let packets = self.packets;
+        self.starved += self
+            .iter()
+            .filter(|s| (threshold..2 * threshold).contains(&(packets - s.queued_at)))
+            .count() as u64;
+    }
+
+    /// The priority to order a stream by, which is uniform under weighted scheduling
+    fn queued_priority(&self, priority: i32) -> i32 {
+        match self.weighted {
+            true => 0,
+            false => priority,
+        }
+    }
+
     fn pop(&mut self) -> Option<PendingStream> {
         self.next.take().or_else(|| self.streams.pop())
     }
@@ -457,6 +841,8 @@ struct PendingStream {
     // The way this type is used ensures that every instance has a unique `recency` value, so this field should be kept below
     // the `priority` and `recency` fields, so that it does not interfere with the behaviour of the `Ord` derive
     id: StreamId,
+    /// The value of `PendingStreamsQueue::packets` when the stream was queued
+    queued_at: u64,
 }
 
 /// Application events about streams
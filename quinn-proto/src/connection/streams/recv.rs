@@ -1,5 +1,6 @@
 use std::collections::hash_map::Entry;
 use std::mem;
+use std::ops::Range;
 
 use thiserror::Error;
 use tracing::debug;
@@ -8,6 +9,7 @@ use super::state::get_or_insert_recv;
 use super::{ClosedStream, Retransmits, ShouldTransmit, StreamId, StreamsState};
 use crate::connection::assembler::{Assembler, Chunk, IllegalOrderedRead};
 use crate::connection::streams::state::StreamRecv;
+use crate::range_set::RangeSet;
 use crate::{TransportError, VarInt, frame};
 
 #[derive(Debug, Default)]
@@ -17,6 +19,10 @@ pub(super) struct Recv {
     pub(super) assembler: Assembler,
     sent_max_stream_data: u64,
     pub(super) end: u64,
+    /// Offset up to which all data has been received, i.e. the start of the first gap
+    contiguous: u64,
+    /// Ranges received beyond `contiguous`
+    received: RangeSet,
     pub(super) stopped: bool,
 }
 
@@ -27,6 +33,8 @@ impl Recv {
             assembler: Assembler::new(),
             sent_max_stream_data: initial_max_data,
             end: 0,
+            contiguous: 0,
+            received: RangeSet::new(),
             stopped: false,
         })
     }
@@ -37,6 +45,8 @@ impl Recv {
         self.assembler.reinit();
         self.sent_max_stream_data = initial_max_data;
         self.end = 0;
+        self.contiguous = 0;
+        self.received = RangeSet::new();
         self.stopped = false;
     }
 
@@ -49,6 +59,7 @@ impl Recv {
         payload_len: usize,
         received: u64,
         max_data: u64,
+        max_out_of_order: Option<u64>,
     ) -> Result<(u64, bool), TransportError> {
         let end = frame.offset + frame.data.len() as u64;
         if end >= 2u64.pow(62) {
@@ -60,11 +71,30 @@ impl Recv {
         if let Some(final_offset) = self.final_offset() {
             if end > final_offset || (frame.fin && end != final_offset) {
                 debug!(end, final_offset, "final size error");
-                return Err(TransportError::FINAL_SIZE_ERROR(""));
+                return Err(TransportError::FINAL_SIZE_ERROR("inconsistent value"));
             }
+        } else if frame.fin && end < self.end {
+            debug!(end, high_water_mark = self.end, "final size error");
+            return Err(TransportError::FINAL_SIZE_ERROR(
+                "lower than high water mark",
+            ));
         }
 
         let new_bytes = self.credit_consumed_by(end, received, max_data)?;
+        // Gaps are only tracked if there's a limit to enforce
+        if let Some(max_out_of_order) = max_out_of_order.filter(|_| !self.stopped) {
+            self.record_received(frame.offset..end);
+            if self.end.max(end) - self.contiguous > max_out_of_order {
+                debug!(
+                    end,
+                    contiguous = self.contiguous,
+                    "out-of-order data limit exceeded"
+                );
+                return Err(TransportError::FLOW_CONTROL_ERROR(
+                    "too much out-of-order data buffered",
+                ));
+            }
+        }
 
         // Stopped streams don't need to wait for the actual data, they just need to know
         // how much there was.
@@ -84,6 +114,48 @@ impl Recv {
         Ok((new_bytes, frame.fin && self.stopped))
     }
 
+    /// Process a `STREAM_SKIP` frame
+    ///
+    /// Return value is `(number_of_new_bytes_ingested, number_of_unread_bytes_skipped)`
+    pub(super) fn skip(
+        &mut self,
+        offset: u64,
+        received: u64,
+        max_data: u64,
+    ) -> Result<(u64, u64), TransportError> {
+        if self
+            .final_offset()
+            .is_some_and(|final_offset| offset > final_offset)
+        {
+            debug!(offset, "skipped past final size");
+            return Err(TransportError::FINAL_SIZE_ERROR("skipped past final size"));
+        }
+
+        let new_bytes = self.credit_consumed_by(offset, received, max_data)?;
+        self.end = self.end.max(offset);
+        if self.stopped {
+            return Ok((new_bytes, 0));
+        }
+        self.record_received(0..offset);
+        Ok((new_bytes, self.assembler.skip_to(offset)))
+    }
+
+    /// Track the gaps in the received data, to bound how much may be buffered beyond them
+    fn record_received(&mut self, range: Range<u64>) {
+        if range.start > self.contiguous {
+            self.received.insert(range);
+            return;
+        }
+        self.contiguous = self.contiguous.max(range.end);
+        while let Some(next) = self.received.peek_min() {
+            if next.start > self.contiguous {
+                break;
+            }
+            self.contiguous = self.contiguous.max(next.end);
+            self.received.pop_min();
+        }
+    }
+
     pub(super) fn stop(&mut self) -> Result<(u64, ShouldTransmit), ClosedStream> {
         if self.stopped {
             return Err(ClosedStream { _private: () });
@@ -91,6 +163,7 @@ impl Recv {
 
         self.stopped = true;
         self.assembler.clear();
+        self.received = RangeSet::new();
         // Issue flow control credit for unread data
         let read_credits = self.end - self.assembler.bytes_read();
         // This may send a spurious STOP_SENDING if we've already received all data, but it's a bit
@@ -258,25 +331,27 @@ impl<'a> Chunks<'a> {
         streams: &'a mut StreamsState,
         pending: &'a mut Retransmits,
     ) -> Result<Self, ReadableError> {
+        let window = streams.stream_receive_window(id);
         let mut entry = match streams.recv.entry(id) {
             Entry::Occupied(entry) => entry,
             Entry::Vacant(_) => return Err(ReadableError::ClosedStream),
         };
 
-        let mut recv =
-            match get_or_insert_recv(streams.stream_receive_window)(entry.get_mut()).stopped {
-                true => return Err(ReadableError::ClosedStream),
-                false => entry.remove().unwrap().into_inner(), // this can't fail due to the previous get_or_insert_with
-            };
+        let mut recv = match get_or_insert_recv(window)(entry.get_mut()).stopped {
+            true => return Err(ReadableError::ClosedStream),
+            false => entry.remove().unwrap().into_inner(), // this can't fail due to the previous get_or_insert_with
+        };
 
+        let bytes_read = recv.assembler.bytes_read();
         recv.assembler.ensure_ordering(ordered)?;
         Ok(Self {
             id,
             ordered,
             streams,
             pending,
+            // Entering unordered mode may pass over gaps the peer abandoned
+            read: recv.assembler.bytes_read() - bytes_read,
             state: ChunksState::Readable(recv),
-            read: 0,
         })
     }
 
@@ -295,8 +370,11 @@ impl<'a> Chunks<'a> {
             ChunksState::Finalized => panic!("must not call next() after finalize()"),
         };
 
-        if let Some(chunk) = rs.assembler.read(max_length, self.ordered) {
-            self.read += chunk.bytes.len() as u64;
+        let bytes_read = rs.assembler.bytes_read();
+        let chunk = rs.assembler.read(max_length, self.ordered);
+        // Includes any gaps the peer abandoned, which the read passed over
+        self.read += rs.assembler.bytes_read() - bytes_read;
+        if let Some(chunk) = chunk {
             return Ok(Some(chunk));
         }
 
@@ -360,7 +438,8 @@ impl<'a> Chunks<'a> {
 
         // If the stream hasn't finished, we may need to issue stream-level flow control credit
         if let ChunksState::Readable(mut rs) = state {
-            let (_, max_stream_data) = rs.max_stream_data(self.streams.stream_receive_window);
+            let window = self.streams.stream_receive_window(self.id);
+            let (_, max_stream_data) = rs.max_stream_data(window);
             should_transmit |= max_stream_data.0;
             if max_stream_data.0 {
                 self.pending.max_stream_data.insert(self.id);
@@ -467,6 +546,7 @@ mod tests {
                 123,
                 data_recvd,
                 data_recvd + 1024,
+                None,
             )
             .unwrap();
         data_recvd += new_bytes;
@@ -500,6 +580,7 @@ mod tests {
                 123,
                 data_recvd,
                 data_recvd + 1024,
+                None,
             )
             .unwrap();
         data_recvd += new_bytes;
@@ -525,6 +606,7 @@ mod tests {
                 123,
                 data_recvd,
                 data_recvd + 1024,
+                None,
             )
             .unwrap();
         assert_eq!(
@@ -540,4 +622,42 @@ mod tests {
             "stream flow control credit isn't issued after stop"
         );
     }
+
+    #[test]
+    fn out_of_order_limit() {
+        const LIMIT: u64 = 100;
+        let mut s = Recv::new(1024);
+        let ingest = |s: &mut Recv, offset: u64, len: usize, limit: Option<u64>| {
+            s.ingest(
+                frame::Stream {
+                    id: StreamId::new(Side::Client, Dir::Uni, 0),
+                    offset,
+                    fin: false,
+                    data: Bytes::from(vec![0; len]),
+                },
+                len,
+                0,
+                1024,
+                limit,
+            )
+        };
+
+        // Data may run ahead of a gap by up to the limit
+        ingest(&mut s, 10, 10, Some(LIMIT)).unwrap();
+        ingest(&mut s, 90, 10, Some(LIMIT)).unwrap();
+        // Filling the gap moves the point the limit is measured from
+        ingest(&mut s, 0, 10, Some(LIMIT)).unwrap();
+        ingest(&mut s, 100, 10, Some(LIMIT)).unwrap();
+        ingest(&mut s, 20, 70, Some(LIMIT)).unwrap();
+        ingest(&mut s, 200, 10, Some(LIMIT)).unwrap();
+
+        // Data at a far-off offset is rejected
+        let err = ingest(&mut s, 900, 10, Some(LIMIT)).unwrap_err();
+        assert_eq!(err.code, crate::TransportErrorCode::FLOW_CONTROL_ERROR);
+
+        // Without a limit, gaps aren't tracked at all
+        let mut s = Recv::new(1024);
+        ingest(&mut s, 900, 10, None).unwrap();
+        assert!(s.received.is_empty());
+    }
 }
@@ -1,7 +1,9 @@
+use std::collections::VecDeque;
+
 use bytes::Bytes;
 use thiserror::Error;
 
-use crate::{VarInt, connection::send_buffer::SendBuffer, frame};
+use crate::{Duration, Instant, VarInt, connection::send_buffer::SendBuffer, frame};
 
 #[derive(Debug)]
 pub(super) struct Send {
@@ -9,12 +11,30 @@ pub(super) struct Send {
     pub(super) state: SendState,
     pub(super) pending: SendBuffer,
     pub(super) priority: i32,
+    /// Bytes left in this stream's current turn under weighted scheduling
+    pub(super) quantum_left: u64,
     /// Whether a frame containing a FIN bit must be transmitted, even if we don't have any new data
     pub(super) fin_pending: bool,
     /// Whether this stream is in the `connection_blocked` list of `Streams`
     pub(super) connection_blocked: bool,
     /// The reason the peer wants us to stop, if `STOP_SENDING` was received
     pub(super) stop_reason: Option<VarInt>,
+    /// Data which must be acknowledged by a deadline, in order of offset
+    deadlines: VecDeque<Deadline>,
+    /// Thresholds of unacknowledged data set by the application, if any
+    watermarks: Option<Watermarks>,
+    /// Amount of written data to accumulate before sending it, if writes are coalesced
+    coalesce_threshold: Option<u64>,
+    /// Offset up to which written data may be sent while writes are coalesced
+    flushed: u64,
+    /// Limit beyond which unacknowledged data is abandoned, if the stream is partially reliable
+    partial_reliability: Option<PartialReliability>,
+    /// Offset below which data was abandoned
+    pub(super) skipped: u64,
+    /// Highest offset below which the peer acknowledged that data was abandoned
+    skip_acked: u64,
+    /// Offsets up to which sent data goes stale under [`PartialReliability::Age`], and when
+    stale_at: VecDeque<(u64, Instant)>,
 }
 
 impl Send {
@@ -24,9 +44,18 @@ impl Send {
             state: SendState::Ready,
             pending: SendBuffer::new(),
             priority: 0,
+            quantum_left: 0,
             fin_pending: false,
             connection_blocked: false,
             stop_reason: None,
+            deadlines: VecDeque::new(),
+            watermarks: None,
+            coalesce_threshold: None,
+            flushed: 0,
+            partial_reliability: None,
+            skipped: 0,
+            skip_acked: 0,
+            stale_at: VecDeque::new(),
         })
     }
 
@@ -43,6 +72,7 @@ impl Send {
                 finish_acked: false,
             };
             self.fin_pending = true;
+            self.flush();
             Ok(())
         } else {
             Err(FinishError::ClosedStream)
@@ -80,15 +110,155 @@ impl Send {
             self.pending.write(chunk);
         }
 
+        if let Some(ref mut watermarks) = self.watermarks {
+            watermarks.reached |= self.pending.unacked() >= watermarks.high;
+        }
+        if let Some(threshold) = self.coalesce_threshold {
+            if self.pending.offset() - self.flushed.max(self.pending.sent()) >= threshold {
+                self.flush();
+            }
+        }
         Ok(result)
     }
 
+    /// Hold back written data until `threshold` bytes have accumulated, or stop doing so
+    pub(super) fn set_coalesce_threshold(&mut self, threshold: Option<u64>) {
+        self.flush();
+        self.coalesce_threshold = threshold;
+    }
+
+    /// Allow all data written so far to be sent
+    pub(super) fn flush(&mut self) {
+        self.flushed = self.pending.offset();
+    }
+
     /// Update stream state due to a reset sent by the local application
     pub(super) fn reset(&mut self) {
         use SendState::*;
         if let DataSent { .. } | Ready = self.state {
             self.state = ResetSent;
         }
+        self.deadlines.clear();
+        self.stale_at.clear();
+    }
+
+    /// Require all data written so far to be acknowledged by `at`, or else reset the stream with
+    /// `error_code`
+    pub(super) fn set_deadline(&mut self, at: Instant, error_code: VarInt) {
+        self.deadlines.push_back(Deadline {
+            end: self.pending.offset(),
+            at,
+            error_code,
+        });
+    }
+
+    /// Earliest deadline for data that may not have been acknowledged yet
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        let stale_at = self.stale_at.front().map(|&(_, at)| at);
+        self.deadlines.iter().map(|d| d.at).chain(stale_at).min()
+    }
+
+    /// Returns the error code to reset the stream with if some data missed its deadline
+    pub(super) fn expire_deadlines(&mut self, now: Instant) -> Option<VarInt> {
+        let acked = self.pending.acked_prefix();
+        while self.deadlines.front().is_some_and(|d| d.end <= acked) {
+            self.deadlines.pop_front();
+        }
+        self.deadlines
+            .iter()
+            .find(|d| d.at <= now)
+            .map(|d| d.error_code)
+    }
+
+    pub(super) fn set_partial_reliability(&mut self, limit: Option<PartialReliability>) {
+        self.partial_reliability = limit;
+        self.stale_at.clear();
+    }
+
+    /// Record that data up to `end` was sent at `now`
+    ///
+    /// Returns when that data goes stale, if the stream is partially reliable by age.
+    pub(super) fn on_sent(&mut self, end: u64, now: Instant) -> Option<Instant> {
+        let Some(PartialReliability::Age(age)) = self.partial_reliability else {
+            return None;
+        };
+        let at = now + age;
+        self.stale_at.push_back((end, at));
+        Some(at)
+    }
+
+    /// Returns the offset below which data should be abandoned, if any
+    ///
+    /// Under [`PartialReliability::Age`], only data that went stale by `now` is abandoned, and
+    /// none if `now` is `None`.
+    pub(super) fn stale_offset(&mut self, now: Option<Instant>) -> Option<u64> {
+        let offset = match self.partial_reliability? {
+            // Data that was never sent isn't stale, however far behind it is
+            PartialReliability::Bytes(limit) => self
+                .pending
+                .offset()
+                .saturating_sub(limit)
+                .min(self.pending.sent()),
+            PartialReliability::Age(_) => {
+                let now = now?;
+                let mut offset = 0;
+                while let Some(&(end, _)) = self.stale_at.front().filter(|&&(_, at)| at <= now) {
+                    offset = end;
+                    self.stale_at.pop_front();
+                }
+                offset
+            }
+        };
+        (offset > self.skipped.max(self.pending.acked_prefix())).then_some(offset)
+    }
+
+    /// Abandon all data below `offset`, which must exceed any previously abandoned offset
+    ///
+    /// Returns the amount of unacknowledged data that was abandoned.
+    pub(super) fn skip_to(&mut self, offset: u64) -> u64 {
+        debug_assert!(offset > self.skipped);
+        let unacked = self.pending.unacked();
+        self.pending.skip_to(offset);
+        self.skipped = offset;
+        while self.stale_at.front().is_some_and(|&(end, _)| end <= offset) {
+            self.stale_at.pop_front();
+        }
+        unacked - self.pending.unacked()
+    }
+
+    /// Handle acknowledgement of a `STREAM_SKIP` frame
+    ///
+    /// Returns whether the stream has been finished and all data has been acknowledged by the peer
+    pub(super) fn skip_acked(&mut self, offset: u64) -> bool {
+        self.skip_acked = self.skip_acked.max(offset);
+        self.is_complete()
+    }
+
+    pub(super) fn set_watermarks(&mut self, low: u64, high: u64) {
+        self.watermarks = Some(Watermarks {
+            low,
+            high,
+            reached: self.pending.unacked() >= high,
+        });
+    }
+
+    /// Whether unacknowledged data reached the high watermark and hasn't yet fallen to the low
+    /// watermark
+    pub(super) fn watermark_reached(&self) -> bool {
+        self.watermarks.as_ref().is_some_and(|w| w.reached)
+    }
+
+    /// Returns whether unacknowledged data just fell to the low watermark after having reached the
+    /// high watermark
+    pub(super) fn watermark_drained(&mut self) -> bool {
+        let unacked = self.pending.unacked();
+        match self.watermarks {
+            Some(ref mut w) if w.reached && unacked <= w.low => {
+                w.reached = false;
+                true
+            }
+            _ => false,
+        }
     }
 
     /// Handle STOP_SENDING
@@ -107,15 +277,21 @@ impl Send {
     /// Returns whether the stream has been finished and all data has been acknowledged by the peer
     pub(super) fn ack(&mut self, frame: frame::StreamMeta) -> bool {
         self.pending.ack(frame.offsets);
-        match self.state {
-            SendState::DataSent {
-                ref mut finish_acked,
-            } => {
-                *finish_acked |= frame.fin;
-                *finish_acked && self.pending.is_fully_acked()
-            }
-            _ => false,
+        if let SendState::DataSent {
+            ref mut finish_acked,
+        } = self.state
+        {
+            *finish_acked |= frame.fin;
         }
+        self.is_complete()
+    }
+
+    /// Whether the stream has been finished, and the peer acknowledged all data and all
+    /// abandoned data having been skipped
+    fn is_complete(&self) -> bool {
+        self.state == SendState::DataSent { finish_acked: true }
+            && self.pending.is_fully_acked()
+            && self.skip_acked == self.skipped
     }
 
     /// Handle increase to stream-level flow control limit
@@ -135,7 +311,15 @@ impl Send {
     }
 
     pub(super) fn is_pending(&self) -> bool {
-        self.pending.has_unsent_data() || self.fin_pending
+        self.pending.has_unsent_data(self.sendable()) || self.fin_pending
+    }
+
+    /// Offset up to which written data may be sent, excluding data held back to coalesce writes
+    pub(super) fn sendable(&self) -> u64 {
+        match self.coalesce_threshold {
+            Some(_) => self.flushed,
+            None => self.pending.offset(),
+        }
     }
 
     pub(super) fn is_writable(&self) -> bool {
@@ -143,6 +327,38 @@ impl Send {
     }
 }
 
+/// Limit beyond which a partially reliable stream abandons unacknowledged data
+///
+/// See [`SendStream::set_partial_reliability`](crate::SendStream::set_partial_reliability).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PartialReliability {
+    /// Abandon sent data that remains unacknowledged more than this many bytes behind the end of
+    /// the data written so far
+    Bytes(u64),
+    /// Abandon data that isn't acknowledged this long after it was first sent
+    Age(Duration),
+}
+
+/// A point in a stream that must be acknowledged by a certain time
+#[derive(Debug)]
+struct Deadline {
+    /// Offset up to which data must be acknowledged
+    end: u64,
+    at: Instant,
+    /// Error code to reset the stream with if the deadline passes
+    error_code: VarInt,
+}
+
+/// Application-defined thresholds for the amount of unacknowledged data on a stream
+#[derive(Debug)]
+struct Watermarks {
+    low: u64,
+    high: u64,
+    /// Whether the high watermark was reached since unacknowledged data last fell to the low
+    /// watermark
+    reached: bool,
+}
+
 /// A [`BytesSource`] implementation for `&'a mut [Bytes]`
 ///
 /// The type allows to dequeue [`Bytes`] chunks from an array of chunks, up to
@@ -278,6 +494,30 @@ pub enum WriteError {
     ClosedStream,
 }
 
+/// The limit preventing writes to a send stream
+///
+/// See [`SendStream::write_stall_reason`](super::SendStream::write_stall_reason).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum WriteStallReason {
+    /// The peer's connection-level flow control limit has been reached
+    ///
+    /// Writes resume when the peer raises the limit with a MAX_DATA frame, which it does as the
+    /// application on its side reads data from any stream.
+    ConnectionFlowControl,
+    /// Data written but not yet acknowledged fills the local send window
+    ///
+    /// This usually means the congestion controller or loss recovery is holding data back, as
+    /// data can't be acknowledged before it's sent. See
+    /// [`TransportConfig::send_window`](crate::TransportConfig::send_window).
+    SendWindow,
+    /// The peer's flow control limit for this stream has been reached
+    ///
+    /// Writes resume when the peer raises the limit with a MAX_STREAM_DATA frame, which it does as
+    /// the application on its side reads from the stream.
+    StreamFlowControl,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub(super) enum SendState {
     /// Sending new data
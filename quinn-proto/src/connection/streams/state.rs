@@ -5,7 +5,7 @@ use std::{
 };
 
 use bytes::BufMut;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use tracing::{debug, trace};
 
 use super::{
@@ -13,13 +13,16 @@ use super::{
     StreamHalf, ThinRetransmits,
 };
 use crate::{
-    Dir, MAX_STREAM_COUNT, Side, StreamId, TransportError, VarInt,
+    Dir, Duration, Instant, MAX_STREAM_COUNT, Side, StreamId, TransportError, VarInt,
     coding::BufMutExt,
     connection::stats::FrameStats,
     frame::{self, FrameStruct, StreamMetaVec},
     transport_parameters::TransportParameters,
 };
 
+/// Bytes a stream of weight 1 may send per turn under weighted scheduling, about one packet
+const WEIGHTED_QUANTUM: u64 = 1200;
+
 /// Wrapper around `Recv` that facilitates reusing `Recv` instances
 #[derive(Debug)]
 pub(super) enum StreamRecv {
@@ -53,15 +56,11 @@ impl StreamRecv {
         }
     }
 
-    // Reinitialize the stream so the inner `Recv` can be reused
-    pub(super) fn free(self, initial_max_data: u64) -> Self {
-        match self {
-            Self::Free(_) => unreachable!("Self::Free on reinit()"),
-            Self::Open(mut recv) => {
-                recv.reinit(initial_max_data);
-                Self::Free(recv)
-            }
-        }
+    // Reinitialize the inner `Recv` so it can be reused by a stream with the given receive window
+    pub(super) fn reinit(self, initial_max_data: u64) -> Self {
+        let mut recv = self.into_inner();
+        recv.reinit(initial_max_data);
+        Self::Free(recv)
     }
 }
 
@@ -88,6 +87,13 @@ pub struct StreamsState {
     max_concurrent_remote_count: [u64; 2],
     /// Whether `max_concurrent_remote_count` has ever changed
     flow_control_adjusted: bool,
+    /// Number of accepted remotely-initiated streams of each directionality the application may be
+    /// handling before we stop issuing stream credit
+    pub(super) max_unhandled: Option<u64>,
+    /// Remotely-initiated streams accepted but not yet released by the application
+    ///
+    /// Only tracked when `max_unhandled` is set.
+    pub(super) unhandled: FxHashSet<StreamId>,
     /// Lowest remotely-initiated stream index that haven't actually been opened by the peer
     pub(super) next_remote: [u64; 2],
     /// Whether the remote endpoint has opened any streams the application doesn't know about yet,
@@ -110,8 +116,12 @@ pub struct StreamsState {
     pub(super) connection_blocked: Vec<StreamId>,
     /// Connection-level flow control budget dictated by the peer
     pub(super) max_data: u64,
-    /// The initial receive window
+    /// The connection-level receive window, which may grow up to `max_receive_window`
     receive_window: u64,
+    /// Upper bound for automatic growth of `receive_window`
+    max_receive_window: u64,
+    /// When a `MAX_DATA` frame raising the limit was last sent
+    max_data_raised_at: Option<Instant>,
     /// Limit on incoming data, which is transmitted through `MAX_DATA` frames
     local_max_data: u64,
     /// The last value of `MAX_DATA` which had been queued for transmission in
@@ -127,13 +137,18 @@ pub struct StreamsState {
     ///
     /// Note this may be less than `unacked_data` if the user has set a new value.
     pub(super) send_window: u64,
-    /// Configured upper bound for how much unacked data the peer can send us per stream
-    pub(super) stream_receive_window: u64,
+    /// Configured upper bounds for how much unacked data the peer can send us per stream, for
+    /// locally opened bidirectional, remotely opened bidirectional, and unidirectional streams
+    stream_receive_windows: (u64, u64, u64),
+    /// Configured upper bound for how far received data may extend past the first gap in a stream
+    max_out_of_order: Option<u64>,
 
     // Pertinent state from the TransportParameters supplied by the peer
     initial_max_stream_data_uni: VarInt,
     initial_max_stream_data_bidi_local: VarInt,
     initial_max_stream_data_bidi_remote: VarInt,
+    /// Whether the peer accepts `STREAM_SKIP` frames for partially reliable streams
+    pub(super) peer_partial_reliability: bool,
 
     /// The shrink to be applied to local_max_data when receive_window is shrunk
     receive_window_shrink_debt: u64,
@@ -141,6 +156,11 @@ pub struct StreamsState {
     pub(in crate::connection) pending_data_blocked: bool,
     /// The max_data offset at which we last sent DATA_BLOCKED
     pub(super) sent_data_blocked_at: u64,
+    /// Earliest deadline set by `SendStream::write_with_deadline` that may not have passed yet
+    ///
+    /// May be earlier than any outstanding deadline, since deadlines are only pruned when it
+    /// elapses.
+    pub(super) next_deadline: Option<Instant>,
 }
 
 impl StreamsState {
@@ -165,6 +185,8 @@ impl StreamsState {
             allocated_remote_count: [max_remote_bi.into(), max_remote_uni.into()],
             max_concurrent_remote_count: [max_remote_bi.into(), max_remote_uni.into()],
             flow_control_adjusted: false,
+            max_unhandled: None,
+            unhandled: FxHashSet::default(),
             next_remote: [0, 0],
             opened: [false, false],
             next_reported_remote: [0, 0],
@@ -174,19 +196,28 @@ impl StreamsState {
             connection_blocked: Vec::new(),
             max_data: 0,
             receive_window: receive_window.into(),
+            max_receive_window: 0,
+            max_data_raised_at: None,
             local_max_data: receive_window.into(),
             sent_max_data: receive_window,
             data_sent: 0,
             data_recvd: 0,
             unacked_data: 0,
             send_window,
-            stream_receive_window: stream_receive_window.into(),
+            stream_receive_windows: (
+                stream_receive_window.into(),
+                stream_receive_window.into(),
+                stream_receive_window.into(),
+            ),
+            max_out_of_order: None,
             initial_max_stream_data_uni: 0u32.into(),
             initial_max_stream_data_bidi_local: 0u32.into(),
             initial_max_stream_data_bidi_remote: 0u32.into(),
+            peer_partial_reliability: false,
             receive_window_shrink_debt: 0,
             pending_data_blocked: false,
             sent_data_blocked_at: 0,
+            next_deadline: None,
         };
 
         for dir in Dir::iter() {
@@ -202,6 +233,7 @@ impl StreamsState {
         self.initial_max_stream_data_uni = params.initial_max_stream_data_uni;
         self.initial_max_stream_data_bidi_local = params.initial_max_stream_data_bidi_local;
         self.initial_max_stream_data_bidi_remote = params.initial_max_stream_data_bidi_remote;
+        self.peer_partial_reliability = params.partial_reliability;
         self.max[Dir::Bi as usize] = params.initial_max_streams_bidi.into();
         self.max[Dir::Uni as usize] = params.initial_max_streams_uni.into();
         self.received_max_data(params.initial_max_data);
@@ -216,6 +248,10 @@ impl StreamsState {
     /// Ensure we have space for at least a full flow control window of remotely-initiated streams
     /// to be open, and notify the peer if the window has moved
     fn ensure_remote_streams(&mut self, dir: Dir) {
+        if self.unhandled_limited(dir) {
+            // Withhold credit until the application catches up
+            return;
+        }
         let new_count = self.max_concurrent_remote_count[dir as usize]
             .saturating_sub(self.allocated_remote_count[dir as usize]);
         for i in 0..new_count {
@@ -268,11 +304,8 @@ impl StreamsState {
             debug!("received illegal STREAM frame");
         })?;
 
-        let Some(rs) = self
-            .recv
-            .get_mut(&id)
-            .map(get_or_insert_recv(self.stream_receive_window))
-        else {
+        let window = self.stream_receive_window(id);
+        let Some(rs) = self.recv.get_mut(&id).map(get_or_insert_recv(window)) else {
             trace!("dropping frame for closed stream");
             return Ok(ShouldTransmit(false));
         };
@@ -282,8 +315,13 @@ impl StreamsState {
             return Ok(ShouldTransmit(false));
         }
 
-        let (new_bytes, closed) =
-            rs.ingest(frame, payload_len, self.data_recvd, self.local_max_data)?;
+        let (new_bytes, closed) = rs.ingest(
+            frame,
+            payload_len,
+            self.data_recvd,
+            self.local_max_data,
+            self.max_out_of_order,
+        )?;
         self.data_recvd = self.data_recvd.saturating_add(new_bytes);
 
         if !rs.stopped {
@@ -318,11 +356,8 @@ impl StreamsState {
             debug!("received illegal RESET_STREAM frame");
         })?;
 
-        let Some(rs) = self
-            .recv
-            .get_mut(&id)
-            .map(get_or_insert_recv(self.stream_receive_window))
-        else {
+        let window = self.stream_receive_window(id);
+        let Some(rs) = self.recv.get_mut(&id).map(get_or_insert_recv(window)) else {
             trace!("received RESET_STREAM on closed stream");
             return Ok(ShouldTransmit(false));
         };
@@ -359,6 +394,43 @@ impl StreamsState {
         })
     }
 
+    /// Process incoming `STREAM_SKIP` frame
+    ///
+    /// If successful, returns whether a `MAX_DATA` frame needs to be transmitted
+    pub(crate) fn received_stream_skip(
+        &mut self,
+        frame: frame::StreamSkip,
+    ) -> Result<ShouldTransmit, TransportError> {
+        let id = frame.id;
+        self.validate_receive_id(id).inspect_err(|_| {
+            debug!("received illegal STREAM_SKIP frame");
+        })?;
+
+        let window = self.stream_receive_window(id);
+        let Some(rs) = self.recv.get_mut(&id).map(get_or_insert_recv(window)) else {
+            trace!("received STREAM_SKIP on closed stream");
+            return Ok(ShouldTransmit(false));
+        };
+
+        if !rs.is_receiving() {
+            trace!("dropping STREAM_SKIP for reset stream");
+            return Ok(ShouldTransmit(false));
+        }
+
+        let (new_bytes, skipped) =
+            rs.skip(frame.offset.into(), self.data_recvd, self.local_max_data)?;
+        self.data_recvd = self.data_recvd.saturating_add(new_bytes);
+        if rs.stopped {
+            // We don't buffer data on stopped streams, so issue flow control credit immediately
+            return Ok(self.add_read_credits(new_bytes));
+        }
+
+        // The application needs to read to learn that it may continue past the gap, or that the
+        // stream finished. Skipped data won't ever be read, so its credit is issued immediately.
+        self.on_stream_frame(true, id);
+        Ok(self.add_read_credits(skipped))
+    }
+
     /// Process incoming `STOP_SENDING` frame
     #[allow(unreachable_pub)] // fuzzing only
     pub fn received_stop_sending(&mut self, id: StreamId, error_code: VarInt) {
@@ -417,6 +489,8 @@ impl StreamsState {
         retransmits: &mut ThinRetransmits,
         stats: &mut FrameStats,
         max_size: usize,
+        now: Instant,
+        rtt: Duration,
     ) {
         // RESET_STREAM
         while buf.len() + frame::ResetStream::SIZE_BOUND < max_size {
@@ -440,6 +514,28 @@ impl StreamsState {
             stats.reset_stream += 1;
         }
 
+        // STREAM_SKIP
+        while buf.len() + frame::StreamSkip::SIZE_BOUND < max_size {
+            let Some((id, offset)) = pending.stream_skip.pop() else {
+                break;
+            };
+            let Some(stream) = self.send.get(&id).and_then(|s| s.as_ref()) else {
+                continue;
+            };
+            if stream.is_reset() || offset < stream.skipped {
+                // Superseded by the reset, or a frame skipping further, which is queued too
+                continue;
+            }
+            trace!(stream = %id, offset, "STREAM_SKIP");
+            retransmits.get_or_create().stream_skip.push((id, offset));
+            frame::StreamSkip {
+                id,
+                offset: VarInt::try_from(offset).expect("impossibly large offset"),
+            }
+            .encode(buf);
+            stats.stream_skip += 1;
+        }
+
         // STOP_SENDING
         while buf.len() + frame::StopSending::SIZE_BOUND < max_size {
             let Some(frame) = pending.stop_sending.pop() else {
@@ -461,6 +557,9 @@ impl StreamsState {
         // MAX_DATA
         if pending.max_data && buf.len() + 9 < max_size {
             pending.max_data = false;
+            if self.local_max_data > self.sent_max_data.into_inner() {
+                self.maybe_grow_receive_window(now, rtt);
+            }
 
             // `local_max_data` can grow bigger than `VarInt`.
             // For transmission inside QUIC frames we need to clamp it to the
@@ -487,6 +586,7 @@ impl StreamsState {
                 break;
             };
             pending.max_stream_data.remove(&id);
+            let window = self.stream_receive_window(id);
             let Some(rs) = self
                 .recv
                 .get_mut(&id)
@@ -500,7 +600,7 @@ impl StreamsState {
             }
             retransmits.get_or_create().max_stream_data.insert(id);
 
-            let (max, _) = rs.max_stream_data(self.stream_receive_window);
+            let (max, _) = rs.max_stream_data(window);
             rs.record_sent_max_stream_data(max);
 
             trace!(stream = %id, max = max, "MAX_STREAM_DATA");
@@ -551,6 +651,7 @@ impl StreamsState {
         buf: &mut Vec<u8>,
         max_buf_size: usize,
         fair: bool,
+        now: Instant,
     ) -> StreamMetaVec {
         let mut stream_frames = StreamMetaVec::new();
         while buf.len() + frame::Stream::SIZE_BOUND < max_buf_size {
@@ -580,18 +681,56 @@ impl StreamsState {
             if stream.is_reset() {
                 continue;
             }
+            // Partially reliable streams may have abandoned all the data they had pending
+            if !stream.is_pending() {
+                continue;
+            }
 
             // Now that we know the `StreamId`, we can better account for how many bytes
             // are required to encode it.
-            let max_buf_size = max_buf_size - buf.len() - 1 - VarInt::size(id.into());
-            let (offsets, encode_length) = stream.pending.poll_transmit(max_buf_size);
+            let mut max_buf_size = max_buf_size - buf.len() - 1 - VarInt::size(id.into());
+            let weighted = self.pending.weighted;
+            if weighted {
+                // Deficit round-robin: a stream starting its turn is granted a quantum scaled by
+                // its weight, and keeps the turn until the quantum is used up. Leave room for the
+                // offset and length, which `poll_transmit` accounts for within `max_len`.
+                if stream.quantum_left == 0 {
+                    stream.quantum_left = stream.priority.max(1) as u64 * WEIGHTED_QUANTUM;
+                }
+                let quantum_left = usize::try_from(stream.quantum_left).unwrap_or(usize::MAX);
+                max_buf_size = max_buf_size.min(quantum_left.saturating_add(16));
+            }
+            let sent = stream.pending.sent();
+            let (offsets, encode_length) = stream
+                .pending
+                .poll_transmit(max_buf_size, stream.sendable());
+            if offsets.end > sent && self.peer_partial_reliability {
+                if let Some(stale_at) = stream.on_sent(offsets.end, now) {
+                    self.next_deadline = Some(
+                        self.next_deadline
+                            .map_or(stale_at, |next| next.min(stale_at)),
+                    );
+                }
+            }
             let fin = offsets.end == stream.pending.offset()
                 && matches!(stream.state, SendState::DataSent { .. });
             if fin {
                 stream.fin_pending = false;
             }
 
-            if stream.is_pending() {
+            if weighted {
+                stream.quantum_left = stream
+                    .quantum_left
+                    .saturating_sub(offsets.end - offsets.start);
+                if !stream.is_pending() {
+                    // A stream that runs out of data forfeits the rest of its turn
+                    stream.quantum_left = 0;
+                } else if stream.quantum_left > 0 {
+                    self.pending.reinsert_pending(id, stream.priority);
+                } else {
+                    self.pending.push_pending(id, stream.priority);
+                }
+            } else if stream.is_pending() {
                 // If the stream still has pending data, reinsert it, possibly with an updated priority value
                 // Fairness with other streams is achieved by implementing round-robin scheduling,
                 // so that the other streams will have a chance to write data
@@ -619,6 +758,9 @@ impl StreamsState {
             stream_frames.push(meta);
         }
 
+        if !stream_frames.is_empty() {
+            self.pending.on_packet();
+        }
         stream_frames
     }
 
@@ -659,9 +801,16 @@ impl StreamsState {
             return;
         }
         let id = frame.id;
-        self.unacked_data -= frame.offsets.end - frame.offsets.start;
+        // Abandoned data was accounted for when it was skipped
+        self.unacked_data -= frame
+            .offsets
+            .end
+            .saturating_sub(frame.offsets.start.max(stream.skipped));
         if !stream.ack(frame) {
             // The stream is unfinished or may still need retransmits
+            if stream.watermark_drained() {
+                self.events.push_back(StreamEvent::Writable { id });
+            }
             return;
         }
 
@@ -670,6 +819,63 @@ impl StreamsState {
         self.events.push_back(StreamEvent::Finished { id });
     }
 
+    /// Handle acknowledgement of a `STREAM_SKIP` frame
+    pub(crate) fn stream_skip_acked(&mut self, id: StreamId, offset: u64) {
+        let hash_map::Entry::Occupied(mut entry) = self.send.entry(id) else {
+            return;
+        };
+        let Some(stream) = entry.get_mut().as_mut() else {
+            return;
+        };
+        if stream.is_reset() || !stream.skip_acked(offset) {
+            return;
+        }
+
+        entry.remove_entry();
+        self.stream_freed(id, StreamHalf::Send);
+        self.events.push_back(StreamEvent::Finished { id });
+    }
+
+    /// Earliest time at which [`expire_deadlines`](Self::expire_deadlines) needs to be called
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.next_deadline
+    }
+
+    /// Reset streams with data that wasn't acknowledged by its deadline, and abandon stale data on
+    /// partially reliable streams
+    pub(crate) fn expire_deadlines(&mut self, now: Instant, pending: &mut Retransmits) {
+        if self.next_deadline.is_none_or(|at| at > now) {
+            return;
+        }
+        self.next_deadline = None;
+        for (&id, stream) in self.send.iter_mut() {
+            let Some(stream) = stream.as_mut() else {
+                continue;
+            };
+            let Some(error_code) = stream.expire_deadlines(now) else {
+                // Peers that don't support partial reliability would reject `STREAM_SKIP`
+                if self.peer_partial_reliability {
+                    if let Some(offset) = stream.stale_offset(Some(now)) {
+                        trace!(stream = %id, offset, "abandoning stale data");
+                        self.unacked_data -= stream.skip_to(offset);
+                        pending.stream_skip.push((id, offset));
+                    }
+                }
+                self.next_deadline = match (self.next_deadline, stream.next_deadline()) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                continue;
+            };
+            debug!(stream = %id, "data missed its deadline; resetting");
+            self.unacked_data -= stream.pending.unacked();
+            stream.reset();
+            pending.reset_stream.push((id, error_code));
+            // Wake up anyone blocked on writing so they learn the stream is closed
+            self.events.push_back(StreamEvent::Writable { id });
+        }
+    }
+
     pub(crate) fn retransmit(&mut self, frame: frame::StreamMeta) {
         let Some(stream) = self.send.get_mut(&frame.id).and_then(|s| s.as_mut()) else {
             // Loss of data on a closed stream is a noop
@@ -853,12 +1059,93 @@ impl StreamsState {
         id.index() >= self.next[id.dir() as usize]
     }
 
+    /// Streams opened by either peer for which we still have send state
+    pub(crate) fn open_send_streams(&self) -> impl Iterator<Item = StreamId> + '_ {
+        self.send.keys().copied().filter(|&id| self.is_opened(id))
+    }
+
+    /// Streams opened by either peer for which we still have receive state
+    pub(crate) fn open_recv_streams(&self) -> impl Iterator<Item = StreamId> + '_ {
+        self.recv.keys().copied().filter(|&id| self.is_opened(id))
+    }
+
+    /// Whether `id` has been opened, as opposed to merely being permitted for the peer to open
+    fn is_opened(&self, id: StreamId) -> bool {
+        let next = match id.initiator() == self.side {
+            true => &self.next,
+            false => &self.next_remote,
+        };
+        id.index() < next[id.dir() as usize]
+    }
+
     pub(crate) fn set_max_concurrent(&mut self, dir: Dir, count: VarInt) {
         self.flow_control_adjusted = true;
         self.max_concurrent_remote_count[dir as usize] = count.into();
         self.ensure_remote_streams(dir);
     }
 
+    pub(crate) fn set_max_unhandled(&mut self, count: Option<VarInt>) {
+        self.max_unhandled = count.map(u64::from);
+    }
+
+    pub(crate) fn set_max_out_of_order(&mut self, bytes: Option<VarInt>) {
+        self.max_out_of_order = bytes.map(u64::from);
+    }
+
+    pub(crate) fn set_max_receive_window(&mut self, bytes: Option<VarInt>) {
+        self.max_receive_window = bytes.map_or(0, u64::from);
+    }
+
+    /// Set distinct receive windows for each kind of stream, in the order of
+    /// [`TransportConfig::stream_receive_windows`](crate::TransportConfig::stream_receive_windows)
+    ///
+    /// Must be called before any streams are opened.
+    pub(crate) fn set_stream_receive_windows(&mut self, windows: (VarInt, VarInt, VarInt)) {
+        self.stream_receive_windows = (windows.0.into(), windows.1.into(), windows.2.into());
+    }
+
+    /// Upper bound for how much unacked data the peer can send us on stream `id`
+    pub(super) fn stream_receive_window(&self, id: StreamId) -> u64 {
+        match id.dir() {
+            Dir::Uni => self.stream_receive_windows.2,
+            Dir::Bi if id.initiator() == self.side => self.stream_receive_windows.0,
+            Dir::Bi => self.stream_receive_windows.1,
+        }
+    }
+
+    pub(crate) fn set_weighted_priorities(&mut self, weighted: bool) {
+        self.pending.weighted = weighted;
+    }
+
+    pub(crate) fn set_starvation_threshold(&mut self, packets: u32) {
+        self.pending.starvation_threshold = packets.into();
+    }
+
+    /// Number of times a stream with pending data waited too long to send any, see
+    /// [`ConnectionStats::starved_streams`](crate::ConnectionStats::starved_streams)
+    pub(crate) fn starved_streams(&self) -> u64 {
+        self.pending.starved
+    }
+
+    /// Restart the count reported by [`Self::starved_streams()`] from zero
+    pub(crate) fn reset_starved_streams(&mut self) {
+        self.pending.starved = 0;
+    }
+
+    /// Whether the application is handling as many remotely-initiated streams as permitted
+    fn unhandled_limited(&self, dir: Dir) -> bool {
+        self.max_unhandled.is_some_and(|max| {
+            self.unhandled.iter().filter(|id| id.dir() == dir).count() as u64 >= max
+        })
+    }
+
+    /// Record that the application has finished handling a stream it accepted
+    pub(super) fn stream_handled(&mut self, id: StreamId) {
+        if self.unhandled.remove(&id) {
+            self.ensure_remote_streams(id.dir());
+        }
+    }
+
     pub(crate) fn max_concurrent(&self, dir: Dir) -> u64 {
         self.allocated_remote_count[dir as usize]
     }
@@ -871,6 +1158,27 @@ impl StreamsState {
         (self.data_sent, self.max_data, self.unacked_data, self.send_window)
     }
 
+    /// Stream data deemed lost and queued for retransmission, summed over all streams
+    pub(crate) fn retransmit_pending(&self) -> u64 {
+        // Streams with data to retransmit are always queued as pending
+        self.pending
+            .iter()
+            .filter_map(|s| self.send.get(&s.id)?.as_ref())
+            .filter(|s| !s.is_reset())
+            .map(|s| s.pending.retransmit_pending())
+            .sum()
+    }
+
+    /// Stream data received so far, and the connection-level limit advertised to the peer
+    pub(crate) fn receive_flow_control_snapshot(&self) -> (u64, u64) {
+        (self.data_recvd, self.local_max_data)
+    }
+
+    /// Current connection-level receive window
+    pub(crate) fn receive_window(&self) -> u64 {
+        self.receive_window
+    }
+
     /// Set the receive_window and returns whether the receive_window has been
     /// expanded or shrunk: true if expanded, false if shrunk.
     pub(crate) fn set_receive_window(&mut self, receive_window: VarInt) -> bool {
@@ -897,11 +1205,33 @@ impl StreamsState {
         }
         // bidirectional OR (unidirectional AND remote)
         if bi || remote {
-            let recv = self.free_recv.pop();
+            let window = self.stream_receive_window(id);
+            // Freed streams may have had a different receive window
+            let recv = self.free_recv.pop().map(|recv| recv.reinit(window));
             assert!(self.recv.insert(id, recv).is_none());
         }
     }
 
+    /// Double the receive window if `MAX_DATA` updates are raised within two round trips of each
+    /// other, indicating that the window rather than the application limits throughput
+    fn maybe_grow_receive_window(&mut self, now: Instant, rtt: Duration) {
+        let prev = self.max_data_raised_at.replace(now);
+        if self.receive_window >= self.max_receive_window
+            || prev.is_none_or(|prev| now.saturating_duration_since(prev) >= 2 * rtt)
+        {
+            return;
+        }
+        let window = self
+            .receive_window
+            .saturating_mul(2)
+            .min(self.max_receive_window);
+        trace!(window, "growing connection receive window");
+        self.local_max_data = self
+            .local_max_data
+            .saturating_add(window - self.receive_window);
+        self.receive_window = window;
+    }
+
     /// Adds credits to the connection flow control window
     ///
     /// Returns whether a `MAX_DATA` frame should be enqueued as soon as possible.
@@ -951,7 +1281,8 @@ impl StreamsState {
     }
 
     pub(super) fn stream_recv_freed(&mut self, id: StreamId, recv: StreamRecv) {
-        self.free_recv.push(recv.free(self.stream_receive_window));
+        let window = self.stream_receive_window(id);
+        self.free_recv.push(recv.reinit(window));
         self.stream_freed(id, StreamHalf::Recv);
     }
 
@@ -1156,6 +1487,91 @@ mod tests {
         assert_eq!(client.local_max_data - initial_max, 4096);
     }
 
+    #[test]
+    fn reset_below_received_data() {
+        let mut client = make(Side::Client);
+        let id = StreamId::new(Side::Server, Dir::Uni, 0);
+        assert_eq!(
+            client
+                .received(
+                    frame::Stream {
+                        id,
+                        offset: 0,
+                        fin: false,
+                        data: Bytes::from_static(&[0; 2048]),
+                    },
+                    2048
+                )
+                .unwrap(),
+            ShouldTransmit(false)
+        );
+        let error = client
+            .received_reset(frame::ResetStream {
+                id,
+                error_code: 0u32.into(),
+                final_offset: 1024u32.into(),
+            })
+            .unwrap_err();
+        assert_eq!(error.code, TransportErrorCode::FINAL_SIZE_ERROR);
+    }
+
+    #[test]
+    fn fin_below_received_data() {
+        let mut client = make(Side::Client);
+        let id = StreamId::new(Side::Server, Dir::Uni, 0);
+        assert_eq!(
+            client
+                .received(
+                    frame::Stream {
+                        id,
+                        offset: 1024,
+                        fin: false,
+                        data: Bytes::from_static(&[0; 1024]),
+                    },
+                    1024
+                )
+                .unwrap(),
+            ShouldTransmit(false)
+        );
+        let error = client
+            .received(
+                frame::Stream {
+                    id,
+                    offset: 0,
+                    fin: true,
+                    data: Bytes::from_static(&[0; 1024]),
+                },
+                1024,
+            )
+            .unwrap_err();
+        assert_eq!(error.code, TransportErrorCode::FINAL_SIZE_ERROR);
+
+        // A reset can't change a final size set by a STREAM frame either
+        let mut client = make(Side::Client);
+        assert_eq!(
+            client
+                .received(
+                    frame::Stream {
+                        id,
+                        offset: 0,
+                        fin: true,
+                        data: Bytes::from_static(&[0; 1024]),
+                    },
+                    1024
+                )
+                .unwrap(),
+            ShouldTransmit(false)
+        );
+        let error = client
+            .received_reset(frame::ResetStream {
+                id,
+                error_code: 0u32.into(),
+                final_offset: 2048u32.into(),
+            })
+            .unwrap_err();
+        assert_eq!(error.code, TransportErrorCode::FINAL_SIZE_ERROR);
+    }
+
     #[test]
     fn duplicate_reset_flow_control() {
         let mut client = make(Side::Client);
@@ -1394,7 +1810,7 @@ mod tests {
         high.write(b"high").unwrap();
 
         let mut buf = Vec::with_capacity(40);
-        let meta = server.write_stream_frames(&mut buf, 40, true);
+        let meta = server.write_stream_frames(&mut buf, 40, true, Instant::now());
         assert_eq!(meta[0].id, id_high);
         assert_eq!(meta[1].id, id_mid);
         assert_eq!(meta[2].id, id_low);
@@ -1403,6 +1819,63 @@ mod tests {
         assert_eq!(server.pending.len(), 0);
     }
 
+    #[test]
+    fn stream_priority_extremes() {
+        let mut server = make(Side::Server);
+        server.set_params(&TransportParameters {
+            initial_max_streams_bidi: 3u32.into(),
+            initial_max_data: 300u32.into(),
+            initial_max_stream_data_bidi_remote: 100u32.into(),
+            ..TransportParameters::default()
+        });
+
+        let (mut pending, state) = (Retransmits::default(), ConnState::Established);
+        let mut streams = Streams {
+            state: &mut server,
+            conn_state: &state,
+        };
+        let ids = [
+            streams.open(Dir::Bi).unwrap(),
+            streams.open(Dir::Bi).unwrap(),
+            streams.open(Dir::Bi).unwrap(),
+        ];
+        let [id_low, id_high_a, id_high_b] = ids;
+
+        for (id, priority) in ids.into_iter().zip([i32::MIN, i32::MAX, i32::MAX]) {
+            let mut stream = SendStream {
+                id,
+                state: &mut server,
+                pending: &mut pending,
+                conn_state: &state,
+            };
+            assert_eq!(stream.priority().unwrap(), 0);
+            stream.set_priority(priority).unwrap();
+            assert_eq!(stream.priority().unwrap(), priority);
+            stream.write(&[0; 100]).unwrap();
+        }
+
+        let mut metas = vec![];
+        let mut buf = Vec::with_capacity(1024);
+        loop {
+            let buf_len = buf.len();
+            let meta = server.write_stream_frames(&mut buf, buf_len + 40, true, Instant::now());
+            if meta.is_empty() {
+                break;
+            }
+            metas.extend(meta);
+        }
+
+        // Equal priorities alternate, and the lowest priority goes last
+        let stream_ids = metas.iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(
+            stream_ids,
+            vec![
+                id_high_a, id_high_b, id_high_a, id_high_b, id_high_a, id_high_b, id_low, id_low,
+                id_low
+            ]
+        );
+    }
+
     #[test]
     fn requeue_stream_priority() {
         let mut server = make(Side::Server);
@@ -1453,7 +1926,7 @@ mod tests {
         high.set_priority(-1).unwrap();
 
         let mut buf = Vec::with_capacity(1000);
-        let meta = server.write_stream_frames(&mut buf, 40, true);
+        let meta = server.write_stream_frames(&mut buf, 40, true, Instant::now());
         assert_eq!(meta.len(), 1);
         assert_eq!(meta[0].id, id_high);
 
@@ -1461,7 +1934,7 @@ mod tests {
         assert_eq!(server.pending.len(), 2);
 
         // Send the remaining data. The initial mid priority one should go first now
-        let meta = server.write_stream_frames(&mut buf, 1000, true);
+        let meta = server.write_stream_frames(&mut buf, 1000, true, Instant::now());
         assert_eq!(meta.len(), 2);
         assert_eq!(meta[0].id, id_mid);
         assert_eq!(meta[1].id, id_high);
@@ -1522,7 +1995,7 @@ mod tests {
             // loop until all the streams are written
             loop {
                 let buf_len = buf.len();
-                let meta = server.write_stream_frames(&mut buf, buf_len + 40, fair);
+                let meta = server.write_stream_frames(&mut buf, buf_len + 40, fair, Instant::now());
                 if meta.is_empty() {
                     break;
                 }
@@ -1593,7 +2066,7 @@ mod tests {
 
         // Write the first chunk of stream_a
         let buf_len = buf.len();
-        let meta = server.write_stream_frames(&mut buf, buf_len + 40, false);
+        let meta = server.write_stream_frames(&mut buf, buf_len + 40, false, Instant::now());
         assert!(!meta.is_empty());
         metas.extend(meta);
 
@@ -1610,7 +2083,7 @@ mod tests {
         // loop until all the streams are written
         loop {
             let buf_len = buf.len();
-            let meta = server.write_stream_frames(&mut buf, buf_len + 40, false);
+            let meta = server.write_stream_frames(&mut buf, buf_len + 40, false, Instant::now());
             if meta.is_empty() {
                 break;
             }
@@ -1629,6 +2102,117 @@ mod tests {
         );
     }
 
+    #[test]
+    fn weighted_stream_priority() {
+        for weighted in [true, false] {
+            let mut server = make(Side::Server);
+            server.set_weighted_priorities(weighted);
+            server.set_params(&TransportParameters {
+                initial_max_streams_bidi: 3u32.into(),
+                initial_max_data: (1024 * 1024u32).into(),
+                initial_max_stream_data_bidi_remote: (1024 * 1024u32).into(),
+                ..TransportParameters::default()
+            });
+
+            let (mut pending, state) = (Retransmits::default(), ConnState::Established);
+            let mut streams = Streams {
+                state: &mut server,
+                conn_state: &state,
+            };
+            let ids = [
+                streams.open(Dir::Bi).unwrap(),
+                streams.open(Dir::Bi).unwrap(),
+                streams.open(Dir::Bi).unwrap(),
+            ];
+
+            for (&id, priority) in ids.iter().zip([1, 2, 4]) {
+                let mut stream = SendStream {
+                    id,
+                    state: &mut server,
+                    pending: &mut pending,
+                    conn_state: &state,
+                };
+                stream.set_priority(priority).unwrap();
+                stream.write(&[0; 100_000]).unwrap();
+            }
+
+            // Send ten full rounds' worth of packets
+            let mut sent = [0u64; 3];
+            let mut buf = Vec::with_capacity(1200);
+            for _ in 0..70 {
+                buf.clear();
+                for meta in server.write_stream_frames(&mut buf, 1200, true, Instant::now()) {
+                    let i = ids.iter().position(|&id| id == meta.id).unwrap();
+                    sent[i] += meta.offsets.end - meta.offsets.start;
+                }
+            }
+
+            if weighted {
+                // Every stream makes progress, in proportion to its priority
+                let [low, mid, high] = sent.map(|n| n as f64);
+                assert!(low > 0.0);
+                assert!((mid / low - 2.0).abs() < 0.1, "{sent:?}");
+                assert!((high / low - 4.0).abs() < 0.2, "{sent:?}");
+            } else {
+                // The highest priority stream starves the others
+                assert_eq!(sent[0], 0);
+                assert_eq!(sent[1], 0);
+                assert!(sent[2] > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn starved_streams() {
+        for weighted in [true, false] {
+            let mut server = make(Side::Server);
+            server.set_weighted_priorities(weighted);
+            server.set_starvation_threshold(20);
+            server.set_params(&TransportParameters {
+                initial_max_streams_bidi: 3u32.into(),
+                initial_max_data: (1024 * 1024u32).into(),
+                initial_max_stream_data_bidi_remote: (1024 * 1024u32).into(),
+                ..TransportParameters::default()
+            });
+
+            let (mut pending, state) = (Retransmits::default(), ConnState::Established);
+            let mut streams = Streams {
+                state: &mut server,
+                conn_state: &state,
+            };
+            let ids = [
+                streams.open(Dir::Bi).unwrap(),
+                streams.open(Dir::Bi).unwrap(),
+                streams.open(Dir::Bi).unwrap(),
+            ];
+
+            for (&id, priority) in ids.iter().zip([1, 2, 4]) {
+                let mut stream = SendStream {
+                    id,
+                    state: &mut server,
+                    pending: &mut pending,
+                    conn_state: &state,
+                };
+                stream.set_priority(priority).unwrap();
+                stream.write(&[0; 100_000]).unwrap();
+            }
+
+            let mut buf = Vec::with_capacity(1200);
+            for _ in 0..70 {
+                buf.clear();
+                server.write_stream_frames(&mut buf, 1200, true, Instant::now());
+            }
+
+            if weighted {
+                // Every stream gets a turn well within the threshold
+                assert_eq!(server.starved_streams(), 0);
+            } else {
+                // The two lower priority streams never get to send, and each wait is counted once
+                assert_eq!(server.starved_streams(), 2);
+            }
+        }
+    }
+
     #[test]
     fn stop_finished() {
         let mut client = make(Side::Client);
@@ -20,10 +20,14 @@ pub(crate) enum Timer {
     PushNewCid = 7,
     /// When to send an immediate ACK if there are unacked ack-eliciting packets of the peer
     MaxAckDelay = 8,
+    /// When data written with a deadline may have gone unacknowledged for too long
+    StreamDeadline = 9,
+    /// When to send a `PING` frame to refresh NAT bindings after a period without sending
+    NatKeepAlive = 10,
 }
 
 impl Timer {
-    pub(crate) const VALUES: [Self; 9] = [
+    pub(crate) const VALUES: [Self; 11] = [
         Self::LossDetection,
         Self::Idle,
         Self::Close,
@@ -33,13 +37,32 @@ impl Timer {
         Self::Pacing,
         Self::PushNewCid,
         Self::MaxAckDelay,
+        Self::StreamDeadline,
+        Self::NatKeepAlive,
     ];
+
+    /// Name used in diagnostics
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::LossDetection => "loss_detection",
+            Self::Idle => "idle",
+            Self::Close => "close",
+            Self::KeyDiscard => "key_discard",
+            Self::PathValidation => "path_validation",
+            Self::KeepAlive => "keep_alive",
+            Self::Pacing => "pacing",
+            Self::PushNewCid => "push_new_cid",
+            Self::MaxAckDelay => "max_ack_delay",
+            Self::StreamDeadline => "stream_deadline",
+            Self::NatKeepAlive => "nat_keep_alive",
+        }
+    }
 }
 
 /// A table of data associated with each distinct kind of `Timer`
 #[derive(Debug, Copy, Clone, Default)]
 pub(crate) struct TimerTable {
-    data: [Option<Instant>; 10],
+    data: [Option<Instant>; 11],
 }
 
 impl TimerTable {
@@ -8,7 +8,7 @@
 //! Note that usage of any protocol (version) other than TLS 1.3 does not conform to any
 //! published versions of the specification, and will not be supported in QUIC v1.
 
-use std::{any::Any, str, sync::Arc};
+use std::{any::Any, net::SocketAddr, str, sync::Arc};
 
 use bytes::BytesMut;
 
@@ -37,6 +37,14 @@ pub trait Session: Send + Sync + 'static {
     /// Get the peer's identity, if available
     fn peer_identity(&self) -> Option<Box<dyn Any>>;
 
+    /// Application protocol negotiated via ALPN, if any
+    ///
+    /// Returns `None` until the protocol has been chosen, and on connections that didn't negotiate
+    /// one. The default implementation always returns `None`.
+    fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        None
+    }
+
     /// Get the 0-RTT keys if available (clients only)
     ///
     /// On the client side, this method can be used to see if 0-RTT key material is available
@@ -118,6 +126,30 @@ pub trait ClientConfig: Send + Sync {
         server_name: &str,
         params: &TransportParameters,
     ) -> Result<Box<dyn Session>, ConnectError>;
+
+    /// Start a client session for a connection to `remote`
+    ///
+    /// Lets the session take the server's address into account, e.g. when verifying its
+    /// certificate. The default implementation ignores `remote` and calls
+    /// [`start_session`](Self::start_session).
+    #[allow(unused_variables)]
+    fn start_session_with_remote(
+        self: Arc<Self>,
+        version: u32,
+        server_name: &str,
+        remote: SocketAddr,
+        params: &TransportParameters,
+    ) -> Result<Box<dyn Session>, ConnectError> {
+        self.start_session(version, server_name, params)
+    }
+
+    /// Whether a session started now for `server_name` could attempt 0-RTT
+    ///
+    /// Must not consume any resumption state. The default implementation returns `false`.
+    #[allow(unused_variables)]
+    fn can_resume_0rtt(&self, server_name: &str) -> bool {
+        false
+    }
 }
 
 /// Server-side configuration for the crypto protocol
@@ -139,6 +171,16 @@ pub trait ServerConfig: Send + Sync {
         version: u32,
         params: &TransportParameters,
     ) -> Box<dyn Session>;
+
+    /// Application protocol that a session with this configuration would negotiate
+    ///
+    /// `client_hello` is the start of the client's handshake data, as carried in its first
+    /// Initial packet. Returns `None` if no protocol would be negotiated, or if `client_hello` is
+    /// incomplete. The default implementation always returns `None`.
+    #[allow(unused_variables)]
+    fn alpn_protocol(&self, client_hello: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 /// Keys used to protect packet payloads
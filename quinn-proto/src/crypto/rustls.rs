@@ -1,4 +1,11 @@
-use std::{any::Any, io, str, sync::Arc};
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    fmt, io,
+    net::SocketAddr,
+    str,
+    sync::{Arc, Mutex},
+};
 
 #[cfg(all(feature = "aws-lc-rs", not(feature = "ring")))]
 use aws_lc_rs::aead;
@@ -6,11 +13,12 @@ use bytes::BytesMut;
 #[cfg(feature = "ring")]
 use ring::aead;
 pub use rustls::Error;
-#[cfg(feature = "__rustls-post-quantum-test")]
-use rustls::NamedGroup;
 use rustls::{
-    self, CipherSuite,
-    client::danger::ServerCertVerifier,
+    self, CipherSuite, NamedGroup,
+    client::{
+        ClientSessionMemoryCache, ClientSessionStore, Resumption, Tls12ClientSessionValue,
+        Tls13ClientSessionValue, danger::ServerCertVerifier,
+    },
     pki_types::{CertificateDer, PrivateKeyDer, ServerName},
     quic::{Connection, HeaderProtectionKey, KeyChange, PacketKey, Secrets, Suite, Version},
 };
@@ -18,7 +26,8 @@ use rustls::{
 use rustls_platform_verifier::BuilderVerifierExt;
 
 use crate::{
-    ConnectError, ConnectionId, Side, TransportError, TransportErrorCode,
+    ConnectError, ConnectionId, Duration, Instant, QUIC_V2, Side, TransportError,
+    TransportErrorCode,
     crypto::{
         self, CryptoError, ExportKeyingMaterialError, HeaderKey, KeyPair, Keys, UnsupportedVersion,
     },
@@ -87,6 +96,10 @@ impl crypto::Session for TlsSession {
         })
     }
 
+    fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.inner.alpn_protocol().map(|x| x.into())
+    }
+
     fn early_crypto(&self) -> Option<(Box<dyn HeaderKey>, Box<dyn crypto::PacketKey>)> {
         let keys = self.inner.zero_rtt_keys()?;
         Some((Box::new(keys.header), Box::new(keys.packet)))
@@ -188,6 +201,7 @@ impl crypto::Session for TlsSession {
         let (nonce, key) = match self.version {
             Version::V1 => (RETRY_INTEGRITY_NONCE_V1, RETRY_INTEGRITY_KEY_V1),
             Version::V1Draft => (RETRY_INTEGRITY_NONCE_DRAFT, RETRY_INTEGRITY_KEY_DRAFT),
+            Version::V2 => (RETRY_INTEGRITY_NONCE_V2, RETRY_INTEGRITY_KEY_V2),
             _ => unreachable!(),
         };
 
@@ -225,6 +239,13 @@ const RETRY_INTEGRITY_NONCE_V1: [u8; 12] = [
     0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
 ];
 
+const RETRY_INTEGRITY_KEY_V2: [u8; 16] = [
+    0x8f, 0xb4, 0xb0, 0x1b, 0x56, 0xac, 0x48, 0xe2, 0x60, 0xfb, 0xcb, 0xce, 0xad, 0x7c, 0xcc, 0x92,
+];
+const RETRY_INTEGRITY_NONCE_V2: [u8; 12] = [
+    0xd8, 0x69, 0x69, 0xbc, 0x2d, 0x7c, 0x6d, 0x99, 0x90, 0xef, 0xb0, 0x4a,
+];
+
 impl crypto::HeaderKey for Box<dyn HeaderProtectionKey> {
     fn decrypt(&self, pn_offset: usize, packet: &mut [u8]) {
         let (header, sample) = packet.split_at_mut(pn_offset + 4);
@@ -286,11 +307,19 @@ pub struct HandshakeData {
 /// `into_0rtt` to proceed if it recognizes the server name, and defaults to an in-memory cache of
 /// 256 server names.
 ///
+/// [`ClientConfig::can_resume_0rtt()`][can_resume] is only supported by configurations created
+/// by Quinn, which use a session store that can be inspected without consuming tickets; it
+/// always returns `false` for a wrapped custom [`rustls::ClientConfig`].
+///
 /// [root_certs]: crate::config::ClientConfig::with_root_certificates()
 /// [platform]: crate::config::ClientConfig::try_with_platform_verifier()
+/// [can_resume]: crate::config::ClientConfig::can_resume_0rtt()
 pub struct QuicClientConfig {
     pub(crate) inner: Arc<rustls::ClientConfig>,
     initial: Suite,
+    verifier_factory: Option<Arc<VerifierCache>>,
+    /// The session store of `inner`, if we installed it
+    session_store: Option<Arc<SessionStore>>,
 }
 
 impl QuicClientConfig {
@@ -304,11 +333,15 @@ impl QuicClientConfig {
             .with_no_client_auth();
 
         inner.enable_early_data = true;
+        let session_store = Arc::new(SessionStore::new());
+        inner.resumption = Resumption::store(session_store.clone());
         Ok(Self {
             // We're confident that the *ring* default provider contains TLS13_AES_128_GCM_SHA256
             initial: initial_suite_from_provider(inner.crypto_provider())
                 .expect("no initial cipher suite found"),
             inner: Arc::new(inner),
+            verifier_factory: None,
+            session_store: Some(session_store),
         })
     }
 
@@ -317,12 +350,16 @@ impl QuicClientConfig {
     /// QUIC requires that TLS 1.3 be enabled. Advanced users can use any [`rustls::ClientConfig`] that
     /// satisfies this requirement.
     pub(crate) fn new(verifier: Arc<dyn ServerCertVerifier>) -> Self {
-        let inner = Self::inner(verifier);
+        let mut inner = Self::inner(verifier);
+        let session_store = Arc::new(SessionStore::new());
+        inner.resumption = Resumption::store(session_store.clone());
         Self {
             // We're confident that the *ring* default provider contains TLS13_AES_128_GCM_SHA256
             initial: initial_suite_from_provider(inner.crypto_provider())
                 .expect("no initial cipher suite found"),
             inner: Arc::new(inner),
+            verifier_factory: None,
+            session_store: Some(session_store),
         }
     }
 
@@ -334,11 +371,52 @@ impl QuicClientConfig {
         initial: Suite,
     ) -> Result<Self, NoInitialCipherSuite> {
         match initial.suite.common.suite {
-            CipherSuite::TLS13_AES_128_GCM_SHA256 => Ok(Self { inner, initial }),
+            CipherSuite::TLS13_AES_128_GCM_SHA256 => Ok(Self {
+                inner,
+                initial,
+                verifier_factory: None,
+                session_store: None,
+            }),
             _ => Err(NoInitialCipherSuite { specific: true }),
         }
     }
 
+    /// Construct a separate server certificate verifier for each connection
+    ///
+    /// `factory` is called with the server's address when a connection is started, and the
+    /// verifier it returns takes the place of the inner [`rustls::ClientConfig`]'s verifier for
+    /// connections to that address. This lets a verifier correlate the certificate it's asked to
+    /// verify with the connection it belongs to, e.g. to apply per-address authorization policies.
+    ///
+    /// rustls only resumes sessions with the verifier that established them, so the verifiers of
+    /// the most recently used addresses are kept and reused rather than constructed afresh for
+    /// each connection. Session resumption and 0-RTT therefore work as usual for connections to
+    /// the same address, but not across addresses.
+    ///
+    /// # Security
+    ///
+    /// The address is not authenticated in any way: it is the address the connection was opened
+    /// to, which an on-path attacker can answer from. A verifier must still validate the
+    /// certificate chain and the server name as usual, and should only use the address to reject
+    /// certificates it would otherwise accept, never to accept ones it would otherwise reject.
+    /// The address also stays fixed at its initial value if the connection later migrates.
+    ///
+    /// This is client-side only: rustls fixes a [`rustls::ServerConfig`]'s client certificate
+    /// verifier when it is built. Servers can instead check the client's
+    /// [`remote_address()`](crate::Connection::remote_address) against the certificate from
+    /// [`crypto_session()`](crate::Connection::crypto_session)`.peer_identity()` once the
+    /// handshake completes.
+    pub fn with_verifier_factory<F>(mut self, factory: F) -> Self
+    where
+        F: Fn(SocketAddr) -> Arc<dyn ServerCertVerifier> + Send + Sync + 'static,
+    {
+        self.verifier_factory = Some(Arc::new(VerifierCache {
+            factory: Box::new(factory),
+            configs: Mutex::default(),
+        }));
+        self
+    }
+
     pub(crate) fn inner(verifier: Arc<dyn ServerCertVerifier>) -> rustls::ClientConfig {
         // Keep in sync with `with_platform_verifier()` above
         let mut config = rustls::ClientConfig::builder_with_provider(configured_provider())
@@ -353,9 +431,10 @@ impl QuicClientConfig {
     }
 }
 
-impl crypto::ClientConfig for QuicClientConfig {
-    fn start_session(
-        self: Arc<Self>,
+impl QuicClientConfig {
+    fn start(
+        &self,
+        inner: Arc<rustls::ClientConfig>,
         version: u32,
         server_name: &str,
         params: &TransportParameters,
@@ -367,7 +446,7 @@ impl crypto::ClientConfig for QuicClientConfig {
             next_secrets: None,
             inner: rustls::quic::Connection::Client(
                 rustls::quic::ClientConnection::new(
-                    self.inner.clone(),
+                    inner,
                     version,
                     ServerName::try_from(server_name)
                         .map_err(|_| ConnectError::InvalidServerName(server_name.into()))?
@@ -381,6 +460,204 @@ impl crypto::ClientConfig for QuicClientConfig {
     }
 }
 
+impl crypto::ClientConfig for QuicClientConfig {
+    fn start_session(
+        self: Arc<Self>,
+        version: u32,
+        server_name: &str,
+        params: &TransportParameters,
+    ) -> Result<Box<dyn crypto::Session>, ConnectError> {
+        self.start(self.inner.clone(), version, server_name, params)
+    }
+
+    fn start_session_with_remote(
+        self: Arc<Self>,
+        version: u32,
+        server_name: &str,
+        remote: SocketAddr,
+        params: &TransportParameters,
+    ) -> Result<Box<dyn crypto::Session>, ConnectError> {
+        let inner = match &self.verifier_factory {
+            Some(cache) => cache.config(&self.inner, remote),
+            None => self.inner.clone(),
+        };
+        self.start(inner, version, server_name, params)
+    }
+
+    fn can_resume_0rtt(&self, server_name: &str) -> bool {
+        if !self.inner.enable_early_data {
+            return false;
+        }
+        let (Some(store), Ok(server_name)) =
+            (&self.session_store, ServerName::try_from(server_name))
+        else {
+            return false;
+        };
+        store.can_resume_0rtt(&server_name)
+    }
+}
+
+/// A client session store which can tell whether 0-RTT is possible without consuming a ticket
+///
+/// Stores TLS 1.3 tickets itself, with the same limits as rustls' default in-memory cache, and
+/// leaves everything else to such a cache.
+struct SessionStore {
+    inner: ClientSessionMemoryCache,
+    tickets: Mutex<Tickets>,
+}
+
+impl SessionStore {
+    fn new() -> Self {
+        Self {
+            inner: ClientSessionMemoryCache::new(MAX_SERVERS * MAX_TICKETS_PER_SERVER),
+            tickets: Mutex::default(),
+        }
+    }
+
+    /// Whether the ticket that a connection to `server_name` would use permits early data
+    ///
+    /// The lifetime the server assigned to the ticket isn't exposed by rustls, so tickets are
+    /// assumed to be valid for the longest lifetime permitted by RFC 8446. A ticket which the
+    /// server issued with a shorter lifetime may therefore be reported as usable after it expired,
+    /// in which case the connection falls back to a full handshake.
+    fn can_resume_0rtt(&self, server_name: &ServerName<'_>) -> bool {
+        let tickets = self.tickets.lock().unwrap();
+        let Some(ticket) = tickets
+            .by_server
+            .get(server_name)
+            .and_then(|tickets| tickets.back())
+        else {
+            return false;
+        };
+        ticket.value.max_early_data_size() > 0 && ticket.received.elapsed() < MAX_TICKET_LIFETIME
+    }
+}
+
+impl ClientSessionStore for SessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.inner.set_kx_hint(server_name, group);
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.inner.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.inner.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.inner.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.inner.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        let mut tickets = self.tickets.lock().unwrap();
+        let Tickets { servers, by_server } = &mut *tickets;
+        if !by_server.contains_key(&server_name) {
+            if servers.len() == MAX_SERVERS {
+                if let Some(oldest) = servers.pop_front() {
+                    by_server.remove(&oldest);
+                }
+            }
+            servers.push_back(server_name.clone());
+        }
+        let server_tickets = by_server
+            .entry(server_name)
+            .or_insert_with(|| VecDeque::with_capacity(MAX_TICKETS_PER_SERVER));
+        if server_tickets.len() == MAX_TICKETS_PER_SERVER {
+            server_tickets.pop_front();
+        }
+        server_tickets.push_back(Ticket {
+            value,
+            received: Instant::now(),
+        });
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        let mut tickets = self.tickets.lock().unwrap();
+        let ticket = tickets.by_server.get_mut(server_name)?.pop_back()?;
+        Some(ticket.value)
+    }
+}
+
+impl fmt::Debug for SessionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Tickets are sensitive, so leave them out
+        f.debug_struct("SessionStore").finish_non_exhaustive()
+    }
+}
+
+/// TLS 1.3 tickets held by a [`SessionStore`]
+#[derive(Default)]
+struct Tickets {
+    /// Servers with tickets, oldest first
+    servers: VecDeque<ServerName<'static>>,
+    /// Tickets for each server, oldest first
+    by_server: HashMap<ServerName<'static>, VecDeque<Ticket>>,
+}
+
+struct Ticket {
+    value: Tls13ClientSessionValue,
+    /// When the ticket was received
+    received: Instant,
+}
+
+/// Number of servers to keep tickets for, matching rustls' default cache of 256 tickets
+const MAX_SERVERS: usize = 32;
+/// Number of tickets to keep for each server, matching rustls' default cache
+const MAX_TICKETS_PER_SERVER: usize = 8;
+/// Longest ticket lifetime permitted by RFC 8446 §4.6.1
+const MAX_TICKET_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Verifiers constructed by a [`ServerCertVerifierFactory`], kept so that sessions can be resumed
+struct VerifierCache {
+    factory: Box<ServerCertVerifierFactory>,
+    /// Configurations using the verifier for each address, oldest first
+    configs: Mutex<VecDeque<(SocketAddr, Arc<rustls::ClientConfig>)>>,
+}
+
+impl VerifierCache {
+    /// Configuration to use for a connection to `remote`, derived from `inner`
+    fn config(
+        &self,
+        inner: &rustls::ClientConfig,
+        remote: SocketAddr,
+    ) -> Arc<rustls::ClientConfig> {
+        let mut configs = self.configs.lock().unwrap();
+        if let Some((_, config)) = configs.iter().find(|(addr, _)| *addr == remote) {
+            return config.clone();
+        }
+
+        let mut config = inner.clone();
+        config
+            .dangerous()
+            .set_certificate_verifier((self.factory)(remote));
+        let config = Arc::new(config);
+        if configs.len() == MAX_SERVERS {
+            configs.pop_front();
+        }
+        configs.push_back((remote, config.clone()));
+        config
+    }
+}
+
+/// Constructs a server certificate verifier for a connection to the given address
+///
+/// See [`QuicClientConfig::with_verifier_factory()`].
+pub type ServerCertVerifierFactory =
+    dyn Fn(SocketAddr) -> Arc<dyn ServerCertVerifier> + Send + Sync;
+
 impl TryFrom<rustls::ClientConfig> for QuicClientConfig {
     type Error = NoInitialCipherSuite;
 
@@ -397,6 +674,8 @@ impl TryFrom<Arc<rustls::ClientConfig>> for QuicClientConfig {
             initial: initial_suite_from_provider(inner.crypto_provider())
                 .ok_or(NoInitialCipherSuite { specific: false })?,
             inner,
+            verifier_factory: None,
+            session_store: None,
         })
     }
 }
@@ -429,14 +708,19 @@ impl std::error::Error for NoInitialCipherSuite {}
 ///
 /// Quinn implicitly constructs a `QuicServerConfig` with reasonable defaults within
 /// [`ServerConfig::with_single_cert()`][single]. Alternatively, `QuicServerConfig`'s [`TryFrom`]
-/// implementation or `with_initial` method can be used to wrap around a custom
-/// [`rustls::ServerConfig`], in which case care should be taken around certain points:
+/// implementation, `with_initial` method, or [`ServerConfig::with_rustls()`][rustls] can be used
+/// to wrap around a custom [`rustls::ServerConfig`], in which case care should be taken around
+/// certain points:
 ///
 /// - If `max_early_data_size` is not set to `u32::MAX`, the server will not be able to accept
 ///   incoming 0-RTT data. QUIC prohibits `max_early_data_size` values other than 0 or `u32::MAX`.
 /// - The `rustls::ServerConfig` must have TLS 1.3 support enabled for conversion to succeed.
 ///
+/// The wrapped `rustls::ServerConfig` is held in an [`Arc`], so the same configuration can be
+/// shared by many endpoints without rebuilding it.
+///
 /// [single]: crate::config::ServerConfig::with_single_cert()
+/// [rustls]: crate::config::ServerConfig::with_rustls()
 pub struct QuicServerConfig {
     inner: Arc<rustls::ServerConfig>,
     initial: Suite,
@@ -544,6 +828,7 @@ impl crypto::ServerConfig for QuicServerConfig {
         let (nonce, key) = match version {
             Version::V1 => (RETRY_INTEGRITY_NONCE_V1, RETRY_INTEGRITY_KEY_V1),
             Version::V1Draft => (RETRY_INTEGRITY_NONCE_DRAFT, RETRY_INTEGRITY_KEY_DRAFT),
+            Version::V2 => (RETRY_INTEGRITY_NONCE_V2, RETRY_INTEGRITY_KEY_V2),
             _ => unreachable!(),
         };
 
@@ -562,6 +847,65 @@ impl crypto::ServerConfig for QuicServerConfig {
         result.copy_from_slice(tag.as_ref());
         result
     }
+
+    fn alpn_protocol(&self, client_hello: &[u8]) -> Option<Vec<u8>> {
+        // Like rustls, prefer the server's order of protocols
+        let offered = offered_protocols(client_hello)?;
+        self.inner
+            .alpn_protocols
+            .iter()
+            .find(|protocol| offered.contains(&protocol.as_slice()))
+            .cloned()
+    }
+}
+
+/// Protocols offered by the ALPN extension of a TLS ClientHello message
+///
+/// Returns `None` if the message is incomplete or malformed.
+fn offered_protocols(mut msg: &[u8]) -> Option<Vec<&[u8]>> {
+    const CLIENT_HELLO: u8 = 1;
+    const ALPN: [u8; 2] = 16u16.to_be_bytes();
+
+    fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if buf.len() < len {
+            return None;
+        }
+        let (head, tail) = buf.split_at(len);
+        *buf = tail;
+        Some(head)
+    }
+
+    /// Take a vector prefixed by its length, encoded in `len_size` bytes
+    fn take_vec<'a>(buf: &mut &'a [u8], len_size: usize) -> Option<&'a [u8]> {
+        let len = take(buf, len_size)?
+            .iter()
+            .fold(0, |len, &byte| len << 8 | byte as usize);
+        take(buf, len)
+    }
+
+    if take(&mut msg, 1)? != [CLIENT_HELLO] {
+        return None;
+    }
+    let mut body = take_vec(&mut msg, 3)?;
+    take(&mut body, 2 + 32)?; // legacy_version, random
+    take_vec(&mut body, 1)?; // legacy_session_id
+    take_vec(&mut body, 2)?; // cipher_suites
+    take_vec(&mut body, 1)?; // legacy_compression_methods
+    let mut extensions = take_vec(&mut body, 2)?;
+    while !extensions.is_empty() {
+        let ty = take(&mut extensions, 2)?;
+        let mut data = take_vec(&mut extensions, 2)?;
+        if ty != ALPN {
+            continue;
+        }
+        let mut list = take_vec(&mut data, 2)?;
+        let mut protocols = Vec::new();
+        while !list.is_empty() {
+            protocols.push(take_vec(&mut list, 1)?);
+        }
+        return Some(protocols);
+    }
+    Some(Vec::new())
 }
 
 pub(crate) fn initial_suite_from_provider(
@@ -651,6 +995,7 @@ fn interpret_version(version: u32) -> Result<Version, UnsupportedVersion> {
     match version {
         0xff00_001d..=0xff00_0020 => Ok(Version::V1Draft),
         0x0000_0001 | 0xff00_0021..=0xff00_0022 => Ok(Version::V1),
+        QUIC_V2 => Ok(Version::V2),
         _ => Err(UnsupportedVersion),
     }
 }
@@ -29,7 +29,7 @@ use crate::{
     },
     shared::{
         ConnectionEvent, ConnectionEventInner, ConnectionId, DatagramConnectionEvent, EcnCodepoint,
-        EndpointEvent, EndpointEventInner, IssuedCid,
+        EndpointEvent, EndpointEventInner, IssuedCid, PacketTooBigEvent,
     },
     token::{IncomingToken, InvalidRetryTokenError, Token, TokenPayload},
     transport_parameters::{PreferredAddress, TransportParameters},
@@ -53,6 +53,10 @@ pub struct Endpoint {
     /// Buffered Initial and 0-RTT messages for pending incoming connections
     incoming_buffers: Slab<IncomingBuffer>,
     all_incoming_buffers_total_bytes: u64,
+    /// Rate at which handshakes are started for incoming connection attempts
+    handshake_rate: HandshakeRate,
+    /// Incoming connection attempts turned away due to `max_handshakes_per_second`
+    throttled_handshakes: u64,
 }
 
 impl Endpoint {
@@ -79,10 +83,18 @@ impl Endpoint {
             last_stateless_reset: None,
             incoming_buffers: Slab::new(),
             all_incoming_buffers_total_bytes: 0,
+            handshake_rate: HandshakeRate::default(),
+            throttled_handshakes: 0,
         }
     }
 
     /// Replace the server configuration, affecting new incoming connections only
+    ///
+    /// Connection attempts already received keep the configuration in effect when they arrived,
+    /// whether or not they were accepted yet. With `None`, Initial packets starting new
+    /// connections are dropped without any response, as by an endpoint that only acts as a
+    /// client, while connections initiated with [`connect()`](Self::connect) are unaffected
+    /// either way.
     pub fn set_server_config(&mut self, server_config: Option<Arc<ServerConfig>>) {
         self.server_config = server_config;
     }
@@ -132,6 +144,10 @@ impl Endpoint {
     }
 
     /// Process an incoming UDP datagram
+    ///
+    /// `remote` is the datagram's source address, and `local_ip` the destination IP address it was
+    /// received on, if known. If the result is a [`DatagramEvent::Response`], the datagram to send
+    /// has been written to `buf`.
     pub fn handle(
         &mut self,
         now: Instant,
@@ -163,6 +179,8 @@ impl Endpoint {
             }) => {
                 if self.server_config.is_none() {
                     debug!("dropping packet with unsupported version");
+                    self.config
+                        .packet_dropped(DropReason::UnsupportedVersion, remote);
                     return None;
                 }
                 trace!("sending version negotiation");
@@ -191,6 +209,8 @@ impl Endpoint {
             }
             Err(e) => {
                 trace!("malformed header: {}", e);
+                self.config
+                    .packet_dropped(DropReason::InvalidFormat, remote);
                 return None;
             }
         };
@@ -203,14 +223,8 @@ impl Endpoint {
             match route_to {
                 RouteDatagramTo::Incoming(incoming_idx) => {
                     let incoming_buffer = &mut self.incoming_buffers[incoming_idx];
-                    let config = match &self.server_config {
-                        Some(config) => config,
-                        None => {
-                            tracing::error!("Server config missing when handling incoming packet");
-                            return None;
-                        }
-                    };
-
+                    // The server configuration may have been replaced or removed since
+                    let config = &incoming_buffer.server_config;
                     if incoming_buffer
                         .total_bytes
                         .checked_add(datagram_len as u64)
@@ -223,6 +237,9 @@ impl Endpoint {
                         incoming_buffer.datagrams.push(event);
                         incoming_buffer.total_bytes += datagram_len as u64;
                         self.all_incoming_buffers_total_bytes += datagram_len as u64;
+                    } else {
+                        self.config
+                            .packet_dropped(DropReason::IncomingBufferFull, remote);
                     }
 
                     None
@@ -241,23 +258,76 @@ impl Endpoint {
                 "ignoring non-initial packet for unknown connection {}",
                 dst_cid
             );
+            self.config
+                .packet_dropped(DropReason::UnknownConnectionId, remote);
             None
         } else if !event.first_decode.is_initial()
             && self.local_cid_generator.validate(dst_cid).is_err()
         {
             debug!("dropping packet with invalid CID");
+            self.config
+                .packet_dropped(DropReason::UnknownConnectionId, remote);
             None
         } else if dst_cid.is_empty() {
             trace!("dropping unrecognized short packet without ID");
+            self.config
+                .packet_dropped(DropReason::UnknownConnectionId, remote);
             None
         } else {
             // If we got this far, we're receiving a seemingly valid packet for an unknown
             // connection. Send a stateless reset if possible.
+            self.config
+                .packet_dropped(DropReason::UnknownConnectionId, remote);
             self.stateless_reset(now, datagram_len, addresses, dst_cid, buf)
                 .map(DatagramEvent::Response)
         }
     }
 
+    /// Process an ICMP "packet too big" message received for a datagram sent to `remote`
+    ///
+    /// `mtu` is the IP-level MTU reported by the message, and `quoted` the part of the offending
+    /// datagram's UDP payload included in it. Returns the events to pass to the connections which
+    /// might have sent that datagram. Connections validate the message themselves and only act on
+    /// it if [`MtuDiscoveryConfig::packet_too_big`](crate::MtuDiscoveryConfig::packet_too_big) is
+    /// enabled.
+    ///
+    /// Connections are matched by the address they started out with or last received a reset token
+    /// for, so messages concerning a connection which has since migrated might be missed.
+    pub fn handle_packet_too_big(
+        &mut self,
+        now: Instant,
+        remote: SocketAddr,
+        mtu: u32,
+        quoted: &[u8],
+    ) -> Vec<(ConnectionHandle, ConnectionEvent)> {
+        // Subtract the IP and UDP headers to get the largest UDP payload that fits
+        let overhead = match remote.ip() {
+            IpAddr::V4(_) => 28,
+            IpAddr::V6(ip) if ip.to_ipv4_mapped().is_some() => 28,
+            IpAddr::V6(_) => 48,
+        };
+        let mtu = u16::try_from(mtu.saturating_sub(overhead)).unwrap_or(u16::MAX);
+        // Only the header up to the destination CID is needed to validate the message
+        let quoted = &quoted[..quoted.len().min(6 + MAX_CID_SIZE)];
+        self.connections
+            .iter()
+            .filter(|(_, meta)| {
+                meta.addresses.remote == remote
+                    || meta.reset_token.is_some_and(|(addr, _)| addr == remote)
+            })
+            .map(|(ch, _)| {
+                let event = PacketTooBigEvent {
+                    now,
+                    remote,
+                    mtu,
+                    quoted: quoted.to_vec(),
+                };
+                let event = ConnectionEvent(ConnectionEventInner::PacketTooBig(event));
+                (ConnectionHandle(ch), event)
+            })
+            .collect()
+    }
+
     fn stateless_reset(
         &mut self,
         now: Instant,
@@ -274,13 +344,10 @@ impl Endpoint {
             return None;
         }
 
-        /// Minimum amount of padding for the stateless reset to look like a short-header packet
-        const MIN_PADDING_LEN: usize = 5;
-
         // Prevent amplification attacks and reset loops by ensuring we pad to at most 1 byte
         // smaller than the inciting packet.
         let max_padding_len = match inciting_dgram_len.checked_sub(RESET_TOKEN_SIZE) {
-            Some(headroom) if headroom > MIN_PADDING_LEN => headroom - 1,
+            Some(headroom) if headroom > MIN_RESET_PADDING_LEN => headroom - 1,
             _ => {
                 debug!(
                     "ignoring unexpected {} byte packet: not larger than minimum stateless reset size",
@@ -295,19 +362,13 @@ impl Endpoint {
             dst_cid, addresses.remote
         );
         self.last_stateless_reset = Some(now);
-        // Resets with at least this much padding can't possibly be distinguished from real packets
-        const IDEAL_MIN_PADDING_LEN: usize = MIN_PADDING_LEN + MAX_CID_SIZE;
-        let padding_len = if max_padding_len <= IDEAL_MIN_PADDING_LEN {
+        let padding_len = if max_padding_len <= IDEAL_MIN_RESET_PADDING_LEN {
             max_padding_len
         } else {
             self.rng
-                .random_range(IDEAL_MIN_PADDING_LEN..max_padding_len)
+                .random_range(IDEAL_MIN_RESET_PADDING_LEN..max_padding_len)
         };
-        buf.reserve(padding_len + RESET_TOKEN_SIZE);
-        buf.resize(padding_len, 0);
-        self.rng.fill_bytes(&mut buf[0..padding_len]);
-        buf[0] = 0b0100_0000 | (buf[0] >> 2);
-        buf.extend_from_slice(&ResetToken::new(&*self.config.reset_key, dst_cid));
+        self.write_stateless_reset(dst_cid, padding_len, buf);
 
         debug_assert!(buf.len() < inciting_dgram_len);
 
@@ -320,6 +381,37 @@ impl Endpoint {
         })
     }
 
+    /// Construct a stateless reset for the connection the peer addresses with `dst_cid`
+    ///
+    /// The packet ends in the stateless reset token derived from `dst_cid` and this endpoint's
+    /// [`EndpointConfig::reset_key`], preceded by random padding that makes it indistinguishable
+    /// from a short-header packet, as specified in RFC 9000 §10.3. The result is always the
+    /// same size, and its token is the same for a given `dst_cid` and key.
+    ///
+    /// Sending it to the peer of a connection that uses `dst_cid`, or any other endpoint that
+    /// obtained a token for `dst_cid` from an endpoint sharing this reset key, immediately tears
+    /// that connection down with [`ConnectionError::Reset`]. This is meant for testing how peers
+    /// handle stateless resets; endpoints already send them on their own when they receive
+    /// packets for connections they don't know.
+    pub fn stateless_reset_packet(&mut self, dst_cid: ConnectionId) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_stateless_reset(dst_cid, IDEAL_MIN_RESET_PADDING_LEN, &mut buf);
+        buf
+    }
+
+    fn write_stateless_reset(
+        &mut self,
+        dst_cid: ConnectionId,
+        padding_len: usize,
+        buf: &mut Vec<u8>,
+    ) {
+        buf.reserve(padding_len + RESET_TOKEN_SIZE);
+        buf.resize(padding_len, 0);
+        self.rng.fill_bytes(&mut buf[0..padding_len]);
+        buf[0] = 0b0100_0000 | (buf[0] >> 2);
+        buf.extend_from_slice(&ResetToken::new(&*self.config.reset_key, dst_cid));
+    }
+
     /// Initiate a connection
     pub fn connect(
         &mut self,
@@ -351,9 +443,12 @@ impl Endpoint {
             None,
             &mut self.rng,
         );
-        let tls = config
-            .crypto
-            .start_session(config.version, server_name, &params)?;
+        let tls = config.crypto.clone().start_session_with_remote(
+            config.version,
+            server_name,
+            remote,
+            &params,
+        )?;
 
         let conn = self.add_connection(
             ch,
@@ -371,6 +466,8 @@ impl Endpoint {
             SideArgs::Client {
                 token_store: config.token_store,
                 server_name: server_name.into(),
+                crypto: config.crypto,
+                params: Box::new(params),
             },
         );
         Ok((ch, conn))
@@ -401,7 +498,9 @@ impl Endpoint {
     /// Generate a connection ID for `ch`
     fn new_cid(&mut self, ch: ConnectionHandle) -> ConnectionId {
         loop {
-            let cid = self.local_cid_generator.generate_cid();
+            let cid = self
+                .local_cid_generator
+                .generate_cid_from_rng(&mut self.rng);
             if cid.is_empty() {
                 // Zero-length CID; nothing to track
                 debug_assert_eq!(self.local_cid_generator.cid_len(), 0);
@@ -430,15 +529,19 @@ impl Endpoint {
             }
         };
 
+        let remote = addresses.remote;
         let Some(server_config) = &self.server_config else {
-            debug!("packet for unrecognized connection {}", dst_cid);
-            return self
-                .stateless_reset(event.now, datagram_len, addresses, dst_cid, buf)
-                .map(DatagramEvent::Response);
+            // Not even a stateless reset, which the peer couldn't authenticate anyway
+            debug!("ignoring initial for connection {dst_cid} without server config");
+            self.config
+                .packet_dropped(DropReason::UnknownConnectionId, remote);
+            return None;
         };
 
         if datagram_len < MIN_INITIAL_SIZE as usize {
             debug!("ignoring short initial for connection {}", dst_cid);
+            self.config
+                .packet_dropped(DropReason::AntiAmplification, remote);
             return None;
         }
 
@@ -451,6 +554,8 @@ impl Endpoint {
                     "ignoring initial packet version {:#x} unsupported by cryptographic layer",
                     header.version
                 );
+                self.config
+                    .packet_dropped(DropReason::UnsupportedVersion, remote);
                 return None;
             }
         };
@@ -470,12 +575,16 @@ impl Endpoint {
             Ok(packet) => packet,
             Err(e) => {
                 trace!("unable to decode initial packet: {}", e);
+                self.config
+                    .packet_dropped(DropReason::InvalidFormat, remote);
                 return None;
             }
         };
 
         if !packet.reserved_bits_valid() {
             debug!("dropping connection attempt with invalid reserved bits");
+            self.config
+                .packet_dropped(DropReason::InvalidFormat, remote);
             return None;
         }
 
@@ -500,12 +609,17 @@ impl Endpoint {
             }
         };
 
-        let incoming_idx = self.incoming_buffers.insert(IncomingBuffer::default());
+        let incoming_idx = self.incoming_buffers.insert(IncomingBuffer {
+            datagrams: Vec::new(),
+            total_bytes: 0,
+            server_config: server_config.clone(),
+        });
         self.index
             .insert_initial_incoming(header.dst_cid, incoming_idx);
 
-        Some(DatagramEvent::NewConnection(Incoming {
+        let incoming = Incoming {
             received_at: event.now,
+            datagram_len,
             addresses,
             ecn: event.ecn,
             packet: InitialPacket {
@@ -515,10 +629,36 @@ impl Endpoint {
             },
             rest: event.remaining,
             crypto,
+            server_config,
             token,
             incoming_idx,
             improper_drop_warner: IncomingImproperDropWarner,
-        }))
+        };
+
+        if self
+            .config
+            .max_handshakes_per_second
+            .is_some_and(|max| self.handshake_rate.get(event.now) >= u64::from(max))
+        {
+            self.throttled_handshakes += 1;
+            if !incoming.may_retry() {
+                debug!("handshake rate limit exceeded, dropping connection attempt");
+                self.config.packet_dropped(DropReason::RateLimited, remote);
+                self.ignore(incoming);
+                return None;
+            }
+            debug!("handshake rate limit exceeded, sending retry");
+            return match self.retry(incoming, buf) {
+                Ok(transmit) => Some(DatagramEvent::Response(transmit)),
+                Err(e) => {
+                    self.ignore(e.into_incoming());
+                    None
+                }
+            };
+        }
+        self.handshake_rate.record(event.now);
+
+        Some(DatagramEvent::NewConnection(incoming))
     }
 
     /// Attempt to accept this incoming connection (an error may still occur)
@@ -542,21 +682,7 @@ impl Endpoint {
             version,
             ..
         } = incoming.packet.header;
-        let server_config = match server_config {
-            Some(config) => config,
-            None => match self.server_config.as_ref() {
-                Some(config) => config.clone(),
-                None => {
-                    tracing::error!("No server config available for incoming connection");
-                    return Err(Box::new(AcceptError {
-                        cause: ConnectionError::TransportError(
-                            TransportError::INTERNAL_ERROR("missing server configuration")
-                        ),
-                        response: None,
-                    }));
-                }
-            },
-        };
+        let server_config = server_config.unwrap_or_else(|| incoming.server_config.clone());
 
         if server_config
             .transport
@@ -601,6 +727,8 @@ impl Endpoint {
             .is_err()
         {
             debug!(packet_number, "failed to authenticate initial packet");
+            self.config
+                .packet_dropped(DropReason::DecryptionFailed, incoming.addresses.remote);
             self.index.remove_initial(dst_cid);
             return Err(Box::new(AcceptError {
                 cause: TransportError::PROTOCOL_VIOLATION("authentication failed").into(),
@@ -657,6 +785,7 @@ impl Endpoint {
             incoming.received_at,
             incoming.addresses.remote,
             incoming.ecn,
+            incoming.datagram_len,
             packet_number,
             incoming.packet,
             incoming.rest,
@@ -750,13 +879,7 @@ impl Endpoint {
 
         self.clean_up_incoming(&incoming);
 
-        let server_config = match self.server_config.as_ref() {
-            Some(config) => config,
-            None => {
-                tracing::error!("No server config available during retry");
-                return Err(RetryError(Box::new(incoming)));
-            }
-        };
+        let server_config = &incoming.server_config;
 
         incoming.improper_drop_warner.dismiss();
 
@@ -766,7 +889,9 @@ impl Endpoint {
         // with established connections. In the unlikely event that a collision occurs
         // between two connections in the initial phase, both will fail fast and may be
         // retried by the application layer.
-        let loc_cid = self.local_cid_generator.generate_cid();
+        let loc_cid = self
+            .local_cid_generator
+            .generate_retry_cid(&mut self.rng, incoming.packet.header.dst_cid);
 
         let payload = TokenPayload::Retry {
             address: incoming.addresses.remote,
@@ -888,7 +1013,9 @@ impl Endpoint {
         // We don't need to worry about CID collisions in initial closes because the peer
         // shouldn't respond, and if it does, and the CID collides, we'll just drop the
         // unexpected response.
-        let local_id = self.local_cid_generator.generate_cid();
+        let local_id = self
+            .local_cid_generator
+            .generate_cid_from_rng(&mut self.rng);
         let number = PacketNumber::U8(0);
         let header = Header::Initial(InitialHeader {
             dst_cid: remote_id,
@@ -929,6 +1056,20 @@ impl Endpoint {
         self.all_incoming_buffers_total_bytes
     }
 
+    /// Number of handshakes started for incoming connection attempts in the last second
+    ///
+    /// Estimated over the sliding window used to enforce
+    /// [`EndpointConfig::max_handshakes_per_second`].
+    pub fn handshake_rate(&self, now: Instant) -> u64 {
+        self.handshake_rate.get(now)
+    }
+
+    /// Number of incoming connection attempts turned away so far due to
+    /// [`EndpointConfig::max_handshakes_per_second`]
+    pub fn throttled_handshakes(&self) -> u64 {
+        self.throttled_handshakes
+    }
+
     #[cfg(test)]
     pub(crate) fn known_connections(&self) -> usize {
         let x = self.connections.len();
@@ -946,6 +1087,11 @@ impl Endpoint {
         self.index.connection_ids.len()
     }
 
+    #[cfg(test)]
+    pub(crate) fn local_cids(&self) -> impl Iterator<Item = &ConnectionId> {
+        self.index.connection_ids.keys()
+    }
+
     /// Whether we've used up 3/4 of the available CID space
     ///
     /// We leave some space unused so that `new_cid` can be relied upon to finish quickly. We don't
@@ -978,10 +1124,11 @@ impl fmt::Debug for Endpoint {
 }
 
 /// Buffered Initial and 0-RTT messages for a pending incoming connection
-#[derive(Default)]
 struct IncomingBuffer {
     datagrams: Vec<DatagramConnectionEvent>,
     total_bytes: u64,
+    /// The server configuration in effect when the connection attempt arrived
+    server_config: Arc<ServerConfig>,
 }
 
 /// Part of protocol state incoming datagrams can be routed to
@@ -1180,14 +1327,48 @@ pub enum DatagramEvent {
     Response(Transmit),
 }
 
+/// Why a received packet was dropped
+///
+/// Reported to [`EndpointConfig::on_packet_drop`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DropReason {
+    /// The packet's header couldn't be parsed, or violates the protocol before any keys apply
+    InvalidFormat,
+    /// The packet uses a QUIC version the endpoint doesn't support, and no Version Negotiation
+    /// packet is sent in response
+    UnsupportedVersion,
+    /// The destination connection ID doesn't belong to any connection of the endpoint
+    ///
+    /// A stateless reset may be sent in response.
+    UnknownConnectionId,
+    /// The packet couldn't be decrypted, either because it failed authentication or because the
+    /// keys protecting it aren't available (yet)
+    DecryptionFailed,
+    /// A server received an Initial packet in a datagram below the 1200 byte minimum, which
+    /// clients must pad to so that responses can't amplify spoofed traffic
+    AntiAmplification,
+    /// The connection attempt exceeded
+    /// [`EndpointConfig::max_handshakes_per_second`] and couldn't be answered with a Retry
+    RateLimited,
+    /// The packet arrived from an address the connection doesn't accept it from, because
+    /// migration is disabled or the handshake isn't complete yet
+    MigrationRejected,
+    /// The buffer for packets of connection attempts awaiting a decision by the application is
+    /// full; see [`ServerConfig::incoming_buffer_size`]
+    IncomingBufferFull,
+}
+
 /// An incoming connection for which the server has not yet begun its part of the handshake.
 pub struct Incoming {
     received_at: Instant,
+    datagram_len: usize,
     addresses: FourTuple,
     ecn: Option<EcnCodepoint>,
     packet: InitialPacket,
     rest: Option<BytesMut>,
     crypto: Keys,
+    server_config: Arc<ServerConfig>,
     token: IncomingToken,
     incoming_idx: usize,
     improper_drop_warner: IncomingImproperDropWarner,
@@ -1229,6 +1410,52 @@ impl Incoming {
     pub fn orig_dst_cid(&self) -> ConnectionId {
         self.token.orig_dst_cid
     }
+
+    /// The application protocol (ALPN) that accepting this connection would negotiate
+    ///
+    /// Determined from the TLS ClientHello in the client's first Initial packet, without starting
+    /// the handshake, so that servers can dispatch or refuse connections by protocol before
+    /// committing resources to them. The protocol is chosen according to the endpoint's server
+    /// configuration, so it may not match the outcome of [`Endpoint::accept`] with a different
+    /// one.
+    ///
+    /// Returns `None` if no protocol would be negotiated, or if the ClientHello isn't available
+    /// yet because it doesn't fit in the first Initial packet. That can happen with large
+    /// ClientHellos, for instance those carrying post-quantum key shares.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        let mut payload = self.packet.payload.clone();
+        self.crypto
+            .packet
+            .remote
+            .decrypt(
+                self.packet.header.number.expand(0),
+                &self.packet.header_data,
+                &mut payload,
+            )
+            .ok()?;
+
+        let mut frames = frame::Iter::new(payload.freeze())
+            .ok()?
+            .filter_map(|frame| match frame {
+                Ok(frame::Frame::Crypto(frame)) => Some(frame),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        frames.sort_unstable_by_key(|frame| frame.offset);
+        // Reassemble the handshake data from offset zero, which clients may split and reorder
+        let mut client_hello = Vec::new();
+        for frame in frames {
+            let end = client_hello.len() as u64;
+            if frame.offset > end {
+                break;
+            }
+            let overlap = (end - frame.offset) as usize;
+            if let Some(data) = frame.data.get(overlap..) {
+                client_hello.extend_from_slice(data);
+            }
+        }
+        self.server_config.crypto.alpn_protocol(&client_hello)
+    }
 }
 
 impl fmt::Debug for Incoming {
@@ -1361,3 +1588,61 @@ struct FourTuple {
     // A single socket can only listen on a single port, so no need to store it explicitly
     local_ip: Option<IpAddr>,
 }
+
+/// Counts handshakes started over a sliding window of one second
+///
+/// Approximates the sliding window by weighting the count of the previous fixed window by how much
+/// of it the sliding window still covers.
+#[derive(Debug, Default)]
+struct HandshakeRate {
+    /// Start of the current fixed window
+    window_start: Option<Instant>,
+    /// Handshakes started in the current fixed window
+    current: u64,
+    /// Handshakes started in the previous fixed window
+    previous: u64,
+}
+
+impl HandshakeRate {
+    /// Estimate the number of handshakes started in the second up to `now`
+    fn get(&self, now: Instant) -> u64 {
+        let Some(start) = self.window_start else {
+            return 0;
+        };
+        let elapsed = now.saturating_duration_since(start);
+        let (current, previous, into_window) = if elapsed < HANDSHAKE_RATE_WINDOW {
+            (self.current, self.previous, elapsed)
+        } else if elapsed < 2 * HANDSHAKE_RATE_WINDOW {
+            (0, self.current, elapsed - HANDSHAKE_RATE_WINDOW)
+        } else {
+            return 0;
+        };
+        let covered = (HANDSHAKE_RATE_WINDOW - into_window).as_nanos();
+        current + (u128::from(previous) * covered / HANDSHAKE_RATE_WINDOW.as_nanos()) as u64
+    }
+
+    fn record(&mut self, now: Instant) {
+        match self.window_start {
+            Some(start) if now.saturating_duration_since(start) < HANDSHAKE_RATE_WINDOW => {}
+            Some(start) if now.saturating_duration_since(start) < 2 * HANDSHAKE_RATE_WINDOW => {
+                self.window_start = Some(start + HANDSHAKE_RATE_WINDOW);
+                self.previous = mem::take(&mut self.current);
+            }
+            _ => {
+                self.window_start = Some(now);
+                self.previous = 0;
+                self.current = 0;
+            }
+        }
+        self.current += 1;
+    }
+}
+
+/// Period over which `HandshakeRate` counts handshakes
+const HANDSHAKE_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Minimum amount of padding for the stateless reset to look like a short-header packet
+const MIN_RESET_PADDING_LEN: usize = 5;
+
+/// Resets with at least this much padding can't possibly be distinguished from real packets
+const IDEAL_MIN_RESET_PADDING_LEN: usize = MIN_RESET_PADDING_LEN + MAX_CID_SIZE;
@@ -133,6 +133,8 @@ frame_types! {
     // ACK Frequency
     ACK_FREQUENCY = 0xaf,
     IMMEDIATE_ACK = 0x1f,
+    // Quinn extension for partially reliable streams
+    STREAM_SKIP = 0x3f51_5300,
     // DATAGRAM
 }
 
@@ -163,6 +165,7 @@ pub(crate) enum Frame {
     Datagram(Datagram),
     AckFrequency(AckFrequency),
     ImmediateAck,
+    StreamSkip(StreamSkip),
     HandshakeDone,
 }
 
@@ -204,6 +207,7 @@ impl Frame {
             Datagram(_) => FrameType(*DATAGRAM_TYS.start()),
             AckFrequency(_) => FrameType::ACK_FREQUENCY,
             ImmediateAck => FrameType::IMMEDIATE_ACK,
+            StreamSkip(_) => FrameType::STREAM_SKIP,
             HandshakeDone => FrameType::HANDSHAKE_DONE,
         }
     }
@@ -580,6 +584,7 @@ impl Iter {
     }
 
     fn try_next(&mut self) -> Result<Frame, IterErr> {
+        self.last_ty = None;
         let ty = self.bytes.get::<FrameType>()?;
         self.last_ty = Some(ty);
         Ok(match ty {
@@ -702,6 +707,10 @@ impl Iter {
                 reordering_threshold: self.bytes.get()?,
             }),
             FrameType::IMMEDIATE_ACK => Frame::ImmediateAck,
+            FrameType::STREAM_SKIP => Frame::StreamSkip(StreamSkip {
+                id: self.bytes.get()?,
+                offset: self.bytes.get()?,
+            }),
             _ => {
                 if let Some(s) = ty.stream() {
                     Frame::Stream(Stream {
@@ -872,6 +881,26 @@ impl StopSending {
     }
 }
 
+/// Tells the receiver of a partially reliable stream that data below `offset` won't be
+/// retransmitted and should be skipped
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct StreamSkip {
+    pub(crate) id: StreamId,
+    pub(crate) offset: VarInt,
+}
+
+impl FrameStruct for StreamSkip {
+    const SIZE_BOUND: usize = 4 + 8 + 8;
+}
+
+impl StreamSkip {
+    pub(crate) fn encode<W: BufMut>(&self, out: &mut W) {
+        out.write(FrameType::STREAM_SKIP); // 4 bytes
+        out.write(self.id); // <= 8 bytes
+        out.write(self.offset); // <= 8 bytes
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub(crate) struct NewConnectionId {
     pub(crate) sequence: u64,
@@ -1012,4 +1041,30 @@ mod test {
         assert_eq!(frames.len(), 1);
         assert_matches!(&frames[0], Frame::ImmediateAck);
     }
+
+    #[test]
+    fn stream_skip_coding() {
+        let mut buf = Vec::new();
+        let original = StreamSkip {
+            id: StreamId::new(crate::Side::Client, Dir::Uni, 3),
+            offset: VarInt(1 << 20),
+        };
+        original.encode(&mut buf);
+        let frames = frames(buf);
+        assert_eq!(frames.len(), 1);
+        assert_matches!(&frames[0], Frame::StreamSkip(decoded) if decoded == &original);
+    }
+
+    #[test]
+    fn truncated_frame_type() {
+        let mut buf = Vec::new();
+        FrameType::PING.encode(&mut buf);
+        // First byte of a two-byte frame type
+        buf.push(0x40);
+        let mut iter = Iter::new(Bytes::from(buf)).unwrap();
+        assert_matches!(iter.next(), Some(Ok(Frame::Ping)));
+        let err = iter.next().unwrap().unwrap_err();
+        assert_eq!(err.ty, None);
+        assert!(iter.next().is_none());
+    }
 }
@@ -11,6 +11,29 @@
 //! a single socket and mostly manages configuration and dispatches incoming datagrams to the
 //! related `Connection`. `Connection` types contain the bulk of the protocol logic related to
 //! managing a single connection and all the related state (such as streams).
+//!
+//! # Driving the state machine
+//!
+//! The caller owns all I/O and the clock, and is responsible for the following:
+//!
+//! - **Incoming datagrams** are passed to [`Endpoint::handle`], with the current time and the
+//!   address they came from. The resulting [`DatagramEvent`] is either forwarded to the matching
+//!   connection with [`Connection::handle_event`], offered for acceptance with
+//!   [`Endpoint::accept`], or is a [`Transmit`] to send right away.
+//! - **Outgoing datagrams** are drained with [`Connection::poll_transmit`] until it returns
+//!   `None`. Each [`Transmit`] describes the first [`Transmit::size`] bytes written to the supplied
+//!   buffer, which hold one datagram, or several of [`Transmit::segment_size`] bytes each if
+//!   segmentation offload was allowed through `max_datagrams`.
+//! - **Timers** are reported by [`Connection::poll_timeout`]; once the deadline passes, call
+//!   [`Connection::handle_timeout`].
+//! - **Endpoint events** from [`Connection::poll_endpoint_events`] are passed to
+//!   [`Endpoint::handle_event`], forwarding any resulting event back to the connection.
+//! - **Application events** are read with [`Connection::poll`].
+//!
+//! Any call which may have changed a connection's state, including application I/O on its
+//! streams, should be followed by draining its events and transmits and rechecking its timeout.
+//! The `sans_io` example in this crate's repository drives a complete handshake this way, without
+//! any sockets. These methods are the stable embedding surface used by the `quinn` crate itself.
 
 #![cfg_attr(not(fuzzing), warn(missing_docs))]
 #![cfg_attr(test, allow(dead_code))]
@@ -44,10 +67,12 @@ pub use bloom_token_log::BloomTokenLog;
 
 mod connection;
 pub use crate::connection::{
-    Chunk, Chunks, ClosedStream, Connection, ConnectionError, ConnectionStats, Datagrams, Event,
-    FinishError, FlowControlStats, FrameStats, PathStats, ReadError, ReadableError, RecvStream,
-    RttEstimator, SendDatagramError, SendStream, ShouldTransmit, StreamEvent, Streams, UdpStats,
-    WriteError, Written,
+    Chunk, Chunks, ClosedStream, Connection, ConnectionDebug, ConnectionError, ConnectionIdStats,
+    ConnectionStats, DatagramBackpressure, DatagramDropStats, Datagrams, DroppedPacketStats, Event,
+    FinishError, FlowControlStats, FrameStats, MtuSearchState, PartialReliability, PathStats,
+    ReadError, ReadableError, RecoveryStats, RecvStream, RttEstimator, SendDatagramError,
+    SendStream, ShouldTransmit, SpaceRecoveryStats, StreamEvent, Streams, UdpStats, WriteError,
+    WriteStallReason, Written,
 };
 #[cfg(feature = "qlog")]
 pub use connection::qlog::QlogStream;
@@ -60,7 +85,8 @@ mod config;
 pub use config::QlogConfig;
 pub use config::{
     AckFrequencyConfig, ClientConfig, ConfigError, EndpointConfig, IdleTimeout, MtuDiscoveryConfig,
-    ServerConfig, StdSystemTime, TimeSource, TransportConfig, ValidationTokenConfig,
+    PacketDirection, PacketInfo, PacketObserver, ServerConfig, StdSystemTime, TimeSource,
+    TransportConfig, TransportParametersValidator, ValidationTokenConfig,
 };
 
 pub mod crypto;
@@ -71,13 +97,14 @@ pub use crate::frame::{ApplicationClose, ConnectionClose, Datagram, FrameType};
 
 mod endpoint;
 pub use crate::endpoint::{
-    AcceptError, ConnectError, ConnectionHandle, DatagramEvent, Endpoint, Incoming, RetryError,
+    AcceptError, ConnectError, ConnectionHandle, DatagramEvent, DropReason, Endpoint, Incoming,
+    RetryError,
 };
 
 mod packet;
 pub use packet::{
     ConnectionIdParser, FixedLengthConnectionIdParser, LongType, PacketDecodeError, PartialDecode,
-    ProtectedHeader, ProtectedInitialHeader,
+    ProtectedHeader, ProtectedInitialHeader, SpaceId,
 };
 
 mod shared;
@@ -156,6 +183,16 @@ pub mod fuzzing {
     }
 }
 
+/// QUIC version 2, as specified in RFC 9369
+///
+/// Version 2 only differs from version 1 in the constants used to derive Initial and Retry
+/// protection keys and in the codes of long header packet types, to keep middleboxes from
+/// ossifying on version 1. It must be added to [`EndpointConfig::supported_versions`] on both
+/// sides, as it's not among the [`DEFAULT_SUPPORTED_VERSIONS`]. Clients then use it by setting
+/// [`ClientConfig::version`]; servers which don't support it answer with version negotiation, upon
+/// which the client falls back to version 1 at the cost of a round trip.
+pub const QUIC_V2: u32 = 0x6b33_43cf;
+
 /// The QUIC protocol version implemented.
 pub const DEFAULT_SUPPORTED_VERSIONS: &[u32] = &[
     0x00000001,
@@ -169,6 +206,7 @@ pub const DEFAULT_SUPPORTED_VERSIONS: &[u32] = &[
 
 /// Whether an endpoint was the initiator of a connection
 #[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum Side {
     /// The initiator of a connection
@@ -4,7 +4,7 @@ use bytes::{Buf, BufMut, Bytes, BytesMut};
 use thiserror::Error;
 
 use crate::{
-    ConnectionId,
+    ConnectionId, QUIC_V2,
     coding::{self, BufExt, BufMutExt},
     crypto,
 };
@@ -315,7 +315,7 @@ impl Header {
                 number,
                 version,
             }) => {
-                w.write(u8::from(LongHeaderType::Initial) | number.tag());
+                w.write(LongHeaderType::Initial.to_byte(version) | number.tag());
                 w.write(version);
                 dst_cid.encode_long(w);
                 src_cid.encode_long(w);
@@ -336,7 +336,7 @@ impl Header {
                 number,
                 version,
             } => {
-                w.write(u8::from(LongHeaderType::Standard(ty)) | number.tag());
+                w.write(LongHeaderType::Standard(ty).to_byte(version) | number.tag());
                 w.write(version);
                 dst_cid.encode_long(w);
                 src_cid.encode_long(w);
@@ -353,7 +353,7 @@ impl Header {
                 ref src_cid,
                 version,
             } => {
-                w.write(u8::from(LongHeaderType::Retry));
+                w.write(LongHeaderType::Retry.to_byte(version));
                 w.write(version);
                 dst_cid.encode_long(w);
                 src_cid.encode_long(w);
@@ -640,7 +640,7 @@ impl ProtectedHeader {
                 });
             }
 
-            match LongHeaderType::from_byte(first)? {
+            match LongHeaderType::from_byte(first, version) {
                 LongHeaderType::Initial => {
                     let token_len = buf.get_var()? as usize;
                     let token_start = buf.position() as usize;
@@ -837,28 +837,35 @@ pub(crate) enum LongHeaderType {
 }
 
 impl LongHeaderType {
-    fn from_byte(b: u8) -> Result<Self, PacketDecodeError> {
+    fn from_byte(b: u8, version: u32) -> Self {
         use {LongHeaderType::*, LongType::*};
         debug_assert!(b & LONG_HEADER_FORM != 0, "not a long packet");
-        Ok(match (b & 0x30) >> 4 {
+        let mut bits = (b & 0x30) >> 4;
+        if version == QUIC_V2 {
+            // QUIC v2 rotates the type codes by one (RFC 9369 §3.2)
+            bits = bits.wrapping_sub(1) & 0x3;
+        }
+        match bits {
             0x0 => Initial,
             0x1 => Standard(ZeroRtt),
             0x2 => Standard(Handshake),
             0x3 => Retry,
             _ => unreachable!(),
-        })
+        }
     }
-}
 
-impl From<LongHeaderType> for u8 {
-    fn from(ty: LongHeaderType) -> Self {
+    fn to_byte(self, version: u32) -> u8 {
         use {LongHeaderType::*, LongType::*};
-        match ty {
-            Initial => LONG_HEADER_FORM | FIXED_BIT,
-            Standard(ZeroRtt) => LONG_HEADER_FORM | FIXED_BIT | (0x1 << 4),
-            Standard(Handshake) => LONG_HEADER_FORM | FIXED_BIT | (0x2 << 4),
-            Retry => LONG_HEADER_FORM | FIXED_BIT | (0x3 << 4),
+        let mut bits = match self {
+            Initial => 0x0,
+            Standard(ZeroRtt) => 0x1,
+            Standard(Handshake) => 0x2,
+            Retry => 0x3,
+        };
+        if version == QUIC_V2 {
+            bits = (bits + 1) & 0x3;
         }
+        LONG_HEADER_FORM | FIXED_BIT | (bits << 4)
     }
 }
 
@@ -904,9 +911,10 @@ const KEY_PHASE_BIT: u8 = 0x04;
 
 /// Packet number space identifiers
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
-pub(crate) enum SpaceId {
+pub enum SpaceId {
     /// Unprotected packets, used to bootstrap the handshake
     Initial = 0,
+    /// Packets carrying the remainder of the handshake
     Handshake = 1,
     /// Application data space, used for 0-RTT and post-handshake/1-RTT packets
     Data = 2,
@@ -957,6 +965,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn long_header_type_codes() {
+        use {LongHeaderType::*, LongType::*};
+        for ty in [Initial, Standard(ZeroRtt), Standard(Handshake), Retry] {
+            for version in [1, QUIC_V2] {
+                assert_eq!(LongHeaderType::from_byte(ty.to_byte(version), version), ty);
+            }
+        }
+        assert_eq!(Initial.to_byte(1), 0xc0);
+        assert_eq!(Retry.to_byte(1), 0xf0);
+        // RFC 9369 §3.2
+        assert_eq!(Initial.to_byte(QUIC_V2), 0xd0);
+        assert_eq!(Standard(ZeroRtt).to_byte(QUIC_V2), 0xe0);
+        assert_eq!(Standard(Handshake).to_byte(QUIC_V2), 0xf0);
+        assert_eq!(Retry.to_byte(QUIC_V2), 0xc0);
+    }
+
     #[cfg(any(feature = "rustls-aws-lc-rs", feature = "rustls-ring"))]
     #[test]
     fn header_encoding() {
@@ -14,6 +14,8 @@ pub(crate) enum ConnectionEventInner {
     Datagram(DatagramConnectionEvent),
     /// New connection identifiers have been issued for the Connection
     NewIdentifiers(Vec<IssuedCid>, Instant),
+    /// An ICMP "packet too big" message was received that may concern the Connection
+    PacketTooBig(PacketTooBigEvent),
 }
 
 /// Variant of [`ConnectionEventInner`].
@@ -26,6 +28,18 @@ pub(crate) struct DatagramConnectionEvent {
     pub(crate) remaining: Option<BytesMut>,
 }
 
+/// Variant of [`ConnectionEventInner`].
+#[derive(Debug)]
+pub(crate) struct PacketTooBigEvent {
+    pub(crate) now: Instant,
+    /// Destination address of the packet that didn't fit
+    pub(crate) remote: SocketAddr,
+    /// Largest UDP payload size the path supports according to the message
+    pub(crate) mtu: u16,
+    /// Leading bytes of the QUIC packet quoted by the message
+    pub(crate) quoted: Vec<u8>,
+}
+
 /// Events sent from a Connection to an Endpoint
 #[derive(Debug)]
 pub struct EndpointEvent(pub(crate) EndpointEventInner);
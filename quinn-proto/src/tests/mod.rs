@@ -2,7 +2,10 @@ use std::{
     convert::TryInto,
     mem,
     net::{Ipv4Addr, Ipv6Addr, SocketAddr},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
 };
 
 use assert_matches::assert_matches;
@@ -26,10 +29,11 @@ use tracing::info;
 
 use super::*;
 use crate::{
-    Duration, Instant,
+    Duration, Instant, MAX_CID_SIZE, RESET_TOKEN_SIZE,
     cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator},
     crypto::rustls::QuicServerConfig,
     frame::FrameStruct,
+    packet::FIXED_BIT,
     transport_parameters::TransportParameters,
 };
 mod util;
@@ -117,6 +121,57 @@ fn version_negotiate_client() {
     );
 }
 
+/// Connect with a client that first offers `version`, which the server doesn't support
+fn version_negotiate_with(version: u32, client_versions: Vec<u32>) -> (Pair, ConnectionHandle) {
+    let mut server_endpoint = EndpointConfig::default();
+    server_endpoint.supported_versions(vec![1]);
+    let server = Endpoint::new(
+        Arc::new(server_endpoint),
+        Some(Arc::new(server_config())),
+        true,
+    );
+    let mut client_endpoint = EndpointConfig::default();
+    client_endpoint.supported_versions(client_versions);
+    let client = Endpoint::new(Arc::new(client_endpoint), None, true);
+    let mut pair = Pair::new_from_endpoint(client, server);
+
+    let mut client_config = client_config();
+    client_config.version(version);
+    let client_ch = pair.begin_connect(client_config);
+    pair.drive();
+    (pair, client_ch)
+}
+
+#[test]
+fn version_negotiate_fallback() {
+    let _guard = subscribe();
+    let (mut pair, client_ch) = version_negotiate_with(0xff00_0020, vec![0xff00_0020, 1]);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert_eq!(pair.client_conn_mut(client_ch).version(), 1);
+    let server_ch = pair.server.assert_accept();
+    assert_eq!(pair.server_conn_mut(server_ch).version(), 1);
+}
+
+#[test]
+fn version_negotiate_no_common_version() {
+    let _guard = subscribe();
+    let (mut pair, client_ch) = version_negotiate_with(0xff00_0020, vec![0xff00_0020]);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::VersionMismatch,
+        })
+    );
+    pair.server.assert_no_accept();
+}
+
 #[test]
 fn lifecycle() {
     let _guard = subscribe();
@@ -145,6 +200,69 @@ fn lifecycle() {
     assert_eq!(pair.server.known_cids(), 0);
 }
 
+#[test]
+fn quic_v2() {
+    let _guard = subscribe();
+    let mut endpoint_config = EndpointConfig::default();
+    endpoint_config.supported_versions(vec![1, QUIC_V2]);
+    let mut pair = Pair::new(Arc::new(endpoint_config), server_config());
+    // Exercise Retry packets too, since they're protected with version-specific keys
+    pair.server.handle_incoming = Box::new(validate_incoming);
+    let mut client_config = client_config();
+    client_config.version(QUIC_V2);
+    let (client_ch, server_ch) = pair.connect_with(client_config);
+    assert_eq!(pair.client_conn_mut(client_ch).version(), QUIC_V2);
+    assert_eq!(pair.server_conn_mut(server_ch).version(), QUIC_V2);
+    assert!(pair.server_conn_mut(server_ch).address_prevalidated());
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(
+        pair.server_streams(server_ch).accept(Dir::Uni),
+        Some(stream) if stream == s
+    );
+}
+
+#[test]
+fn quic_v2_fallback() {
+    let _guard = subscribe();
+    let (mut pair, client_ch) = version_negotiate_with(QUIC_V2, vec![QUIC_V2, 1]);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert_eq!(pair.client_conn_mut(client_ch).version(), 1);
+}
+
+#[test]
+fn retry_tag_test_vectors() {
+    let _guard = subscribe();
+    let server = server_crypto();
+    let orig_dst_cid = ConnectionId::new(&hex!("8394c8f03e515708"));
+    // RFC 9001 §A.4 and RFC 9369 §A.4
+    for (version, packet) in [
+        (
+            1,
+            &hex!("ff000000010008f067a5502a4262b5746f6b656e04a265ba2eff4d829058fb3f0f2496ba")[..],
+        ),
+        (
+            QUIC_V2,
+            &hex!("cf6b3343cf0008f067a5502a4262b5746f6b656ec8646ce8bfe33952d955543665dcc7b6")[..],
+        ),
+    ] {
+        let (header, tag) = packet.split_at(packet.len() - 16);
+        assert_eq!(
+            crypto::ServerConfig::retry_tag(&server, version, orig_dst_cid, header),
+            tag
+        );
+    }
+}
+
 #[test]
 fn draft_version_compat() {
     let _guard = subscribe();
@@ -178,6 +296,52 @@ fn draft_version_compat() {
     assert_eq!(pair.server.known_cids(), 0);
 }
 
+/// Send a burst of pings from the client and return which values of the QUIC bit were observed on
+/// its short-header packets, as `(set, cleared)`
+fn short_header_quic_bits(server_greases: bool) -> (bool, bool) {
+    let mut server_endpoint = EndpointConfig::default();
+    server_endpoint.grease_quic_bit(server_greases);
+    let server = Endpoint::new(
+        Arc::new(server_endpoint),
+        Some(Arc::new(server_config())),
+        true,
+    );
+    let client = Endpoint::new(Default::default(), None, true);
+    let mut pair = Pair::new_from_endpoint(client, server);
+    let (client_ch, _) = pair.connect();
+    pair.drive();
+
+    let (mut set, mut cleared) = (false, false);
+    for _ in 0..64 {
+        pair.client_conn_mut(client_ch).ping();
+        pair.client.drive(pair.time, pair.server.addr);
+        for (_, packet) in &pair.client.outbound {
+            if packet[0] & 0x80 != 0 {
+                continue;
+            }
+            if packet[0] & FIXED_BIT != 0 {
+                set = true;
+            } else {
+                cleared = true;
+            }
+        }
+        pair.drive();
+    }
+    (set, cleared)
+}
+
+#[test]
+fn grease_quic_bit_negotiated() {
+    let _guard = subscribe();
+    assert_eq!(short_header_quic_bits(true), (true, true));
+}
+
+#[test]
+fn grease_quic_bit_not_negotiated() {
+    let _guard = subscribe();
+    assert_eq!(short_header_quic_bits(false), (true, false));
+}
+
 #[test]
 fn server_stateless_reset() {
     let _guard = subscribe();
@@ -239,6 +403,36 @@ fn client_stateless_reset() {
     );
 }
 
+#[test]
+fn crafted_stateless_reset() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+    pair.drive();
+
+    let cid = pair.client_conn_mut(client_ch).active_rem_cid();
+    let reset = pair.server.endpoint.stateless_reset_packet(cid);
+    assert_eq!(reset.len(), 5 + MAX_CID_SIZE + RESET_TOKEN_SIZE);
+    assert_eq!(reset[0] & 0b1100_0000, 0b0100_0000);
+    let again = pair.server.endpoint.stateless_reset_packet(cid);
+    assert_eq!(
+        again[again.len() - RESET_TOKEN_SIZE..],
+        reset[reset.len() - RESET_TOKEN_SIZE..]
+    );
+
+    info!("resetting");
+    pair.client
+        .inbound
+        .push_back((pair.time, None, reset[..].into()));
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::Reset
+        })
+    );
+}
+
 /// Verify that stateless resets are rate-limited
 #[test]
 fn stateless_reset_limit() {
@@ -344,6 +538,81 @@ fn finish_stream_simple() {
     let _ = chunks.finalize();
 }
 
+#[test]
+fn write_coalescing() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    const MSG: &[u8] = b"tiny write";
+    const WRITES: usize = 50;
+    // Write `MSG` repeatedly, giving the connection a chance to transmit after each write, and
+    // return the number of STREAM frames sent
+    let write_all = |pair: &mut Pair, s: StreamId| {
+        let before = pair.client_conn_mut(client_ch).stats().frame_tx.stream;
+        for _ in 0..WRITES {
+            pair.client_send(client_ch, s).write(MSG).unwrap();
+            pair.drive_client();
+        }
+        pair.client_send(client_ch, s).finish().unwrap();
+        pair.drive();
+        pair.client_conn_mut(client_ch).stats().frame_tx.stream - before
+    };
+
+    let plain = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    let plain_frames = write_all(&mut pair, plain);
+    // One frame per write, plus one carrying just the FIN
+    assert_eq!(plain_frames, WRITES as u64 + 1);
+
+    let coalesced = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, coalesced)
+        .set_write_coalescing(Some(4 * MSG.len() as u64))
+        .unwrap();
+    let coalesced_frames = write_all(&mut pair, coalesced);
+    // Every fourth write releases the held data, and `finish` sends what's left with the FIN
+    assert_eq!(coalesced_frames, (WRITES as u64).div_ceil(4));
+    assert!(coalesced_frames < plain_frames);
+
+    for s in [plain, coalesced] {
+        assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(id) if id == s);
+        let mut recv = pair.server_recv(server_ch, s);
+        let mut chunks = recv.read(true).unwrap();
+        let mut data = Vec::new();
+        while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+            data.extend_from_slice(&chunk.bytes);
+        }
+        let _ = chunks.finalize();
+        assert_eq!(data, MSG.repeat(WRITES));
+    }
+
+    info!("flushing held data");
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s)
+        .set_write_coalescing(Some(1024))
+        .unwrap();
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), None);
+    pair.client_send(client_ch, s).flush().unwrap();
+    // Data written after the flush stays held back, even though the flushed data goes out
+    pair.client_send(client_ch, s).write(b"held").unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(id) if id == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Ok(Some(chunk)) if chunk.bytes == MSG);
+    assert_matches!(chunks.next(usize::MAX), Err(ReadError::Blocked));
+    let _ = chunks.finalize();
+
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive();
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Ok(Some(chunk)) if chunk.bytes == b"held"[..]);
+    assert_matches!(chunks.next(usize::MAX), Ok(None));
+    let _ = chunks.finalize();
+}
+
 #[test]
 fn reset_stream() {
     let _guard = subscribe();
@@ -374,91 +643,707 @@ fn reset_stream() {
 }
 
 #[test]
-fn stop_stream() {
+fn write_owned() {
+    /// Buffer that records when it's freed
+    struct Owner {
+        data: Vec<u8>,
+        released: Arc<AtomicBool>,
+    }
+
+    impl AsRef<[u8]> for Owner {
+        fn as_ref(&self) -> &[u8] {
+            &self.data
+        }
+    }
+
+    impl Drop for Owner {
+        fn drop(&mut self) {
+            self.released.store(true, Ordering::Relaxed);
+        }
+    }
+
     let _guard = subscribe();
     let mut pair = Pair::default();
     let (client_ch, server_ch) = pair.connect();
 
+    let expected = (0..8000u32).map(|i| i as u8).collect::<Vec<_>>();
+    let released = Arc::new(AtomicBool::new(false));
+    let data = Bytes::from_owner(Owner {
+        data: expected.clone(),
+        released: released.clone(),
+    });
     let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
-    const MSG: &[u8] = b"hello";
-    pair.client_send(client_ch, s).write(MSG).unwrap();
-    pair.drive();
+    assert_eq!(
+        pair.client_send(client_ch, s).write_owned(data).unwrap(),
+        expected.len()
+    );
+    pair.client_send(client_ch, s).finish().unwrap();
 
-    info!("stopping stream");
-    const ERROR: VarInt = VarInt(42);
-    pair.server_recv(server_ch, s).stop(ERROR).unwrap();
-    pair.drive();
+    // Lose the first transmission. The only remaining reference to the buffer is the stream's,
+    // so it can't have been copied.
+    pair.drive_client();
+    pair.server.inbound.clear();
+    assert!(!released.load(Ordering::Relaxed));
 
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
-    );
-    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    // Retransmit from the retained buffer until everything is acknowledged
+    let end = pair.time + Duration::from_secs(10);
+    while !released.load(Ordering::Relaxed) && pair.time < end {
+        if !pair.step() {
+            if let Some(t) = min_opt(pair.client.next_wakeup(), pair.server.next_wakeup()) {
+                pair.time = t;
+            }
+        }
+    }
+    assert!(released.load(Ordering::Relaxed));
+    assert!(pair.client_conn_mut(client_ch).stats().path.lost_packets > 0);
 
-    assert_matches!(
-        pair.client_send(client_ch, s).write(b"foo"),
-        Err(WriteError::Stopped(ERROR))
-    );
-    assert_matches!(
-        pair.client_send(client_ch, s).finish(),
-        Err(FinishError::Stopped(ERROR))
-    );
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    let mut received = Vec::new();
+    while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+        received.extend_from_slice(&chunk.bytes);
+    }
+    let _ = chunks.finalize();
+    assert_eq!(received, expected);
 }
 
 #[test]
-fn reject_self_signed_server_cert() {
+fn reset_stats() {
     let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.stream_starvation_threshold(4);
+    let mut client_config = client_config();
+    client_config.transport_config(Arc::new(transport));
     let mut pair = Pair::default();
-    info!("connecting");
+    let (client_ch, _) = pair.connect_with(client_config);
 
-    // Create a self-signed certificate with a different distinguished name than the default one,
-    // such that path building cannot confuse the default root the server is using and the one
-    // the client is trusting (in which case we'd get a different error).
-    let mut cert = rcgen::CertificateParams::new(["localhost".into()]).unwrap();
-    let mut issuer = rcgen::DistinguishedName::new();
-    issuer.push(
-        rcgen::DnType::OrganizationName,
-        "Crazy Quinn's House of Certificates",
-    );
-    cert.distinguished_name = issuer;
-    let cert = cert
-        .self_signed(&rcgen::KeyPair::generate().unwrap())
+    // A busy stream of higher priority starves the other one
+    let busy = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, busy).set_priority(1).unwrap();
+    pair.client_send(client_ch, busy)
+        .write(&[0; 50_000])
+        .unwrap();
+    let starved = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, starved)
+        .write(&[0; 10])
         .unwrap();
-    let client_ch = pair.begin_connect(client_config_with_certs(vec![cert.into()]));
-
     pair.drive();
 
-    assert_matches!(pair.client_conn_mut(client_ch).poll(),
-                    Some(Event::ConnectionLost { reason: ConnectionError::TransportError(ref error)})
-                    if error.code == TransportErrorCode::crypto(AlertDescription::UnknownCA.into()));
+    let before = pair.client_conn_mut(client_ch).reset_stats();
+    assert!(before.udp_tx.datagrams > 0);
+    assert!(before.frame_rx.crypto > 0);
+    assert!(before.handshake_duration.is_some());
+    assert_eq!(before.starved_streams, 1);
+
+    let after = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(after.udp_tx.datagrams, 0);
+    assert_eq!(after.udp_rx.bytes, 0);
+    assert_eq!(after.frame_rx.crypto, 0);
+    assert_eq!(after.path.sent_packets, 0);
+    assert_eq!(after.starved_streams, 0);
+    // Gauges and handshake facts are preserved
+    assert_eq!(after.path.rtt, before.path.rtt);
+    assert_eq!(after.path.cwnd, before.path.cwnd);
+    assert_eq!(after.handshake_duration, before.handshake_duration);
+
+    // Counting resumes from zero
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive();
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.frame_tx.ping, 1);
+    assert_eq!(stats.frame_rx.crypto, 0);
+    assert!(stats.udp_tx.datagrams > 0 && stats.udp_tx.datagrams < before.udp_tx.datagrams);
+    assert!(stats.path.sent_packets > 0);
 }
 
 #[test]
-fn reject_missing_client_cert() {
+fn debug_snapshot() {
     let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    let s = pair.client_streams(client_ch).open(Dir::Bi).unwrap();
+    pair.client_send(client_ch, s).write(&[0; 1000]).unwrap();
+    pair.drive();
 
-    let mut store = RootCertStore::empty();
-    // `WebPkiClientVerifier` requires a non-empty store, so we stick our own certificate into it
-    // because it's convenient.
-    store.add(CERTIFIED_KEY.cert.der().clone()).unwrap();
+    let now = pair.time;
+    let client = pair.client_conn_mut(client_ch).debug_snapshot(now);
+    assert_eq!(client.side, Side::Client);
+    assert_eq!(client.state, "established");
+    assert_eq!(client.remote_address, pair.server.addr);
+    assert!(client.stats.udp_tx.datagrams > 0);
+    assert_eq!(client.stats.flow_control.data_sent, 1000);
+    assert!(client.congestion.congestion_window > 0);
+    assert_eq!(client.send_streams, 1);
+    assert_eq!(client.remote_open_streams, [0, 0]);
+    assert_eq!(client.pto_count, 0);
+    assert!(client.idle_timeout.is_some());
+    let (_, idle) = client
+        .pending_timers
+        .iter()
+        .find(|(name, _)| *name == "idle")
+        .unwrap();
+    assert!(*idle <= client.idle_timeout.unwrap());
+
+    let server = pair.server_conn_mut(server_ch).debug_snapshot(now);
+    assert_eq!(server.side, Side::Server);
+    assert_eq!(server.remote_open_streams, [1, 0]);
+    assert_eq!(server.data_received, 1000);
+    assert!(server.local_max_data >= server.data_received);
+    // Formatting for logs includes every section
+    let formatted = format!("{server:?}");
+    assert!(formatted.contains("congestion_window"));
+    assert!(formatted.contains("pending_timers"));
+}
 
-    let key = PrivatePkcs8KeyDer::from(CERTIFIED_KEY.signing_key.serialize_der());
-    let cert = CERTIFIED_KEY.cert.der().clone();
+#[test]
+fn flow_control_violation() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.stream_receive_window(1000u32.into());
+    let mut server_config = server_config();
+    server_config.transport_config(Arc::new(transport));
+    let mut pair = Pair::new(Default::default(), server_config);
+    let (client_ch, server_ch) = pair.connect();
 
-    let provider = Arc::new(default_provider());
-    let config = rustls::ServerConfig::builder_with_provider(provider.clone())
-        .with_protocol_versions(&[&rustls::version::TLS13])
-        .unwrap()
-        .with_client_cert_verifier(
-            WebPkiClientVerifier::builder_with_provider(Arc::new(store), provider)
-                .build()
-                .unwrap(),
-        )
-        .with_single_cert(vec![cert], PrivateKeyDer::from(key))
-        .unwrap();
-    let config = QuicServerConfig::try_from(config).unwrap();
+    pair.client_conn_mut(client_ch).ignore_peer_flow_control();
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(&[0; 2000]).unwrap();
+    pair.drive();
 
-    let mut pair = Pair::new(
+    // The server detects the violation, and attributes it to the offending frame
+    let error = loop {
+        match pair.server_conn_mut(server_ch).poll() {
+            Some(Event::ConnectionLost { reason }) => break reason,
+            Some(_) => continue,
+            None => panic!("connection not lost"),
+        }
+    };
+    assert_matches!(error, ConnectionError::TransportError(_));
+    assert_eq!(
+        error.transport_error_code(),
+        Some(TransportErrorCode::FLOW_CONTROL_ERROR)
+    );
+    assert_eq!(error.frame_type().unwrap().to_string(), "STREAM");
+
+    // The client learns the same from the peer's CONNECTION_CLOSE
+    let error = loop {
+        match pair.client_conn_mut(client_ch).poll() {
+            Some(Event::ConnectionLost { reason }) => break reason,
+            Some(_) => continue,
+            None => panic!("connection not lost"),
+        }
+    };
+    assert_matches!(error, ConnectionError::ConnectionClosed(_));
+    assert_eq!(
+        error.transport_error_code(),
+        Some(TransportErrorCode::FLOW_CONTROL_ERROR)
+    );
+    assert_eq!(error.frame_type().unwrap().to_string(), "STREAM");
+}
+
+#[test]
+fn write_deadline_expired() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    const ERROR: VarInt = VarInt(7);
+    let deadline = pair.time + Duration::from_millis(1);
+    pair.client_send(client_ch, s)
+        .write_with_deadline(MSG, deadline, ERROR)
+        .unwrap();
+
+    info!("losing stream data");
+    pair.client.drive(pair.time, pair.server.addr);
+    assert!(!pair.client.outbound.is_empty());
+    pair.client.outbound.clear();
+    pair.drive();
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.frame_tx.reset_stream, 1);
+    assert_matches!(
+        pair.client_send(client_ch, s).write(MSG),
+        Err(WriteError::ClosedStream)
+    );
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Err(ReadError::Reset(ERROR)));
+    let _ = chunks.finalize();
+}
+
+#[test]
+fn write_deadline_met() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    let deadline = pair.time + Duration::from_secs(1);
+    pair.client_send(client_ch, s)
+        .write_with_deadline(MSG, deadline, VarInt(7))
+        .unwrap();
+    pair.drive();
+    pair.time = pair.time.max(deadline);
+    pair.drive();
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.frame_tx.reset_stream, 0);
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
+    );
+    let _ = chunks.finalize();
+}
+
+/// Pair whose server accepts partially reliable streams
+fn partial_reliability_pair() -> Pair {
+    let mut transport = TransportConfig::default();
+    transport.partial_reliability(true);
+    let mut server_config = server_config();
+    server_config.transport_config(Arc::new(transport));
+    Pair::new(Default::default(), server_config)
+}
+
+#[test]
+fn partial_reliability_bytes() {
+    let _guard = subscribe();
+    let mut pair = partial_reliability_pair();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s)
+        .set_partial_reliability(Some(PartialReliability::Bytes(1000)))
+        .unwrap();
+    pair.client_send(client_ch, s).write(&[1; 500]).unwrap();
+
+    info!("losing stream data");
+    pair.client.drive(pair.time, pair.server.addr);
+    assert!(!pair.client.outbound.is_empty());
+    pair.client.outbound.clear();
+
+    // Falling more than 1000 bytes behind abandons the lost data, but none of the new data,
+    // which wasn't sent yet
+    pair.client_send(client_ch, s).write(&[2; 2000]).unwrap();
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive();
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.frame_tx.stream_skip, 1);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Stream(StreamEvent::Finished { id })) if id == s
+    );
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    let mut received = Vec::new();
+    while let Some(chunk) = chunks.next(usize::MAX).unwrap() {
+        assert_eq!(chunk.offset, 500 + received.len() as u64);
+        received.extend_from_slice(&chunk.bytes);
+    }
+    assert_eq!(received, [2; 2000]);
+    let _ = chunks.finalize();
+}
+
+#[test]
+fn partial_reliability_age() {
+    let _guard = subscribe();
+    let mut pair = partial_reliability_pair();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s)
+        .set_partial_reliability(Some(PartialReliability::Age(Duration::from_millis(1))))
+        .unwrap();
+    const MSG1: &[u8] = b"stale";
+    const MSG2: &[u8] = b"fresh";
+    pair.client_send(client_ch, s).write(MSG1).unwrap();
+
+    info!("losing stream data");
+    pair.client.drive(pair.time, pair.server.addr);
+    assert!(!pair.client.outbound.is_empty());
+    pair.client.outbound.clear();
+    pair.drive();
+
+    // The lost data went stale before it could be retransmitted
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.frame_tx.stream_skip, 1);
+    assert_eq!(stats.frame_tx.stream, 1);
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    {
+        // Nothing is readable yet, but the skip doesn't leave the stream stalled
+        let mut recv = pair.server_recv(server_ch, s);
+        let mut chunks = recv.read(true).unwrap();
+        assert_matches!(chunks.next(usize::MAX), Err(ReadError::Blocked));
+        let _ = chunks.finalize();
+    }
+
+    pair.client_send(client_ch, s).write(MSG2).unwrap();
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive();
+
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    assert_matches!(
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == MSG1.len() as u64 && chunk.bytes == MSG2
+    );
+    assert_matches!(chunks.next(usize::MAX), Ok(None));
+    let _ = chunks.finalize();
+}
+
+#[test]
+fn partial_reliability_unsupported_by_peer() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let partial = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, partial)
+        .set_partial_reliability(Some(PartialReliability::Bytes(100)))
+        .unwrap();
+    pair.client_send(client_ch, partial)
+        .write(&[1; 500])
+        .unwrap();
+    pair.client_send(client_ch, partial).finish().unwrap();
+    // An expiring deadline on another stream checks all streams for stale data
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    let deadline = pair.time + Duration::from_millis(1);
+    pair.client_send(client_ch, s)
+        .write_with_deadline(b"hello", deadline, VarInt(7))
+        .unwrap();
+
+    info!("losing stream data");
+    pair.client.drive(pair.time, pair.server.addr);
+    assert!(!pair.client.outbound.is_empty());
+    pair.client.outbound.clear();
+    pair.drive();
+
+    // The stream stays fully reliable, as the peer would reject STREAM_SKIP frames
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.frame_tx.reset_stream, 1);
+    assert_eq!(stats.frame_tx.stream_skip, 0);
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == partial);
+    let mut recv = pair.server_recv(server_ch, partial);
+    let mut chunks = recv.read(true).unwrap();
+    let mut received = Vec::new();
+    while let Some(chunk) = chunks.next(usize::MAX).unwrap() {
+        received.extend_from_slice(&chunk.bytes);
+    }
+    assert_eq!(received, [1; 500]);
+    let _ = chunks.finalize();
+}
+
+#[test]
+fn send_watermarks() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s)
+        .set_watermarks(1000, 4000)
+        .unwrap();
+    pair.client_send(client_ch, s).write(&[0; 3000]).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).writable(), Ok(()));
+    pair.client_send(client_ch, s).write(&[0; 2000]).unwrap();
+    assert_eq!(
+        pair.client_send(client_ch, s).writable(),
+        Err(WriteError::Blocked)
+    );
+
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Stream(StreamEvent::Writable { id })) if id == s
+    );
+    assert_eq!(pair.client_send(client_ch, s).writable(), Ok(()));
+
+    // Writing below the high watermark doesn't pause the producer again
+    pair.client_send(client_ch, s).write(&[0; 3000]).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).writable(), Ok(()));
+}
+
+#[test]
+fn send_watermarks_zero_low() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+
+    // A low watermark of zero resumes the producer once everything was acknowledged
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s)
+        .set_watermarks(0, 1000)
+        .unwrap();
+    pair.client_send(client_ch, s).write(&[0; 2000]).unwrap();
+    assert_eq!(
+        pair.client_send(client_ch, s).writable(),
+        Err(WriteError::Blocked)
+    );
+
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Stream(StreamEvent::Writable { id })) if id == s
+    );
+    assert_eq!(pair.client_send(client_ch, s).writable(), Ok(()));
+}
+
+#[test]
+fn reset_all_streams() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    const MSG: &[u8] = b"hello";
+    let mut streams = Vec::new();
+    for dir in [Dir::Uni, Dir::Uni, Dir::Bi] {
+        let s = pair.client_streams(client_ch).open(dir).unwrap();
+        pair.client_send(client_ch, s).write(MSG).unwrap();
+        streams.push(s);
+    }
+    pair.drive();
+    for _ in 0..2 {
+        assert!(pair.server_streams(server_ch).accept(Dir::Uni).is_some());
+    }
+    assert!(pair.server_streams(server_ch).accept(Dir::Bi).is_some());
+
+    info!("resetting all streams");
+    const ERROR: VarInt = VarInt(9);
+    pair.client_conn_mut(client_ch).reset_all_streams(ERROR);
+    pair.drive();
+
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .frame_tx
+            .reset_stream,
+        3
+    );
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .frame_tx
+            .stop_sending,
+        1
+    );
+    for &s in &streams {
+        let mut recv = pair.server_recv(server_ch, s);
+        let mut chunks = recv.read(false).unwrap();
+        assert_matches!(chunks.next(usize::MAX), Err(ReadError::Reset(ERROR)));
+        let _ = chunks.finalize();
+    }
+    assert_matches!(
+        pair.server_send(server_ch, streams[2]).write(MSG),
+        Err(WriteError::Stopped(ERROR))
+    );
+}
+
+#[test]
+fn finish_all_streams() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    const MSG: &[u8] = b"hello";
+    let mut streams = Vec::new();
+    for _ in 0..3 {
+        let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+        pair.client_send(client_ch, s).write(MSG).unwrap();
+        streams.push(s);
+    }
+    pair.drive();
+    let mut recvs = Vec::new();
+    for _ in 0..3 {
+        recvs.push(pair.server_streams(server_ch).accept(Dir::Uni).unwrap());
+    }
+    // A stopped stream can't be finished, and must be reset instead
+    const ERROR: VarInt = VarInt(7);
+    pair.server_recv(server_ch, recvs[2]).stop(ERROR).unwrap();
+    pair.drive();
+
+    pair.client_conn_mut(client_ch).finish_all_streams();
+    pair.drive();
+
+    assert_eq!(pair.client_streams(client_ch).send_streams(), 0);
+    for &s in &recvs[..2] {
+        let mut recv = pair.server_recv(server_ch, s);
+        let mut chunks = recv.read(false).unwrap();
+        assert_matches!(chunks.next(usize::MAX), Ok(Some(chunk)) if chunk.bytes == MSG);
+        assert_matches!(chunks.next(usize::MAX), Ok(None));
+        let _ = chunks.finalize();
+    }
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .frame_tx
+            .reset_stream,
+        1
+    );
+}
+
+#[test]
+fn stop_stream() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+
+    info!("stopping stream");
+    const ERROR: VarInt = VarInt(42);
+    pair.server_recv(server_ch, s).stop(ERROR).unwrap();
+    pair.drive();
+
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+
+    assert_matches!(
+        pair.client_send(client_ch, s).write(b"foo"),
+        Err(WriteError::Stopped(ERROR))
+    );
+    assert_matches!(
+        pair.client_send(client_ch, s).finish(),
+        Err(FinishError::Stopped(ERROR))
+    );
+}
+
+#[test]
+fn reject_self_signed_server_cert() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    info!("connecting");
+
+    // Create a self-signed certificate with a different distinguished name than the default one,
+    // such that path building cannot confuse the default root the server is using and the one
+    // the client is trusting (in which case we'd get a different error).
+    let mut cert = rcgen::CertificateParams::new(["localhost".into()]).unwrap();
+    let mut issuer = rcgen::DistinguishedName::new();
+    issuer.push(
+        rcgen::DnType::OrganizationName,
+        "Crazy Quinn's House of Certificates",
+    );
+    cert.distinguished_name = issuer;
+    let cert = cert
+        .self_signed(&rcgen::KeyPair::generate().unwrap())
+        .unwrap();
+    let client_ch = pair.begin_connect(client_config_with_certs(vec![cert.into()]));
+
+    pair.drive();
+
+    assert_matches!(pair.client_conn_mut(client_ch).poll(),
+                    Some(Event::ConnectionLost { reason: ConnectionError::TransportError(ref error)})
+                    if error.code == TransportErrorCode::crypto(AlertDescription::UnknownCA.into()));
+}
+
+#[test]
+fn verifier_sees_remote_address() {
+    let _guard = subscribe();
+    for accept in [true, false] {
+        let mut pair = Pair::default();
+        let allowed = match accept {
+            true => pair.server.addr,
+            false => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 1),
+        };
+        let crypto = client_crypto()
+            .with_verifier_factory(move |remote| Arc::new(AcceptAnyFrom { allowed, remote }));
+        let client_ch = pair.begin_connect(ClientConfig::new(Arc::new(crypto)));
+        pair.drive();
+
+        if accept {
+            assert_matches!(
+                pair.client_conn_mut(client_ch).poll(),
+                Some(Event::HandshakeDataReady)
+            );
+            assert_matches!(
+                pair.client_conn_mut(client_ch).poll(),
+                Some(Event::Connected)
+            );
+        } else {
+            assert_matches!(pair.client_conn_mut(client_ch).poll(),
+                            Some(Event::ConnectionLost { reason: ConnectionError::TransportError(ref error)})
+                            if error.code == TransportErrorCode::crypto(AlertDescription::AccessDenied.into()));
+        }
+    }
+}
+
+#[test]
+fn verifier_factory_resumes_sessions() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.server.handle_incoming = Box::new(validate_incoming);
+    let allowed = pair.server.addr;
+    let constructed = Arc::new(AtomicUsize::new(0));
+    let crypto = client_crypto().with_verifier_factory({
+        let constructed = constructed.clone();
+        move |remote| {
+            constructed.fetch_add(1, Ordering::Relaxed);
+            Arc::new(AcceptAnyFrom { allowed, remote })
+        }
+    });
+    let config = ClientConfig::new(Arc::new(crypto));
+
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
+    pair.drive();
+
+    // The verifier that established the session is reused, so the session can be resumed
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    assert_eq!(constructed.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn reject_missing_client_cert() {
+    let _guard = subscribe();
+
+    let mut store = RootCertStore::empty();
+    // `WebPkiClientVerifier` requires a non-empty store, so we stick our own certificate into it
+    // because it's convenient.
+    store.add(CERTIFIED_KEY.cert.der().clone()).unwrap();
+
+    let key = PrivatePkcs8KeyDer::from(CERTIFIED_KEY.signing_key.serialize_der());
+    let cert = CERTIFIED_KEY.cert.der().clone();
+
+    let provider = Arc::new(default_provider());
+    let config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_client_cert_verifier(
+            WebPkiClientVerifier::builder_with_provider(Arc::new(store), provider)
+                .build()
+                .unwrap(),
+        )
+        .with_single_cert(vec![cert], PrivateKeyDer::from(key))
+        .unwrap();
+    let config = QuicServerConfig::try_from(config).unwrap();
+
+    let mut pair = Pair::new(
         Default::default(),
         ServerConfig::with_crypto(Arc::new(config)),
     );
@@ -512,6 +1397,145 @@ fn congestion() {
     pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
 }
 
+#[test]
+fn in_flight_and_retransmit_gauges() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+    assert_eq!(pair.client_conn_mut(client_ch).bytes_in_flight(), 0);
+
+    // Send data without receiving ACKs until the congestion window is full
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    while pair.client_conn_mut(client_ch).congestion_window() > 1500 {
+        pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
+        pair.drive_client();
+    }
+    let path = pair.client_conn_mut(client_ch).stats().path;
+    assert!(path.cwnd - path.bytes_in_flight <= 1500);
+    assert_eq!(
+        path.bytes_in_flight,
+        pair.client_conn_mut(client_ch).bytes_in_flight()
+    );
+    assert_eq!(path.bytes_pending_retransmit, 0);
+
+    // Lose the first few packets, and let the client learn of that from the ACKs for the rest
+    for _ in 0..3 {
+        pair.server.inbound.pop_front();
+    }
+    pair.drive_server();
+    pair.client.drive_incoming(pair.time, pair.server.addr);
+    // Handle the ACKs without sending retransmissions yet
+    let events = pair.client.conn_events.remove(&client_ch).unwrap();
+    let conn = pair.client_conn_mut(client_ch);
+    for event in events {
+        conn.handle_event(event);
+    }
+    assert!(conn.bytes_in_flight() < path.bytes_in_flight / 2);
+    let pending = conn.bytes_pending_retransmit();
+    assert!(pending >= 2 * 1024, "{pending} bytes pending");
+    assert_eq!(conn.stats().path.bytes_pending_retransmit, pending);
+
+    // Retransmissions drain the gauge, and the final ACKs empty the flight
+    pair.drive();
+    let conn = pair.client_conn_mut(client_ch);
+    assert_eq!(conn.bytes_pending_retransmit(), 0);
+    assert_eq!(conn.bytes_in_flight(), 0);
+}
+
+#[test]
+fn hystart_bottleneck() {
+    let _guard = subscribe();
+
+    /// Upload through a bottleneck, returning the client's path stats
+    fn upload(hystart: bool) -> PathStats {
+        let mut cubic = congestion::CubicConfig::default();
+        cubic.hystart(hystart);
+        let mut transport = TransportConfig::default();
+        transport.congestion_controller_factory(Arc::new(cubic));
+        let mut client_config = client_config();
+        client_config.transport_config(Arc::new(transport));
+
+        let mut pair = Pair::default();
+        pair.latency = Duration::from_millis(25);
+        let (client_ch, _) = pair.connect_with(client_config);
+        // 10 Mbit/s, with a queue holding one bandwidth-delay product
+        pair.bottleneck = Some(Bottleneck::new(1_250_000, 62_500));
+
+        const LEN: usize = 1_000_000;
+        let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+        let n = pair.client_send(client_ch, s).write(&vec![0; LEN]).unwrap();
+        assert_eq!(n, LEN);
+        pair.drive();
+        pair.client_conn_mut(client_ch).stats().path
+    }
+
+    let standard = upload(false);
+    let hystart = upload(true);
+    assert_eq!(standard.hystart_exits, 0);
+    assert_eq!(hystart.hystart_exits, 1);
+    assert!(standard.lost_packets > 0);
+    assert!(
+        hystart.lost_packets < standard.lost_packets,
+        "{} packets lost with HyStart++, {} without",
+        hystart.lost_packets,
+        standard.lost_packets
+    );
+}
+
+#[test]
+fn max_congestion_window() {
+    let _guard = subscribe();
+    const CAP: u64 = 100_000;
+
+    /// Upload through a deep-buffered bottleneck, returning the largest congestion window, bytes
+    /// in flight and RTT observed by the client
+    fn upload(cap: u64) -> (u64, u64, Duration) {
+        let mut transport = TransportConfig::default();
+        transport.max_congestion_window(cap);
+        let mut client_config = client_config();
+        client_config.transport_config(Arc::new(transport));
+
+        let mut pair = Pair::default();
+        pair.latency = Duration::from_millis(25);
+        let (client_ch, _) = pair.connect_with(client_config);
+        // 10 Mbit/s, with a queue holding 80 bandwidth-delay products
+        pair.bottleneck = Some(Bottleneck::new(1_250_000, 5_000_000));
+
+        const LEN: usize = 1_000_000;
+        let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+        let n = pair.client_send(client_ch, s).write(&vec![0; LEN]).unwrap();
+        assert_eq!(n, LEN);
+        let (mut cwnd, mut in_flight, mut rtt) = (0, 0, Duration::ZERO);
+        while pair.step() {
+            let path = pair.client_conn_mut(client_ch).stats().path;
+            cwnd = cwnd.max(path.cwnd);
+            in_flight = in_flight.max(path.bytes_in_flight);
+            rtt = rtt.max(path.latest_rtt);
+        }
+        assert_eq!(
+            pair.client_send(client_ch, s).bytes_acked().unwrap(),
+            LEN as u64
+        );
+        (cwnd, in_flight, rtt)
+    }
+
+    let (cwnd, in_flight, rtt) = upload(u64::MAX);
+    assert!(cwnd > CAP, "uncapped window only grew to {cwnd}");
+
+    let (capped_cwnd, capped_in_flight, capped_rtt) = upload(CAP);
+    assert!(capped_cwnd <= CAP);
+    assert!(capped_in_flight <= CAP);
+    // At most the cap is queued at the bottleneck, which drains it in 80ms
+    assert!(
+        capped_rtt < Duration::from_millis(50 + 80 + 10),
+        "queueing inflated the RTT to {capped_rtt:?}"
+    );
+    assert!(
+        capped_rtt < rtt,
+        "capped RTT {capped_rtt:?}, uncapped {rtt:?} with {in_flight} bytes in flight"
+    );
+}
+
 #[test]
 fn high_latency_handshake() {
     let _guard = subscribe();
@@ -524,6 +1548,35 @@ fn high_latency_handshake() {
     assert!(pair.server_conn_mut(server_ch).using_ecn());
 }
 
+#[test]
+fn can_resume_0rtt() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.server.handle_incoming = Box::new(validate_incoming);
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(CERTIFIED_KEY.cert.der().clone()).unwrap();
+    let config = ClientConfig::with_root_certificates(Arc::new(roots)).unwrap();
+    assert!(!config.can_resume_0rtt("localhost"));
+
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
+    pair.drive();
+
+    // Checking doesn't consume the ticket
+    assert!(config.can_resume_0rtt("localhost"));
+    assert!(config.can_resume_0rtt("localhost"));
+    assert!(!config.can_resume_0rtt("example.com"));
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+
+    // Not supported for wrapped rustls configurations
+    assert!(!client_config().can_resume_0rtt("localhost"));
+}
+
 #[test]
 fn zero_rtt_happypath() {
     let _guard = subscribe();
@@ -564,7 +1617,11 @@ fn zero_rtt_happypath() {
     );
 
     assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert!(stats.used_0rtt && stats.zero_rtt_accepted);
     let server_ch = pair.server.assert_accept();
+    let stats = pair.server_conn_mut(server_ch).stats();
+    assert!(stats.used_0rtt && stats.zero_rtt_accepted);
 
     assert_matches!(
         pair.server_conn_mut(server_ch).poll(),
@@ -653,6 +1710,8 @@ fn zero_rtt_rejection() {
     pair.client_send(client_ch, s).write(MSG).unwrap();
     pair.drive();
     assert!(!pair.client_conn_mut(client_ch).accepted_0rtt());
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert!(stats.used_0rtt && !stats.zero_rtt_accepted);
     let server_ch = pair.server.assert_accept();
     assert_matches!(
         pair.server_conn_mut(server_ch).poll(),
@@ -811,31 +1870,133 @@ fn alpn_success() {
         "corge".into(),
     ])));
 
-    // Establish normal connection
-    let client_ch = pair.begin_connect(client_config);
+    // Establish normal connection
+    let client_ch = pair.begin_connect(client_config);
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeConfirmed)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected)
+    );
+
+    let hd = pair
+        .client_conn_mut(client_ch)
+        .crypto_session()
+        .handshake_data()
+        .unwrap()
+        .downcast::<crate::crypto::rustls::HandshakeData>()
+        .unwrap();
+    assert_eq!(hd.protocol.unwrap(), &b"bar"[..]);
+}
+
+#[test]
+fn alpn_per_connection() {
+    let _guard = subscribe();
+    for protocol in ["bar", "foo"] {
+        let server_config = ServerConfig::with_crypto(Arc::new(server_crypto_with_alpn(vec![
+            "foo".into(),
+            "bar".into(),
+        ])));
+        let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
+        let client_config =
+            ClientConfig::new(Arc::new(client_crypto_with_alpn(vec![protocol.into()])));
+        let (_, server_ch) = pair.connect_with(client_config);
+        let session = pair.server_conn_mut(server_ch).crypto_session();
+        assert_eq!(session.alpn_protocol().unwrap(), protocol.as_bytes());
+        let hd = session
+            .handshake_data()
+            .unwrap()
+            .downcast::<crate::crypto::rustls::HandshakeData>()
+            .unwrap();
+        assert_eq!(hd.protocol.unwrap(), protocol.as_bytes());
+        assert_eq!(hd.server_name.unwrap(), "localhost");
+    }
+
+    // Neither side configured any protocols
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(
+        pair.server_conn_mut(server_ch)
+            .crypto_session()
+            .alpn_protocol(),
+        None
+    );
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .crypto_session()
+            .alpn_protocol(),
+        None
+    );
+}
+
+#[test]
+fn alpn_dispatch() {
+    let _guard = subscribe();
+
+    /// Start connecting with `protocols` to a server that dispatches on ALPN
+    fn connect(protocols: Vec<Vec<u8>>) -> (Pair, ConnectionHandle) {
+        let server_config = ServerConfig::with_crypto(Arc::new(server_crypto_with_alpn(vec![
+            "foo".into(),
+            "bar".into(),
+        ])));
+        let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
+        pair.server.handle_incoming =
+            Box::new(|incoming| match incoming.alpn_protocol().as_deref() {
+                Some(b"foo") => IncomingConnectionBehavior::Accept,
+                Some(b"bar") => IncomingConnectionBehavior::Wait,
+                _ => IncomingConnectionBehavior::Reject,
+            });
+        let client_config = ClientConfig::new(Arc::new(client_crypto_with_alpn(protocols)));
+        let client_ch = pair.begin_connect(client_config);
+        pair.drive();
+        (pair, client_ch)
+    }
+
+    // Accepted straight away
+    let (mut pair, client_ch) = connect(vec!["foo".into()]);
+    pair.server.assert_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+
+    // Handed off, and accepted later
+    let (mut pair, client_ch) = connect(vec!["quux".into(), "bar".into()]);
+    pair.server.assert_no_accept();
+    let incoming = pair.server.waiting_incoming.pop().unwrap();
+    assert_eq!(incoming.alpn_protocol().unwrap(), b"bar");
+    pair.server.try_accept(incoming, pair.time).unwrap();
     pair.drive();
-    let server_ch = pair.server.assert_accept();
     assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
+        pair.client_conn_mut(client_ch).poll(),
         Some(Event::HandshakeDataReady)
     );
     assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::HandshakeConfirmed)
-    );
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
+        pair.client_conn_mut(client_ch).poll(),
         Some(Event::Connected)
     );
 
-    let hd = pair
-        .client_conn_mut(client_ch)
-        .crypto_session()
-        .handshake_data()
-        .unwrap()
-        .downcast::<crate::crypto::rustls::HandshakeData>()
-        .unwrap();
-    assert_eq!(hd.protocol.unwrap(), &b"bar"[..]);
+    // No protocol in common
+    let (mut pair, client_ch) = connect(vec!["quux".into()]);
+    pair.server.assert_no_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(close)
+        }) if close.error_code == TransportErrorCode::CONNECTION_REFUSED
+    );
 }
 
 #[test]
@@ -892,6 +2053,92 @@ fn alpn_mismatch() {
     );
 }
 
+#[test]
+fn raise_stream_id_limit() {
+    let _guard = subscribe();
+    let server = ServerConfig {
+        transport: Arc::new(TransportConfig {
+            max_concurrent_uni_streams: 1u32.into(),
+            ..TransportConfig::default()
+        }),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+
+    assert!(pair.client_streams(client_ch).open(Dir::Uni).is_some());
+    assert_eq!(pair.client_streams(client_ch).open(Dir::Uni), None);
+
+    info!("raising limit");
+    pair.server_conn_mut(server_ch)
+        .set_max_concurrent_streams(Dir::Uni, 3u32.into());
+    assert_eq!(
+        pair.server_conn_mut(server_ch)
+            .max_concurrent_streams(Dir::Uni),
+        3
+    );
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Stream(StreamEvent::Available { dir: Dir::Uni }))
+    );
+    assert!(pair.client_streams(client_ch).open(Dir::Uni).is_some());
+    assert!(pair.client_streams(client_ch).open(Dir::Uni).is_some());
+    assert_eq!(pair.client_streams(client_ch).open(Dir::Uni), None);
+
+    info!("lowering limit");
+    pair.server_conn_mut(server_ch)
+        .set_max_concurrent_streams(Dir::Uni, 1u32.into());
+    pair.drive();
+    // Credit that was already granted is not retracted
+    assert_eq!(
+        pair.server_conn_mut(server_ch)
+            .max_concurrent_streams(Dir::Uni),
+        3
+    );
+    assert_eq!(pair.client_streams(client_ch).open(Dir::Uni), None);
+}
+
+#[test]
+fn unhandled_stream_backpressure() {
+    let _guard = subscribe();
+    let server = ServerConfig {
+        transport: Arc::new(TransportConfig {
+            max_concurrent_uni_streams: 1u32.into(),
+            max_unhandled_streams: Some(1u32.into()),
+            ..TransportConfig::default()
+        }),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+
+    const MSG: &[u8] = b"hello";
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive();
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Ok(Some(_)));
+    assert_eq!(chunks.next(usize::MAX), Ok(None));
+    let _ = chunks.finalize();
+    pair.drive();
+
+    // The stream is fully read, but the application hasn't finished handling it
+    assert_eq!(
+        pair.client_streams(client_ch).open(Dir::Uni),
+        None,
+        "server withholds credit while the stream is unhandled"
+    );
+
+    pair.server_recv(server_ch, s).handled();
+    pair.drive();
+    assert!(pair.client_streams(client_ch).open(Dir::Uni).is_some());
+}
+
 #[test]
 fn stream_id_limit() {
     let _guard = subscribe();
@@ -980,6 +2227,52 @@ fn stream_id_limit() {
     let _ = chunks.finalize();
 }
 
+#[test]
+fn available_streams() {
+    let _guard = subscribe();
+    let server = ServerConfig {
+        transport: Arc::new(TransportConfig {
+            max_concurrent_uni_streams: 2u32.into(),
+            ..TransportConfig::default()
+        }),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(pair.client_streams(client_ch).available(Dir::Uni), 2);
+    assert_eq!(pair.client_streams(client_ch).available(Dir::Bi), 100);
+
+    let mut opened = Vec::new();
+    while let Some(s) = pair.client_streams(client_ch).open(Dir::Uni) {
+        opened.push(s);
+    }
+    assert_eq!(opened.len(), 2);
+    assert_eq!(pair.client_streams(client_ch).available(Dir::Uni), 0);
+    assert_eq!(pair.client_streams(client_ch).available(Dir::Bi), 100);
+
+    for &s in &opened {
+        pair.client_send(client_ch, s).finish().unwrap();
+    }
+    pair.drive();
+    assert_eq!(pair.client_streams(client_ch).available(Dir::Uni), 0);
+
+    // Credit is only granted again once the server has handled the streams
+    for &s in &opened {
+        assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s));
+        let mut recv = pair.server_recv(server_ch, s);
+        let mut chunks = recv.read(false).unwrap();
+        assert_eq!(chunks.next(usize::MAX), Ok(None));
+        let _ = chunks.finalize();
+    }
+    pair.drive();
+    assert_eq!(pair.client_streams(client_ch).available(Dir::Uni), 2);
+
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), Bytes::new());
+    assert_eq!(pair.client_streams(client_ch).available(Dir::Uni), 0);
+}
+
 #[test]
 fn key_update_simple() {
     let _guard = subscribe();
@@ -1081,6 +2374,96 @@ fn key_update_reordered() {
     assert_eq!(pair.server_conn_mut(server_ch).stats().path.lost_packets, 0);
 }
 
+/// Deliver one ping-bearing packet after three later ones and return how many packets the client
+/// declared lost
+fn reordered_ping_losses(packet_threshold: u32) -> u64 {
+    let mut transport = TransportConfig::default();
+    transport
+        .loss_detection(packet_threshold, 9.0 / 8.0)
+        .unwrap();
+    reordered_ping_rounds(transport, 1)[0].0
+}
+
+/// Perform `rounds` rounds of delivering one ping-bearing packet after three later ones on a single
+/// connection, returning the number of packets the client declared lost in each round and its
+/// reordering window afterwards
+fn reordered_ping_rounds(mut transport: TransportConfig, rounds: usize) -> Vec<(u64, Duration)> {
+    transport.deterministic_packet_numbers(true);
+    let mut client_config = client_config();
+    client_config.transport_config(Arc::new(transport));
+
+    let mut pair = Pair::default_with_deterministic_pns();
+    // Keep the RTT well above the timer granularity, so that the reordering window reflects it
+    pair.latency = Duration::from_millis(10);
+    let (client_ch, _) = pair.connect_with(client_config);
+
+    let mut results = Vec::new();
+    for _ in 0..rounds {
+        let lost_before = pair.client_conn_mut(client_ch).stats().path.lost_packets;
+
+        pair.client_conn_mut(client_ch).ping();
+        pair.client.drive(pair.time, pair.server.addr);
+        assert_eq!(pair.client.outbound.len(), 1);
+        pair.client.delay_outbound();
+
+        for _ in 0..3 {
+            pair.client_conn_mut(client_ch).ping();
+            pair.client.drive(pair.time, pair.server.addr);
+        }
+        pair.drive_client();
+        // Release the first ping shortly after the later ones, well within an RTT
+        pair.time += Duration::from_millis(2);
+        pair.client.finish_delay();
+        pair.drive();
+
+        let stats = pair.client_conn_mut(client_ch).stats();
+        results.push((
+            stats.path.lost_packets - lost_before,
+            stats.path.reordering_window,
+        ));
+    }
+    results
+}
+
+#[test]
+fn loss_detection_packet_threshold_tolerates_reordering() {
+    let _guard = subscribe();
+    assert_eq!(reordered_ping_losses(3), 1);
+    assert_eq!(reordered_ping_losses(5), 0);
+}
+
+#[test]
+fn loss_detection_adapts_to_reordering() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.max_time_threshold(Some(2.0)).unwrap();
+    let rounds = reordered_ping_rounds(transport, 2);
+    // The first reordering is mistaken for a loss, after which the window widens
+    assert_eq!(rounds[0].0, 1);
+    assert_eq!(rounds[1].0, 0);
+
+    let fixed = reordered_ping_rounds(TransportConfig::default(), 2);
+    assert_eq!(fixed[0].0, 1);
+    assert_eq!(fixed[1].0, 1);
+    assert!(rounds[1].1 > fixed[1].1);
+}
+
+#[test]
+fn loss_detection_rejects_invalid_thresholds() {
+    let mut config = TransportConfig::default();
+    assert!(config.loss_detection(0, 9.0 / 8.0).is_err());
+    assert!(config.loss_detection(3, 0.5).is_err());
+    assert!(config.loss_detection(3, f32::NAN).is_err());
+    assert!(config.loss_detection(3, f32::INFINITY).is_err());
+    assert!(config.loss_detection(10, 2.0).is_ok());
+    assert_eq!(config.packet_threshold, 10);
+    assert_eq!(config.time_threshold, 2.0);
+    assert!(config.max_time_threshold(Some(0.5)).is_err());
+    assert!(config.max_time_threshold(Some(f32::NAN)).is_err());
+    assert!(config.max_time_threshold(Some(4.0)).is_ok());
+    assert!(config.max_time_threshold(None).is_ok());
+}
+
 #[test]
 fn initial_retransmit() {
     let _guard = subscribe();
@@ -1224,6 +2607,180 @@ fn idle_timeout() {
     );
 }
 
+#[test]
+fn handshake_recovery_stats() {
+    let _guard = subscribe();
+    // Without CIDs to issue, the server sends no 1-RTT packets before the handshake completes,
+    // which would otherwise be lost when they arrive before the client can decrypt them
+    let cid_generator_factory: fn() -> Box<dyn ConnectionIdGenerator> =
+        || Box::new(RandomConnectionIdGenerator::new(0));
+    let mut pair = Pair::new(
+        Arc::new(EndpointConfig {
+            connection_id_generator_factory: Arc::new(cid_generator_factory),
+            ..EndpointConfig::default()
+        }),
+        server_config(),
+    );
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive_client();
+    pair.server.inbound.clear(); // Lose the client's first flight
+
+    // Wait for the client's probe and lose the server's first flight in response
+    while pair.server.inbound.is_empty() {
+        pair.time = pair.client.next_wakeup().unwrap();
+        pair.drive_client();
+    }
+    pair.drive_server();
+    assert!(!pair.client.inbound.is_empty());
+    pair.client.inbound.clear();
+
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    assert!(!pair.client_conn_mut(client_ch).is_handshaking());
+
+    let client = pair.client_conn_mut(client_ch).stats().recovery;
+    assert!(client.initial.ptos >= 1);
+    assert_eq!(client.data.ptos, 0);
+    assert_eq!(client.data.lost_packets, 0);
+
+    let server = pair.server_conn_mut(server_ch).stats().recovery;
+    assert!(server.initial.lost_packets >= 1);
+    assert!(server.handshake.ptos >= 1);
+    assert_eq!(server.data.ptos, 0);
+    assert_eq!(server.data.lost_packets, 0);
+}
+
+#[test]
+fn packet_threshold_loss() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(5);
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+    // Let the pacer refill, so the stream's packets are sent in a single burst
+    pair.time += Duration::from_millis(100);
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = &[0xab; 8 * 1024];
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.client_send(client_ch, s).finish().unwrap();
+
+    // Lose the first of several packets carrying the stream's data
+    let start = pair.time;
+    pair.client.drive(pair.time, pair.server.addr);
+    assert!(pair.client.outbound.len() > 3);
+    pair.client.outbound.pop_front();
+    pair.drive_client();
+    let stream_frames = pair.client_conn_mut(client_ch).stats().frame_tx.stream;
+
+    // Acknowledgements for the later packets reveal the loss one round trip later, and the lost
+    // data is retransmitted right away rather than after a PTO
+    pair.time += pair.latency;
+    pair.drive_server();
+    pair.time += pair.latency;
+    pair.drive_client();
+    assert_eq!(pair.time - start, 2 * pair.latency);
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.recovery.data.lost_packets, 1);
+    assert!(stats.frame_tx.stream > stream_frames);
+
+    // The retransmission completes the stream on arrival
+    pair.time += pair.latency;
+    pair.drive_server();
+    assert_matches!(
+        pair.server_streams(server_ch).accept(Dir::Uni),
+        Some(stream) if stream == s
+    );
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    let mut received = 0;
+    while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+        received += chunk.bytes.len();
+    }
+    let _ = chunks.finalize();
+    assert_eq!(received, MSG.len());
+    pair.drive();
+
+    let stats = pair.client_conn_mut(client_ch).stats().recovery.data;
+    assert_eq!(stats.lost_packets, 1);
+    assert_eq!(stats.lost_by_packet_threshold, 1);
+    assert_eq!(stats.lost_by_time_threshold, 0);
+    assert_eq!(stats.ptos, 0);
+}
+
+#[test]
+fn max_pto_count() {
+    let _guard = subscribe();
+    assert!(TransportConfig::default().max_pto_count(0).is_err());
+    assert!(TransportConfig::default().max_pto_count(1).is_err());
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+    let mut transport = TransportConfig::default();
+    transport
+        .max_idle_timeout(Some(IDLE_TIMEOUT.try_into().unwrap()))
+        .max_pto_count(3)
+        .unwrap();
+    let mut client_config = client_config();
+    client_config.transport_config(Arc::new(transport));
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect_with(client_config);
+    pair.client_conn_mut(client_ch).ping();
+    let start = pair.time;
+
+    let closed_at = loop {
+        // Stepping advances the clock after driving the connections
+        let now = pair.time;
+        if !pair.step() {
+            if let Some(t) = min_opt(pair.client.next_wakeup(), pair.server.next_wakeup()) {
+                pair.time = t;
+            }
+        }
+        // Simulate a black hole
+        pair.client.inbound.clear();
+        pair.server.inbound.clear();
+        if pair.client_conn_mut(client_ch).is_closed() {
+            break now;
+        }
+    };
+
+    assert!(closed_at - start < IDLE_TIMEOUT / 10);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::TimedOut,
+        })
+    );
+}
+
+#[test]
+fn pto_probe_count() {
+    let _guard = subscribe();
+    assert!(TransportConfig::default().pto_probe_count(0).is_err());
+    assert!(TransportConfig::default().pto_probe_count(3).is_err());
+
+    for probes in [1, 2] {
+        let mut transport = TransportConfig::default();
+        transport.pto_probe_count(probes).unwrap();
+        let mut client_config = client_config();
+        client_config.transport_config(Arc::new(transport));
+        let mut pair = Pair::default();
+        let (client_ch, _) = pair.connect_with(client_config);
+        pair.drive();
+
+        pair.client_conn_mut(client_ch).ping();
+        pair.drive_client();
+        for pto in 1..=3 {
+            // Simulate a black hole, so that the PTO fires
+            pair.server.inbound.clear();
+            pair.time = pair.client.next_wakeup().unwrap();
+            pair.drive_client();
+            let stats = pair.client_conn_mut(client_ch).stats();
+            assert_eq!(stats.recovery.data.ptos, pto);
+            assert_eq!(pair.server.inbound.len(), usize::from(probes));
+        }
+    }
+}
+
 #[test]
 fn connection_close_sends_acks() {
     let _guard = subscribe();
@@ -1248,6 +2805,31 @@ fn connection_close_sends_acks() {
     );
 }
 
+#[test]
+fn coalesced_packets() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive_client();
+    pair.server.drive(pair.time, pair.client.addr);
+
+    // The server's first datagram coalesces its Initial and Handshake packets
+    let (_, datagram) = pair.server.outbound.pop_front().unwrap();
+    pair.server.outbound.clear();
+    let mut datagram = BytesMut::from(&datagram[..]);
+    datagram.extend_from_slice(&[0; 16]); // Trailing garbage
+    pair.client.inbound.push_back((pair.time, None, datagram));
+    pair.client.drive(pair.time, pair.server.addr);
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.coalesced_packets_received, 1);
+    assert_eq!(stats.frame_rx.crypto, 2);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+}
+
 #[test]
 fn server_hs_retransmit() {
     let _guard = subscribe();
@@ -1281,6 +2863,10 @@ fn migration() {
         CLIENT_PORTS.lock().unwrap().next().unwrap(),
     );
     pair.client_conn_mut(client_ch).ping();
+    // A lone PING datagram earns the server too little anti-amplification credit to answer on
+    // the new path, which would leave the client probing repeatedly; send some data along
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(&[0; 100]).unwrap();
 
     // Assert that just receiving the ping message is accounted into the servers
     // anti-amplification budget
@@ -1307,6 +2893,147 @@ fn migration() {
             - client_stats_after_connect.frame_tx.immediate_ack,
         1
     );
+
+    // Only the server, which follows the client to its new address, counts a migration
+    assert_eq!(client_stats_after_migrate.path.migration_attempts, 0);
+    let server_stats = pair.server_conn_mut(server_ch).stats();
+    assert_eq!(server_stats.path.migration_attempts, 1);
+    assert_eq!(server_stats.path.migration_successes, 1);
+}
+
+#[test]
+fn migration_disabled() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.allow_migration(false);
+    let mut server_config = server_config();
+    server_config.transport_config(Arc::new(transport));
+    let mut pair = Pair::new(Default::default(), server_config);
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+    let server_addr = pair.server_conn_mut(server_ch).remote_address();
+    let server_pings = pair.server_conn_mut(server_ch).stats().frame_rx.ping;
+
+    pair.client.addr = SocketAddr::new(
+        Ipv4Addr::new(127, 0, 0, 1).into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    pair.client_conn_mut(client_ch).local_address_changed();
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive();
+
+    // The server ignored everything sent from the new address
+    assert_eq!(
+        pair.server_conn_mut(server_ch).remote_address(),
+        server_addr
+    );
+    assert_eq!(
+        pair.server_conn_mut(server_ch).stats().frame_rx.ping,
+        server_pings
+    );
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+}
+
+#[test]
+fn migration_cooldown() {
+    let _guard = subscribe();
+
+    /// Switch the client between two addresses with every packet, then settle on a third
+    fn flap(cooldown: Duration) -> PathStats {
+        let mut transport = TransportConfig::default();
+        transport.migration_cooldown(cooldown);
+        let mut server_config = server_config();
+        server_config.transport_config(Arc::new(transport));
+        let mut pair = Pair::new(Default::default(), server_config);
+        let (client_ch, server_ch) = pair.connect();
+        pair.drive();
+
+        let addrs = [(); 3].map(|()| {
+            SocketAddr::new(
+                Ipv4Addr::new(127, 0, 0, 1).into(),
+                CLIENT_PORTS.lock().unwrap().next().unwrap(),
+            )
+        });
+        for i in 0..20 {
+            pair.client.addr = addrs[i % 2];
+            pair.client_conn_mut(client_ch).ping();
+            pair.drive_client();
+            pair.drive_server();
+            pair.time += Duration::from_millis(1);
+        }
+        pair.client.addr = addrs[2];
+        pair.client_conn_mut(client_ch).ping();
+        pair.drive();
+
+        // The server ends up following the client to its final address either way
+        assert_eq!(
+            pair.server_conn_mut(server_ch).remote_address(),
+            pair.client.addr
+        );
+        assert!(!pair.server_conn_mut(server_ch).is_closed());
+        pair.server_conn_mut(server_ch).stats().path
+    }
+
+    let unlimited = flap(Duration::ZERO);
+    assert!(unlimited.migration_attempts >= 20);
+
+    let limited = flap(Duration::from_secs(10));
+    // Once to follow the flapping client, and once more after the path to its last address in
+    // the flapping pair failed
+    assert_eq!(limited.migration_attempts, 2);
+    assert_eq!(limited.migration_successes, 1);
+}
+
+#[test]
+fn peer_transport_parameters() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport
+        .max_concurrent_bidi_streams(7u32.into())
+        .max_concurrent_uni_streams(3u32.into())
+        .receive_window(123_456u32.into())
+        .stream_receive_window(65_432u32.into())
+        .max_idle_timeout(Some(VarInt(4_000).into()))
+        .allow_migration(false);
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let client_ch = pair.begin_connect(client_config());
+    assert!(
+        pair.client_conn_mut(client_ch)
+            .peer_transport_parameters()
+            .is_none()
+    );
+
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    let params = pair
+        .client_conn_mut(client_ch)
+        .peer_transport_parameters()
+        .unwrap();
+    assert_eq!(params.initial_max_streams_bidi(), VarInt(7));
+    assert_eq!(params.initial_max_streams_uni(), VarInt(3));
+    assert_eq!(params.initial_max_data(), VarInt(123_456));
+    assert_eq!(params.initial_max_stream_data_bidi_local(), VarInt(65_432));
+    assert_eq!(params.initial_max_stream_data_bidi_remote(), VarInt(65_432));
+    assert_eq!(params.initial_max_stream_data_uni(), VarInt(65_432));
+    assert_eq!(params.max_idle_timeout(), VarInt(4_000));
+    assert_eq!(
+        params.max_udp_payload_size(),
+        pair.server.endpoint.config().max_udp_payload_size
+    );
+    assert!(params.disable_active_migration());
+
+    // The server sees the client's defaults
+    let params = pair
+        .server_conn_mut(server_ch)
+        .peer_transport_parameters()
+        .unwrap();
+    let defaults = TransportConfig::default();
+    assert_eq!(params.initial_max_data(), defaults.receive_window);
+    assert!(!params.disable_active_migration());
 }
 
 fn test_flow_control(config: TransportConfig, window_size: usize) {
@@ -1430,6 +3157,175 @@ fn conn_flow_control() {
     );
 }
 
+#[test]
+fn stream_receive_window_per_kind() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport
+        .stream_receive_window(1000u32.into())
+        .stream_receive_window_bidi_local(2000u32.into())
+        .stream_receive_window_bidi_remote(3000u32.into())
+        .stream_receive_window_uni(4000u32.into());
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+
+    let params = pair
+        .client_conn_mut(client_ch)
+        .peer_transport_parameters()
+        .unwrap();
+    assert_eq!(params.initial_max_stream_data_bidi_local, VarInt(2000));
+    assert_eq!(params.initial_max_stream_data_bidi_remote, VarInt(3000));
+    assert_eq!(params.initial_max_stream_data_uni, VarInt(4000));
+
+    // Streams opened by the client are remote to the server
+    let s = pair.client_streams(client_ch).open(Dir::Bi).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).write(&[0; 5000]), Ok(3000));
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).write(&[0; 5000]), Ok(4000));
+
+    // Streams opened by the server are local to it
+    let s = pair.server_streams(server_ch).open(Dir::Bi).unwrap();
+    pair.server_send(server_ch, s).write(b"hi").unwrap();
+    pair.drive();
+    assert_eq!(pair.client_streams(client_ch).accept(Dir::Bi), Some(s));
+    assert_eq!(pair.client_send(client_ch, s).write(&[0; 5000]), Ok(2000));
+
+    // Reading extends each window by its own size
+    pair.drive();
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    let mut read = 0;
+    while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+        read += chunk.bytes.len();
+    }
+    let _ = chunks.finalize();
+    assert_eq!(read, 2000);
+    pair.drive();
+    assert_eq!(pair.client_send(client_ch, s).write(&[0; 5000]), Ok(2000));
+}
+
+#[test]
+fn receive_window_auto_tuning() {
+    let _guard = subscribe();
+
+    /// Upload through a high-latency path while the server reads eagerly, returning the time
+    /// taken and the server's final connection-level receive window
+    fn upload(max_receive_window: Option<VarInt>) -> (Duration, u64) {
+        let mut transport = TransportConfig::default();
+        transport
+            .receive_window((64 * 1024u32).into())
+            .max_receive_window(max_receive_window);
+        let server = ServerConfig {
+            transport: Arc::new(transport),
+            ..server_config()
+        };
+        let mut pair = Pair::new(Default::default(), server);
+        pair.latency = Duration::from_millis(50);
+        let (client_ch, server_ch) = pair.connect();
+        let start = pair.time;
+
+        const LEN: usize = 4 * 1024 * 1024;
+        let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+        let data = vec![0; LEN];
+        let (mut written, mut read) = (0, 0);
+        while read < LEN {
+            if written < LEN {
+                let mut send = pair.client_send(client_ch, s);
+                written += send.write(&data[written..]).unwrap_or(0);
+            }
+            assert!(pair.step(), "upload stalled after {read} bytes");
+            pair.server_streams(server_ch).accept(Dir::Uni);
+            let mut recv = pair.server_recv(server_ch, s);
+            let Ok(mut chunks) = recv.read(true) else {
+                continue;
+            };
+            while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+                read += chunk.bytes.len();
+            }
+            let _ = chunks.finalize();
+        }
+        let window = pair
+            .server_conn_mut(server_ch)
+            .stats()
+            .flow_control
+            .receive_window;
+        (pair.time - start, window)
+    }
+
+    const MAX: u32 = 4 * 1024 * 1024;
+    let (fixed_time, fixed_window) = upload(None);
+    assert_eq!(fixed_window, 64 * 1024);
+    let (tuned_time, tuned_window) = upload(Some(MAX.into()));
+    assert!(tuned_window > 64 * 1024 && tuned_window <= MAX.into());
+    // With a fixed window, only 64KiB can be sent per 100ms round trip
+    assert!(
+        tuned_time * 4 < fixed_time,
+        "took {tuned_time:?} with auto-tuning, {fixed_time:?} without"
+    );
+}
+
+#[test]
+fn write_stall_reason() {
+    let _guard = subscribe();
+    let server = ServerConfig {
+        transport: Arc::new(TransportConfig {
+            stream_receive_window: 1000u32.into(),
+            receive_window: 1500u32.into(),
+            ..TransportConfig::default()
+        }),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, _) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).write_stall_reason(), None);
+    assert_eq!(pair.client_send(client_ch, s).write(&[0; 2000]), Ok(1000));
+    assert_eq!(
+        pair.client_send(client_ch, s).write_stall_reason(),
+        Some(WriteStallReason::StreamFlowControl)
+    );
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).write(&[0; 2000]), Ok(500));
+    assert_eq!(
+        pair.client_send(client_ch, s).write_stall_reason(),
+        Some(WriteStallReason::ConnectionFlowControl)
+    );
+}
+
+#[test]
+fn stream_byte_counters() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    const LEN: usize = 3000;
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).write(&[0; LEN]), Ok(LEN));
+    assert_eq!(pair.client_send(client_ch, s).bytes_sent(), Ok(0));
+    assert_eq!(pair.client_send(client_ch, s).bytes_acked(), Ok(0));
+
+    // Lose the first transmission so everything has to be retransmitted
+    pair.drive_client();
+    assert_eq!(pair.client_send(client_ch, s).bytes_sent(), Ok(LEN as u64));
+    assert_eq!(pair.client_send(client_ch, s).bytes_acked(), Ok(0));
+    pair.server.inbound.clear();
+
+    pair.drive();
+    assert_eq!(pair.client_send(client_ch, s).bytes_sent(), Ok(LEN as u64));
+    assert_eq!(pair.client_send(client_ch, s).bytes_acked(), Ok(LEN as u64));
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s));
+    assert_eq!(
+        pair.server_recv(server_ch, s).bytes_received(),
+        Ok(LEN as u64)
+    );
+}
+
 #[test]
 fn stop_opens_bidi() {
     let _guard = subscribe();
@@ -1461,65 +3357,272 @@ fn stop_opens_bidi() {
     assert_matches!(chunks.next(usize::MAX), Err(ReadError::Blocked));
     let _ = chunks.finalize();
 
-    assert_matches!(
-        pair.server_send(server_ch, s).write(b"foo"),
-        Err(WriteError::Stopped(ERROR))
-    );
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::Stream(StreamEvent::Stopped {
-            id: _,
-            error_code: ERROR
-        }))
-    );
-    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    assert_matches!(
+        pair.server_send(server_ch, s).write(b"foo"),
+        Err(WriteError::Stopped(ERROR))
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Stopped {
+            id: _,
+            error_code: ERROR
+        }))
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+}
+
+#[test]
+fn implicit_open() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    let s1 = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    let s2 = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s2).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s1));
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s2));
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), None);
+}
+
+#[test]
+fn zero_length_cid() {
+    let _guard = subscribe();
+    let cid_generator_factory: fn() -> Box<dyn ConnectionIdGenerator> =
+        || Box::new(RandomConnectionIdGenerator::new(0));
+    let mut pair = Pair::new(
+        Arc::new(EndpointConfig {
+            connection_id_generator_factory: Arc::new(cid_generator_factory),
+            ..EndpointConfig::default()
+        }),
+        server_config(),
+    );
+    let (client_ch, server_ch) = pair.connect();
+    // Ensure we can reconnect after a previous connection is cleaned up
+    info!("closing");
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(42), Bytes::new());
+    pair.drive();
+    pair.server
+        .connections
+        .get_mut(&server_ch)
+        .unwrap()
+        .close(pair.time, VarInt(42), Bytes::new());
+    pair.connect();
+}
+
+#[test]
+fn fixed_length_cid() {
+    let _guard = subscribe();
+    const LEN: usize = 12;
+    let mut endpoint_config = EndpointConfig::default();
+    endpoint_config.connection_id_length(LEN).unwrap();
+    let mut pair = Pair::new(Arc::new(endpoint_config), server_config());
+    let (client_ch, server_ch) = pair.connect();
+
+    // Exchange some data to exercise short-header parsing
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+
+    // Both endpoints issue more CIDs than the one used in the handshake
+    assert!(pair.client.known_cids() > 1);
+    assert!(pair.server.known_cids() > 1);
+    for cid in pair.client.local_cids().chain(pair.server.local_cids()) {
+        assert_eq!(cid.len(), LEN);
+    }
+}
+
+#[test]
+fn spin_bit() {
+    let _guard = subscribe();
+    // The spin bit is disabled at random on some paths, so find a seed for which both endpoints
+    // use it
+    for seed in 0.. {
+        let mut endpoint_config = EndpointConfig::default();
+        endpoint_config.rng_seed(Some([seed; 32]));
+        let mut pair = Pair::new(Arc::new(endpoint_config), server_config());
+        pair.latency = Duration::from_millis(10);
+        let (client_ch, server_ch) = pair.connect();
+        if !pair.client_conn_mut(client_ch).spin_enabled()
+            || !pair.server_conn_mut(server_ch).spin_enabled()
+        {
+            continue;
+        }
+        pair.drive();
+        // The first PING carries the spin value the client sent last, after which every round trip
+        // flips it
+        pair.client_conn_mut(client_ch).ping();
+        pair.drive();
+
+        for _ in 0..10 {
+            let spins = pair.spins;
+            pair.client_conn_mut(client_ch).ping();
+            pair.drive();
+            assert_eq!(pair.spins - spins, 1);
+        }
+        return;
+    }
+    panic!("no seed enables the spin bit on both endpoints");
+}
+
+#[test]
+fn spin_bit_disallowed() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.allow_spin(false);
+    let transport = Arc::new(transport);
+    let mut server_config = server_config();
+    server_config.transport_config(transport.clone());
+    let mut client_config = client_config();
+    client_config.transport_config(transport);
+    let mut pair = Pair::new(Default::default(), server_config);
+    let (client_ch, server_ch) = pair.connect_with(client_config);
+    assert!(!pair.client_conn_mut(client_ch).spin_enabled());
+    assert!(!pair.server_conn_mut(server_ch).spin_enabled());
+}
+
+#[test]
+fn handshake_duration() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(10);
+    let client_ch = pair.begin_connect(client_config());
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.time_to_first_flight, None);
+    assert_eq!(stats.handshake_duration, None);
+    assert!(!stats.used_0rtt);
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    // Both sides send their first flight as soon as they're polled
+    for stats in [
+        pair.client_conn_mut(client_ch).stats(),
+        pair.server_conn_mut(server_ch).stats(),
+    ] {
+        assert_eq!(stats.time_to_first_flight, Some(Duration::ZERO));
+    }
+
+    let client_duration = pair
+        .client_conn_mut(client_ch)
+        .stats()
+        .handshake_duration
+        .unwrap();
+    assert!(client_duration >= 2 * pair.latency);
+    let server_duration = pair
+        .server_conn_mut(server_ch)
+        .stats()
+        .handshake_duration
+        .unwrap();
+    assert!(server_duration > Duration::ZERO);
+
+    // Stable once set
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive();
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.handshake_duration, Some(client_duration));
+    assert!(!stats.used_0rtt && !stats.zero_rtt_accepted);
 }
 
 #[test]
-fn implicit_open() {
+fn packet_observer() {
     let _guard = subscribe();
+    let packets = Arc::new(Mutex::new(Vec::new()));
+    let observed = packets.clone();
+    let mut transport = TransportConfig::default();
+    transport.on_packet(Some(Arc::new(move |info| {
+        observed.lock().unwrap().push(info)
+    })));
+    let mut client_config = client_config();
+    client_config.transport_config(Arc::new(transport));
     let mut pair = Pair::default();
-    let (client_ch, server_ch) = pair.connect();
-    let s1 = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
-    let s2 = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
-    pair.client_send(client_ch, s2).write(b"hello").unwrap();
-    pair.drive();
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    let (client_ch, _) = pair.connect_with(client_config);
+    for _ in 0..10 {
+        pair.client_conn_mut(client_ch).ping();
+        pair.drive();
+    }
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    let packets = packets.lock().unwrap();
+    let sent = || {
+        packets
+            .iter()
+            .filter(|p| p.direction == PacketDirection::Sent)
+    };
+    let received = || {
+        packets
+            .iter()
+            .filter(|p| p.direction == PacketDirection::Received)
+    };
+    assert_eq!(sent().count() as u64, stats.path.sent_packets);
+    assert!(sent().count() as u64 >= stats.udp_tx.datagrams);
+    assert_eq!(
+        sent().map(|p| p.size as u64).sum::<u64>(),
+        stats.udp_tx.bytes
     );
-    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s1));
-    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s2));
-    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), None);
+    assert_eq!(
+        received().map(|p| p.size as u64).sum::<u64>(),
+        stats.udp_rx.bytes
+    );
+    for space in [SpaceId::Initial, SpaceId::Handshake, SpaceId::Data] {
+        let numbers = sent().filter(|p| p.space == space).map(|p| p.number);
+        assert!(numbers.clone().zip(numbers.skip(1)).all(|(a, b)| a < b));
+    }
+
+    // Every PING is sent in an ack-eliciting packet and answered by an ACK-only packet
+    let eliciting = |p: &&PacketInfo| p.space == SpaceId::Data && p.ack_eliciting;
+    assert!(stats.frame_tx.ping >= 10);
+    assert!(sent().filter(eliciting).count() as u64 >= stats.frame_tx.ping);
+    let acks_only = received().filter(|p| p.space == SpaceId::Data && !p.ack_eliciting);
+    assert!(acks_only.count() >= 10);
 }
 
 #[test]
-fn zero_length_cid() {
+fn seeded_cids_reproducible() {
     let _guard = subscribe();
-    let cid_generator_factory: fn() -> Box<dyn ConnectionIdGenerator> =
-        || Box::new(RandomConnectionIdGenerator::new(0));
-    let mut pair = Pair::new(
-        Arc::new(EndpointConfig {
-            connection_id_generator_factory: Arc::new(cid_generator_factory),
-            ..EndpointConfig::default()
-        }),
-        server_config(),
-    );
-    let (client_ch, server_ch) = pair.connect();
-    // Ensure we can reconnect after a previous connection is cleaned up
-    info!("closing");
-    pair.client
-        .connections
-        .get_mut(&client_ch)
-        .unwrap()
-        .close(pair.time, VarInt(42), Bytes::new());
-    pair.drive();
-    pair.server
-        .connections
-        .get_mut(&server_ch)
-        .unwrap()
-        .close(pair.time, VarInt(42), Bytes::new());
-    pair.connect();
+    let server_addr = "[::2]:7890".parse().unwrap();
+    let cids = |seed: [u8; 32], hashed: bool| {
+        let mut config = EndpointConfig::default();
+        config.rng_seed(Some(seed));
+        if hashed {
+            config.cid_generator(|| Box::new(HashedConnectionIdGenerator::from_key(42)));
+        } else {
+            config.connection_id_length(12).unwrap();
+        }
+        let mut endpoint = Endpoint::new(Arc::new(config), None, true);
+        let now = Instant::now();
+        for _ in 0..3 {
+            endpoint
+                .connect(now, client_config(), server_addr, "localhost")
+                .unwrap();
+        }
+        let mut cids = endpoint.local_cids().copied().collect::<Vec<_>>();
+        cids.sort_unstable();
+        cids
+    };
+
+    for hashed in [false, true] {
+        let first = cids([1; 32], hashed);
+        assert_eq!(first.len(), 3);
+        assert_eq!(first, cids([1; 32], hashed));
+        assert_ne!(first, cids([2; 32], hashed));
+    }
+}
+
+#[test]
+fn connection_id_length_bounds() {
+    let mut config = EndpointConfig::default();
+    assert!(config.connection_id_length(0).is_err());
+    assert!(config.connection_id_length(21).is_err());
+    assert!(config.connection_id_length(1).is_ok());
+    assert!(config.connection_id_length(20).is_ok());
 }
 
 #[test]
@@ -1549,6 +3652,181 @@ fn keep_alive() {
     }
 }
 
+#[test]
+fn nat_keepalive() {
+    let _guard = subscribe();
+    const INTERVAL: Duration = Duration::from_millis(100);
+    let server = ServerConfig {
+        transport: Arc::new(TransportConfig {
+            nat_keepalive: Some(INTERVAL),
+            ..TransportConfig::default()
+        }),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+    let start = pair.time;
+    let before = pair.server_conn_mut(server_ch).stats();
+
+    // The client never sends anything ack-eliciting, so only the NAT keep-alive produces traffic
+    let end = start + 10 * INTERVAL;
+    while pair.time < end {
+        if !pair.step() {
+            if let Some(time) = min_opt(pair.client.next_wakeup(), pair.server.next_wakeup()) {
+                pair.time = time;
+            }
+        }
+    }
+    assert!(!pair.client_conn_mut(client_ch).is_closed());
+
+    let after = pair.server_conn_mut(server_ch).stats();
+    let pings = after.frame_tx.ping - before.frame_tx.ping;
+    let datagrams = after.udp_tx.datagrams - before.udp_tx.datagrams;
+    assert!((9..=11).contains(&pings), "{pings} NAT keep-alives");
+    assert_eq!(datagrams, pings);
+    // Each keep-alive is a bare PING, well below the padded size of a full packet
+    let bytes = after.udp_tx.bytes - before.udp_tx.bytes;
+    assert!(bytes / datagrams < 64);
+}
+
+#[test]
+fn ping_acknowledged() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+    while pair.client_conn_mut(client_ch).poll().is_some() {}
+
+    pair.client_conn_mut(client_ch).ping();
+    pair.client_conn_mut(client_ch).ping();
+    assert_eq!(pair.client_conn_mut(client_ch).pings_acknowledged(), 0);
+    pair.drive();
+    assert_eq!(pair.client_conn_mut(client_ch).pings_acknowledged(), 2);
+
+    // A lost PING is sent again
+    pair.client_conn_mut(client_ch).ping();
+    pair.client.drive(pair.time, pair.server.addr);
+    assert!(!pair.client.outbound.is_empty());
+    pair.client.outbound.clear();
+    assert_eq!(pair.client_conn_mut(client_ch).pings_acknowledged(), 2);
+    pair.drive();
+    assert_eq!(pair.client_conn_mut(client_ch).pings_acknowledged(), 3);
+}
+
+#[test]
+fn corrupted_packets_counted() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    info!("corrupting payload");
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+    let (_, _, packet) = pair.server.inbound.back_mut().unwrap();
+    let last = packet.len() - 1;
+    packet[last] ^= 0xff;
+    pair.drive_server();
+
+    info!("truncating packet");
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+    let (_, _, packet) = pair.server.inbound.back_mut().unwrap();
+    packet.truncate(1 + 8 + 4);
+    pair.drive_server();
+
+    let stats = pair.server_conn_mut(server_ch).stats();
+    assert_eq!(stats.decryption_failures.data, 1);
+    assert_eq!(stats.decryption_failures.initial, 0);
+    assert_eq!(stats.header_protection_failures.data, 1);
+
+    // The connection survives and delivers subsequent packets
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive();
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+    let pings = pair.server_conn_mut(server_ch).stats().frame_rx.ping;
+    assert!(pings > stats.frame_rx.ping);
+}
+
+#[test]
+fn malformed_headers_dropped() {
+    use rand::{Rng, SeedableRng};
+    let _guard = subscribe();
+    let mut rng = rand_pcg::Pcg32::from_seed(0xdeadbeefdeadbeefdeadbeefdeadbeef_u128.to_le_bytes());
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    // Capture a genuine short header packet to use as a template
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+    let (_, _, template) = pair.server.inbound.pop_back().unwrap();
+    // Flags byte followed by the server's 8-byte connection ID
+    let header_len = 1 + 8;
+    let before = pair.server_conn_mut(server_ch).stats();
+
+    info!("injecting malformed short header packets");
+    const SHORT: u64 = 200;
+    for i in 0..SHORT {
+        let mut packet = template.clone();
+        match i % 4 {
+            // Flip bits in the protected part of the first byte and the packet number
+            0 => {
+                packet[0] ^= rng.random_range(1..0x20);
+                packet[header_len] ^= rng.random_range(1..=0xff);
+            }
+            // Truncate, possibly leaving too little for the header protection sample
+            1 => packet.truncate(rng.random_range(header_len + 1..template.len())),
+            // Replace everything after the connection ID with noise
+            2 => rng.fill(&mut packet[header_len..]),
+            // Corrupt a single byte of the payload
+            _ => {
+                let idx = rng.random_range(header_len..packet.len());
+                packet[idx] ^= rng.random_range(1..=0xff);
+            }
+        }
+        pair.server.inbound.push_back((pair.time, None, packet));
+    }
+    pair.drive_server();
+
+    let stats = pair.server_conn_mut(server_ch).stats();
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+    assert_eq!(
+        stats.header_protection_failures.data - before.header_protection_failures.data
+            + stats.decryption_failures.data
+            - before.decryption_failures.data,
+        SHORT
+    );
+    assert!(stats.header_protection_failures.data > before.header_protection_failures.data);
+    assert!(stats.decryption_failures.data > before.decryption_failures.data);
+    assert_eq!(stats.frame_rx.ping, before.frame_rx.ping);
+
+    info!("injecting malformed long header packets");
+    let server_cid = template[1..header_len].to_vec();
+    for i in 0..SHORT {
+        let mut packet = BytesMut::new();
+        // Initial, 0-RTT, Handshake and Retry packet types, with random low bits
+        packet.extend_from_slice(&[0xc0 | ((i as u8 % 4) << 4) | rng.random_range(0..0x10)]);
+        packet.extend_from_slice(&1u32.to_be_bytes());
+        packet.extend_from_slice(&[server_cid.len() as u8]);
+        packet.extend_from_slice(&server_cid);
+        let len = rng.random_range(0..64);
+        let mut noise = vec![0; len];
+        rng.fill(&mut noise[..]);
+        packet.extend_from_slice(&noise);
+        pair.server.inbound.push_back((pair.time, None, packet));
+    }
+    pair.drive_server();
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+    assert_eq!(pair.server.connections.len(), 1);
+    assert!(pair.server.outbound.is_empty());
+
+    // The connection survives and delivers subsequent packets
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive();
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+    let pings = pair.server_conn_mut(server_ch).stats().frame_rx.ping;
+    assert!(pings > before.frame_rx.ping);
+}
+
 #[test]
 fn cid_rotation() {
     let _guard = subscribe();
@@ -1608,6 +3886,74 @@ fn cid_rotation() {
     }
 }
 
+#[test]
+fn cid_rotation_interval() {
+    let _guard = subscribe();
+    const INTERVAL: Duration = Duration::from_secs(2);
+    let mut pair = Pair::default();
+    let mut transport = TransportConfig::default();
+    transport.connection_id_rotation_interval(Some(INTERVAL));
+    let client_config = ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    };
+    let (client_ch, server_ch) = pair.connect_with(client_config);
+    let (mut prev_local, _) = pair.client_conn_mut(client_ch).active_local_cid_seq();
+    let mut prev_rem = pair.client_conn_mut(client_ch).active_rem_cid_seq();
+    for _ in 0..3 {
+        let stop = pair.time + INTERVAL;
+        while pair.time < stop {
+            if !pair.step() {
+                if let Some(time) = min_opt(pair.client.next_wakeup(), pair.server.next_wakeup()) {
+                    pair.time = time;
+                }
+            }
+        }
+        pair.drive();
+
+        // Old CIDs in both directions were retired and replaced
+        let (local, _) = pair.client_conn_mut(client_ch).active_local_cid_seq();
+        let rem = pair.client_conn_mut(client_ch).active_rem_cid_seq();
+        assert!(local > prev_local);
+        assert!(rem > prev_rem);
+        prev_local = local;
+        prev_rem = rem;
+    }
+
+    // The connection still works on the latest CIDs
+    let pings = pair.server_conn_mut(server_ch).stats().frame_rx.ping;
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive();
+    assert!(!pair.client_conn_mut(client_ch).is_closed());
+    assert_eq!(
+        pair.server_conn_mut(server_ch).stats().frame_rx.ping,
+        pings + 1
+    );
+}
+
+#[test]
+fn rtt_stats_under_jitter() {
+    let _guard = subscribe();
+    const FLOOR: Duration = Duration::from_millis(10);
+    let mut pair = Pair::default();
+    pair.latency = FLOOR;
+    let (client_ch, _) = pair.connect();
+    pair.drive();
+
+    for i in 0..20 {
+        // One-way delay alternates between the floor and up to 30ms above it
+        pair.latency = FLOOR + Duration::from_millis(i % 4 * 10);
+        pair.client_conn_mut(client_ch).ping();
+        pair.drive();
+    }
+
+    let stats = pair.client_conn_mut(client_ch).stats().path;
+    assert!(stats.min_rtt >= 2 * FLOOR);
+    assert!(stats.min_rtt < 2 * FLOOR + Duration::from_millis(30));
+    assert!(stats.latest_rtt >= stats.min_rtt);
+    assert!(stats.rtt_variance > Duration::ZERO);
+}
+
 #[test]
 fn cid_retirement() {
     let _guard = subscribe();
@@ -1618,28 +3964,110 @@ fn cid_retirement() {
     pair.server_conn_mut(server_ch)
         .rotate_local_cid(1, Instant::now());
     pair.drive();
-    // Any unexpected behavior may trigger TransportError::CONNECTION_ID_LIMIT_ERROR
+    // Any unexpected behavior may trigger TransportError::CONNECTION_ID_LIMIT_ERROR
+    assert!(!pair.client_conn_mut(client_ch).is_closed());
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+    assert_matches!(pair.client_conn_mut(client_ch).active_rem_cid_seq(), 1);
+
+    use crate::LOC_CID_COUNT;
+    use crate::cid_queue::CidQueue;
+    let mut active_cid_num = CidQueue::LEN as u64;
+    active_cid_num = active_cid_num.min(LOC_CID_COUNT);
+
+    let next_retire_prior_to = active_cid_num + 1;
+    pair.client_conn_mut(client_ch).ping();
+    // Server retires all valid remote CIDs
+    pair.server_conn_mut(server_ch)
+        .rotate_local_cid(next_retire_prior_to, Instant::now());
+    pair.drive();
+    assert!(!pair.client_conn_mut(client_ch).is_closed());
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+
+    assert_eq!(
+        pair.client_conn_mut(client_ch).active_rem_cid_seq(),
+        next_retire_prior_to,
+    );
+}
+
+#[test]
+fn cid_exhaustion() {
+    let _guard = subscribe();
+    const INTERVAL: Duration = Duration::from_secs(1);
+    let mut transport = TransportConfig::default();
+    transport.active_connection_id_limit(2).unwrap();
+    let server = ServerConfig {
+        transport: Arc::new(transport.clone()),
+        ..server_config()
+    };
+    transport.connection_id_rotation_interval(Some(INTERVAL));
+    let client_config = ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect_with(client_config);
+
+    // With one spare CID each way, every rotation must wait for the peer to retire the previous
+    // CIDs before new ones can be issued
+    let mut prev_rem = pair.client_conn_mut(client_ch).active_rem_cid_seq();
+    for _ in 0..4 {
+        let stop = pair.time + INTERVAL;
+        while pair.time < stop {
+            if !pair.step() {
+                if let Some(time) = min_opt(pair.client.next_wakeup(), pair.server.next_wakeup()) {
+                    pair.time = time;
+                }
+            }
+        }
+        pair.drive();
+        let rem = pair.client_conn_mut(client_ch).active_rem_cid_seq();
+        assert!(rem > prev_rem);
+        prev_rem = rem;
+    }
+    assert!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .cids
+            .local_limit_reached
+            > 0
+    );
+
+    info!("switching CIDs faster than the server issues them");
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .cids
+            .remote_exhausted,
+        0
+    );
+    pair.client_conn_mut(client_ch).local_address_changed();
+    pair.client_conn_mut(client_ch).local_address_changed();
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .cids
+            .remote_exhausted,
+        1
+    );
+    let rem = pair.client_conn_mut(client_ch).active_rem_cid_seq();
+    assert_eq!(rem, prev_rem + 1);
+    pair.drive();
+
+    // Nothing exceeded the limits, and the connection works on the latest CIDs
     assert!(!pair.client_conn_mut(client_ch).is_closed());
     assert!(!pair.server_conn_mut(server_ch).is_closed());
-    assert_matches!(pair.client_conn_mut(client_ch).active_rem_cid_seq(), 1);
-
-    use crate::LOC_CID_COUNT;
-    use crate::cid_queue::CidQueue;
-    let mut active_cid_num = CidQueue::LEN as u64;
-    active_cid_num = active_cid_num.min(LOC_CID_COUNT);
-
-    let next_retire_prior_to = active_cid_num + 1;
+    let pings = pair.server_conn_mut(server_ch).stats().frame_rx.ping;
     pair.client_conn_mut(client_ch).ping();
-    // Server retires all valid remote CIDs
-    pair.server_conn_mut(server_ch)
-        .rotate_local_cid(next_retire_prior_to, Instant::now());
     pair.drive();
-    assert!(!pair.client_conn_mut(client_ch).is_closed());
-    assert!(!pair.server_conn_mut(server_ch).is_closed());
-
+    assert_eq!(
+        pair.server_conn_mut(server_ch).stats().frame_rx.ping,
+        pings + 1
+    );
+    // A spare CID is available again
+    pair.client_conn_mut(client_ch).local_address_changed();
     assert_eq!(
         pair.client_conn_mut(client_ch).active_rem_cid_seq(),
-        next_retire_prior_to,
+        rem + 1
     );
 }
 
@@ -1691,6 +4119,61 @@ fn finish_stream_flow_control_reordered() {
     let _ = chunks.finalize();
 }
 
+#[test]
+fn read_at_reordered() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+
+    const MSG1: &[u8] = b"hello";
+    const MSG2: &[u8] = b" world";
+    pair.client_send(client_ch, s).write(MSG1).unwrap();
+    pair.client.drive(pair.time, pair.server.addr);
+    pair.client.delay_outbound(); // Hold back the first fragment
+    pair.client_send(client_ch, s).write(MSG2).unwrap();
+    pair.drive_client();
+    pair.server.drive(pair.time, pair.client.addr); // Receive the second fragment only
+
+    let mut buf = [0; 16];
+    let recv = pair.server_recv(server_ch, s);
+    assert_eq!(recv.read_at(0, &mut buf), Ok(None));
+    assert_eq!(
+        recv.read_at(MSG1.len() as u64, &mut buf),
+        Ok(Some(MSG2.len()))
+    );
+    assert_eq!(&buf[..MSG2.len()], MSG2);
+    assert_eq!(recv.read_at(8, &mut buf), Ok(Some(3)));
+    assert_eq!(&buf[..3], b"rld");
+
+    pair.client.finish_delay();
+    pair.drive_client();
+    pair.server.drive(pair.time, pair.client.addr); // Receive the first fragment
+
+    let recv = pair.server_recv(server_ch, s);
+    assert_eq!(recv.read_at(0, &mut buf), Ok(Some(11)));
+    assert_eq!(&buf[..11], b"hello world");
+    assert_eq!(recv.read_at(3, &mut buf[..4]), Ok(Some(4)));
+    assert_eq!(&buf[..4], b"lo w");
+
+    // Peeking didn't consume anything
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    assert_matches!(
+        chunks.next(MSG1.len()),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG1
+    );
+    let _ = chunks.finalize();
+
+    let recv = pair.server_recv(server_ch, s);
+    assert_eq!(recv.read_at(0, &mut buf), Ok(None));
+    assert_eq!(
+        recv.read_at(MSG1.len() as u64, &mut buf),
+        Ok(Some(MSG2.len()))
+    );
+}
+
 #[test]
 fn handshake_1rtt_handling() {
     let _guard = subscribe();
@@ -1891,6 +4374,34 @@ fn tail_loss_respect_max_datagrams() {
     assert_eq!(client_stats.udp_tx.ios, client_stats.udp_tx.datagrams);
 }
 
+#[test]
+fn segmentation_disabled_per_connection() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    // Two connections from the same client endpoint, only one of which keeps using segmentation
+    let (enabled_ch, _) = pair.connect();
+    let (disabled_ch, _) = pair.connect();
+    pair.client_conn_mut(disabled_ch)
+        .set_segmentation_enabled(false);
+    let initial = [enabled_ch, disabled_ch].map(|ch| pair.client_conn_mut(ch).stats().udp_tx);
+
+    for ch in [enabled_ch, disabled_ch] {
+        let s = pair.client_streams(ch).open(Dir::Uni).unwrap();
+        pair.client_send(ch, s).write(&[0; 32 * 1024]).unwrap();
+        pair.client_send(ch, s).finish().unwrap();
+    }
+    pair.drive();
+
+    // Number of I/O operations and datagrams used by each connection to send its stream's data
+    let [enabled, disabled] = [enabled_ch, disabled_ch].map(|ch| {
+        let stats = pair.client_conn_mut(ch).stats().udp_tx;
+        let initial = initial[usize::from(ch == disabled_ch)];
+        (stats.ios - initial.ios, stats.datagrams - initial.datagrams)
+    });
+    assert!(enabled.0 < enabled.1, "{enabled:?}");
+    assert_eq!(disabled.0, disabled.1);
+}
+
 #[test]
 fn datagram_send_recv() {
     let _guard = subscribe();
@@ -1951,6 +4462,9 @@ fn datagram_recv_buffer_overflow() {
     assert_eq!(pair.server_datagrams(server_ch).recv().unwrap(), DATA2);
     assert_eq!(pair.server_datagrams(server_ch).recv().unwrap(), DATA3);
     assert_matches!(pair.server_datagrams(server_ch).recv(), None);
+    let drops = pair.server_conn_mut(server_ch).stats().datagram_drops;
+    assert_eq!(drops.buffer_full, 1);
+    assert_eq!(drops.bytes, DATA1.len() as u64);
 
     pair.client_datagrams(client_ch)
         .send(DATA1.into(), true)
@@ -1960,6 +4474,86 @@ fn datagram_recv_buffer_overflow() {
     assert_matches!(pair.server_datagrams(server_ch).recv(), None);
 }
 
+#[test]
+fn datagram_recv_paused() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    pair.client_datagrams(client_ch)
+        .send(b"before"[..].into(), true)
+        .unwrap();
+    pair.drive();
+    pair.server_datagrams(server_ch)
+        .pause_recv(DatagramBackpressure::Drop);
+    assert!(pair.server_datagrams(server_ch).is_recv_paused());
+    for _ in 0..3 {
+        pair.client_datagrams(client_ch)
+            .send(b"during"[..].into(), false)
+            .unwrap();
+    }
+    pair.drive();
+
+    // Datagrams buffered before pausing are still delivered
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::DatagramReceived)
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    assert_eq!(
+        pair.server_datagrams(server_ch).recv().unwrap(),
+        &b"before"[..]
+    );
+    assert_matches!(pair.server_datagrams(server_ch).recv(), None);
+    let drops = pair.server_conn_mut(server_ch).stats().datagram_drops;
+    assert_eq!(drops.paused, 3);
+    assert_eq!(drops.buffer_full, 0);
+    assert_eq!(drops.bytes, 3 * b"during".len() as u64);
+    // Dropping doesn't signal congestion
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.congestion_events, 0);
+
+    pair.server_datagrams(server_ch).resume_recv();
+    pair.client_datagrams(client_ch)
+        .send(b"after"[..].into(), true)
+        .unwrap();
+    pair.drive();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::DatagramReceived)
+    );
+    assert_eq!(
+        pair.server_datagrams(server_ch).recv().unwrap(),
+        &b"after"[..]
+    );
+}
+
+#[test]
+fn datagram_recv_paused_signals_congestion() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    assert!(pair.client_conn_mut(client_ch).using_ecn());
+
+    pair.server_datagrams(server_ch)
+        .pause_recv(DatagramBackpressure::SignalCongestion);
+    pair.client_datagrams(client_ch)
+        .send(b"whee"[..].into(), true)
+        .unwrap();
+    pair.drive();
+    assert_eq!(
+        pair.server_conn_mut(server_ch)
+            .stats()
+            .datagram_drops
+            .paused,
+        1
+    );
+    let client = pair.client_conn_mut(client_ch);
+    assert_eq!(client.stats().path.congestion_events, 1);
+    // The marking is consistent with what was sent, so ECN stays enabled
+    assert!(client.using_ecn());
+}
+
 #[test]
 fn datagram_unsupported() {
     let _guard = subscribe();
@@ -1982,6 +4576,80 @@ fn datagram_unsupported() {
     }
 }
 
+#[test]
+fn accept_after_server_config_cleared() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.server.handle_incoming = Box::new(|_| IncomingConnectionBehavior::Wait);
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    let incoming = pair.server.waiting_incoming.pop().unwrap();
+
+    // A connection attempt that already arrived keeps the configuration it arrived under
+    pair.server.set_server_config(None);
+    pair.server.try_accept(incoming, pair.time).unwrap();
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+}
+
+fn require_datagrams(pair: &mut Pair, client_transport: TransportConfig) -> ConnectionHandle {
+    let mut server_config = server_config();
+    server_config.transport_parameters_validator(Some(Arc::new(|params| {
+        match params.max_datagram_frame_size() {
+            Some(_) => Ok(()),
+            None => Err("datagrams required".into()),
+        }
+    })));
+    pair.server.set_server_config(Some(Arc::new(server_config)));
+    let mut client_config = client_config();
+    client_config.transport_config(Arc::new(client_transport));
+    let client_ch = pair.begin_connect(client_config);
+    pair.drive();
+    client_ch
+}
+
+#[test]
+fn transport_parameters_validator_rejects() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let client_ch = require_datagrams(
+        &mut pair,
+        TransportConfig {
+            datagram_receive_buffer_size: None,
+            ..TransportConfig::default()
+        },
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost { reason: ConnectionError::ConnectionClosed(close) })
+            if close.error_code == TransportErrorCode::CONNECTION_REFUSED
+                && &close.reason[..] == b"datagrams required"
+    );
+    assert_eq!(pair.server.known_connections(), 0);
+}
+
+#[test]
+fn transport_parameters_validator_accepts() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let client_ch = require_datagrams(&mut pair, TransportConfig::default());
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+}
+
 #[test]
 fn large_initial() {
     let _guard = subscribe();
@@ -2204,7 +4872,7 @@ fn handshake_anti_deadlock_probe() {
 /// Ensures that the server can respond with 3 initial packets during the handshake
 /// before the anti-amplification limit kicks in when MTUs are similar.
 #[test]
-fn server_can_send_10_inital_packets() {
+fn server_can_send_3_inital_packets() {
     let _guard = subscribe();
     let mut transport = TransportConfig::default();
     // Assume a low-latency connection so pacing doesn't interfere with the test
@@ -2222,8 +4890,8 @@ fn server_can_send_10_inital_packets() {
     pair.drive_client();
     // Server sends first flight, gets blocked on anti-amplification
     pair.drive_server();
-    // Server should have queued 10 packets at this time
-    assert_eq!(pair.client.inbound.len(), 10);
+    // Server should have queued 3 packets at this time
+    assert_eq!(pair.client.inbound.len(), 3);
 
     pair.drive();
     assert_matches!(
@@ -2236,6 +4904,62 @@ fn server_can_send_10_inital_packets() {
     );
 }
 
+/// Sends a client Initial to a server that never hears from the client again, and returns the
+/// server's (received, sent) byte counts once it gives up retransmitting
+fn unvalidated_server_traffic(factor: u8) -> (u64, u64) {
+    let mut transport = TransportConfig::default();
+    transport.amplification_factor(factor).unwrap();
+    let (cert, key) = big_cert_and_key();
+    let mut server = server_config_with_cert(cert.clone(), key);
+    server.transport_config(Arc::new(transport));
+    let mut pair = Pair::new(Default::default(), server);
+
+    pair.begin_connect(client_config_with_certs(vec![cert]));
+    pair.drive_client();
+    pair.drive_server();
+    let server_ch = *pair.server.connections.keys().next().unwrap();
+    let conn = pair.server_conn_mut(server_ch);
+    let (received, sent) = (conn.total_recvd(), conn.stats().udp_tx.bytes);
+    assert_eq!(
+        conn.stats().path.anti_amplification_credit,
+        Some(received * u64::from(factor) - sent)
+    );
+
+    // Lose everything the server sends, so the client's address is never validated
+    for _ in 0..10 {
+        pair.client.inbound.clear();
+        let Some(t) = pair.server.next_wakeup() else {
+            break;
+        };
+        pair.time = pair.time.max(t);
+        pair.drive_server();
+    }
+
+    let conn = pair.server_conn_mut(server_ch);
+    (conn.total_recvd(), conn.stats().udp_tx.bytes)
+}
+
+#[test]
+fn anti_amplification_limit() {
+    let _guard = subscribe();
+    for factor in [1, 3] {
+        let (received, sent) = unvalidated_server_traffic(factor);
+        assert!(sent > 0);
+        assert!(
+            sent <= received * u64::from(factor),
+            "sent {sent} bytes after receiving {received} with factor {factor}"
+        );
+    }
+}
+
+#[test]
+fn amplification_factor_bounds() {
+    let mut config = TransportConfig::default();
+    assert!(config.amplification_factor(0).is_err());
+    assert!(config.amplification_factor(4).is_err());
+    assert!(config.amplification_factor(1).is_ok());
+}
+
 /// Generate a big fat certificate that can't fit inside the initial anti-amplification limit
 fn big_cert_and_key() -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
     let cert = rcgen::generate_simple_self_signed(
@@ -2348,6 +5072,95 @@ fn connect_detects_mtu() {
     }
 }
 
+#[test]
+fn mtu_search_state() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.mtu = 1500;
+    let client_ch = pair.begin_connect(client_config());
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.mtu_search_state, MtuSearchState::Searching);
+    let initial_mtu = stats.path.current_mtu;
+
+    pair.drive();
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert!(stats.path.current_mtu > initial_mtu);
+    assert_eq!(stats.path.current_mtu, 1452);
+    assert_eq!(stats.path.mtu_search_state, MtuSearchState::Done);
+}
+
+#[test]
+fn mtu_search_state_disabled() {
+    let _guard = subscribe();
+    let client_config = ClientConfig {
+        transport: Arc::new(TransportConfig {
+            mtu_discovery_config: None,
+            ..TransportConfig::default()
+        }),
+        ..client_config()
+    };
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect_with(client_config);
+    pair.drive();
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.mtu_search_state, MtuSearchState::Disabled);
+}
+
+#[test]
+fn respects_peer_max_udp_payload_size() {
+    let _guard = subscribe();
+    const PEER_MAX: u16 = 1300;
+
+    let mtud_disabled = TransportConfig {
+        mtu_discovery_config: None,
+        initial_mtu: 1400,
+        ..TransportConfig::default()
+    };
+    for transport in [TransportConfig::default(), mtud_disabled] {
+        let server_endpoint_config = EndpointConfig {
+            max_udp_payload_size: PEER_MAX.into(),
+            ..EndpointConfig::default()
+        };
+        let server = Endpoint::new(
+            Arc::new(server_endpoint_config),
+            Some(Arc::new(server_config())),
+            true,
+        );
+        let client = Endpoint::new(Arc::new(EndpointConfig::default()), None, true);
+        let mut pair = Pair::new_from_endpoint(client, server);
+        pair.mtu = 1500;
+        let client_config = ClientConfig {
+            transport: Arc::new(transport),
+            ..client_config()
+        };
+        let (client_ch, server_ch) = pair.connect_with(client_config);
+        pair.drive();
+
+        assert_eq!(pair.client_conn_mut(client_ch).max_mtu(), PEER_MAX);
+        assert_eq!(pair.client_conn_mut(client_ch).current_mtu(), PEER_MAX);
+        // The client advertised the default limit, so the server is only bound by its own config
+        assert_eq!(pair.server_conn_mut(server_ch).max_mtu(), 1452);
+
+        // Enough data to fill several maximum-size packets
+        let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+        pair.client_send(client_ch, s).write(&[0; 10_000]).unwrap();
+        pair.client.drive(pair.time, pair.server.addr);
+        let sizes = pair
+            .client
+            .outbound
+            .iter()
+            .map(|(_, buf)| buf.len())
+            .collect::<Vec<_>>();
+        assert!(
+            sizes.iter().all(|&len| len <= PEER_MAX as usize),
+            "{sizes:?}"
+        );
+        assert!(sizes.contains(&(PEER_MAX as usize)), "{sizes:?}");
+        pair.drive();
+    }
+}
+
 #[test]
 fn migrate_detects_new_mtu_and_respects_original_peer_max_udp_payload_size() {
     let _guard = subscribe();
@@ -2482,11 +5295,78 @@ fn blackhole_after_mtu_change_repairs_itself() {
     // The whole packet arrived in the end
     assert_eq!(buf.len(), 1300);
 
-    // Sanity checks (black hole detected after 3 lost packets)
-    let client_stats = pair.client_conn_mut(client_ch).stats();
-    assert!(client_stats.path.lost_packets >= 3);
-    assert!(client_stats.path.congestion_events >= 3);
-    assert_eq!(client_stats.path.black_holes_detected, 1);
+    // Sanity checks (black hole detected after 3 lost packets)
+    let client_stats = pair.client_conn_mut(client_ch).stats();
+    assert!(client_stats.path.lost_packets >= 3);
+    assert!(client_stats.path.congestion_events >= 3);
+    assert_eq!(client_stats.path.black_holes_detected, 1);
+    assert_eq!(
+        client_stats.path.mtu_search_state,
+        MtuSearchState::BlackHole
+    );
+}
+
+#[test]
+fn packet_too_big_lowers_mtu() {
+    let _guard = subscribe();
+    let mut mtud_config = MtuDiscoveryConfig::default();
+    mtud_config.packet_too_big(true);
+    let mut transport = TransportConfig::default();
+    transport.mtu_discovery_config(Some(mtud_config));
+    let mut pair = Pair::default();
+    pair.mtu = 1500;
+    let (client_ch, _) = pair.connect_with(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+    pair.drive();
+    assert_eq!(pair.client_conn_mut(client_ch).path_mtu(), 1452);
+
+    // A short header packet quoting the server's CID
+    let cid = pair.client_conn_mut(client_ch).active_rem_cid();
+    let mut quoted = vec![0x40];
+    quoted.extend_from_slice(&cid);
+    quoted.extend_from_slice(&[0; 16]);
+    let mut bogus = quoted.clone();
+    bogus[1] ^= 0xff;
+
+    let server_addr = pair.server.addr;
+    let other_addr = SocketAddr::new(server_addr.ip(), server_addr.port() + 1);
+    let now = pair.time;
+    for (remote, quoted) in [(server_addr, &bogus), (other_addr, &quoted)] {
+        for (_, event) in pair
+            .client
+            .endpoint
+            .handle_packet_too_big(now, remote, 1400, quoted)
+        {
+            pair.client_conn_mut(client_ch).handle_event(event);
+        }
+    }
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.current_mtu, 1452);
+    assert_eq!(stats.path.icmp_mtu_reductions, 0);
+
+    // 1400 bytes at the IPv6 level leave 1352 bytes for the UDP payload
+    let events = pair
+        .client
+        .endpoint
+        .handle_packet_too_big(now, server_addr, 1400, &quoted);
+    assert_eq!(events.len(), 1);
+    for (_, event) in events {
+        pair.client_conn_mut(client_ch).handle_event(event);
+    }
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.current_mtu, 1352);
+    assert_eq!(stats.path.icmp_mtu_reductions, 1);
+    assert_eq!(stats.path.mtu_search_state, MtuSearchState::Done);
+
+    // The connection keeps working with the lowered MTU
+    pair.mtu = 1352;
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(&[42; 4000]).unwrap();
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive();
+    assert_eq!(pair.client_conn_mut(client_ch).stats().path.lost_packets, 0);
 }
 
 #[test]
@@ -2612,6 +5492,59 @@ fn single_ack_eliciting_packet_triggers_ack_after_delay() {
     );
 }
 
+#[test]
+fn configured_max_ack_delay_delays_ack() {
+    let _guard = subscribe();
+    const MAX_ACK_DELAY: Duration = Duration::from_millis(60);
+    let mut transport = TransportConfig::default();
+    transport
+        .deterministic_packet_numbers(true)
+        .max_ack_delay(MAX_ACK_DELAY)
+        .unwrap();
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, _) = pair.connect_with(client_config_with_deterministic_pns());
+    pair.drive();
+
+    let start = pair.time;
+    pair.client_conn_mut(client_ch).ping();
+    pair.client.capture_inbound_packets = true;
+    pair.drive();
+
+    // The server held its ACK for exactly the configured delay, and reported it
+    assert_eq!(pair.time, start + MAX_ACK_DELAY);
+    assert_eq!(pair.client.captured_packets.len(), 1);
+    let frames = frame::Iter::new(pair.client.captured_packets.remove(0).into())
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_matches!(&frames[..], [Frame::Ack(ack)] if {
+        let ack_delay_exp = TransportParameters::default().ack_delay_exponent;
+        ack.delay << ack_delay_exp.into_inner() == MAX_ACK_DELAY.as_micros() as u64
+    });
+}
+
+#[test]
+fn max_ack_delay_bounds() {
+    let mut config = TransportConfig::default();
+    assert!(config.max_ack_delay(Duration::ZERO).is_err());
+    assert!(config.max_ack_delay(Duration::from_micros(999)).is_err());
+    assert!(
+        config
+            .max_ack_delay(Duration::from_millis(1 << 14))
+            .is_err()
+    );
+    assert!(config.max_ack_delay(Duration::from_millis(1)).is_ok());
+    assert!(
+        config
+            .max_ack_delay(Duration::from_millis((1 << 14) - 1))
+            .is_ok()
+    );
+}
+
 #[test]
 fn immediate_ack_triggers_ack() {
     let _guard = subscribe();
@@ -2631,6 +5564,37 @@ fn immediate_ack_triggers_ack() {
     assert_eq!(acks_after_ping - acks_after_connect, 1);
 }
 
+#[test]
+fn request_immediate_ack_bypasses_ack_delay() {
+    let _guard = subscribe();
+    // The server would otherwise wait for 10 ack-eliciting packets or 30ms before ACKing
+    let (mut pair, client_ch, server_ch) = setup_ack_frequency_test(Duration::from_millis(30));
+
+    let client_stats_before = pair.client_conn_mut(client_ch).stats();
+    let server_stats_before = pair.server_conn_mut(server_ch).stats();
+    pair.client_conn_mut(client_ch).request_immediate_ack();
+    pair.drive_client();
+    pair.time += pair.latency;
+    pair.drive_server();
+    let server_stats_after = pair.server_conn_mut(server_ch).stats();
+    assert_eq!(
+        server_stats_after.frame_rx.immediate_ack - server_stats_before.frame_rx.immediate_ack,
+        1
+    );
+    assert_eq!(
+        server_stats_after.frame_tx.acks - server_stats_before.frame_tx.acks,
+        1
+    );
+
+    pair.time += pair.latency;
+    pair.drive_client();
+    let client_stats_after = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(
+        client_stats_after.frame_rx.acks - client_stats_before.frame_rx.acks,
+        1
+    );
+}
+
 #[test]
 fn out_of_order_ack_eliciting_packet_triggers_ack() {
     let _guard = subscribe();
@@ -2727,6 +5691,45 @@ fn single_ack_eliciting_packet_with_ce_bit_triggers_immediate_ack() {
     );
 }
 
+#[test]
+fn received_ecn_codepoints() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+
+    // Everything sent so far was marked ECT(0)
+    let stats = pair.server_conn_mut(server_ch).stats().path;
+    assert!(stats.received_ect0 > 0);
+    assert_eq!(stats.received_ect1, 0);
+    assert_eq!(stats.received_ce, 0);
+    assert_eq!(stats.received_not_ect, 0);
+
+    pair.client_conn_mut(client_ch).ping();
+    pair.congestion_experienced = true;
+    pair.drive_client();
+    pair.congestion_experienced = false;
+    let packets = pair.server.inbound.len() as u64;
+    assert!(packets > 0);
+    pair.drive_server();
+    let ce_stats = pair.server_conn_mut(server_ch).stats().path;
+    assert_eq!(ce_stats.received_ce, packets);
+    assert_eq!(ce_stats.received_ect0, stats.received_ect0);
+
+    // A path that strips the codepoint
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+    for (_, ecn, _) in &mut pair.server.inbound {
+        *ecn = None;
+    }
+    let packets = pair.server.inbound.len() as u64;
+    pair.drive_server();
+    let stats = pair.server_conn_mut(server_ch).stats().path;
+    assert_eq!(stats.received_not_ect, packets);
+    assert_eq!(stats.received_ce, ce_stats.received_ce);
+    assert_eq!(stats.received_ect0, ce_stats.received_ect0);
+}
+
 fn setup_ack_frequency_test(max_ack_delay: Duration) -> (Pair, ConnectionHandle, ConnectionHandle) {
     let mut client_config = client_config_with_deterministic_pns();
     let mut ack_freq_config = AckFrequencyConfig::default();
@@ -3051,6 +6054,49 @@ fn ack_frequency_update_max_delay() {
     );
 }
 
+#[test]
+fn adaptive_ack_eliciting_threshold() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.adaptive_ack_eliciting_threshold(Some(VarInt::from_u32(15)));
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    // The client skips packet numbers, which must not be mistaken for losses
+    let (client_ch, server_ch) = pair.connect();
+    let stats = pair.server_conn_mut(server_ch).stats();
+    assert_eq!(stats.ack_eliciting_threshold, 1);
+    // Spread out packet arrivals, so the server has the opportunity to acknowledge each one
+    pair.bottleneck = Some(Bottleneck::new(1_250_000, 10_000_000));
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    let data = [0; 64 * 1024];
+    let mut packets_per_ack = Vec::new();
+    for _ in 0..5 {
+        let before = pair.server_conn_mut(server_ch).stats();
+        pair.client_send(client_ch, s).write(&data).unwrap();
+        pair.drive();
+        let after = pair.server_conn_mut(server_ch).stats();
+        let packets = after.udp_rx.datagrams - before.udp_rx.datagrams;
+        let acks = after.frame_tx.acks - before.frame_tx.acks;
+        packets_per_ack.push(packets / acks);
+
+        pair.server_streams(server_ch).accept(Dir::Uni);
+        let mut recv = pair.server_recv(server_ch, s);
+        let mut chunks = recv.read(false).unwrap();
+        while let Ok(Some(_)) = chunks.next(usize::MAX) {}
+        let _ = chunks.finalize();
+        pair.drive();
+    }
+    info!(?packets_per_ack);
+    assert!(packets_per_ack.is_sorted());
+    assert!(packets_per_ack[0] < packets_per_ack[4]);
+    let stats = pair.server_conn_mut(server_ch).stats();
+    assert_eq!(stats.ack_eliciting_threshold, 15);
+}
+
 fn stream_chunks(mut recv: RecvStream<'_>) -> Vec<u8> {
     let mut buf = Vec::new();
 
@@ -3088,6 +6134,43 @@ fn pure_sender_voluntarily_acks() {
     assert!(receiver_acks_final > receiver_acks_initial);
 }
 
+#[test]
+fn handshake_rate_limit() {
+    let _guard = subscribe();
+    let mut endpoint_config = EndpointConfig::default();
+    endpoint_config.max_handshakes_per_second(Some(2)).unwrap();
+    let mut pair = Pair::new(Arc::new(endpoint_config), server_config());
+
+    // Flood the server with connection attempts
+    for _ in 0..5 {
+        pair.begin_connect(client_config());
+    }
+    pair.drive_client();
+    pair.server.drive(pair.time, pair.client.addr);
+    assert_eq!(pair.server.connections.len(), 2);
+    assert_eq!(pair.server.endpoint.throttled_handshakes(), 3);
+    assert_eq!(pair.server.endpoint.handshake_rate(pair.time), 2);
+    // Attempts beyond the limit are answered with a Retry
+    let retries = pair
+        .server
+        .outbound
+        .iter()
+        .filter(|(_, packet)| packet[0] & 0xf0 == 0xf0)
+        .count();
+    assert_eq!(retries, 3);
+
+    // Lose the server's responses, so the three throttled clients each retransmit their Initial
+    // in two probes. One client is admitted as the rate has dropped in the meantime, and its second
+    // probe joins the new connection, while the other two clients' probes are throttled again.
+    pair.server.outbound.clear();
+    pair.time += Duration::from_millis(1500);
+    assert_eq!(pair.server.endpoint.handshake_rate(pair.time), 1);
+    pair.drive_client();
+    pair.server.drive(pair.time, pair.client.addr);
+    assert_eq!(pair.server.connections.len(), 3);
+    assert_eq!(pair.server.endpoint.throttled_handshakes(), 7);
+}
+
 #[test]
 fn reject_manually() {
     let _guard = subscribe();
@@ -3147,6 +6230,87 @@ fn validate_then_reject_manually() {
     assert_eq!(pair.server.known_cids(), 0);
 }
 
+#[test]
+fn retry_across_cluster() {
+    let _guard = subscribe();
+
+    /// Issues CIDs whose first byte identifies the node a load balancer routes them to
+    struct ClusterCidGenerator {
+        node: u8,
+        retry_node: u8,
+    }
+
+    impl ClusterCidGenerator {
+        fn cid(node: u8, rng: &mut dyn RngCore) -> ConnectionId {
+            let mut bytes = [node; 8];
+            rng.fill_bytes(&mut bytes[1..]);
+            ConnectionId::new(&bytes)
+        }
+    }
+
+    impl ConnectionIdGenerator for ClusterCidGenerator {
+        fn generate_cid(&mut self) -> ConnectionId {
+            Self::cid(self.node, &mut rand::rng())
+        }
+
+        fn generate_retry_cid(
+            &mut self,
+            rng: &mut dyn RngCore,
+            _orig_dst_cid: ConnectionId,
+        ) -> ConnectionId {
+            Self::cid(self.retry_node, rng)
+        }
+
+        fn cid_len(&self) -> usize {
+            8
+        }
+
+        fn cid_lifetime(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    // Nodes share the server config, and thereby the key authenticating tokens
+    let server_config = Arc::new(server_config());
+    let node = |node| {
+        let mut endpoint_config = EndpointConfig::default();
+        endpoint_config.cid_generator(move || {
+            Box::new(ClusterCidGenerator {
+                node,
+                retry_node: 2,
+            })
+        });
+        Endpoint::new(Arc::new(endpoint_config), Some(server_config.clone()), true)
+    };
+    let client = Endpoint::new(Default::default(), None, true);
+    let mut pair = Pair::new_from_endpoint(client, node(1));
+    pair.server.handle_incoming = Box::new(validate_incoming);
+
+    // Node 1 sends a Retry steering the client to node 2
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive_client();
+    pair.drive_server();
+    assert_eq!(pair.server.known_connections(), 0);
+    let (_, _, retry) = pair.client.inbound.front().unwrap();
+    let src_cid = &retry[6 + retry[5] as usize..];
+    assert_eq!(src_cid[..2], [8, 2], "SCID of length 8 routing to node 2");
+
+    // Node 2 validates the token issued by node 1 and completes the handshake
+    pair.server.endpoint = node(2);
+    pair.drive();
+    assert_eq!(pair.server.known_connections(), 1);
+    let server_ch = pair.server.assert_accept();
+    assert!(pair.server_conn_mut(server_ch).address_prevalidated());
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+}
+
 #[test]
 fn endpoint_and_connection_impl_send_sync() {
     const fn is_send_sync<T: Send + Sync>() {}
@@ -3238,6 +6402,88 @@ fn gso_truncation() {
     }
 }
 
+#[test]
+fn initial_padding() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    assert!(transport.initial_padding(1199..=1400).is_err());
+    #[allow(clippy::reversed_empty_ranges)]
+    let empty = 1400..=1300;
+    assert!(transport.initial_padding(empty).is_err());
+    transport
+        .initial_mtu(1400)
+        .initial_padding(1200..=1400)
+        .unwrap();
+    let mut client_config = client_config();
+    client_config.transport_config(Arc::new(transport));
+
+    /// Size of the first datagram sent by each of a number of new connections
+    fn first_datagram_sizes(pair: &mut Pair, config: &ClientConfig) -> Vec<usize> {
+        (0..16)
+            .map(|_| {
+                pair.begin_connect(config.clone());
+                pair.client.drive(pair.time, pair.server.addr);
+                let (_, datagram) = pair.client.outbound.drain(..).next().unwrap();
+                pair.client.outbound.clear();
+                datagram.len()
+            })
+            .collect()
+    }
+
+    let mut pair = Pair::default();
+    let sizes = first_datagram_sizes(&mut pair, &self::client_config());
+    assert!(sizes.iter().all(|&size| size == 1200), "{sizes:?}");
+
+    let sizes = first_datagram_sizes(&mut pair, &client_config);
+    assert!(sizes.iter().all(|size| (1200..=1400).contains(size)));
+    assert!(sizes.iter().any(|&size| size != sizes[0]), "{sizes:?}");
+
+    // The server accepts the randomly padded Initials
+    let mut pair = Pair::default();
+    pair.connect_with(client_config);
+}
+
+#[test]
+fn initial_padding_path_validation() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport
+        .initial_mtu(1400)
+        .initial_padding(1200..=1400)
+        .unwrap();
+    let mut server_config = server_config();
+    server_config.transport_config(Arc::new(transport));
+
+    let sizes = (0..16)
+        .map(|_| {
+            let mut pair = Pair::new(Default::default(), server_config.clone());
+            let (client_ch, _) = pair.connect();
+            pair.drive();
+
+            let old_addr = pair.client.addr;
+            pair.client.addr = SocketAddr::new(
+                Ipv4Addr::new(127, 0, 0, 1).into(),
+                CLIENT_PORTS.lock().unwrap().next().unwrap(),
+            );
+            pair.client_conn_mut(client_ch).ping();
+            pair.drive_client();
+            pair.server.drive(pair.time, pair.client.addr);
+
+            // The server validates the previous path with a PATH_CHALLENGE
+            let mut challenges = pair
+                .server
+                .outbound
+                .iter()
+                .filter(|(transmit, _)| transmit.destination == old_addr);
+            let (_, datagram) = challenges.next().unwrap();
+            assert!(challenges.next().is_none());
+            datagram.len()
+        })
+        .collect::<Vec<_>>();
+    assert!(sizes.iter().all(|size| (1200..=1400).contains(size)));
+    assert!(sizes.iter().any(|&size| size != sizes[0]), "{sizes:?}");
+}
+
 /// Verify that UDP datagrams are padded to MTU if specified in the transport config.
 #[test]
 fn pad_to_mtu() {
@@ -3460,6 +6706,99 @@ fn reject_short_idcid() {
     };
 }
 
+#[test]
+fn packet_drop_reasons() {
+    let _guard = subscribe();
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let mut endpoint_config = EndpointConfig::default();
+    endpoint_config.on_packet_drop(Arc::new({
+        let dropped = dropped.clone();
+        move |reason, remote| dropped.lock().unwrap().push((reason, remote))
+    }));
+    let endpoint_config = Arc::new(endpoint_config);
+    let remote = "[::2]:7890".parse().unwrap();
+    let now = Instant::now();
+    let mut buf = Vec::new();
+
+    let mut server = Endpoint::new(
+        endpoint_config.clone(),
+        Some(Arc::new(server_config())),
+        true,
+    );
+    // Truncated long header
+    let event = server.handle(
+        now,
+        remote,
+        None,
+        None,
+        hex!("c0 00 00")[..].into(),
+        &mut buf,
+    );
+    assert!(event.is_none());
+    // Initial in a datagram below the minimum size
+    let mut initial = BytesMut::from(hex!("c4 00000001 08 0001020304050607 00 00 3f").as_ref());
+    initial.resize(100, 0);
+    let event = server.handle(now, remote, None, None, initial, &mut buf);
+    assert!(event.is_none());
+    // Short header packet for no connection
+    server.handle(now, remote, None, None, [0u8; 1024][..].into(), &mut buf);
+
+    // Clients don't negotiate versions
+    let mut client = Endpoint::new(endpoint_config.clone(), None, true);
+    let event = client.handle(
+        now,
+        remote,
+        None,
+        None,
+        hex!("c0 0a0a0a0a 00 00 00")[..].into(),
+        &mut buf,
+    );
+    assert!(event.is_none());
+
+    assert_eq!(
+        mem::take(&mut *dropped.lock().unwrap()),
+        [
+            (DropReason::InvalidFormat, remote),
+            (DropReason::AntiAmplification, remote),
+            (DropReason::UnknownConnectionId, remote),
+            (DropReason::UnsupportedVersion, remote),
+        ]
+    );
+
+    // Packets dropped by connections
+    let mut transport = TransportConfig::default();
+    transport.allow_migration(false);
+    let mut server_config = server_config();
+    server_config.transport_config(Arc::new(transport));
+    let mut pair = Pair::new(endpoint_config, server_config);
+    let (client_ch, _) = pair.connect();
+    pair.drive();
+    assert!(dropped.lock().unwrap().is_empty());
+
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+    let (_, _, packet) = pair.server.inbound.back_mut().unwrap();
+    *packet.last_mut().unwrap() ^= 0xff;
+    pair.drive_server();
+    assert_eq!(
+        mem::take(&mut *dropped.lock().unwrap()),
+        [(DropReason::DecryptionFailed, pair.client.addr)]
+    );
+
+    pair.client.addr = SocketAddr::new(
+        Ipv4Addr::new(127, 0, 0, 1).into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    pair.client_conn_mut(client_ch).local_address_changed();
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+    pair.drive_server();
+    assert_eq!(
+        mem::take(&mut *dropped.lock().unwrap()),
+        [(DropReason::MigrationRejected, pair.client.addr)]
+    );
+}
+
 /// Ensure that a connection can be made when a preferred address is advertised by the server,
 /// regardless of whether the address is actually used.
 #[test]
@@ -3472,6 +6811,87 @@ fn preferred_address() {
     pair.connect();
 }
 
+#[test]
+fn migrate_to_preferred_address() {
+    let _guard = subscribe();
+    for auto in [false, true] {
+        let preferred = "[::1]:65535".parse().unwrap();
+        let mut server_config = server_config();
+        server_config.preferred_address_v6(Some(preferred));
+        let preferred = SocketAddr::V6(preferred);
+        let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
+        let original = pair.server.addr;
+
+        let mut transport = TransportConfig::default();
+        transport.migrate_to_preferred_address(auto);
+        let mut client_config = client_config();
+        client_config.transport_config(Arc::new(transport));
+        let client_ch = pair.begin_connect(client_config);
+
+        // Run the handshake until the client is done with it, by which point it would have
+        // migrated if configured to
+        let mut confirmed = false;
+        while !confirmed {
+            assert!(pair.step());
+            while let Some(event) = pair.client_conn_mut(client_ch).poll() {
+                confirmed |= matches!(event, Event::HandshakeConfirmed);
+            }
+        }
+        let conn = pair.client_conn_mut(client_ch);
+        assert_eq!(conn.peer_preferred_address(), Some(preferred));
+        if auto {
+            assert_eq!(conn.remote_address(), preferred);
+        } else {
+            assert_eq!(conn.remote_address(), original);
+            pair.drive();
+            assert_eq!(pair.client_conn_mut(client_ch).remote_address(), original);
+            let now = pair.time;
+            let conn = pair.client_conn_mut(client_ch);
+            assert_eq!(conn.migrate_to_preferred_address(now), Some(preferred));
+            assert_eq!(conn.migrate_to_preferred_address(now), None);
+        }
+
+        // The server is reachable at its preferred address, so validation succeeds
+        pair.server.addr = preferred;
+        pair.drive();
+        let server_ch = pair.server.assert_accept();
+        assert_eq!(pair.client_conn_mut(client_ch).remote_address(), preferred);
+
+        let stats = pair.client_conn_mut(client_ch).stats();
+        assert_eq!(stats.path.migration_attempts, 1);
+        assert_eq!(stats.path.migration_successes, 1);
+
+        let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+        pair.client_send(client_ch, s).write(b"hello").unwrap();
+        pair.drive();
+        assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s));
+    }
+}
+
+#[test]
+fn preferred_address_unreachable() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    server_config.preferred_address_v6(Some("[::1]:65535".parse().unwrap()));
+    let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
+    let (client_ch, _) = pair.connect();
+    let original = pair.server.addr;
+
+    let now = pair.time;
+    assert!(
+        pair.client_conn_mut(client_ch)
+            .migrate_to_preferred_address(now)
+            .is_some()
+    );
+    pair.drive();
+    // Validation failed, so the client fell back to the original address
+    assert_eq!(pair.client_conn_mut(client_ch).remote_address(), original);
+    assert!(!pair.client_conn_mut(client_ch).is_closed());
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.migration_attempts, 1);
+    assert_eq!(stats.path.migration_successes, 0);
+}
+
 #[test]
 fn handshake_sequence() {
     let _guard = subscribe();
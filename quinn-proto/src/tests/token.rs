@@ -314,6 +314,74 @@ fn use_token_expired() {
     assert_eq!(pair.server.known_cids(), 0);
 }
 
+#[test]
+fn use_token_lifts_amplification_limit() {
+    let _guard = subscribe();
+    let (received, sent, prevalidated) = first_flight_with_token(true);
+    assert!(prevalidated);
+    assert!(
+        sent > 3 * received,
+        "sent {sent} bytes after receiving {received}"
+    );
+}
+
+#[test]
+fn use_token_amplification_limit_kept() {
+    let _guard = subscribe();
+    let (received, sent, prevalidated) = first_flight_with_token(false);
+    assert!(!prevalidated);
+    assert!(
+        sent <= 3 * received,
+        "sent {sent} bytes after receiving {received}"
+    );
+}
+
+/// Connect once to obtain a validation token, then begin a second connection with it and have the
+/// server send more 0.5-RTT data than the anti-amplification limit allows before hearing back
+///
+/// Returns the bytes the server received and sent, and whether it considered the client's address
+/// validated by the token.
+fn first_flight_with_token(lift_amplification_limit: bool) -> (u64, u64, bool) {
+    let mut server_config = server_config();
+    server_config
+        .validation_token
+        .lift_amplification_limit(lift_amplification_limit);
+    let mut pair = Pair::new(Default::default(), server_config);
+    let client_config = client_config();
+    let (client_ch, server_ch) = pair.connect_with(client_config.clone());
+    assert!(!pair.server_conn_mut(server_ch).address_prevalidated());
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(42), Bytes::new());
+    pair.drive();
+
+    pair.begin_connect(client_config);
+    pair.drive_client();
+    pair.drive_server();
+    let server_ch = pair.server.assert_accept();
+    let s = pair.server_streams(server_ch).open(Dir::Uni).unwrap();
+    pair.server_send(server_ch, s).write(&[0; 16_000]).unwrap();
+
+    // Lose everything the server sends, so the client's address isn't validated otherwise
+    for _ in 0..10 {
+        pair.client.inbound.clear();
+        let Some(t) = pair.server.next_wakeup() else {
+            break;
+        };
+        pair.time = pair.time.max(t);
+        pair.drive_server();
+    }
+
+    let conn = pair.server_conn_mut(server_ch);
+    (
+        conn.total_recvd(),
+        conn.stats().udp_tx.bytes,
+        conn.address_prevalidated(),
+    )
+}
+
 pub(super) struct FakeTimeSource(Mutex<SystemTime>);
 
 impl FakeTimeSource {
@@ -13,9 +13,12 @@ use std::{
 use assert_matches::assert_matches;
 use bytes::BytesMut;
 use rustls::{
-    KeyLogFile,
-    client::WebPkiServerVerifier,
-    pki_types::{CertificateDer, PrivateKeyDer},
+    CertificateError, DigitallySignedStruct, KeyLogFile, SignatureScheme,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
 };
 use tracing::{info_span, trace};
 
@@ -36,6 +39,8 @@ pub(super) struct Pair {
     pub(super) mtu: usize,
     /// Simulates explicit congestion notification
     pub(super) congestion_experienced: bool,
+    /// Simulates a bottleneck link on the path from the client to the server
+    pub(super) bottleneck: Option<Bottleneck>,
     // One-way
     pub(super) latency: Duration,
     /// Number of spin bit flips
@@ -79,6 +84,7 @@ impl Pair {
             spins: 0,
             last_spin: false,
             congestion_experienced: false,
+            bottleneck: None,
         }
     }
 
@@ -141,9 +147,19 @@ impl Pair {
                 info!(packet_size, "dropping packet (max size exceeded)");
                 continue;
             }
+            let transmitted = match self.bottleneck {
+                Some(ref mut link) => match link.enqueue(self.time, packet_size) {
+                    Some(t) => t,
+                    None => {
+                        info!(packet_size, "dropping packet (bottleneck queue full)");
+                        continue;
+                    }
+                },
+                None => self.time,
+            };
             if buffer[0] & packet::LONG_HEADER_FORM == 0 {
                 let spin = buffer[0] & packet::SPIN_BIT != 0;
-                self.spins += (spin == self.last_spin) as u64;
+                self.spins += (spin != self.last_spin) as u64;
                 self.last_spin = spin;
             }
             if let Some(ref socket) = self.client.socket {
@@ -152,7 +168,7 @@ impl Pair {
             if self.server.addr == packet.destination {
                 let ecn = set_congestion_experienced(packet.ecn, self.congestion_experienced);
                 self.server.inbound.push_back((
-                    self.time + self.latency,
+                    transmitted + self.latency,
                     ecn,
                     buffer.as_ref().into(),
                 ));
@@ -286,17 +302,50 @@ impl Default for Pair {
     }
 }
 
+/// A link of limited bandwidth, preceded by a drop-tail queue
+pub(super) struct Bottleneck {
+    /// Bytes transmitted per second
+    rate: u64,
+    /// Bytes that may wait in the queue before further packets are dropped
+    buffer: u64,
+    /// When the link will have transmitted every queued packet
+    busy_until: Option<Instant>,
+}
+
+impl Bottleneck {
+    pub(super) fn new(rate: u64, buffer: u64) -> Self {
+        Self {
+            rate,
+            buffer,
+            busy_until: None,
+        }
+    }
+
+    /// Returns when a packet arriving at `now` will have been transmitted, or `None` if the queue
+    /// is full and the packet is dropped
+    fn enqueue(&mut self, now: Instant, size: usize) -> Option<Instant> {
+        let start = self.busy_until.map_or(now, |t| t.max(now));
+        let queued = ((start - now).as_secs_f64() * self.rate as f64) as u64;
+        if queued + size as u64 > self.buffer {
+            return None;
+        }
+        let transmitted = start + Duration::from_secs_f64(size as f64 / self.rate as f64);
+        self.busy_until = Some(transmitted);
+        Some(transmitted)
+    }
+}
+
 pub(super) struct TestEndpoint {
     pub(super) endpoint: Endpoint,
     pub(super) addr: SocketAddr,
     socket: Option<UdpSocket>,
-    timeout: Option<Instant>,
+    timeouts: HashMap<ConnectionHandle, Instant>,
     pub(super) outbound: VecDeque<(Transmit, Bytes)>,
     delayed: VecDeque<(Transmit, Bytes)>,
     pub(super) inbound: VecDeque<(Instant, Option<EcnCodepoint>, BytesMut)>,
     accepted: Option<Result<ConnectionHandle, ConnectionError>>,
     pub(super) connections: HashMap<ConnectionHandle, Connection>,
-    conn_events: HashMap<ConnectionHandle, VecDeque<ConnectionEvent>>,
+    pub(super) conn_events: HashMap<ConnectionHandle, VecDeque<ConnectionEvent>>,
     pub(super) captured_packets: Vec<Vec<u8>>,
     pub(super) capture_inbound_packets: bool,
     pub(super) handle_incoming: Box<dyn FnMut(&Incoming) -> IncomingConnectionBehavior>,
@@ -334,7 +383,7 @@ impl TestEndpoint {
             endpoint,
             addr,
             socket,
-            timeout: None,
+            timeouts: HashMap::default(),
             outbound: VecDeque::new(),
             delayed: VecDeque::new(),
             inbound: VecDeque::new(),
@@ -413,15 +462,13 @@ impl TestEndpoint {
         loop {
             let mut endpoint_events: Vec<(ConnectionHandle, EndpointEvent)> = vec![];
             for (ch, conn) in self.connections.iter_mut() {
-                if self.timeout.is_some_and(|x| x <= now) {
-                    self.timeout = None;
+                if self.timeouts.get(ch).is_some_and(|&x| x <= now) {
+                    self.timeouts.remove(ch);
                     conn.handle_timeout(now);
                 }
 
-                for (_, mut events) in self.conn_events.drain() {
-                    for event in events.drain(..) {
-                        conn.handle_event(event);
-                    }
+                for event in self.conn_events.remove(ch).into_iter().flatten() {
+                    conn.handle_event(event);
                 }
 
                 while let Some(event) = conn.poll_endpoint_events() {
@@ -432,7 +479,10 @@ impl TestEndpoint {
                     self.outbound.extend(split_transmit(transmit, &buf[..size]));
                     buf.clear();
                 }
-                self.timeout = conn.poll_timeout();
+                match conn.poll_timeout() {
+                    Some(timeout) => self.timeouts.insert(*ch, timeout),
+                    None => self.timeouts.remove(ch),
+                };
             }
 
             if endpoint_events.is_empty() {
@@ -451,7 +501,7 @@ impl TestEndpoint {
 
     pub(super) fn next_wakeup(&self) -> Option<Instant> {
         let next_inbound = self.inbound.front().map(|x| x.0);
-        min_opt(self.timeout, next_inbound)
+        min_opt(self.timeouts.values().min().copied(), next_inbound)
     }
 
     fn is_idle(&self) -> bool {
@@ -664,6 +714,67 @@ fn client_crypto_inner(
     inner.try_into().unwrap()
 }
 
+/// Accepts any server certificate, as long as the server is at the `allowed` address
+///
+/// Never use this outside of tests: the certificate itself isn't verified at all.
+#[derive(Debug)]
+pub(super) struct AcceptAnyFrom {
+    pub(super) allowed: SocketAddr,
+    pub(super) remote: SocketAddr,
+}
+
+impl ServerCertVerifier for AcceptAnyFrom {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.remote == self.allowed {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &configured_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &configured_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        configured_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
 pub(super) fn min_opt<T: Ord>(x: Option<T>, y: Option<T>) -> Option<T> {
     match (x, y) {
         (Some(x), Some(y)) => Some(cmp::min(x, y)),
@@ -163,6 +163,9 @@ impl IncomingToken {
                 })
             }
             TokenPayload::Validation { ip, issued } => {
+                if !server_config.validation_token.lift_amplification_limit {
+                    return Ok(unvalidated);
+                }
                 if ip != remote_address.ip() {
                     return Ok(unvalidated);
                 }
@@ -61,6 +61,44 @@ impl fmt::Display for Error {
 impl std::error::Error for Error {}
 
 /// Transport-level error code
+///
+/// Error codes are defined in [RFC 9000 §20.1], and map onto the associated constants as follows:
+///
+/// | Code            | Constant                                                       |
+/// |-----------------|----------------------------------------------------------------|
+/// | `0x00`          | [`NO_ERROR`](Self::NO_ERROR)                                   |
+/// | `0x01`          | [`INTERNAL_ERROR`](Self::INTERNAL_ERROR)                       |
+/// | `0x02`          | [`CONNECTION_REFUSED`](Self::CONNECTION_REFUSED)               |
+/// | `0x03`          | [`FLOW_CONTROL_ERROR`](Self::FLOW_CONTROL_ERROR)               |
+/// | `0x04`          | [`STREAM_LIMIT_ERROR`](Self::STREAM_LIMIT_ERROR)               |
+/// | `0x05`          | [`STREAM_STATE_ERROR`](Self::STREAM_STATE_ERROR)               |
+/// | `0x06`          | [`FINAL_SIZE_ERROR`](Self::FINAL_SIZE_ERROR)                   |
+/// | `0x07`          | [`FRAME_ENCODING_ERROR`](Self::FRAME_ENCODING_ERROR)           |
+/// | `0x08`          | [`TRANSPORT_PARAMETER_ERROR`](Self::TRANSPORT_PARAMETER_ERROR) |
+/// | `0x09`          | [`CONNECTION_ID_LIMIT_ERROR`](Self::CONNECTION_ID_LIMIT_ERROR) |
+/// | `0x0a`          | [`PROTOCOL_VIOLATION`](Self::PROTOCOL_VIOLATION)               |
+/// | `0x0b`          | [`INVALID_TOKEN`](Self::INVALID_TOKEN)                         |
+/// | `0x0c`          | [`APPLICATION_ERROR`](Self::APPLICATION_ERROR)                 |
+/// | `0x0d`          | [`CRYPTO_BUFFER_EXCEEDED`](Self::CRYPTO_BUFFER_EXCEEDED)       |
+/// | `0x0e`          | [`KEY_UPDATE_ERROR`](Self::KEY_UPDATE_ERROR)                   |
+/// | `0x0f`          | [`AEAD_LIMIT_REACHED`](Self::AEAD_LIMIT_REACHED)               |
+/// | `0x10`          | [`NO_VIABLE_PATH`](Self::NO_VIABLE_PATH)                       |
+/// | `0x100`-`0x1ff` | [`crypto(alert)`](Self::crypto), for TLS alert `alert`         |
+///
+/// Any other code, e.g. from a future extension, is preserved as is and can be retrieved by
+/// converting to `u64`. Codes can be matched against the constants in `match` patterns:
+///
+/// ```
+/// # use quinn_proto::{ConnectionError, TransportErrorCode};
+/// fn is_flow_control_violation(error: &ConnectionError) -> bool {
+///     matches!(
+///         error.transport_error_code(),
+///         Some(TransportErrorCode::FLOW_CONTROL_ERROR)
+///     )
+/// }
+/// ```
+///
+/// [RFC 9000 §20.1]: https://www.rfc-editor.org/rfc/rfc9000.html#section-20.1
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct Code(u64);
 
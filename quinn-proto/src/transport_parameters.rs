@@ -19,7 +19,6 @@ use crate::{
     LOC_CID_COUNT, MAX_CID_SIZE, MAX_STREAM_COUNT, RESET_TOKEN_SIZE, ResetToken, Side,
     TIMER_GRANULARITY, TransportError, VarInt,
     cid_generator::ConnectionIdGenerator,
-    cid_queue::CidQueue,
     coding::{BufExt, BufMutExt, UnexpectedEnd},
     config::{EndpointConfig, ServerConfig, TransportConfig},
     shared::ConnectionId,
@@ -89,6 +88,10 @@ macro_rules! make_struct {
             /// Frequency
             pub(crate) min_ack_delay: Option<VarInt>,
 
+            /// The endpoint accepts STREAM_SKIP frames, a Quinn extension for partially reliable
+            /// streams
+            pub(crate) partial_reliability: bool,
+
             // Server-only
             /// The value of the Destination Connection ID field from the first Initial packet sent
             /// by the client
@@ -112,6 +115,12 @@ macro_rules! make_struct {
             pub(crate) write_order: Option<[u8; TransportParameterId::SUPPORTED.len()]>,
         }
 
+        impl TransportParameters {
+            $($(#[$doc])* pub fn $name(&self) -> VarInt {
+                self.$name
+            })*
+        }
+
         // We deliberately don't implement the `Default` trait, since that would be public, and
         // downstream crates should never construct `TransportParameters` except by decoding those
         // supplied by a peer.
@@ -126,6 +135,7 @@ macro_rules! make_struct {
                     initial_src_cid: None,
                     grease_quic_bit: false,
                     min_ack_delay: None,
+                    partial_reliability: false,
 
                     original_dst_cid: None,
                     retry_src_cid: None,
@@ -142,6 +152,34 @@ macro_rules! make_struct {
 apply_params!(make_struct);
 
 impl TransportParameters {
+    /// Whether the endpoint refuses active connection migration
+    pub fn disable_active_migration(&self) -> bool {
+        self.disable_active_migration
+    }
+
+    /// Maximum size of datagram frames the endpoint accepts, or `None` if it doesn't support
+    /// unreliable datagrams
+    pub fn max_datagram_frame_size(&self) -> Option<VarInt> {
+        self.max_datagram_frame_size
+    }
+
+    /// Whether the endpoint is willing to receive QUIC packets containing any value for the fixed
+    /// bit
+    pub fn grease_quic_bit(&self) -> bool {
+        self.grease_quic_bit
+    }
+
+    /// Minimum amount of time in microseconds by which the endpoint is able to delay sending
+    /// acknowledgments, if it supports QUIC Acknowledgement Frequency
+    pub fn min_ack_delay(&self) -> Option<VarInt> {
+        self.min_ack_delay
+    }
+
+    /// Whether the endpoint accepts partially reliable streams, a Quinn extension
+    pub fn partial_reliability(&self) -> bool {
+        self.partial_reliability
+    }
+
     pub(crate) fn new(
         config: &TransportConfig,
         endpoint_config: &EndpointConfig,
@@ -150,21 +188,24 @@ impl TransportParameters {
         server_config: Option<&ServerConfig>,
         rng: &mut impl RngCore,
     ) -> Self {
+        let (bidi_local, bidi_remote, uni) = config.stream_receive_windows();
         Self {
             initial_src_cid: Some(initial_src_cid),
             initial_max_streams_bidi: config.max_concurrent_bidi_streams,
             initial_max_streams_uni: config.max_concurrent_uni_streams,
             initial_max_data: config.receive_window,
-            initial_max_stream_data_bidi_local: config.stream_receive_window,
-            initial_max_stream_data_bidi_remote: config.stream_receive_window,
-            initial_max_stream_data_uni: config.stream_receive_window,
+            initial_max_stream_data_bidi_local: bidi_local,
+            initial_max_stream_data_bidi_remote: bidi_remote,
+            initial_max_stream_data_uni: uni,
             max_udp_payload_size: endpoint_config.max_udp_payload_size,
             max_idle_timeout: config.max_idle_timeout.unwrap_or(VarInt(0)),
-            disable_active_migration: server_config.is_some_and(|c| !c.migration),
+            max_ack_delay: config.max_ack_delay,
+            disable_active_migration: !config.allow_migration
+                || server_config.is_some_and(|c| !c.migration),
             active_connection_id_limit: if cid_gen.cid_len() == 0 {
                 2 // i.e. default, i.e. unsent
             } else {
-                CidQueue::LEN as u32
+                config.active_connection_id_limit
             }
             .into(),
             max_datagram_frame_size: config
@@ -186,6 +227,7 @@ impl TransportParameters {
                     Some(VarInt::from_u32(1000)) // 1ms
                 }
             },
+            partial_reliability: config.partial_reliability,
             grease_transport_parameter: Some(ReservedTransportParameter::random(rng)),
             write_order: Some({
                 let mut order = std::array::from_fn(|i| i as u8);
@@ -208,6 +250,7 @@ impl TransportParameters {
             || cached.initial_max_streams_uni > self.initial_max_streams_uni
             || cached.max_datagram_frame_size > self.max_datagram_frame_size
             || cached.grease_quic_bit && !self.grease_quic_bit
+            || cached.partial_reliability && !self.partial_reliability
         {
             return Err(TransportError::PROTOCOL_VIOLATION(
                 "0-RTT accepted with incompatible transport parameters",
@@ -393,6 +436,12 @@ impl TransportParameters {
                         w.write(x);
                     }
                 }
+                TransportParameterId::PartialReliability => {
+                    if self.partial_reliability {
+                        w.write_var(id as u64);
+                        w.write_var(0);
+                    }
+                }
                 id => {
                     macro_rules! write_params {
                         {$($(#[$doc:meta])* $name:ident ($id:ident) = $default:expr,)*} => {
@@ -503,6 +552,10 @@ impl TransportParameters {
                     0 => params.grease_quic_bit = true,
                     _ => return Err(Error::Malformed),
                 },
+                TransportParameterId::PartialReliability => match len {
+                    0 => params.partial_reliability = true,
+                    _ => return Err(Error::Malformed),
+                },
                 TransportParameterId::MinAckDelayDraft07 => {
                     params.min_ack_delay = match r.get() {
                         Ok(value) => Some(value),
@@ -679,11 +732,14 @@ pub(crate) enum TransportParameterId {
 
     // https://datatracker.ietf.org/doc/html/draft-ietf-quic-ack-frequency#section-10.1
     MinAckDelayDraft07 = 0xFF04DE1B,
+
+    // Quinn extension for partially reliable streams, not registered with IANA
+    PartialReliability = 0x3F51_5300,
 }
 
 impl TransportParameterId {
     /// Array with all supported transport parameter IDs
-    const SUPPORTED: [Self; 21] = [
+    const SUPPORTED: [Self; 22] = [
         Self::MaxIdleTimeout,
         Self::MaxUdpPayloadSize,
         Self::InitialMaxData,
@@ -705,6 +761,7 @@ impl TransportParameterId {
         Self::RetrySourceConnectionId,
         Self::GreaseQuicBit,
         Self::MinAckDelayDraft07,
+        Self::PartialReliability,
     ];
 }
 
@@ -744,6 +801,7 @@ impl TryFrom<u64> for TransportParameterId {
             id if Self::RetrySourceConnectionId == id => Self::RetrySourceConnectionId,
             id if Self::GreaseQuicBit == id => Self::GreaseQuicBit,
             id if Self::MinAckDelayDraft07 == id => Self::MinAckDelayDraft07,
+            id if Self::PartialReliability == id => Self::PartialReliability,
             _ => return Err(()),
         };
         Ok(param)
@@ -781,6 +839,7 @@ mod test {
             }),
             grease_quic_bit: true,
             min_ack_delay: Some(2_000u32.into()),
+            partial_reliability: true,
             ..TransportParameters::default()
         };
         params.write(&mut buf);
@@ -912,4 +971,26 @@ mod test {
         high_limit.validate_resumption_from(&low_limit).unwrap();
         low_limit.validate_resumption_from(&high_limit).unwrap_err();
     }
+
+    #[test]
+    fn max_ack_delay_from_config() {
+        let mut config = TransportConfig::default();
+        config
+            .max_ack_delay(crate::Duration::from_millis(40))
+            .unwrap();
+        let params = TransportParameters::new(
+            &config,
+            &EndpointConfig::default(),
+            &crate::RandomConnectionIdGenerator::new(8),
+            ConnectionId::new(&[]),
+            None,
+            &mut rand::rng(),
+        );
+        assert_eq!(params.max_ack_delay, VarInt(40));
+
+        let mut buf = Vec::new();
+        params.write(&mut buf);
+        let decoded = TransportParameters::read(Side::Client, &mut buf.as_slice()).unwrap();
+        assert_eq!(decoded.max_ack_delay, VarInt(40));
+    }
 }
@@ -5,7 +5,7 @@ use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
 };
 
-use super::{IO_ERROR_LOG_INTERVAL, RecvMeta, Transmit, UdpSockRef, UdpSocketStateConfig, log_sendmsg_error};
+use super::{IO_ERROR_LOG_INTERVAL, IcmpError, RecvMeta, Transmit, UdpSockRef, UdpSocketStateConfig, log_sendmsg_error};
 
 /// Fallback UDP socket interface that stubs out all special functionality
 ///
@@ -98,16 +98,24 @@ impl UdpSocketState {
         1
     }
 
-    /// Resize the send buffer of `socket` to `bytes`
+    /// Resize the send buffer of `socket` to `bytes`, returning the size actually applied
+    ///
+    /// The operating system may not honor the request exactly, so the size is read back after
+    /// setting it.
     #[inline]
-    pub fn set_send_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<()> {
-        socket.0.set_send_buffer_size(bytes)
+    pub fn set_send_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<usize> {
+        socket.0.set_send_buffer_size(bytes)?;
+        socket.0.send_buffer_size()
     }
 
-    /// Resize the receive buffer of `socket` to `bytes`
+    /// Resize the receive buffer of `socket` to `bytes`, returning the size actually applied
+    ///
+    /// The operating system may not honor the request exactly, so the size is read back after
+    /// setting it.
     #[inline]
-    pub fn set_recv_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<()> {
-        socket.0.set_recv_buffer_size(bytes)
+    pub fn set_recv_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<usize> {
+        socket.0.set_recv_buffer_size(bytes)?;
+        socket.0.recv_buffer_size()
     }
 
     /// Get the size of the `socket` send buffer
@@ -126,6 +134,31 @@ impl UdpSocketState {
     pub fn may_fragment(&self) -> bool {
         true
     }
+
+    /// Set or clear the don't-fragment bit on datagrams sent from `socket`
+    ///
+    /// Not supported by this implementation: enabling it fails with
+    /// [`io::ErrorKind::Unsupported`], and datagrams may always be fragmented.
+    pub fn set_dont_fragment(&self, _socket: UdpSockRef<'_>, enabled: bool) -> io::Result<()> {
+        match enabled {
+            true => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "don't-fragment is not supported on this platform",
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Read an ICMP error from the error queue of `socket`, if any
+    ///
+    /// Not supported by this implementation: always yields `Ok(None)`.
+    pub fn recv_icmp_error(
+        &self,
+        _socket: UdpSockRef<'_>,
+        _buf: &mut [u8],
+    ) -> io::Result<Option<IcmpError>> {
+        Ok(None)
+    }
 }
 
 fn send(socket: UdpSockRef<'_>, transmit: &Transmit<'_>) -> io::Result<()> {
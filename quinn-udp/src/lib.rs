@@ -113,6 +113,11 @@ impl Default for AppleDatapath {
 pub struct UdpSocketStateConfig {
     /// Apple UDP datapath selection. Ignored on non-Apple targets.
     pub apple_datapath: AppleDatapath,
+    /// Queue ICMP errors for datagrams sent on the socket, to be read with
+    /// [`UdpSocketState::recv_icmp_error`]
+    ///
+    /// Sets `IP_RECVERR` and `IPV6_RECVERR`. Ignored on targets other than Linux and Android.
+    pub recv_icmp_errors: bool,
 }
 
 /// Number of UDP packets to send/receive at a time
@@ -155,6 +160,23 @@ pub struct RecvMeta {
     pub interface_index: Option<u32>,
 }
 
+impl RecvMeta {
+    /// Split the bytes received into `buf` into the datagrams they consist of
+    ///
+    /// Yields the first [`len`] bytes of `buf` in pieces of [`stride`] bytes, the last of which
+    /// may be shorter.
+    ///
+    /// [`len`]: RecvMeta::len
+    /// [`stride`]: RecvMeta::stride
+    pub fn datagrams<'a>(&self, buf: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + use<'a> {
+        let stride = match self.stride {
+            0 => self.len.max(1),
+            stride => stride,
+        };
+        buf[..self.len].chunks(stride)
+    }
+}
+
 impl Default for RecvMeta {
     /// Constructs a value with arbitrary fields, intended to be overwritten
     fn default() -> Self {
@@ -169,6 +191,24 @@ impl Default for RecvMeta {
     }
 }
 
+/// An ICMP error reported by the network for a datagram sent on a socket
+///
+/// See [`UdpSocketStateConfig::recv_icmp_errors`].
+#[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
+pub struct IcmpError {
+    /// The destination address of the datagram which caused the error
+    pub destination: SocketAddr,
+    /// The path MTU reported by an ICMP "packet too big" or "fragmentation needed" message
+    ///
+    /// Measured in bytes of IP packet, including IP and UDP headers. `None` for other kinds of
+    /// errors.
+    pub mtu: Option<u32>,
+    /// The number of bytes of the datagram's UDP payload, as quoted by the ICMP message, which
+    /// were written to the buffer
+    pub len: usize,
+}
+
 /// An outgoing packet
 #[derive(Debug, Clone)]
 pub struct Transmit<'a> {
@@ -16,7 +16,7 @@ use socket2::SockRef;
 
 #[allow(unused_imports)]
 use super::{
-    AppleDatapath, EcnCodepoint, IO_ERROR_LOG_INTERVAL, RecvMeta, Transmit, UdpSockRef,
+    AppleDatapath, EcnCodepoint, IO_ERROR_LOG_INTERVAL, IcmpError, RecvMeta, Transmit, UdpSockRef,
     UdpSocketStateConfig, cmsg, log_sendmsg_error,
 };
 
@@ -67,7 +67,9 @@ pub struct UdpSocketState {
     last_send_error: Mutex<Instant>,
     max_gso_segments: AtomicUsize,
     gro_segments: usize,
-    may_fragment: bool,
+    may_fragment: AtomicBool,
+    /// Whether ICMP errors are queued on the socket's error queue
+    recv_icmp_errors: bool,
     #[cfg(apple)]
     apple_datapath: AppleDatapath,
 
@@ -129,43 +131,22 @@ impl UdpSocketState {
             }
         }
 
-        let mut may_fragment = false;
+        let may_fragment = !set_dont_fragment_options(&*io, is_ipv4, true)?;
         #[cfg(any(target_os = "linux", target_os = "android"))]
         {
             // opportunistically try to enable GRO. See gro::gro_segments().
             let _ = set_socket_option(&*io, libc::SOL_UDP, libc::UDP_GRO, OPTION_ON);
 
-            // Forbid IPv4 fragmentation. Set even for IPv6 to account for IPv6 mapped IPv4 addresses.
-            // Set `may_fragment` to `true` if this option is not supported on the platform.
-            may_fragment |= !set_socket_option_supported(
-                &*io,
-                libc::IPPROTO_IP,
-                libc::IP_MTU_DISCOVER,
-                libc::IP_PMTUDISC_PROBE,
-            )?;
-
             if is_ipv4 {
                 set_socket_option(&*io, libc::IPPROTO_IP, libc::IP_PKTINFO, OPTION_ON)?;
-            } else {
-                // Set `may_fragment` to `true` if this option is not supported on the platform.
-                may_fragment |= !set_socket_option_supported(
-                    &*io,
-                    libc::IPPROTO_IPV6,
-                    libc::IPV6_MTU_DISCOVER,
-                    libc::IPV6_PMTUDISC_PROBE,
-                )?;
             }
         }
-        #[cfg(any(target_os = "freebsd", apple))]
-        {
-            if is_ipv4 {
-                // Set `may_fragment` to `true` if this option is not supported on the platform.
-                may_fragment |= !set_socket_option_supported(
-                    &*io,
-                    libc::IPPROTO_IP,
-                    libc::IP_DONTFRAG,
-                    OPTION_ON,
-                )?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if config.recv_icmp_errors {
+            // Set for IPv4 even on IPv6 sockets to account for IPv6 mapped IPv4 addresses
+            set_socket_option(&*io, libc::IPPROTO_IP, libc::IP_RECVERR, OPTION_ON)?;
+            if !is_ipv4 {
+                set_socket_option(&*io, libc::IPPROTO_IPV6, libc::IPV6_RECVERR, OPTION_ON)?;
             }
         }
         #[cfg(any(bsd, apple, solarish))]
@@ -182,16 +163,6 @@ impl UdpSocketState {
         if !is_ipv4 {
             set_socket_option(&*io, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, OPTION_ON)?;
             set_socket_option(&*io, libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS, OPTION_ON)?;
-            // Linux's IP_PMTUDISC_PROBE allows us to operate under interface MTU rather than the
-            // kernel's path MTU guess, but actually disabling fragmentation requires this too. See
-            // __ip6_append_data in ip6_output.c.
-            // Set `may_fragment` to `true` if this option is not supported on the platform.
-            may_fragment |= !set_socket_option_supported(
-                &*io,
-                libc::IPPROTO_IPV6,
-                libc::IPV6_DONTFRAG,
-                OPTION_ON,
-            )?;
         }
 
         let now = Instant::now();
@@ -211,7 +182,9 @@ impl UdpSocketState {
                 }
             }),
             gro_segments: gro::gro_segments(),
-            may_fragment,
+            may_fragment: AtomicBool::new(may_fragment),
+            recv_icmp_errors: cfg!(any(target_os = "linux", target_os = "android"))
+                && config.recv_icmp_errors,
             #[cfg(apple)]
             apple_datapath: config.apple_datapath,
             sendmsg_einval: AtomicBool::new(false),
@@ -277,16 +250,28 @@ impl UdpSocketState {
         self.gro_segments
     }
 
-    /// Resize the send buffer of `socket` to `bytes`
+    /// Resize the send buffer of `socket` to `bytes`, returning the size actually applied
+    ///
+    /// The operating system may not honor the request exactly, so the size is read back after
+    /// setting it. Linux and Android double the requested value to leave room for bookkeeping
+    /// overhead, after clamping it to the `net.core.wmem_max` sysctl, so the result may be
+    /// larger or smaller than `bytes`.
     #[inline]
-    pub fn set_send_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<()> {
-        socket.0.set_send_buffer_size(bytes)
+    pub fn set_send_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<usize> {
+        socket.0.set_send_buffer_size(bytes)?;
+        socket.0.send_buffer_size()
     }
 
-    /// Resize the receive buffer of `socket` to `bytes`
+    /// Resize the receive buffer of `socket` to `bytes`, returning the size actually applied
+    ///
+    /// The operating system may not honor the request exactly, so the size is read back after
+    /// setting it. Linux and Android double the requested value to leave room for bookkeeping
+    /// overhead, after clamping it to the `net.core.rmem_max` sysctl, so the result may be
+    /// larger or smaller than `bytes`.
     #[inline]
-    pub fn set_recv_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<()> {
-        socket.0.set_recv_buffer_size(bytes)
+    pub fn set_recv_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<usize> {
+        socket.0.set_recv_buffer_size(bytes)?;
+        socket.0.recv_buffer_size()
     }
 
     /// Get the size of the `socket` send buffer
@@ -303,10 +288,67 @@ impl UdpSocketState {
 
     /// Whether transmitted datagrams might get fragmented by the IP layer
     ///
-    /// Returns `false` on targets which employ e.g. the `IPV6_DONTFRAG` socket option.
+    /// Returns `false` on targets which employ e.g. the `IPV6_DONTFRAG` socket option, unless
+    /// fragmentation was re-enabled with [`set_dont_fragment()`](Self::set_dont_fragment).
     #[inline]
     pub fn may_fragment(&self) -> bool {
+        self.may_fragment.load(Ordering::Relaxed)
+    }
+
+    /// Set or clear the don't-fragment bit on datagrams sent from `socket`
+    ///
+    /// Enabled by default where supported. While enabled, the IP layer rejects datagrams larger
+    /// than the path MTU instead of fragmenting them, so sends exceeding it fail with `EMSGSIZE`
+    /// or are dropped along the path; this is what makes path MTU discovery reliable. Disabling
+    /// it lets large datagrams through at the cost of fragmentation, which is lossier and often
+    /// filtered.
+    ///
+    /// Uses `IP_MTU_DISCOVER`/`IPV6_MTU_DISCOVER` on Linux and Android, `IP_DONTFRAG` on FreeBSD
+    /// and Apple platforms, and `IPV6_DONTFRAG` for IPv6 everywhere. Fails with
+    /// [`io::ErrorKind::Unsupported`] if enabling it isn't supported for this socket, in which
+    /// case [`may_fragment()`](Self::may_fragment) returns `true`.
+    pub fn set_dont_fragment(&self, socket: UdpSockRef<'_>, enabled: bool) -> io::Result<()> {
+        let io = socket.0;
+        let is_ipv4 = io.local_addr()?.family() == libc::AF_INET as libc::sa_family_t;
+        let applied = set_dont_fragment_options(&*io, is_ipv4, enabled)?;
         self.may_fragment
+            .store(!(enabled && applied), Ordering::Relaxed);
+        if enabled && !applied {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "don't-fragment is not supported on this socket",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read an ICMP error from the error queue of `socket`, if any
+    ///
+    /// The UDP payload of the datagram which caused the error, as quoted by the ICMP message, is
+    /// written to `buf`, truncated to its length. Yields `Ok(None)` once the queue is empty, or
+    /// immediately unless [`UdpSocketStateConfig::recv_icmp_errors`] was enabled on a supported
+    /// platform. Errors which didn't originate from an ICMP message, such as those raised locally
+    /// by the kernel, are skipped.
+    ///
+    /// Note that ICMP messages are easily spoofed by off-path attackers. The quoted payload
+    /// should be checked to belong to a datagram that was actually sent before acting on them.
+    pub fn recv_icmp_error(
+        &self,
+        socket: UdpSockRef<'_>,
+        buf: &mut [u8],
+    ) -> io::Result<Option<IcmpError>> {
+        if !self.recv_icmp_errors {
+            return Ok(None);
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            recv_icmp_error(socket.0.as_raw_fd(), buf)
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let _ = (socket, buf);
+            Ok(None)
+        }
     }
 
     /// Returns true if we previously got an EINVAL error from `sendmsg` syscall.
@@ -930,6 +972,69 @@ impl ControlMetadata {
     }
 }
 
+/// Reads ICMP errors from the error queue of `fd`, skipping any other errors
+///
+/// Yields `Ok(None)` once the queue is empty.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn recv_icmp_error(fd: libc::c_int, buf: &mut [u8]) -> io::Result<Option<IcmpError>> {
+    let mut name = MaybeUninit::<libc::sockaddr_storage>::uninit();
+    let mut ctrl = cmsg::Aligned(MaybeUninit::<[u8; CMSG_LEN]>::uninit());
+    let buf_len = buf.len();
+    let mut iov = IoSliceMut::new(buf);
+    loop {
+        let mut hdr = unsafe { mem::zeroed::<libc::msghdr>() };
+        prepare_recv_slow(&mut iov, &mut name, &mut ctrl, &mut hdr);
+        let n = unsafe { libc::recvmsg(fd, &mut hdr, libc::MSG_ERRQUEUE) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            match e.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock => return Ok(None),
+                _ => return Err(e),
+            }
+        }
+
+        for cmsg in unsafe { cmsg::Iter::new(&hdr) } {
+            if !matches!(
+                (cmsg.cmsg_level, cmsg.cmsg_type),
+                (libc::IPPROTO_IP, libc::IP_RECVERR) | (libc::IPPROTO_IPV6, libc::IPV6_RECVERR)
+            ) {
+                continue;
+            }
+            let err = unsafe { cmsg::decode::<libc::sock_extended_err, libc::cmsghdr>(cmsg) };
+            let Some(mtu) = icmp_error_mtu(&err) else {
+                break;
+            };
+            let name = unsafe { name.assume_init() };
+            return Ok(Some(IcmpError {
+                destination: decode_socket_addr(&name)?,
+                mtu,
+                len: (n as usize).min(buf_len),
+            }));
+        }
+    }
+}
+
+/// The MTU reported by an extended socket error, if it stems from an ICMP message
+///
+/// Yields `Some(None)` for ICMP messages other than "packet too big".
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn icmp_error_mtu(err: &libc::sock_extended_err) -> Option<Option<u32>> {
+    // ICMP (RFC 792) and ICMPv6 (RFC 4443) "packet too big" messages
+    const ICMP_DEST_UNREACH: u8 = 3;
+    const ICMP_FRAG_NEEDED: u8 = 4;
+    const ICMPV6_PKT_TOOBIG: u8 = 2;
+
+    let too_big = match err.ee_origin {
+        libc::SO_EE_ORIGIN_ICMP => {
+            err.ee_type == ICMP_DEST_UNREACH && err.ee_code == ICMP_FRAG_NEEDED
+        }
+        libc::SO_EE_ORIGIN_ICMP6 => err.ee_type == ICMPV6_PKT_TOOBIG,
+        _ => return None,
+    };
+    Some(too_big.then_some(err.ee_info))
+}
+
 /// Decodes a `sockaddr_storage` into a `SocketAddr`
 fn decode_socket_addr(name: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
     match libc::c_int::from(name.ss_family) {
@@ -1166,6 +1271,67 @@ mod gro {
     }
 }
 
+/// Sets the socket options which forbid (or permit) fragmentation of outgoing datagrams
+///
+/// Yields `Ok(false)` if any of the options isn't supported on the current platform.
+fn set_dont_fragment_options(
+    socket: &impl AsRawFd,
+    is_ipv4: bool,
+    enabled: bool,
+) -> io::Result<bool> {
+    let mut supported = true;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        // Forbid IPv4 fragmentation. Set even for IPv6 to account for IPv6 mapped IPv4 addresses.
+        supported &= set_socket_option_supported(
+            socket,
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            match enabled {
+                true => libc::IP_PMTUDISC_PROBE,
+                false => libc::IP_PMTUDISC_DONT,
+            },
+        )?;
+
+        if !is_ipv4 {
+            supported &= set_socket_option_supported(
+                socket,
+                libc::IPPROTO_IPV6,
+                libc::IPV6_MTU_DISCOVER,
+                match enabled {
+                    true => libc::IPV6_PMTUDISC_PROBE,
+                    false => libc::IPV6_PMTUDISC_DONT,
+                },
+            )?;
+        }
+    }
+    #[cfg(any(target_os = "freebsd", apple))]
+    {
+        if is_ipv4 {
+            supported &= set_socket_option_supported(
+                socket,
+                libc::IPPROTO_IP,
+                libc::IP_DONTFRAG,
+                enabled as libc::c_int,
+            )?;
+        }
+    }
+
+    // Standardized in RFC 3542. Linux's IP_PMTUDISC_PROBE allows us to operate under interface
+    // MTU rather than the kernel's path MTU guess, but actually disabling fragmentation requires
+    // this too. See __ip6_append_data in ip6_output.c.
+    if !is_ipv4 {
+        supported &= set_socket_option_supported(
+            socket,
+            libc::IPPROTO_IPV6,
+            libc::IPV6_DONTFRAG,
+            enabled as libc::c_int,
+        )?;
+    }
+
+    Ok(supported)
+}
+
 /// Returns whether the given socket option is supported on the current platform
 ///
 /// Yields `Ok(true)` if the option was set successfully, `Ok(false)` if setting
@@ -1214,3 +1380,42 @@ mod gro {
         1
     }
 }
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    #[test]
+    fn icmp_error_mtu() {
+        let err = |origin, type_, code| libc::sock_extended_err {
+            ee_errno: libc::EMSGSIZE as u32,
+            ee_origin: origin,
+            ee_type: type_,
+            ee_code: code,
+            ee_pad: 0,
+            ee_info: 1300,
+            ee_data: 0,
+        };
+        // Fragmentation needed and packet too big
+        assert_eq!(
+            super::icmp_error_mtu(&err(libc::SO_EE_ORIGIN_ICMP, 3, 4)),
+            Some(Some(1300))
+        );
+        assert_eq!(
+            super::icmp_error_mtu(&err(libc::SO_EE_ORIGIN_ICMP6, 2, 0)),
+            Some(Some(1300))
+        );
+        // Port unreachable
+        assert_eq!(
+            super::icmp_error_mtu(&err(libc::SO_EE_ORIGIN_ICMP, 3, 3)),
+            Some(None)
+        );
+        assert_eq!(
+            super::icmp_error_mtu(&err(libc::SO_EE_ORIGIN_ICMP6, 1, 4)),
+            Some(None)
+        );
+        // Errors raised locally rather than by ICMP messages
+        assert_eq!(
+            super::icmp_error_mtu(&err(libc::SO_EE_ORIGIN_LOCAL, 0, 0)),
+            None
+        );
+    }
+}
@@ -4,7 +4,10 @@ use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     os::windows::io::AsRawSocket,
     ptr,
-    sync::{LazyLock, Mutex},
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
     time::Instant,
 };
 
@@ -12,7 +15,8 @@ use libc::{c_int, c_uint};
 use windows_sys::Win32::Networking::WinSock;
 
 use crate::{
-    EcnCodepoint, IO_ERROR_LOG_INTERVAL, RecvMeta, Transmit, UdpSockRef, UdpSocketStateConfig,
+    EcnCodepoint, IO_ERROR_LOG_INTERVAL, IcmpError, RecvMeta, Transmit, UdpSockRef,
+    UdpSocketStateConfig,
     cmsg::{self, CMsgHdr},
     log::debug,
     log_sendmsg_error,
@@ -24,6 +28,7 @@ use crate::{
 #[derive(Debug)]
 pub struct UdpSocketState {
     last_send_error: Mutex<Instant>,
+    may_fragment: AtomicBool,
 }
 
 impl UdpSocketState {
@@ -119,6 +124,7 @@ impl UdpSocketState {
         let now = Instant::now();
         Ok(Self {
             last_send_error: Mutex::new(now.checked_sub(2 * IO_ERROR_LOG_INTERVAL).unwrap_or(now)),
+            may_fragment: AtomicBool::new(false),
         })
     }
 
@@ -314,16 +320,24 @@ impl UdpSocketState {
         64
     }
 
-    /// Resize the send buffer of `socket` to `bytes`
+    /// Resize the send buffer of `socket` to `bytes`, returning the size actually applied
+    ///
+    /// The operating system may not honor the request exactly, so the size is read back after
+    /// setting it.
     #[inline]
-    pub fn set_send_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<()> {
-        socket.0.set_send_buffer_size(bytes)
+    pub fn set_send_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<usize> {
+        socket.0.set_send_buffer_size(bytes)?;
+        socket.0.send_buffer_size()
     }
 
-    /// Resize the receive buffer of `socket` to `bytes`
+    /// Resize the receive buffer of `socket` to `bytes`, returning the size actually applied
+    ///
+    /// The operating system may not honor the request exactly, so the size is read back after
+    /// setting it.
     #[inline]
-    pub fn set_recv_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<()> {
-        socket.0.set_recv_buffer_size(bytes)
+    pub fn set_recv_buffer_size(&self, socket: UdpSockRef<'_>, bytes: usize) -> io::Result<usize> {
+        socket.0.set_recv_buffer_size(bytes)?;
+        socket.0.recv_buffer_size()
     }
 
     /// Get the size of the `socket` send buffer
@@ -338,9 +352,56 @@ impl UdpSocketState {
         socket.0.recv_buffer_size()
     }
 
+    /// Whether transmitted datagrams might get fragmented by the IP layer
+    ///
+    /// Returns `false` unless fragmentation was re-enabled with
+    /// [`set_dont_fragment()`](Self::set_dont_fragment).
     #[inline]
     pub fn may_fragment(&self) -> bool {
-        false
+        self.may_fragment.load(Ordering::Relaxed)
+    }
+
+    /// Set or clear the don't-fragment bit on datagrams sent from `socket`
+    ///
+    /// Enabled by default. While enabled, the IP layer rejects datagrams larger than the path MTU
+    /// instead of fragmenting them, which is what makes path MTU discovery reliable. Disabling it
+    /// lets large datagrams through at the cost of fragmentation, which is lossier and often
+    /// filtered.
+    ///
+    /// Uses `IP_DONTFRAGMENT` for IPv4 and `IPV6_DONTFRAG` for IPv6.
+    pub fn set_dont_fragment(&self, socket: UdpSockRef<'_>, enabled: bool) -> io::Result<()> {
+        let addr = socket.0.local_addr()?;
+        let is_ipv6 = addr.as_socket_ipv6().is_some();
+        let is_ipv4 = addr.as_socket_ipv4().is_some() || !socket.0.only_v6()?;
+        if is_ipv4 {
+            set_socket_option(
+                &*socket.0,
+                WinSock::IPPROTO_IP,
+                WinSock::IP_DONTFRAGMENT,
+                enabled as u32,
+            )?;
+        }
+        if is_ipv6 {
+            set_socket_option(
+                &*socket.0,
+                WinSock::IPPROTO_IPV6,
+                WinSock::IPV6_DONTFRAG,
+                enabled as u32,
+            )?;
+        }
+        self.may_fragment.store(!enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Read an ICMP error from the error queue of `socket`, if any
+    ///
+    /// Not supported by this implementation: always yields `Ok(None)`.
+    pub fn recv_icmp_error(
+        &self,
+        _socket: UdpSockRef<'_>,
+        _buf: &mut [u8],
+    ) -> io::Result<Option<IcmpError>> {
+        Ok(None)
     }
 }
 
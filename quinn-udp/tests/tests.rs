@@ -209,6 +209,26 @@ fn gso() {
     );
 }
 
+#[test]
+fn recv_short_final_segment() {
+    const STRIDE: usize = 128;
+    // Distinct contents for each datagram, so that misplaced segment boundaries are detected,
+    // followed by unused buffer space
+    let mut buf = vec![0xAB; STRIDE];
+    buf.extend_from_slice(&[0xCD; STRIDE]);
+    buf.extend_from_slice(&[0xEF; STRIDE / 2]);
+    buf.extend_from_slice(&[0; STRIDE]);
+    let mut meta = RecvMeta::default();
+    meta.len = 2 * STRIDE + STRIDE / 2;
+    meta.stride = STRIDE;
+
+    let datagrams = meta.datagrams(&buf).collect::<Vec<_>>();
+    assert_eq!(
+        datagrams,
+        [&[0xAB; STRIDE][..], &[0xCD; STRIDE], &[0xEF; STRIDE / 2]]
+    );
+}
+
 #[test]
 fn socket_buffers() {
     const BUFFER_SIZE: usize = 123456;
@@ -246,10 +266,14 @@ fn socket_buffers() {
             BUFFER_SIZE * FACTOR,
             "make sure buffer is not already desired size"
         );
-        socket_state
+        let buffer_after = socket_state
             .set_send_buffer_size(sock.into(), BUFFER_SIZE)
             .expect("set send buffer size {buffer_before} -> {BUFFER_SIZE}");
-        let buffer_after = socket_state.send_buffer_size(sock.into()).unwrap();
+        assert!(buffer_after >= BUFFER_SIZE);
+        assert_eq!(
+            buffer_after,
+            socket_state.send_buffer_size(sock.into()).unwrap()
+        );
         assert_eq!(
             buffer_after,
             BUFFER_SIZE * FACTOR,
@@ -258,10 +282,14 @@ fn socket_buffers() {
 
         // Change the receive buffer size.
         let buffer_before = socket_state.recv_buffer_size(sock.into()).unwrap();
-        socket_state
+        let buffer_after = socket_state
             .set_recv_buffer_size(sock.into(), BUFFER_SIZE)
             .expect("set recv buffer size {buffer_before} -> {BUFFER_SIZE}");
-        let buffer_after = socket_state.recv_buffer_size(sock.into()).unwrap();
+        assert!(buffer_after >= BUFFER_SIZE);
+        assert_eq!(
+            buffer_after,
+            socket_state.recv_buffer_size(sock.into()).unwrap()
+        );
         assert_eq!(
             buffer_after,
             BUFFER_SIZE * FACTOR,
@@ -282,6 +310,84 @@ fn socket_buffers() {
     );
 }
 
+#[test]
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    apple,
+    windows
+))]
+fn dont_fragment() {
+    let v4 = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let v6 = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0));
+    for sock in [Ok(v4), v6].into_iter().filter_map(Result::ok) {
+        let state = UdpSocketState::new((&sock).into()).unwrap();
+        assert!(!state.may_fragment());
+
+        state.set_dont_fragment((&sock).into(), false).unwrap();
+        assert!(state.may_fragment());
+
+        state.set_dont_fragment((&sock).into(), true).unwrap();
+        assert!(!state.may_fragment());
+    }
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn icmp_port_unreachable() {
+    use quinn_udp::UdpSocketStateConfig;
+    use std::os::fd::AsRawFd;
+
+    let send = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let state = UdpSocketState::new_with_config(
+        (&send).into(),
+        UdpSocketStateConfig {
+            recv_icmp_errors: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut buf = [0; 64];
+    assert!(
+        state
+            .recv_icmp_error((&send).into(), &mut buf)
+            .unwrap()
+            .is_none()
+    );
+
+    // The kernel answers a datagram to a port nobody listens on with "port unreachable"
+    let dst = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let payload = b"quoted QUIC packet";
+    send.send_to(payload, dst).unwrap();
+
+    // A queued error is reported as `POLLERR`
+    let mut pollfd = libc::pollfd {
+        fd: send.as_raw_fd(),
+        events: 0,
+        revents: 0,
+    };
+    assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 5000) }, 1);
+    assert_ne!(pollfd.revents & libc::POLLERR, 0);
+
+    let error = state
+        .recv_icmp_error((&send).into(), &mut buf)
+        .unwrap()
+        .expect("ICMP error not received");
+    assert_eq!(error.destination, dst);
+    assert_eq!(error.mtu, None);
+    assert_eq!(&buf[..error.len], payload);
+    assert!(
+        state
+            .recv_icmp_error((&send).into(), &mut buf)
+            .unwrap()
+            .is_none()
+    );
+}
+
 fn test_send_recv(send: &Socket, recv: &Socket, transmit: Transmit<'_>) {
     let send_state = UdpSocketState::new(send.into()).unwrap();
     let recv_state = UdpSocketState::new(recv.into()).unwrap();
@@ -294,9 +400,10 @@ fn test_send_recv(send: &Socket, recv: &Socket, transmit: Transmit<'_>) {
     let mut buf = [0; u16::MAX as usize];
     let mut meta = RecvMeta::default();
     let segment_size = transmit.segment_size.unwrap_or(transmit.contents.len());
-    let expected_datagrams = transmit.contents.len() / segment_size;
+    // The last datagram is shorter if the contents aren't a multiple of the segment size
+    let expected = transmit.contents.chunks(segment_size).collect::<Vec<_>>();
     let mut datagrams = 0;
-    while datagrams < expected_datagrams {
+    while datagrams < expected.len() {
         let n = recv_state
             .recv(
                 recv.into(),
@@ -305,15 +412,10 @@ fn test_send_recv(send: &Socket, recv: &Socket, transmit: Transmit<'_>) {
             )
             .unwrap();
         assert_eq!(n, 1);
-        let segments = meta.len / meta.stride;
-        for i in 0..segments {
-            assert_eq!(
-                &buf[(i * meta.stride)..((i + 1) * meta.stride)],
-                &transmit.contents
-                    [(datagrams + i) * segment_size..(datagrams + i + 1) * segment_size]
-            );
+        for segment in meta.datagrams(&buf) {
+            assert_eq!(segment, expected[datagrams]);
+            datagrams += 1;
         }
-        datagrams += segments;
 
         assert_eq!(
             meta.addr.port(),
@@ -360,7 +462,7 @@ fn test_send_recv(send: &Socket, recv: &Socket, transmit: Transmit<'_>) {
             assert_eq!(meta.ecn, transmit.ecn);
         }
     }
-    assert_eq!(datagrams, expected_datagrams);
+    assert_eq!(datagrams, expected.len());
 }
 
 fn ip_to_v6_mapped(x: IpAddr) -> IpAddr {
@@ -1,5 +1,6 @@
 use std::{
     any::Any,
+    collections::{BTreeSet, VecDeque},
     fmt,
     future::Future,
     io,
@@ -12,24 +13,29 @@ use std::{
     task::{Context, Poll, Waker, ready},
 };
 
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use pin_project_lite::pin_project;
 use rustc_hash::FxHashMap;
 use thiserror::Error;
 use tokio::sync::{Notify, futures::Notified, mpsc, oneshot};
 use tracing::{Instrument, Span, debug_span};
 
+#[cfg(feature = "metrics")]
+use crate::metrics::ConnectionMetrics;
 use crate::{
-    ConnectionEvent, Duration, Instant, VarInt,
+    ConnectionEvent, Duration, EndpointEvent, Instant, VarInt,
     mutex::Mutex,
     recv_stream::RecvStream,
-    runtime::{AsyncTimer, Runtime, UdpSender},
+    request::{self, RequestConfig, RequestError},
+    runtime::{AsyncTimer, AsyncUdpSocket, Runtime, UdpSender},
+    send_budget::SendBudget,
     send_stream::SendStream,
     udp_transmit,
 };
 use proto::{
-    ConnectionError, ConnectionHandle, ConnectionStats, Dir, EndpointEvent, Side, StreamEvent,
-    StreamId, TransportError, TransportErrorCode, congestion::Controller,
+    ConnectionDebug, ConnectionError, ConnectionHandle, ConnectionStats, DatagramBackpressure, Dir,
+    Side, StreamEvent, StreamId, TransportError, TransportErrorCode, coding::Codec,
+    congestion::Controller, transport_parameters::TransportParameters,
 };
 
 /// In-progress connection attempt future
@@ -48,6 +54,7 @@ impl Connecting {
         conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
         sender: Pin<Box<dyn UdpSender>>,
         runtime: Arc<dyn Runtime>,
+        send_budget: Option<Arc<SendBudget>>,
     ) -> Self {
         let (on_handshake_data_send, on_handshake_data_recv) = oneshot::channel();
         let (on_connected_send, on_connected_recv) = oneshot::channel();
@@ -62,6 +69,7 @@ impl Connecting {
                 on_connected_send,
                 sender,
                 runtime.clone(),
+                send_budget,
             )),
             shared: Shared::default(),
         }));
@@ -266,6 +274,8 @@ impl Future for ConnectionDriver {
         keep_going |= conn.drive_timer(cx);
         conn.forward_endpoint_events();
         conn.forward_app_events(&self.0.shared);
+        #[cfg(feature = "metrics")]
+        conn.report_metrics();
 
         if !conn.inner.is_drained() {
             if keep_going {
@@ -331,7 +341,32 @@ impl Connection {
         }
     }
 
+    /// Number of unidirectional streams that can currently be opened without waiting
+    ///
+    /// Returns 0 when [`open_uni()`](Self::open_uni) would have to wait for the peer to allow more
+    /// streams, or the connection is closed. This is a snapshot: the peer grants more streams
+    /// over time, and other tasks may open streams concurrently.
+    pub fn available_uni_streams(&self) -> u64 {
+        let mut conn = self.0.state.lock("available_uni_streams");
+        conn.inner.streams().available(Dir::Uni)
+    }
+
+    /// Number of bidirectional streams that can currently be opened without waiting
+    ///
+    /// Returns 0 when [`open_bi()`](Self::open_bi) would have to wait for the peer to allow more
+    /// streams, or the connection is closed. This is a snapshot: the peer grants more streams
+    /// over time, and other tasks may open streams concurrently.
+    pub fn available_bidi_streams(&self) -> u64 {
+        let mut conn = self.0.state.lock("available_bidi_streams");
+        conn.inner.streams().available(Dir::Bi)
+    }
+
     /// Accept the next incoming uni-directional stream
+    ///
+    /// If [`TransportConfig::max_unhandled_streams`](crate::TransportConfig::max_unhandled_streams)
+    /// is set, accepted streams count against it until their [`RecvStream`] is
+    /// dropped, applying backpressure to the peer when they aren't processed quickly enough. The
+    /// same applies to [`accept_bi()`](Self::accept_bi).
     pub fn accept_uni(&self) -> AcceptUni<'_> {
         AcceptUni {
             conn: &self.0,
@@ -364,8 +399,77 @@ impl Connection {
         }
     }
 
+    /// Poll for an application datagram
+    ///
+    /// Like [`read_datagram()`](Self::read_datagram), for use where a future can't be stored. If
+    /// no datagram is buffered, `cx`'s waker is registered to be woken as soon as one arrives or
+    /// the connection is lost. Only the waker of the most recent call is retained.
+    pub fn poll_read_datagram(&self, cx: &mut Context<'_>) -> Poll<Result<Bytes, ConnectionError>> {
+        let mut state = self.0.state.lock("poll_read_datagram");
+        // Drain buffered datagrams before reporting errors, like `ReadDatagram`
+        if let Some(x) = state.inner.datagrams().recv() {
+            return Poll::Ready(Ok(x));
+        } else if let Some(ref e) = state.error {
+            return Poll::Ready(Err(e.clone()));
+        }
+        state.datagram_reader = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Discard received application datagrams until
+    /// [`resume_datagram_recv()`](Self::resume_datagram_recv) is called
+    ///
+    /// For applications that temporarily can't keep up with incoming datagrams. Datagrams are
+    /// inherently unreliable, so this never blocks the peer; instead, datagrams arriving while
+    /// paused are dropped and counted in
+    /// [`ConnectionStats::datagram_drops`](proto::ConnectionStats::datagram_drops), and with
+    /// [`DatagramBackpressure::SignalCongestion`] the peer is asked to slow down. See
+    /// [`proto::Datagrams::pause_recv`].
+    pub fn pause_datagram_recv(&self, backpressure: DatagramBackpressure) {
+        self.0
+            .state
+            .lock("pause_datagram_recv")
+            .inner
+            .datagrams()
+            .pause_recv(backpressure);
+    }
+
+    /// Resume receiving application datagrams after
+    /// [`pause_datagram_recv()`](Self::pause_datagram_recv)
+    pub fn resume_datagram_recv(&self) {
+        self.0
+            .state
+            .lock("resume_datagram_recv")
+            .inner
+            .datagrams()
+            .resume_recv();
+    }
+
+    /// Receive an application datagram sent with
+    /// [`send_datagram_on_flow()`](Self::send_datagram_on_flow)
+    ///
+    /// Yields the flow identifier the datagram was tagged with, and the datagram with the
+    /// identifier removed. Datagrams which don't start with a valid identifier are discarded.
+    ///
+    /// Flow identifiers are an application-level convention, not a QUIC extension, so both peers
+    /// must agree to use them; mixing them with [`read_datagram()`](Self::read_datagram) on the
+    /// same connection is only meaningful if the application can tell the datagrams apart.
+    pub async fn read_datagram_flow(&self) -> Result<(VarInt, Bytes), ConnectionError> {
+        loop {
+            let mut data = self.read_datagram().await?;
+            match VarInt::decode(&mut data) {
+                Ok(flow_id) => return Ok((flow_id, data)),
+                Err(_) => tracing::trace!("discarding datagram without a flow identifier"),
+            }
+        }
+    }
+
     /// Wait for the connection to be closed for any reason
     ///
+    /// Resolves immediately if the connection is already closed. Any number of tasks may wait
+    /// concurrently, each through its own clone of the `Connection`; all of them are woken on
+    /// closure and observe the same reason.
+    ///
     /// Despite the return type's name, closed connections are often not an error condition at the
     /// application layer. Cases that might be routine include [`ConnectionError::LocallyClosed`]
     /// and [`ConnectionError::ApplicationClosed`].
@@ -433,6 +537,23 @@ impl Connection {
         conn.close(error_code, Bytes::copy_from_slice(reason), &self.0.shared);
     }
 
+    /// Reset every open send stream and stop every open receive stream with `error_code`
+    ///
+    /// Streams opened concurrently are either affected or not, depending on whether they were
+    /// opened before this call. Pending reads and writes on affected streams fail with
+    /// [`ReadError::ClosedStream`](crate::ReadError::ClosedStream) or
+    /// [`WriteError::ClosedStream`](crate::WriteError::ClosedStream).
+    ///
+    /// Call this before [`close()`](Self::close), and allow the connection time to transmit, for
+    /// the peer to observe the resets; see [`proto::Connection::reset_all_streams`].
+    pub fn reset_all_streams(&self, error_code: VarInt) {
+        let mut conn = self.0.state.lock("reset_all_streams");
+        conn.inner.reset_all_streams(error_code);
+        wake_all(&mut conn.blocked_writers);
+        wake_all(&mut conn.blocked_readers);
+        conn.wake();
+    }
+
     /// Wait for the handshake to be confirmed.
     ///
     /// As a server, who must be authenticated by clients,
@@ -465,6 +586,121 @@ impl Connection {
         }
     }
 
+    /// Wait until every stream's sent data has been acknowledged by the peer
+    ///
+    /// Resolves once no stream has a send half that may still carry unacknowledged data: each
+    /// locally opened stream, and each bidirectional stream accepted from the peer, must have
+    /// been either finished with all of its data acknowledged, or reset with the reset
+    /// acknowledged. Resolves immediately if no such streams exist.
+    ///
+    /// The condition is re-evaluated on every wakeup, so streams opened before the returned
+    /// future completes delay it until they too are done; a stream that is opened but never
+    /// finished or reset keeps it pending indefinitely. Once resolved, the future does not
+    /// re-arm: call this method again to wait for streams opened afterwards.
+    pub async fn all_streams_finished(&self) -> Result<(), ConnectionError> {
+        loop {
+            {
+                let mut conn = self.0.state.lock("all_streams_finished");
+                if let Some(error) = conn.error.as_ref() {
+                    return Err(error.clone());
+                }
+                if conn.inner.streams().send_streams() == 0 {
+                    return Ok(());
+                }
+                // Construct the future while the lock is held to ensure we can't miss a wakeup
+                // if the `Notify` is signaled immediately after we release the lock. `await` it
+                // after the lock guard is out of scope.
+                self.0.shared.streams_finished.notified()
+            }
+            .await;
+        }
+    }
+
+    /// Finish every open stream, wait for the peer to acknowledge all sent data, then close
+    ///
+    /// Suited to the end of a request/response exchange: a final response written before calling
+    /// this is acknowledged by the peer's QUIC stack before CONNECTION_CLOSE is sent, so the peer
+    /// can still read it from the stream even after observing the close. Streams stopped by the
+    /// peer are reset instead of finished. Closes with `error_code` and `reason` as in
+    /// [`close()`](Self::close) once [`all_streams_finished()`](Self::all_streams_finished)
+    /// resolves.
+    ///
+    /// There is no timeout beyond the connection's own: if the peer becomes unreachable, this
+    /// fails once the idle timeout expires, but a peer which stays connected without reading the
+    /// data it's sent can stall it indefinitely due to flow control. Wrap the future in a timeout
+    /// and call [`close()`](Self::close) if it expires to bound the wait. Dropping the future
+    /// before it completes leaves the connection open, with its streams finished.
+    ///
+    /// Fails if the connection was lost before all data was acknowledged.
+    pub async fn finish_and_close(
+        &self,
+        error_code: VarInt,
+        reason: &[u8],
+    ) -> Result<(), ConnectionError> {
+        {
+            let mut conn = self.0.state.lock("finish_and_close");
+            if let Some(error) = conn.error.as_ref() {
+                return Err(error.clone());
+            }
+            conn.inner.finish_all_streams();
+            conn.wake();
+        }
+        self.all_streams_finished().await?;
+        self.close(error_code, reason);
+        Ok(())
+    }
+
+    /// Send `payload` on a new bidirectional stream and read the peer's response
+    ///
+    /// Equivalent to [`request_with()`](Self::request_with) with the default [`RequestConfig`].
+    pub async fn request(&self, payload: Bytes) -> Result<Bytes, RequestError> {
+        self.request_with(payload, &RequestConfig::default()).await
+    }
+
+    /// Send `payload` on a new bidirectional stream and read the peer's response
+    ///
+    /// Opens a stream, writes `payload` and finishes the stream, then reads until the peer
+    /// finishes its side, returning everything it wrote. The peer is expected to handle each
+    /// stream it accepts as one request, writing its response and then finishing the stream.
+    ///
+    /// This is opinionated sugar for the simplest request/response protocols: the request is
+    /// buffered in full, the response is collected in memory up to
+    /// [`RequestConfig::max_response_size`], and there's no framing or way to signal errors
+    /// besides resetting the stream. Protocols needing more control should use
+    /// [`open_bi()`](Self::open_bi) directly.
+    ///
+    /// If the request fails or the future is dropped, any stream still open is reset or stopped
+    /// with error code 0.
+    pub async fn request_with(
+        &self,
+        payload: Bytes,
+        config: &RequestConfig,
+    ) -> Result<Bytes, RequestError> {
+        let exchange = async {
+            let (send, mut recv) = self.open_bi().await?;
+            request::write_request(send, payload).await?;
+            let response = recv.read_to_end(config.max_response_size).await?;
+            Ok(Bytes::from(response))
+        };
+        let Some(timeout) = config.timeout else {
+            return exchange.await;
+        };
+
+        let mut timer = {
+            let state = self.0.state.lock("request");
+            state.runtime.new_timer(state.runtime.now() + timeout)
+        };
+        let mut exchange = std::pin::pin!(exchange);
+        std::future::poll_fn(|cx| {
+            if let Poll::Ready(result) = exchange.as_mut().poll(cx) {
+                return Poll::Ready(result);
+            }
+            ready!(timer.as_mut().poll(cx));
+            Poll::Ready(Err(RequestError::TimedOut))
+        })
+        .await
+    }
+
     /// Transmit `data` as an unreliable, unordered application datagram
     ///
     /// Application datagrams are a low-level primitive. They may be lost or delivered out of order,
@@ -493,6 +729,71 @@ impl Connection {
         }
     }
 
+    /// Transmit `data` as an application datagram tagged with `flow_id`
+    ///
+    /// Prefixes `data` with `flow_id` encoded as a QUIC variable-length integer, allowing the peer
+    /// to demultiplex datagrams belonging to different logical flows with
+    /// [`read_datagram_flow()`](Self::read_datagram_flow). Both peers must agree to use this
+    /// convention. The prefix counts towards the datagram's size; see
+    /// [`max_datagram_size_on_flow()`](Self::max_datagram_size_on_flow).
+    ///
+    /// Otherwise behaves like [`send_datagram()`](Self::send_datagram).
+    pub fn send_datagram_on_flow(
+        &self,
+        flow_id: VarInt,
+        data: Bytes,
+    ) -> Result<(), SendDatagramError> {
+        let mut buf = BytesMut::with_capacity(flow_id.size() + data.len());
+        flow_id.encode(&mut buf);
+        buf.put(data);
+        self.send_datagram(buf.freeze())
+    }
+
+    /// Transmit a batch of unreliable, unordered application datagrams
+    ///
+    /// Queues datagrams from the front of `data` for as long as they fit in the outgoing datagram
+    /// buffer (see [`datagram_send_buffer_space()`]), removing them from `data` and returning how
+    /// many were queued. Unlike [`send_datagram()`], previously queued datagrams are never
+    /// discarded; those left in `data` can be retried once buffer space frees up, e.g. after
+    /// [`send_datagram_wait()`] completes. This amortizes locking and wake-ups over the batch.
+    ///
+    /// Queuing also stops at the first datagram that's too large to send, leaving it at the front
+    /// of `data`. Errors are only returned if no datagram was queued, so an oversized datagram
+    /// following others is reported as [`SendDatagramError::TooLarge`] by the next call.
+    ///
+    /// [`datagram_send_buffer_space()`]: Connection::datagram_send_buffer_space
+    /// [`send_datagram()`]: Connection::send_datagram
+    /// [`send_datagram_wait()`]: Connection::send_datagram_wait
+    pub fn send_datagrams(&self, data: &mut Vec<Bytes>) -> Result<usize, SendDatagramError> {
+        let conn = &mut *self.0.state.lock("send_datagrams");
+        if let Some(ref x) = conn.error {
+            return Err(SendDatagramError::ConnectionLost(x.clone()));
+        }
+        use proto::SendDatagramError::*;
+        let mut datagrams = conn.inner.datagrams();
+        let mut queued = 0;
+        let result = loop {
+            let Some(datagram) = data.get(queued) else {
+                break Ok(());
+            };
+            match datagrams.send(datagram.clone(), false) {
+                Ok(()) => queued += 1,
+                Err(e) => break Err(e),
+            }
+        };
+        data.drain(..queued);
+        if queued > 0 {
+            conn.wake();
+            return Ok(queued);
+        }
+        match result {
+            Ok(()) | Err(Blocked(..)) => Ok(0),
+            Err(UnsupportedByPeer) => Err(SendDatagramError::UnsupportedByPeer),
+            Err(Disabled) => Err(SendDatagramError::Disabled),
+            Err(TooLarge) => Err(SendDatagramError::TooLarge),
+        }
+    }
+
     /// Transmit `data` as an unreliable, unordered application datagram
     ///
     /// Unlike [`send_datagram()`], this method will wait for buffer space during congestion
@@ -529,6 +830,16 @@ impl Connection {
             .max_size()
     }
 
+    /// Compute the maximum size of datagrams that may be passed to
+    /// [`send_datagram_on_flow()`](Self::send_datagram_on_flow) with `flow_id`
+    ///
+    /// Equal to [`max_datagram_size()`](Self::max_datagram_size) less the size of the encoded
+    /// flow identifier, which is between 1 and 8 bytes depending on its value.
+    pub fn max_datagram_size_on_flow(&self, flow_id: VarInt) -> Option<usize> {
+        self.max_datagram_size()
+            .map(|size| size.saturating_sub(flow_id.size()))
+    }
+
     /// Bytes available in the outgoing datagram buffer
     ///
     /// When greater than zero, calling [`send_datagram()`](Self::send_datagram) with a datagram of
@@ -543,10 +854,35 @@ impl Connection {
     }
 
     /// The side of the connection (client or server)
+    ///
+    /// [`Side::Client`] if the connection was initiated by [`Endpoint::connect`], and
+    /// [`Side::Server`] if it was accepted from an [`Incoming`]. Fixed for the lifetime of the
+    /// connection, so code shared by both directions can use it to tell them apart.
+    ///
+    /// [`Endpoint::connect`]: crate::Endpoint::connect
+    /// [`Incoming`]: crate::Incoming
     pub fn side(&self) -> Side {
         self.0.state.lock("side").inner.side()
     }
 
+    /// The QUIC version in use, e.g. `1` for QUIC v1
+    ///
+    /// See [`proto::Connection::version`] for the meaning of other values.
+    pub fn version(&self) -> u32 {
+        self.0.state.lock("version").inner.version()
+    }
+
+    /// Whether the peer's address was validated by a token before the handshake began
+    ///
+    /// See [`proto::Connection::address_prevalidated`]. Always `false` for clients.
+    pub fn address_prevalidated(&self) -> bool {
+        self.0
+            .state
+            .lock("address_prevalidated")
+            .inner
+            .address_prevalidated()
+    }
+
     /// The peer's UDP address
     ///
     /// If `ServerConfig::migration` is `true`, clients may change addresses at will, e.g. when
@@ -555,6 +891,19 @@ impl Connection {
         self.0.state.lock("remote_address").inner.remote_address()
     }
 
+    /// Transport parameters advertised by the peer during the handshake
+    ///
+    /// `None` until they have been received. These are the peer's values rather than the
+    /// effective ones negotiated from both sides; see
+    /// [`proto::Connection::peer_transport_parameters`] for details.
+    pub fn peer_transport_parameters(&self) -> Option<TransportParameters> {
+        self.0
+            .state
+            .lock("peer_transport_parameters")
+            .inner
+            .peer_transport_parameters()
+    }
+
     /// The local IP address which was used when the peer established
     /// the connection
     ///
@@ -568,16 +917,200 @@ impl Connection {
         self.0.state.lock("local_ip").inner.local_ip()
     }
 
+    /// Move this connection's I/O to a dedicated UDP socket
+    ///
+    /// Unlike [`Endpoint::rebind_abstract`](crate::Endpoint::rebind_abstract), which moves every
+    /// connection of an endpoint, this affects only this connection. Its packets are sent from
+    /// `socket` from now on, including after any later rebind of the endpoint, so it keeps a
+    /// stable local port, e.g. for the sake of NAT bindings. Incoming packets are accepted on both
+    /// `socket` and the endpoint's socket. `socket` should not be used for anything else, and
+    /// should have the same address family as the endpoint's socket.
+    ///
+    /// This is an active migration: the peer sees packets arriving from a new address, and
+    /// validates the new path before trusting it, so sending is briefly limited by the peer's
+    /// anti-amplification limit and congestion control may restart from its initial window.
+    /// Packets in flight to a previously set dedicated socket are lost and retransmitted. The
+    /// migration fails, eventually timing out the connection, if the peer disallows it, e.g. with
+    /// [`ServerConfig::migration`](crate::ServerConfig::migration).
+    ///
+    /// Only clients may migrate, so this fails with [`io::ErrorKind::Unsupported`] on the server
+    /// side of a connection.
+    pub fn set_local_socket(&self, socket: Box<dyn AsyncUdpSocket>) -> io::Result<()> {
+        let mut conn = self.0.state.lock("set_local_socket");
+        if conn.inner.side().is_server() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "servers cannot migrate connections",
+            ));
+        }
+        conn.sender = socket.create_sender();
+        conn.inner.local_address_changed();
+        // If the endpoint driver is gone, the connection is lost regardless
+        let _ = conn
+            .endpoint_events
+            .send((conn.handle, EndpointEvent::SetSocket(socket)));
+        conn.wake();
+        Ok(())
+    }
+
+    /// The preferred address advertised by the server, if any
+    ///
+    /// Always `None` on servers. See [`proto::Connection::peer_preferred_address`].
+    pub fn peer_preferred_address(&self) -> Option<SocketAddr> {
+        self.0
+            .state
+            .lock("peer_preferred_address")
+            .inner
+            .peer_preferred_address()
+    }
+
+    /// Start migrating to the server's preferred address
+    ///
+    /// Returns the address being migrated to, or `None` if there's nothing to do. The new path is
+    /// validated with a PATH_CHALLENGE, and the connection falls back to its previous address if
+    /// the server doesn't answer there. To do this automatically once the handshake is confirmed,
+    /// use [`TransportConfig::migrate_to_preferred_address`]. See
+    /// [`proto::Connection::migrate_to_preferred_address`] for details.
+    ///
+    /// [`TransportConfig::migrate_to_preferred_address`]: crate::TransportConfig::migrate_to_preferred_address
+    pub fn migrate_to_preferred_address(&self) -> Option<SocketAddr> {
+        let mut conn = self.0.state.lock("migrate_to_preferred_address");
+        let now = conn.runtime.now();
+        let remote = conn.inner.migrate_to_preferred_address(now)?;
+        conn.wake();
+        Some(remote)
+    }
+
+    /// Send a PING to the peer and measure how long it takes to be acknowledged
+    ///
+    /// Unlike [`rtt()`](Self::rtt), which is a smoothed estimate, this probes the peer's liveness
+    /// right now. The PING is queued as soon as this is called, and the future resolves with the
+    /// time from then until an acknowledgement was processed, or fails if the connection is lost
+    /// first. Lost PINGs are retransmitted, so the future only stalls if the peer stops
+    /// responding entirely, in which case the idle timeout eventually closes the connection.
+    ///
+    /// The measured time includes however long the peer delayed its acknowledgement, up to its
+    /// `max_ack_delay` transport parameter, so it may exceed the network round-trip time.
+    ///
+    /// Multiple pings may be outstanding concurrently; an acknowledgement of any PING sent after
+    /// a given call answers that call, and each call measures the time until its own answer.
+    pub fn ping(&self) -> impl Future<Output = Result<Duration, ConnectionError>> + '_ {
+        let request = {
+            let mut state = self.0.state.lock("ping");
+            match state.error {
+                Some(ref error) => Err(error.clone()),
+                None => {
+                    state.inner.ping();
+                    state.pings_requested += 1;
+                    let id = state.pings_requested;
+                    state.pings_pending.insert(id);
+                    state.wake();
+                    Ok((PendingPing { conn: &self.0, id }, state.runtime.now()))
+                }
+            }
+        };
+        async move {
+            let (pending, start) = request?;
+            loop {
+                let notified = {
+                    let state = self.0.state.lock("ping");
+                    if let Some(error) = state.error.as_ref() {
+                        return Err(error.clone());
+                    }
+                    let answered = state
+                        .ping_acks
+                        .iter()
+                        .find(|&&(count, _)| count >= pending.id);
+                    if let Some(&(_, at)) = answered {
+                        return Ok(at.saturating_duration_since(start));
+                    }
+                    // Construct the future while the lock is held to ensure we can't miss a
+                    // wakeup if the `Notify` is signaled immediately after we release the lock
+                    self.0.shared.ping_acknowledged.notified()
+                };
+                notified.await;
+            }
+        }
+    }
+
+    /// Ask the peer to acknowledge the next packet without delay
+    ///
+    /// Useful after sending a small response, so the peer's ACK promptly opens up the congestion
+    /// window. Requires the peer to support the acknowledgement frequency extension for the ACK to
+    /// be truly immediate; otherwise a PING is sent instead. See
+    /// [`proto::Connection::request_immediate_ack`].
+    pub fn request_immediate_ack(&self) {
+        let mut state = self.0.state.lock("request_immediate_ack");
+        state.inner.request_immediate_ack();
+        state.wake();
+    }
+
     /// Current best estimate of this connection's latency (round-trip-time)
     pub fn rtt(&self) -> Duration {
         self.0.state.lock("rtt").inner.rtt()
     }
 
+    /// Bytes of packets that have been sent but not yet acknowledged or declared lost
+    ///
+    /// An instantaneous gauge, cheaper to poll than [`stats()`](Self::stats). See
+    /// [`proto::Connection::bytes_in_flight`].
+    pub fn bytes_in_flight(&self) -> u64 {
+        self.0.state.lock("bytes_in_flight").inner.bytes_in_flight()
+    }
+
+    /// Bytes of stream data deemed lost and waiting to be retransmitted
+    ///
+    /// An instantaneous gauge, cheaper to poll than [`stats()`](Self::stats). See
+    /// [`proto::Connection::bytes_pending_retransmit`].
+    pub fn bytes_pending_retransmit(&self) -> u64 {
+        self.0
+            .state
+            .lock("bytes_pending_retransmit")
+            .inner
+            .bytes_pending_retransmit()
+    }
+
+    /// Largest UDP payload this connection may ever send on the current path
+    ///
+    /// The smaller of the peer's advertised `max_udp_payload_size` and the largest size local
+    /// configuration permits. The MTU actually in use, as reported in
+    /// [`PathStats::current_mtu`](proto::PathStats::current_mtu), never exceeds this.
+    pub fn max_mtu(&self) -> u16 {
+        self.0.state.lock("max_mtu").inner.max_mtu()
+    }
+
     /// Returns connection statistics
     pub fn stats(&self) -> ConnectionStats {
         self.0.state.lock("stats").inner.stats()
     }
 
+    /// Returns connection statistics, then restarts cumulative counters from zero
+    ///
+    /// Counters such as the numbers of datagrams, frames and lost packets restart from zero,
+    /// while values describing the connection's current state, such as the RTT and congestion
+    /// window, are preserved. See [`proto::Connection::reset_stats`] for details. The statistics
+    /// are returned as they were immediately before the reset, atomically with respect to the
+    /// connection's own updates, so no counts are lost between successive calls.
+    pub fn reset_stats(&self) -> ConnectionStats {
+        let mut conn = self.0.state.lock("reset_stats");
+        let stats = conn.inner.reset_stats();
+        #[cfg(feature = "metrics")]
+        {
+            let now = conn.runtime.now();
+            conn.metrics.on_stats_reset(stats, now);
+        }
+        stats
+    }
+
+    /// Returns a snapshot of the connection's internal state for debugging
+    ///
+    /// Cheap enough to call on error paths. See [`ConnectionDebug`] for caveats.
+    pub fn debug_snapshot(&self) -> ConnectionDebug {
+        let mut state = self.0.state.lock("debug_snapshot");
+        let now = state.runtime.now();
+        state.inner.debug_snapshot(now)
+    }
+
     /// Current state of the congestion control algorithm, for debugging purposes
     pub fn congestion_state(&self) -> Box<dyn Controller> {
         self.0
@@ -604,6 +1137,20 @@ impl Connection {
             .handshake_data()
     }
 
+    /// Application protocol negotiated via ALPN
+    ///
+    /// Lets servers that offer several protocols tell which one this connection uses. Available
+    /// under the same conditions as [`handshake_data()`](Self::handshake_data). Returns `None` if
+    /// no protocol was negotiated, e.g. because neither side configured any.
+    pub fn alpn(&self) -> Option<Vec<u8>> {
+        self.0
+            .state
+            .lock("alpn")
+            .inner
+            .crypto_session()
+            .alpn_protocol()
+    }
+
     /// Cryptographic identity of the peer
     ///
     /// The dynamic type returned is determined by the configured
@@ -662,7 +1209,8 @@ impl Connection {
     /// Modify the number of remotely initiated unidirectional streams that may be concurrently open
     ///
     /// No streams may be opened by the peer unless fewer than `count` are already open. Large
-    /// `count`s increase both minimum and worst-case memory consumption.
+    /// `count`s increase both minimum and worst-case memory consumption. Lowering the limit only
+    /// restricts streams opened in the future; see [`proto::Connection::set_max_concurrent_streams`].
     pub fn set_max_concurrent_uni_streams(&self, count: VarInt) {
         let mut conn = self.0.state.lock("set_max_concurrent_uni_streams");
         conn.inner.set_max_concurrent_streams(Dir::Uni, count);
@@ -684,10 +1232,22 @@ impl Connection {
         conn.wake();
     }
 
+    /// Whether to batch multiple datagrams into a single system call using GSO
+    ///
+    /// Overrides the socket's segmentation offload capability for this connection only, without
+    /// affecting other connections on the endpoint. Disabling it caps every transmit to a single
+    /// datagram, which may help if the path mishandles large coalesced sends. See
+    /// [`proto::Connection::set_segmentation_enabled`].
+    pub fn set_segmentation_enabled(&self, enabled: bool) {
+        let mut conn = self.0.state.lock("set_segmentation_enabled");
+        conn.inner.set_segmentation_enabled(enabled);
+    }
+
     /// Modify the number of remotely initiated bidirectional streams that may be concurrently open
     ///
     /// No streams may be opened by the peer unless fewer than `count` are already open. Large
-    /// `count`s increase both minimum and worst-case memory consumption.
+    /// `count`s increase both minimum and worst-case memory consumption. Lowering the limit only
+    /// restricts streams opened in the future; see [`proto::Connection::set_max_concurrent_streams`].
     pub fn set_max_concurrent_bi_streams(&self, count: VarInt) {
         let mut conn = self.0.state.lock("set_max_concurrent_bi_streams");
         conn.inner.set_max_concurrent_streams(Dir::Bi, count);
@@ -960,6 +1520,27 @@ pub(crate) struct ConnectionInner {
     pub(crate) shared: Shared,
 }
 
+/// A call to [`Connection::ping`] awaiting its answer
+struct PendingPing<'a> {
+    conn: &'a ConnectionRef,
+    id: u64,
+}
+
+impl Drop for PendingPing<'_> {
+    fn drop(&mut self) {
+        let mut state = self.conn.state.lock("ping");
+        state.pings_pending.remove(&self.id);
+        // Each pending call is answered by the first recorded value at least as large as its id
+        let oldest = state.pings_pending.first().copied().unwrap_or(u64::MAX);
+        while let Some(&(count, _)) = state.ping_acks.front() {
+            if count >= oldest {
+                break;
+            }
+            state.ping_acks.pop_front();
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct Shared {
     handshake_confirmed: Notify,
@@ -970,6 +1551,9 @@ pub(crate) struct Shared {
     stream_incoming: [Notify; 2],
     datagram_received: Notify,
     datagrams_unblocked: Notify,
+    ping_acknowledged: Notify,
+    /// Notified when no stream may have unacknowledged data
+    streams_finished: Notify,
     closed: Notify,
     /// Number of live handles that can used to initiate or handle I/O; excludes the driver
     ref_count: AtomicUsize,
@@ -983,12 +1567,22 @@ pub(crate) struct State {
     on_connected: Option<oneshot::Sender<bool>>,
     connected: bool,
     handshake_confirmed: bool,
+    /// Number of calls to `Connection::ping`
+    pings_requested: u64,
+    /// Number of calls to `Connection::ping` answered by the peer
+    pings_acknowledged: u64,
+    /// Calls to `Connection::ping` whose futures are still alive
+    pings_pending: BTreeSet<u64>,
+    /// Values `pings_acknowledged` took on while pending calls awaited them, and when
+    ping_acks: VecDeque<(u64, Instant)>,
     timer: Option<Pin<Box<dyn AsyncTimer>>>,
     timer_deadline: Option<Instant>,
     conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
     endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
     pub(crate) blocked_writers: FxHashMap<StreamId, Waker>,
     pub(crate) blocked_readers: FxHashMap<StreamId, Waker>,
+    /// Registered by [`Connection::poll_read_datagram`]
+    datagram_reader: Option<Waker>,
     pub(crate) stopped: FxHashMap<StreamId, Arc<Notify>>,
     /// Always set to Some before the connection becomes drained
     pub(crate) error: Option<ConnectionError>,
@@ -997,6 +1591,14 @@ pub(crate) struct State {
     send_buffer: Vec<u8>,
     /// We buffer a transmit when the underlying I/O would block
     buffered_transmit: Option<proto::Transmit>,
+    /// Endpoint-wide limit on the sending rate, if any
+    send_budget: Option<Arc<SendBudget>>,
+    /// Expires when the endpoint's send budget permits sending `buffered_transmit`
+    budget_timer: Option<Pin<Box<dyn AsyncTimer>>>,
+    /// Signaled once the connection is closed and CONNECTION_CLOSE has been handed to the socket
+    close_sent: Vec<oneshot::Sender<()>>,
+    #[cfg(feature = "metrics")]
+    metrics: ConnectionMetrics,
 }
 
 impl State {
@@ -1010,8 +1612,12 @@ impl State {
         on_connected: oneshot::Sender<bool>,
         sender: Pin<Box<dyn UdpSender>>,
         runtime: Arc<dyn Runtime>,
+        send_budget: Option<Arc<SendBudget>>,
     ) -> Self {
         Self {
+            // Evaluated before `inner` and `runtime` are moved
+            #[cfg(feature = "metrics")]
+            metrics: ConnectionMetrics::new(inner.side(), runtime.now()),
             inner,
             driver: None,
             handle,
@@ -1019,18 +1625,26 @@ impl State {
             on_connected: Some(on_connected),
             connected: false,
             handshake_confirmed: false,
+            pings_requested: 0,
+            pings_acknowledged: 0,
+            pings_pending: BTreeSet::new(),
+            ping_acks: VecDeque::new(),
             timer: None,
             timer_deadline: None,
             conn_events,
             endpoint_events,
             blocked_writers: FxHashMap::default(),
             blocked_readers: FxHashMap::default(),
+            datagram_reader: None,
             stopped: FxHashMap::default(),
             error: None,
             sender,
             runtime,
             send_buffer: Vec::new(),
             buffered_transmit: None,
+            send_budget,
+            budget_timer: None,
+            close_sent: Vec::new(),
         }
     }
 
@@ -1059,13 +1673,34 @@ impl State {
                                 None => 1,
                                 Some(s) => t.size.div_ceil(s), // round up
                             };
+                            if let Some(budget) = &self.send_budget {
+                                let send_at = budget.reserve(now, t.size);
+                                if send_at > now {
+                                    self.budget_timer = Some(self.runtime.new_timer(send_at));
+                                }
+                            }
                             t
                         }
-                        None => break,
+                        None => {
+                            if self.inner.is_closed() {
+                                for tx in self.close_sent.drain(..) {
+                                    let _ = tx.send(());
+                                }
+                            }
+                            break;
+                        }
                     }
                 }
             };
 
+            if let Some(timer) = &mut self.budget_timer {
+                if timer.as_mut().poll(cx).is_pending() {
+                    self.buffered_transmit = Some(t);
+                    return Ok(false);
+                }
+                self.budget_timer = None;
+            }
+
             let len = t.size;
             match self
                 .sender
@@ -1095,7 +1730,9 @@ impl State {
     fn forward_endpoint_events(&mut self) {
         while let Some(event) = self.inner.poll_endpoint_events() {
             // If the endpoint driver is gone, noop.
-            let _ = self.endpoint_events.send((self.handle, event));
+            let _ = self
+                .endpoint_events
+                .send((self.handle, EndpointEvent::Proto(event)));
         }
     }
 
@@ -1117,6 +1754,9 @@ impl State {
                 Poll::Ready(Some(ConnectionEvent::Close { reason, error_code })) => {
                     self.close(error_code, reason, shared);
                 }
+                Poll::Ready(Some(ConnectionEvent::CloseSent(tx))) => {
+                    self.close_sent.push(tx);
+                }
                 Poll::Ready(None) => {
                     if self.error.is_some() {
                         // Already closing (e.g. from a preceding Close event);
@@ -1146,6 +1786,8 @@ impl State {
                 }
                 Connected => {
                     self.connected = true;
+                    #[cfg(feature = "metrics")]
+                    self.metrics.on_connected();
                     if let Some(x) = self.on_connected.take() {
                         // We don't care if the on-connected future was dropped
                         let _ = x.send(self.inner.accepted_0rtt());
@@ -1174,6 +1816,9 @@ impl State {
                 }
                 DatagramReceived => {
                     shared.datagram_received.notify_waiters();
+                    if let Some(waker) = self.datagram_reader.take() {
+                        waker.wake();
+                    }
                 }
                 DatagramsUnblocked => {
                     shared.datagrams_unblocked.notify_waiters();
@@ -1190,6 +1835,25 @@ impl State {
                 }
             }
         }
+        // Send streams are also released without an event when a reset is acknowledged, so check
+        // the count directly rather than reacting to `Finished`
+        if self.inner.streams().send_streams() == 0 {
+            shared.streams_finished.notify_waiters();
+        }
+        let answered = self.inner.pings_acknowledged();
+        if answered > self.pings_acknowledged {
+            let previous = self.pings_acknowledged;
+            self.pings_acknowledged = answered;
+            if self
+                .pings_pending
+                .range(previous + 1..=answered)
+                .next()
+                .is_some()
+            {
+                self.ping_acks.push_back((answered, self.runtime.now()));
+                shared.ping_acknowledged.notify_waiters();
+            }
+        }
     }
 
     fn drive_timer(&mut self, cx: &mut Context<'_>) -> bool {
@@ -1242,6 +1906,19 @@ impl State {
         true
     }
 
+    /// Update the connection's metrics, if due
+    #[cfg(feature = "metrics")]
+    fn report_metrics(&mut self) {
+        let now = self.runtime.now();
+        let drained = self.inner.is_drained();
+        if drained || self.metrics.update_due(now) {
+            self.metrics.update(self.inner.stats(), now);
+        }
+        if drained {
+            self.metrics.on_drained();
+        }
+    }
+
     /// Wake up a blocked `Driver` task to process I/O
     pub(crate) fn wake(&mut self) {
         if let Some(x) = self.driver.take() {
@@ -1251,6 +1928,10 @@ impl State {
 
     /// Used to wake up all blocked futures when the connection becomes closed for any reason
     fn terminate(&mut self, reason: ConnectionError, shared: &Shared) {
+        #[cfg(feature = "metrics")]
+        if self.error.is_none() {
+            self.metrics.on_lost(&reason, self.connected);
+        }
         self.error = Some(reason.clone());
         if let Some(x) = self.on_handshake_data.take() {
             let _ = x.send(());
@@ -1262,7 +1943,12 @@ impl State {
         shared.stream_incoming[Dir::Uni as usize].notify_waiters();
         shared.stream_incoming[Dir::Bi as usize].notify_waiters();
         shared.datagram_received.notify_waiters();
+        if let Some(waker) = self.datagram_reader.take() {
+            waker.wake();
+        }
         shared.datagrams_unblocked.notify_waiters();
+        shared.ping_acknowledged.notify_waiters();
+        shared.streams_finished.notify_waiters();
         if let Some(x) = self.on_connected.take() {
             let _ = x.send(false);
         }
@@ -1298,9 +1984,10 @@ impl Drop for State {
     fn drop(&mut self) {
         if !self.inner.is_drained() {
             // Ensure the endpoint can tidy up
-            let _ = self
-                .endpoint_events
-                .send((self.handle, proto::EndpointEvent::drained()));
+            let _ = self.endpoint_events.send((
+                self.handle,
+                EndpointEvent::Proto(proto::EndpointEvent::drained()),
+            ));
         }
     }
 }
@@ -5,6 +5,7 @@ use std::{
     io::{self, IoSliceMut},
     mem,
     net::{SocketAddr, SocketAddrV6},
+    ops::DerefMut,
     pin::Pin,
     str,
     sync::{
@@ -21,15 +22,15 @@ use std::{
 ))]
 use crate::runtime::default_runtime;
 use crate::{
-    Instant,
+    Connection, Duration, Instant, RecvStream, SendStream,
     runtime::{AsyncUdpSocket, Runtime, UdpSender},
     udp_transmit,
 };
 use bytes::{Bytes, BytesMut};
 use pin_project_lite::pin_project;
 use proto::{
-    self as proto, ClientConfig, ConnectError, ConnectionError, ConnectionHandle, DatagramEvent,
-    EndpointEvent, ServerConfig,
+    self as proto, ClientConfig, ConnectError, ConnectionError, ConnectionHandle, ConnectionId,
+    DatagramEvent, ServerConfig,
 };
 use rustc_hash::FxHashMap;
 #[cfg(all(
@@ -38,13 +39,13 @@ use rustc_hash::FxHashMap;
     any(feature = "aws-lc-rs", feature = "ring"),
 ))]
 use socket2::{Domain, Protocol, Socket, Type};
-use tokio::sync::{Notify, futures::Notified, mpsc};
+use tokio::sync::{Notify, futures::Notified, mpsc, oneshot};
 use tracing::{Instrument, Span};
 use udp::{BATCH_SIZE, RecvMeta};
 
 use crate::{
-    ConnectionEvent, EndpointConfig, IO_LOOP_BOUND, RECV_TIME_BOUND, VarInt,
-    connection::Connecting, incoming::Incoming, work_limiter::WorkLimiter,
+    ConnectionEvent, EndpointConfig, EndpointEvent, IO_LOOP_BOUND, RECV_TIME_BOUND, VarInt,
+    connection::Connecting, incoming::Incoming, send_budget::SendBudget, work_limiter::WorkLimiter,
 };
 
 /// A QUIC endpoint.
@@ -113,13 +114,52 @@ impl Endpoint {
         )
     }
 
+    /// Helper to construct a client endpoint that trusts the platform's native root certificates
+    ///
+    /// Binds `addr` like [`Endpoint::client`], and sets a default client configuration that
+    /// verifies servers against the root certificates found in the operating system's certificate
+    /// store, using the default [`TransportConfig`](crate::TransportConfig).
+    ///
+    /// `alpn_protocols` are offered to servers in order of preference. Many servers refuse
+    /// connections that don't negotiate an application protocol, e.g. HTTP/3 servers expect
+    /// `b"h3"`.
+    ///
+    /// Requires the `native-certs` feature. Fails with [`io::ErrorKind::NotFound`] if no usable
+    /// root certificates could be loaded.
+    #[cfg(all(
+        not(wasm_browser),
+        any(feature = "runtime-tokio", feature = "runtime-smol"),
+        feature = "native-certs",
+        any(feature = "rustls-aws-lc-rs", feature = "rustls-ring"),
+    ))]
+    pub fn client_with_native_roots(
+        addr: SocketAddr,
+        alpn_protocols: Vec<Vec<u8>>,
+    ) -> io::Result<Self> {
+        let config = native_roots_client_config(native_root_store()?, alpn_protocols)?;
+        let endpoint = Self::client(addr)?;
+        endpoint.set_default_client_config(config);
+        Ok(endpoint)
+    }
+
     /// Returns relevant stats from this Endpoint
     pub fn stats(&self) -> EndpointStats {
-        self.inner.state.lock().unwrap().stats
+        let state = self.inner.state.lock().unwrap();
+        EndpointStats {
+            handshake_rate: state.inner.handshake_rate(self.runtime.now()),
+            throttled_handshakes: state.inner.throttled_handshakes(),
+            ..state.stats
+        }
     }
 
     /// Helper to construct an endpoint for use with both incoming and outgoing connections
     ///
+    /// Outgoing connections are made with [`connect()`](Self::connect) once a client
+    /// configuration is set with [`set_default_client_config()`](Self::set_default_client_config),
+    /// and share the socket and runtime with incoming ones, so an application acting as both
+    /// client and server needs only one endpoint. Accepting connections can be stopped and resumed
+    /// with [`set_server_config()`](Self::set_server_config).
+    ///
     /// Platform defaults for dual-stack sockets vary. For example, any socket bound to a wildcard
     /// IPv6 address on Windows will not by default be able to communicate with IPv4
     /// addresses. Portable applications should bind an address that matches the family they wish to
@@ -204,7 +244,7 @@ impl Endpoint {
     /// Get the next incoming connection attempt from a client
     ///
     /// Yields [`Incoming`]s, or `None` if the endpoint is [`close`](Self::close)d. [`Incoming`]
-    /// can be `await`ed to obtain the final [`Connection`](crate::Connection), or used to e.g.
+    /// can be `await`ed to obtain the final [`Connection`], or used to e.g.
     /// filter connection attempts or force address validation, or converted into an intermediate
     /// `Connecting` future which can be used to e.g. send 0.5-RTT data.
     pub fn accept(&self) -> Accept<'_> {
@@ -219,6 +259,37 @@ impl Endpoint {
         self.inner.0.state.lock().unwrap().default_client_config = Some(config);
     }
 
+    /// Get a copy of the client configuration used by `connect`, if any
+    ///
+    /// Useful as a starting point for a per-connection configuration passed to
+    /// [`connect_with()`](Self::connect_with).
+    pub fn default_client_config(&self) -> Option<ClientConfig> {
+        self.inner
+            .0
+            .state
+            .lock()
+            .unwrap()
+            .default_client_config
+            .clone()
+    }
+
+    /// Whether [`connect()`](Self::connect) to `server_name` could currently send 0-RTT data
+    ///
+    /// Checks the default client configuration for a resumption ticket that permits early data and
+    /// hasn't expired, without consuming it. Returns `false` if there is no default client
+    /// configuration. See [`ClientConfig::can_resume_0rtt()`] for details, including which crypto
+    /// configurations support this.
+    pub fn can_resume_0rtt(&self, server_name: &str) -> bool {
+        self.inner
+            .0
+            .state
+            .lock()
+            .unwrap()
+            .default_client_config
+            .as_ref()
+            .is_some_and(|config| config.can_resume_0rtt(server_name))
+    }
+
     /// Connect to a remote endpoint
     ///
     /// `server_name` must be covered by the certificate presented by the server. This prevents a
@@ -245,6 +316,13 @@ impl Endpoint {
 
     /// Connect to a remote endpoint using a custom configuration.
     ///
+    /// `config` is used in its entirety in place of the default client configuration, including
+    /// its [`TransportConfig`](crate::TransportConfig); nothing is merged from the default. To
+    /// override only some transport parameters, such as the congestion controller, for a single
+    /// connection, start from [`default_client_config()`](Self::default_client_config) and
+    /// modify it with [`ClientConfig::transport_config_mut()`], which leaves the default and other
+    /// connections unaffected. The endpoint's [`EndpointConfig`] still applies to all connections.
+    ///
     /// See [`connect()`] for details.
     ///
     /// [`connect()`]: Endpoint::connect
@@ -279,6 +357,49 @@ impl Endpoint {
             .insert(ch, conn, sender, self.runtime.clone()))
     }
 
+    /// Connect to a remote endpoint and open the first bidirectional stream
+    ///
+    /// Combines [`connect_with()`](Self::connect_with), [`Connecting::into_0rtt()`] and
+    /// [`Connection::open_bi()`] for request/response protocols. If 0-RTT keys are available from
+    /// a previous session with the server, the returned future resolves without waiting for the
+    /// handshake, so the request can be written to the stream immediately and reaches the server
+    /// in 0-RTT packets. Otherwise, it resolves once the handshake completes, like awaiting
+    /// [`Connecting`].
+    ///
+    /// If the server rejects 0-RTT, the connection itself is still established, but operations on
+    /// the stream fail with [`WriteError::ZeroRttRejected`](crate::WriteError::ZeroRttRejected)
+    /// or [`ReadError::ZeroRttRejected`](crate::ReadError::ZeroRttRejected). The request must
+    /// then be sent again on a newly opened stream.
+    ///
+    /// Configuration errors are returned immediately, as for [`connect()`](Self::connect).
+    ///
+    /// ## Security
+    ///
+    /// 0-RTT data is vulnerable to replay attacks, so the first request should never invoke
+    /// non-idempotent operations. See [`Connecting::into_0rtt()`] for details.
+    #[allow(clippy::type_complexity)]
+    pub fn connect_bi(
+        &self,
+        config: ClientConfig,
+        addr: SocketAddr,
+        server_name: &str,
+    ) -> Result<
+        impl Future<Output = Result<(Connection, (SendStream, RecvStream)), ConnectionError>>
+        + Send
+        + 'static,
+        ConnectError,
+    > {
+        let connecting = self.connect_with(config, addr, server_name)?;
+        Ok(async move {
+            let connection = match connecting.into_0rtt() {
+                Ok((connection, _)) => connection,
+                Err(connecting) => connecting.await?,
+            };
+            let streams = connection.open_bi().await?;
+            Ok((connection, streams))
+        })
+    }
+
     /// Switch to a new UDP socket
     ///
     /// See [`Endpoint::rebind_abstract()`] for details.
@@ -289,9 +410,12 @@ impl Endpoint {
 
     /// Switch to a new UDP socket
     ///
-    /// Allows the endpoint's address to be updated live, affecting all active connections. Incoming
+    /// Allows the endpoint's address to be updated live, affecting all active connections except
+    /// those moved to a dedicated socket with [`Connection::set_local_socket`]. Incoming
     /// connections and connections to servers unreachable from the new address will be lost.
     ///
+    /// [`Connection::set_local_socket`]: crate::Connection::set_local_socket
+    ///
     /// On error, the old UDP socket is retained.
     pub fn rebind_abstract(&self, socket: Box<dyn AsyncUdpSocket>) -> io::Result<()> {
         let addr = socket.local_addr()?;
@@ -300,7 +424,10 @@ impl Endpoint {
         inner.ipv6 = addr.is_ipv6();
 
         // Update connection socket references
-        for sender in inner.recv_state.connections.senders.values() {
+        for (handle, sender) in &inner.recv_state.connections.senders {
+            if inner.connection_sockets.contains_key(handle) {
+                continue;
+            }
             // Ignoring errors from dropped connections
             let _ = sender.send(ConnectionEvent::Rebind(inner.socket.create_sender()));
         }
@@ -315,6 +442,10 @@ impl Endpoint {
     /// Replace the server configuration, affecting new incoming connections only
     ///
     /// Useful for e.g. refreshing TLS certificates without disrupting existing connections.
+    /// Connection attempts already yielded by [`accept()`](Self::accept) keep the configuration in
+    /// effect when they arrived. With `None`, the endpoint stops accepting connections and drops
+    /// new connection attempts without any response, while outgoing connections are unaffected
+    /// either way.
     pub fn set_server_config(&self, server_config: Option<ServerConfig>) {
         self.inner
             .state
@@ -329,6 +460,80 @@ impl Endpoint {
         self.inner.state.lock().unwrap().socket.local_addr()
     }
 
+    /// Process `data` as though it had been received on the endpoint's socket from `from`
+    ///
+    /// The datagram takes the same path as one read from the socket, so it may start a new
+    /// incoming connection, be routed to an existing connection, or provoke a stateless response,
+    /// which is sent to `from` through the socket. Neither an ECN codepoint nor a local destination
+    /// address is associated with it.
+    ///
+    /// For testing only: this makes it possible to feed crafted or malformed packets to an endpoint
+    /// deterministically, without a peer. Requires the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn inject_datagram(&self, from: SocketAddr, data: Bytes) {
+        let mut state = self.inner.state.lock().unwrap();
+        let state = &mut *state;
+        let now = state.runtime.now();
+        let mut meta = RecvMeta::default();
+        meta.addr = from;
+        meta.len = data.len();
+        meta.stride = data.len();
+        let recv_state = &mut state.recv_state;
+        recv_state.connections.handle_datagram(
+            &mut recv_state.incoming,
+            &mut state.inner,
+            &mut state.sender,
+            &mut Vec::new(),
+            now,
+            &meta,
+            BytesMut::from(data),
+        );
+        // Let the driver announce new incoming connections
+        if let Some(driver) = &state.driver {
+            driver.wake_by_ref();
+        }
+    }
+
+    /// Get the file descriptor of the underlying socket
+    ///
+    /// Intended for integration with tools operating on the socket directly, e.g. to attach eBPF
+    /// programs or register it with an external supervisor. Reflects the socket currently in use,
+    /// so the result changes after [`rebind()`](Self::rebind). Returns `None` if the socket
+    /// supplied through [`rebind_abstract()`](Self::rebind_abstract) or
+    /// [`new_with_abstract_socket()`](Self::new_with_abstract_socket) doesn't expose one.
+    ///
+    /// The descriptor remains owned by Quinn: it must not be closed, and it's only valid until the
+    /// endpoint is dropped or rebound. Reading from it or changing its options may interfere with
+    /// the endpoint's operation.
+    #[cfg(unix)]
+    pub fn socket_fd(&self) -> Option<std::os::fd::RawFd> {
+        self.inner.state.lock().unwrap().socket.as_raw_fd()
+    }
+
+    /// Get the handle of the underlying socket
+    ///
+    /// The Windows counterpart of `socket_fd()`, subject to the same caveats: the handle remains
+    /// owned by Quinn and must not be closed, and it's only valid until the endpoint is dropped or
+    /// rebound.
+    #[cfg(windows)]
+    pub fn socket_handle(&self) -> Option<std::os::windows::io::RawSocket> {
+        self.inner.state.lock().unwrap().socket.as_raw_socket()
+    }
+
+    /// Construct a stateless reset for the connection the peer addresses with `dst_cid`
+    ///
+    /// Sending this to the peer of a connection using `dst_cid` tears that connection down with
+    /// [`ConnectionError::Reset`]. Intended for testing how peers handle stateless resets; see
+    /// [`proto::Endpoint::stateless_reset_packet`] for details.
+    pub fn stateless_reset_packet(&self, dst_cid: ConnectionId) -> Vec<u8> {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .inner
+            .stateless_reset_packet(dst_cid)
+    }
+
     /// Get the number of connections that are currently open
     pub fn open_connections(&self) -> usize {
         self.inner.state.lock().unwrap().inner.open_connections()
@@ -353,6 +558,55 @@ impl Endpoint {
         self.inner.shared.incoming.notify_waiters();
     }
 
+    /// Close all of this endpoint's connections and wait for CONNECTION_CLOSE to be sent on each
+    ///
+    /// Like [`close()`], but doesn't return until every connection has handed its
+    /// CONNECTION_CLOSE frame to the socket, or `timeout` elapses. Call this before exiting the
+    /// process so that peers learn about the closure promptly rather than waiting out their idle
+    /// timeout. Returns `false` if the timeout elapsed first.
+    ///
+    /// This is best-effort: the close is sent only once, and like any UDP datagram it may be lost,
+    /// in which case the peer still falls back to its idle timeout. Unlike [`wait_idle()`], this
+    /// doesn't wait for the connections' draining periods to end.
+    ///
+    /// [`close()`]: Endpoint::close
+    /// [`wait_idle()`]: Endpoint::wait_idle
+    pub async fn close_and_flush(
+        &self,
+        error_code: VarInt,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> bool {
+        let deadline = self.runtime.now() + timeout;
+        self.close(error_code, reason);
+        let mut pending = {
+            let endpoint = self.inner.state.lock().unwrap();
+            endpoint
+                .recv_state
+                .connections
+                .senders
+                .values()
+                .filter_map(|sender| {
+                    let (send, recv) = oneshot::channel();
+                    // Connections whose driver is gone have nothing left to send
+                    sender.send(ConnectionEvent::CloseSent(send)).ok()?;
+                    Some(recv)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut timer = self.runtime.new_timer(deadline);
+        std::future::poll_fn(|cx| {
+            // A dropped sender means the connection is gone, which is as good as flushed
+            pending.retain_mut(|recv| Pin::new(recv).poll(cx).is_pending());
+            if pending.is_empty() {
+                return Poll::Ready(true);
+            }
+            timer.as_mut().poll(cx).map(|()| false)
+        })
+        .await
+    }
+
     /// Wait for all connections on the endpoint to be cleanly shut down
     ///
     /// Waiting for this condition before exiting ensures that a good-faith effort is made to notify
@@ -390,6 +644,15 @@ pub struct EndpointStats {
     pub refused_handshakes: u64,
     /// Cummulative number of Quic handshakes ignored on this [Endpoint]
     pub ignored_handshakes: u64,
+    /// Number of handshakes started for incoming connection attempts in the last second
+    ///
+    /// See [`proto::Endpoint::handshake_rate`].
+    pub handshake_rate: u64,
+    /// Cummulative number of incoming connection attempts turned away by
+    /// [`EndpointConfig::max_handshakes_per_second`](proto::EndpointConfig::max_handshakes_per_second)
+    ///
+    /// These are not included in `refused_handshakes` or `ignored_handshakes`.
+    pub throttled_handshakes: u64,
 }
 
 /// A future that drives IO on an endpoint
@@ -539,6 +802,8 @@ pub(crate) struct State {
     /// During an active migration, abandoned_socket receives traffic
     /// until the first packet arrives on the new socket.
     prev_socket: Option<Box<dyn AsyncUdpSocket>>,
+    /// Dedicated sockets of connections moved with `Connection::set_local_socket`
+    connection_sockets: FxHashMap<ConnectionHandle, ConnectionSocket>,
     inner: proto::Endpoint,
     recv_state: RecvState,
     driver: Option<Waker>,
@@ -550,6 +815,14 @@ pub(crate) struct State {
     default_client_config: Option<ClientConfig>,
 }
 
+/// A socket used by a single connection, in addition to the endpoint's own
+#[derive(Debug)]
+struct ConnectionSocket {
+    socket: Box<dyn AsyncUdpSocket>,
+    /// Sends responses to datagrams received on `socket`
+    sender: Pin<Box<dyn UdpSender>>,
+}
+
 #[derive(Debug)]
 pub(crate) struct Shared {
     incoming: Notify,
@@ -576,6 +849,26 @@ impl State {
                 self.prev_socket = None;
             }
         };
+        let mut keep_going = false;
+        self.connection_sockets.retain(|handle, socket| {
+            match self.recv_state.poll_socket(
+                cx,
+                &mut self.inner,
+                &mut *socket.socket,
+                &mut socket.sender,
+                &*self.runtime,
+                now,
+            ) {
+                Ok(poll_res) => {
+                    keep_going |= poll_res.keep_going;
+                    true
+                }
+                Err(e) => {
+                    tracing::warn!("dropping socket of connection {:?}: {}", handle, e);
+                    false
+                }
+            }
+        });
         let poll_res = self.recv_state.poll_socket(
             cx,
             &mut self.inner,
@@ -593,7 +886,7 @@ impl State {
             self.prev_socket = None;
         }
         
-        Ok(poll_res.keep_going)
+        Ok(keep_going || poll_res.keep_going)
     }
 
     fn handle_events(&mut self, cx: &mut Context<'_>, shared: &Shared) -> bool {
@@ -609,8 +902,21 @@ impl State {
                 }
             };
 
+            let event = match event {
+                EndpointEvent::Proto(event) => event,
+                EndpointEvent::SetSocket(socket) => {
+                    let sender = socket.create_sender();
+                    self.connection_sockets
+                        .insert(ch, ConnectionSocket { socket, sender });
+                    // Ensure the new socket is polled to register for wake-ups
+                    cx.waker().wake_by_ref();
+                    continue;
+                }
+            };
+
             if event.is_drained() {
                 self.recv_state.connections.senders.remove(&ch);
+                self.connection_sockets.remove(&ch);
                 if self.recv_state.connections.is_empty() {
                     shared.idle.notify_waiters();
                 }
@@ -707,9 +1013,57 @@ struct ConnectionSet {
     sender: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
     /// Set if the endpoint has been manually closed
     close: Option<(VarInt, Bytes)>,
+    /// Limit on the aggregate sending rate of all connections, shared with each of them
+    send_budget: Option<Arc<SendBudget>>,
 }
 
 impl ConnectionSet {
+    /// Pass a single received datagram to the endpoint, returning whether it was routed to an
+    /// existing connection
+    #[allow(clippy::too_many_arguments)]
+    fn handle_datagram(
+        &mut self,
+        incoming: &mut VecDeque<proto::Incoming>,
+        endpoint: &mut proto::Endpoint,
+        sender: &mut Pin<Box<dyn UdpSender>>,
+        response_buffer: &mut Vec<u8>,
+        now: Instant,
+        meta: &RecvMeta,
+        data: BytesMut,
+    ) -> bool {
+        response_buffer.clear();
+        match endpoint.handle(
+            now,
+            meta.addr,
+            meta.dst_ip,
+            meta.ecn.map(proto_ecn),
+            data,
+            response_buffer,
+        ) {
+            Some(DatagramEvent::NewConnection(new_conn)) => {
+                if self.close.is_none() {
+                    incoming.push_back(new_conn);
+                } else {
+                    let transmit = endpoint.refuse(new_conn, response_buffer);
+                    respond(transmit, response_buffer, sender);
+                }
+            }
+            Some(DatagramEvent::ConnectionEvent(handle, event)) => {
+                if let Some(sender) = self.senders.get_mut(&handle) {
+                    if let Err(e) = sender.send(ConnectionEvent::Proto(event)) {
+                        tracing::warn!("failed to forward connection event: {:?}", e);
+                    }
+                }
+                return true;
+            }
+            Some(DatagramEvent::Response(transmit)) => {
+                respond(transmit, response_buffer, sender);
+            }
+            None => {}
+        }
+        false
+    }
+
     fn insert(
         &mut self,
         handle: ConnectionHandle,
@@ -726,12 +1080,64 @@ impl ConnectionSet {
             .unwrap();
         }
         self.senders.insert(handle, send);
-        Connecting::new(handle, conn, self.sender.clone(), recv, sender, runtime)
+        Connecting::new(
+            handle,
+            conn,
+            self.sender.clone(),
+            recv,
+            sender,
+            runtime,
+            self.send_budget.clone(),
+        )
     }
 
     fn is_empty(&self) -> bool {
         self.senders.is_empty()
     }
+
+    /// Pass the ICMP errors queued on `socket` to the connections they might concern
+    fn handle_icmp_errors(
+        &mut self,
+        endpoint: &mut proto::Endpoint,
+        socket: &dyn AsyncUdpSocket,
+        now: Instant,
+    ) -> io::Result<()> {
+        // Enough to hold the packet header up to the destination CID
+        let mut buf = [0; 64];
+        while let Some(error) = socket.try_recv_icmp_error(&mut buf)? {
+            let Some(mtu) = error.mtu else {
+                continue;
+            };
+            for (handle, event) in
+                endpoint.handle_packet_too_big(now, error.destination, mtu, &buf[..error.len])
+            {
+                if let Some(sender) = self.senders.get_mut(&handle) {
+                    let _ = sender.send(ConnectionEvent::Proto(event));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a receive failed only to report an ICMP error, leaving the socket usable
+///
+/// Besides `ConnectionReset` on Windows, receives fail this way on Linux for each error queued
+/// while [`recv_icmp_errors`](udp::UdpSocketStateConfig::recv_icmp_errors) is enabled. ICMP
+/// messages are trivially spoofed, so they must never bring the endpoint down.
+fn is_icmp_error(e: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    if matches!(
+        e.kind(),
+        ConnectionReset | ConnectionRefused | HostUnreachable | NetworkUnreachable
+    ) {
+        return true;
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if e.raw_os_error() == Some(libc::EMSGSIZE) {
+        return true;
+    }
+    false
 }
 
 fn ensure_ipv6(x: SocketAddr) -> SocketAddrV6 {
@@ -791,7 +1197,7 @@ impl EndpointRef {
         runtime: Arc<dyn Runtime>,
     ) -> Self {
         let (sender, events) = mpsc::unbounded_channel();
-        let recv_state = RecvState::new(sender, socket.max_receive_segments(), &inner);
+        let recv_state = RecvState::new(sender, socket.max_receive_segments(), &inner, &*runtime);
         let sender = socket.create_sender();
         Self(Arc::new(EndpointInner {
             shared: Shared {
@@ -803,6 +1209,7 @@ impl EndpointRef {
                 socket,
                 sender,
                 prev_socket: None,
+                connection_sockets: FxHashMap::default(),
                 inner,
                 ipv6,
                 events,
@@ -850,7 +1257,7 @@ impl std::ops::Deref for EndpointRef {
 struct RecvState {
     incoming: VecDeque<proto::Incoming>,
     connections: ConnectionSet,
-    recv_buf: Box<[u8]>,
+    recv_buf: Box<dyn DerefMut<Target = [u8]> + Send>,
     recv_limiter: WorkLimiter,
 }
 
@@ -859,21 +1266,31 @@ impl RecvState {
         sender: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
         max_receive_segments: usize,
         endpoint: &proto::Endpoint,
+        runtime: &dyn Runtime,
     ) -> Self {
-        let recv_buf = vec![
-            0;
-            endpoint.config().get_max_udp_payload_size().min(64 * 1024) as usize
-                * max_receive_segments
-                * BATCH_SIZE
-        ];
+        let len = endpoint.config().get_max_udp_payload_size().min(64 * 1024) as usize
+            * max_receive_segments
+            * BATCH_SIZE;
+        let mut recv_buf = runtime.alloc_recv_buffer(len);
+        if recv_buf.len() < len {
+            tracing::warn!(
+                "runtime allocated a receive buffer of {} bytes, less than the {len} required",
+                recv_buf.len()
+            );
+            recv_buf = Box::new(vec![0; len].into_boxed_slice());
+        }
         Self {
             connections: ConnectionSet {
                 senders: FxHashMap::default(),
                 sender,
                 close: None,
+                send_budget: endpoint
+                    .config()
+                    .get_max_aggregate_send_rate()
+                    .map(|rate| Arc::new(SendBudget::new(rate))),
             },
             incoming: VecDeque::new(),
-            recv_buf: recv_buf.into(),
+            recv_buf,
             recv_limiter: WorkLimiter::new(RECV_TIME_BOUND),
         }
     }
@@ -890,9 +1307,9 @@ impl RecvState {
         let mut received_connection_packet = false;
         let mut metas = [RecvMeta::default(); BATCH_SIZE];
         let mut iovs: [IoSliceMut<'_>; BATCH_SIZE] = {
-            let mut bufs = self
-                .recv_buf
-                .chunks_mut(self.recv_buf.len() / BATCH_SIZE)
+            let recv_buf = &mut **self.recv_buf;
+            let mut bufs = recv_buf
+                .chunks_mut(recv_buf.len() / BATCH_SIZE)
                 .map(IoSliceMut::new);
 
             // expect() safe as self.recv_buf is chunked into BATCH_SIZE items
@@ -903,46 +1320,24 @@ impl RecvState {
         
         let mut response_buffer = Vec::<u8>::new();
         loop {
-            
+            // Pending errors are reported by the next receive, unless consumed here first
+            self.connections.handle_icmp_errors(endpoint, socket, now)?;
             match socket.poll_recv(cx, &mut iovs, &mut metas) {
                 Poll::Ready(Ok(msgs)) => {
                     self.recv_limiter.record_work(msgs);
-                    for (_, (meta, buf)) in metas.iter().zip(iovs.iter()).take(msgs).enumerate() {
+                    for (meta, buf) in metas.iter().zip(iovs.iter()).take(msgs) {
                         let mut data: BytesMut = buf[0..meta.len].into();
-                        while !data.is_empty() {
-                            let buf = data.split_to(meta.stride.min(data.len()));
-                            response_buffer.clear();
-                            match endpoint.handle(
+                        for datagram in meta.datagrams(buf) {
+                            let buf = data.split_to(datagram.len());
+                            received_connection_packet |= self.connections.handle_datagram(
+                                &mut self.incoming,
+                                endpoint,
+                                sender,
+                                &mut response_buffer,
                                 now,
-                                meta.addr,
-                                meta.dst_ip,
-                                meta.ecn.map(proto_ecn),
+                                meta,
                                 buf,
-                                &mut response_buffer,
-                            ) {
-                                Some(DatagramEvent::NewConnection(incoming)) => {
-                                    if self.connections.close.is_none() {
-                                        self.incoming.push_back(incoming);
-                                    } else {
-                                        let transmit =
-                                            endpoint.refuse(incoming, &mut response_buffer);
-                                        respond(transmit, &response_buffer, sender);
-                                    }
-                                }
-                                Some(DatagramEvent::ConnectionEvent(handle, event)) => {
-                                    received_connection_packet = true;
-                                    let sender = self.connections.senders.get_mut(&handle);
-                                    if let Some(sender) = sender {
-                                        if let Err(e) = sender.send(ConnectionEvent::Proto(event)) {
-                                            tracing::warn!("failed to forward connection event: {:?}", e);
-                                        }
-                                    }
-                                }
-                                Some(DatagramEvent::Response(transmit)) => {
-                                    respond(transmit, &response_buffer, sender);
-                                }
-                                None => {}
-                            }
+                            );
                         }
                     }
                 }
@@ -952,7 +1347,8 @@ impl RecvState {
                         keep_going: false,
                     });
                 }
-                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::ConnectionReset => {
+                Poll::Ready(Err(ref e)) if is_icmp_error(e) => {
+                    // The queued ICMP error itself is drained at the top of the loop
                     continue;
                 }
                 Poll::Ready(Err(e)) => {
@@ -987,3 +1383,59 @@ struct PollProgress {
     /// Whether datagram handling was interrupted early by the work limiter for fairness
     keep_going: bool,
 }
+
+/// Client configuration used by [`Endpoint::client_with_native_roots`], trusting `roots`
+#[cfg(all(
+    not(wasm_browser),
+    feature = "native-certs",
+    any(feature = "rustls-aws-lc-rs", feature = "rustls-ring"),
+))]
+pub(crate) fn native_roots_client_config(
+    roots: rustls::RootCertStore,
+    alpn_protocols: Vec<Vec<u8>>,
+) -> io::Result<ClientConfig> {
+    #[cfg(all(feature = "rustls-aws-lc-rs", not(feature = "rustls-ring")))]
+    let provider = rustls::crypto::aws_lc_rs::default_provider();
+    #[cfg(feature = "rustls-ring")]
+    let provider = rustls::crypto::ring::default_provider();
+
+    let mut crypto = rustls::ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap() // The default providers support TLS 1.3
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    crypto.alpn_protocols = alpn_protocols;
+    crypto.enable_early_data = true;
+    let crypto = proto::crypto::rustls::QuicClientConfig::try_from(crypto)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(ClientConfig::new(Arc::new(crypto)))
+}
+
+/// Load the root certificates from the platform's certificate store
+///
+/// Certificates that fail to parse are skipped. Errors only if no certificate could be loaded.
+#[cfg(all(
+    not(wasm_browser),
+    feature = "native-certs",
+    any(feature = "rustls-aws-lc-rs", feature = "rustls-ring"),
+))]
+pub(crate) fn native_root_store() -> io::Result<rustls::RootCertStore> {
+    let result = rustls_native_certs::load_native_certs();
+    for e in &result.errors {
+        tracing::debug!(%e, "error loading native root certificates");
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    let (added, ignored) = roots.add_parsable_certificates(result.certs);
+    if ignored > 0 {
+        tracing::debug!(ignored, "ignored unparsable native root certificates");
+    }
+    if added == 0 {
+        let msg = match result.errors.first() {
+            Some(e) => format!("no native root certificates found: {e}"),
+            None => "no native root certificates found".to_owned(),
+        };
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+    }
+    Ok(roots)
+}
@@ -98,6 +98,17 @@ impl Incoming {
     pub fn orig_dst_cid(&self) -> ConnectionId {
         self.0.as_ref().unwrap().inner.orig_dst_cid()
     }
+
+    /// The application protocol (ALPN) that accepting this connection would negotiate
+    ///
+    /// Available before accepting, so that servers can dispatch or refuse connections by
+    /// protocol. Returns `None` if no protocol would be negotiated, or if the client's
+    /// ClientHello doesn't fit in its first Initial packet.
+    ///
+    /// See [`proto::Incoming::alpn_protocol`] for details.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.0.as_ref().unwrap().inner.alpn_protocol()
+    }
 }
 
 impl Drop for Incoming {
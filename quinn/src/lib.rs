@@ -44,10 +44,16 @@ use std::pin::Pin;
 mod connection;
 mod endpoint;
 mod incoming;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod mutex;
 mod recv_stream;
+mod request;
 mod runtime;
+mod send_budget;
 mod send_stream;
+#[cfg(all(feature = "socks5", not(wasm_browser)))]
+mod socks5;
 mod work_limiter;
 
 #[cfg(not(wasm_browser))]
@@ -59,12 +65,16 @@ pub(crate) use web_time::{Duration, Instant};
 pub use proto::BloomTokenLog;
 pub use proto::{
     AckFrequencyConfig, ApplicationClose, Chunk, ClientConfig, ClosedStream, ConfigError,
-    ConnectError, ConnectionClose, ConnectionError, ConnectionId, ConnectionIdGenerator,
-    ConnectionStats, Dir, EcnCodepoint, EndpointConfig, FrameStats, FrameType, IdleTimeout,
-    InvalidCid, MtuDiscoveryConfig, NoneTokenLog, NoneTokenStore, PathStats, ServerConfig, Side,
-    StdSystemTime, StreamId, TimeSource, TokenLog, TokenMemoryCache, TokenReuseError, TokenStore,
-    Transmit, TransportConfig, TransportErrorCode, UdpStats, ValidationTokenConfig, VarInt,
-    VarIntBoundsExceeded, Written, congestion, crypto,
+    ConnectError, ConnectionClose, ConnectionDebug, ConnectionError, ConnectionId,
+    ConnectionIdGenerator, ConnectionIdStats, ConnectionStats, DatagramBackpressure,
+    DatagramDropStats, Dir, DropReason, DroppedPacketStats, EcnCodepoint, EndpointConfig,
+    FrameStats, FrameType, IdleTimeout, InvalidCid, MtuDiscoveryConfig, MtuSearchState,
+    NoneTokenLog, NoneTokenStore, PacketDirection, PacketInfo, PacketObserver, PartialReliability,
+    PathStats, RecoveryStats, ServerConfig, Side, SpaceId, SpaceRecoveryStats, StdSystemTime,
+    StreamId, TimeSource, TokenLog, TokenMemoryCache, TokenReuseError, TokenStore, Transmit,
+    TransportConfig, TransportErrorCode, TransportParametersValidator, UdpStats,
+    ValidationTokenConfig, VarInt, VarIntBoundsExceeded, WriteStallReason, Written, congestion,
+    crypto, transport_parameters,
 };
 #[cfg(feature = "qlog")]
 pub use proto::{QlogConfig, QlogStream};
@@ -80,14 +90,19 @@ pub use crate::connection::{
 pub use crate::endpoint::{Accept, Endpoint, EndpointStats};
 pub use crate::incoming::{Incoming, IncomingFuture, RetryError};
 pub use crate::recv_stream::{ReadError, ReadExactError, ReadToEndError, RecvStream, ResetError};
+pub use crate::request::{RequestConfig, RequestError};
 #[cfg(feature = "runtime-smol")]
 pub use crate::runtime::SmolRuntime;
 #[cfg(feature = "runtime-tokio")]
 pub use crate::runtime::TokioRuntime;
 #[cfg(any(feature = "runtime-tokio", feature = "runtime-smol"))]
 pub use crate::runtime::default_runtime;
-pub use crate::runtime::{AsyncTimer, AsyncUdpSocket, Runtime, UdpSender};
-pub use crate::send_stream::{SendStream, StoppedError, WriteError};
+pub use crate::runtime::{
+    AsyncTimer, AsyncUdpSocket, Runtime, UdpSender, VirtualClock, VirtualTimeRuntime,
+};
+pub use crate::send_stream::{SendStream, SharedSendStream, StoppedError, WriteError};
+#[cfg(all(feature = "socks5", not(wasm_browser)))]
+pub use crate::socks5::Socks5UdpSocket;
 
 #[cfg(test)]
 mod tests;
@@ -100,6 +115,15 @@ enum ConnectionEvent {
     },
     Proto(proto::ConnectionEvent),
     Rebind(Pin<Box<dyn UdpSender>>),
+    /// Signal the sender once the connection is closed and has nothing left to transmit
+    CloseSent(tokio::sync::oneshot::Sender<()>),
+}
+
+#[derive(Debug)]
+enum EndpointEvent {
+    Proto(proto::EndpointEvent),
+    /// The connection moved to a dedicated socket, which must now be polled for its packets
+    SetSocket(Box<dyn AsyncUdpSocket>),
 }
 
 fn udp_transmit<'a>(t: &proto::Transmit, buffer: &'a [u8]) -> udp::Transmit<'a> {
@@ -0,0 +1,179 @@
+//! Connection metrics reported through the [`metrics`] crate facade
+//!
+//! When the `metrics` feature is enabled, every connection reports to the globally installed
+//! [`metrics::Recorder`], e.g. one exporting to Prometheus. Counters and histograms are updated
+//! from [`ConnectionStats`] at most once per second while the connection is active, and once
+//! more when it is drained. All metrics are labeled with `side`, which is either `client` or
+//! `server`.
+//!
+//! | Name | Type | Description |
+//! |------|------|-------------|
+//! | `quinn_connections_active` | gauge | Connections that haven't been drained yet |
+//! | `quinn_handshakes_completed_total` | counter | Handshakes that succeeded |
+//! | `quinn_handshakes_failed_total` | counter | Connections lost before the handshake completed |
+//! | `quinn_connections_closed_total` | counter | Connections lost, additionally labeled with `reason` |
+//! | `quinn_datagrams_sent_total` | counter | UDP datagrams sent |
+//! | `quinn_datagrams_received_total` | counter | UDP datagrams received |
+//! | `quinn_bytes_sent_total` | counter | UDP payload bytes sent |
+//! | `quinn_bytes_received_total` | counter | UDP payload bytes received |
+//! | `quinn_packets_lost_total` | counter | Packets deemed lost |
+//! | `quinn_bytes_lost_total` | counter | Bytes in packets deemed lost |
+//! | `quinn_congestion_events_total` | counter | Congestion events |
+//! | `quinn_rtt_seconds` | histogram | Smoothed round-trip time, sampled with each update |
+//!
+//! The `reason` label takes the values `version_mismatch`, `transport_error`,
+//! `connection_closed`, `application_closed`, `reset`, `timed_out`, `locally_closed` and
+//! `cids_exhausted`, following [`ConnectionError`]. Call [`describe()`] after installing a
+//! recorder to register these descriptions with it.
+
+use metrics::{
+    Unit, counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram,
+};
+use proto::{ConnectionError, ConnectionStats, Side};
+
+use crate::{Duration, Instant};
+
+/// Name of the gauge of connections that haven't been drained yet
+pub const CONNECTIONS_ACTIVE: &str = "quinn_connections_active";
+/// Name of the counter of successful handshakes
+pub const HANDSHAKES_COMPLETED: &str = "quinn_handshakes_completed_total";
+/// Name of the counter of connections lost before the handshake completed
+pub const HANDSHAKES_FAILED: &str = "quinn_handshakes_failed_total";
+/// Name of the counter of lost connections, labeled with `reason`
+pub const CONNECTIONS_CLOSED: &str = "quinn_connections_closed_total";
+/// Name of the counter of UDP datagrams sent
+pub const DATAGRAMS_SENT: &str = "quinn_datagrams_sent_total";
+/// Name of the counter of UDP datagrams received
+pub const DATAGRAMS_RECEIVED: &str = "quinn_datagrams_received_total";
+/// Name of the counter of UDP payload bytes sent
+pub const BYTES_SENT: &str = "quinn_bytes_sent_total";
+/// Name of the counter of UDP payload bytes received
+pub const BYTES_RECEIVED: &str = "quinn_bytes_received_total";
+/// Name of the counter of packets deemed lost
+pub const PACKETS_LOST: &str = "quinn_packets_lost_total";
+/// Name of the counter of bytes in packets deemed lost
+pub const BYTES_LOST: &str = "quinn_bytes_lost_total";
+/// Name of the counter of congestion events
+pub const CONGESTION_EVENTS: &str = "quinn_congestion_events_total";
+/// Name of the histogram of smoothed round-trip times
+pub const RTT: &str = "quinn_rtt_seconds";
+
+/// Register descriptions and units of all metrics with the installed recorder
+pub fn describe() {
+    describe_gauge!(
+        CONNECTIONS_ACTIVE,
+        "Connections that haven't been drained yet"
+    );
+    describe_counter!(HANDSHAKES_COMPLETED, "Handshakes that succeeded");
+    describe_counter!(
+        HANDSHAKES_FAILED,
+        "Connections lost before the handshake completed"
+    );
+    describe_counter!(CONNECTIONS_CLOSED, "Connections lost, by reason");
+    describe_counter!(DATAGRAMS_SENT, "UDP datagrams sent");
+    describe_counter!(DATAGRAMS_RECEIVED, "UDP datagrams received");
+    describe_counter!(BYTES_SENT, Unit::Bytes, "UDP payload bytes sent");
+    describe_counter!(BYTES_RECEIVED, Unit::Bytes, "UDP payload bytes received");
+    describe_counter!(PACKETS_LOST, "Packets deemed lost");
+    describe_counter!(BYTES_LOST, Unit::Bytes, "Bytes in packets deemed lost");
+    describe_counter!(CONGESTION_EVENTS, "Congestion events");
+    describe_histogram!(RTT, Unit::Seconds, "Smoothed round-trip time");
+}
+
+/// Minimum time between updates from a connection's statistics
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reports the metrics of a single connection
+#[derive(Debug)]
+pub(crate) struct ConnectionMetrics {
+    side: &'static str,
+    /// Statistics as of the last update, to report the difference
+    reported: ConnectionStats,
+    next_update: Instant,
+    /// Whether the connection has been drained, and so is no longer counted as active
+    drained: bool,
+}
+
+impl ConnectionMetrics {
+    pub(crate) fn new(side: Side, now: Instant) -> Self {
+        let side = match side {
+            Side::Client => "client",
+            Side::Server => "server",
+        };
+        gauge!(CONNECTIONS_ACTIVE, "side" => side).increment(1.0);
+        Self {
+            side,
+            reported: ConnectionStats::default(),
+            next_update: now,
+            drained: false,
+        }
+    }
+
+    pub(crate) fn on_connected(&self) {
+        counter!(HANDSHAKES_COMPLETED, "side" => self.side).increment(1);
+    }
+
+    pub(crate) fn on_lost(&self, reason: &ConnectionError, connected: bool) {
+        if !connected {
+            counter!(HANDSHAKES_FAILED, "side" => self.side).increment(1);
+        }
+        let reason = match reason {
+            ConnectionError::VersionMismatch => "version_mismatch",
+            ConnectionError::TransportError(_) => "transport_error",
+            ConnectionError::ConnectionClosed(_) => "connection_closed",
+            ConnectionError::ApplicationClosed(_) => "application_closed",
+            ConnectionError::Reset => "reset",
+            ConnectionError::TimedOut => "timed_out",
+            ConnectionError::LocallyClosed => "locally_closed",
+            ConnectionError::CidsExhausted => "cids_exhausted",
+        };
+        counter!(CONNECTIONS_CLOSED, "side" => self.side, "reason" => reason).increment(1);
+    }
+
+    /// Whether enough time passed since the last update for another
+    pub(crate) fn update_due(&self, now: Instant) -> bool {
+        now >= self.next_update
+    }
+
+    pub(crate) fn update(&mut self, stats: ConnectionStats, now: Instant) {
+        self.next_update = now + UPDATE_INTERVAL;
+        let side = self.side;
+        let last = &self.reported;
+        counter!(DATAGRAMS_SENT, "side" => side)
+            .increment(stats.udp_tx.datagrams - last.udp_tx.datagrams);
+        counter!(DATAGRAMS_RECEIVED, "side" => side)
+            .increment(stats.udp_rx.datagrams - last.udp_rx.datagrams);
+        counter!(BYTES_SENT, "side" => side).increment(stats.udp_tx.bytes - last.udp_tx.bytes);
+        counter!(BYTES_RECEIVED, "side" => side).increment(stats.udp_rx.bytes - last.udp_rx.bytes);
+        counter!(PACKETS_LOST, "side" => side)
+            .increment(stats.path.lost_packets - last.path.lost_packets);
+        counter!(BYTES_LOST, "side" => side)
+            .increment(stats.path.lost_bytes - last.path.lost_bytes);
+        counter!(CONGESTION_EVENTS, "side" => side)
+            .increment(stats.path.congestion_events - last.path.congestion_events);
+        histogram!(RTT, "side" => side).record(stats.path.rtt.as_secs_f64());
+        self.reported = stats;
+    }
+
+    /// Account for the connection's counters restarting from zero
+    ///
+    /// `stats` are the statistics immediately before the reset.
+    pub(crate) fn on_stats_reset(&mut self, stats: ConnectionStats, now: Instant) {
+        self.update(stats, now);
+        self.reported = ConnectionStats::default();
+    }
+
+    /// Stop counting the connection as active
+    pub(crate) fn on_drained(&mut self) {
+        if !self.drained {
+            self.drained = true;
+            gauge!(CONNECTIONS_ACTIVE, "side" => self.side).decrement(1.0);
+        }
+    }
+}
+
+impl Drop for ConnectionMetrics {
+    fn drop(&mut self) {
+        self.on_drained();
+    }
+}
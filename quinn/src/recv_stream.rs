@@ -248,8 +248,14 @@ impl RecvStream {
     /// Convenience method to read all remaining data into a buffer
     ///
     /// Fails with [`ReadToEndError::TooLong`] on reading more than `size_limit` bytes, discarding
-    /// all data read. Uses unordered reads to be more efficient than using `AsyncRead` would
-    /// allow. `size_limit` should be set to limit worst-case memory use.
+    /// all data read. A stream of exactly `size_limit` bytes is read successfully. Uses unordered
+    /// reads to be more efficient than using `AsyncRead` would allow. `size_limit` should be set
+    /// to limit worst-case memory use.
+    ///
+    /// At most one byte beyond `size_limit` is consumed from the stream before failing, so flow
+    /// control credit is never issued for data past that point and the peer can't send more than
+    /// the stream receive window beyond it. On failure, the stream is left open with any excess
+    /// data unread; use [`stop()`](Self::stop) to discard it and tell the peer to stop sending.
     ///
     /// If unordered reads have already been made, the resulting buffer may have gaps containing
     /// arbitrary data.
@@ -268,6 +274,26 @@ impl RecvStream {
         .await
     }
 
+    /// Copy received data at an explicit `offset` into `buf` without consuming it
+    ///
+    /// Yields the number of contiguous bytes copied, or `None` if the data at `offset` hasn't been
+    /// received yet, or is no longer available because it was already read or the stream was
+    /// reset. Unlike the `read` methods, this never waits for data to arrive.
+    ///
+    /// Data is only released once it's consumed by a `read` method, so data inspected with
+    /// `read_at` continues to occupy memory and the stream receive window until then. Applications
+    /// must keep reading the stream for the peer to be able to send more.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<Option<usize>, ReadError> {
+        let mut conn = self.conn.state.lock("RecvStream::read_at");
+        if self.is_0rtt {
+            conn.check_0rtt().map_err(|()| ReadError::ZeroRttRejected)?;
+        }
+        conn.inner
+            .recv_stream(self.stream)
+            .read_at(offset, buf)
+            .map_err(|_| ReadError::ClosedStream)
+    }
+
     /// Stop accepting data
     ///
     /// Discards unread data and notifies the peer to stop transmitting. Once stopped, further
@@ -283,6 +309,17 @@ impl RecvStream {
         Ok(())
     }
 
+    /// Number of bytes of stream data received from the peer
+    ///
+    /// Counts application data, excluding framing and packet overhead, up to the highest offset
+    /// received so far. Duplicates are counted once, but gaps left by loss or reordering are
+    /// included until filled. This is independent of how much has been read. Fails with
+    /// [`ClosedStream`] once the stream's state has been discarded.
+    pub fn bytes_received(&self) -> Result<u64, ClosedStream> {
+        let mut conn = self.conn.state.lock("RecvStream::bytes_received");
+        conn.inner.recv_stream(self.stream).bytes_received()
+    }
+
     /// Check if this stream has been opened during 0-RTT.
     ///
     /// In which case any non-idempotent request should be considered dangerous at the application
@@ -440,7 +477,18 @@ impl Future for ReadToEnd<'_> {
     type Output = Result<Vec<u8>, ReadToEndError>;
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         loop {
-            match ready!(self.stream.poll_read_chunk(cx, usize::MAX, false))? {
+            // Never consume more than one byte past the limit, so that no flow control credit is
+            // issued for data we'd discard anyway
+            let read = match self.start {
+                u64::MAX => 0,
+                start => self.end - start,
+            };
+            let max_length = (self.size_limit as u64)
+                .saturating_add(1)
+                .saturating_sub(read)
+                .try_into()
+                .unwrap_or(usize::MAX);
+            match ready!(self.stream.poll_read_chunk(cx, max_length, false))? {
                 Some(chunk) => {
                     self.start = self.start.min(chunk.offset);
                     let end = chunk.bytes.len() as u64 + chunk.offset;
@@ -505,30 +553,29 @@ impl tokio::io::AsyncRead for RecvStream {
 
 impl Drop for RecvStream {
     fn drop(&mut self) {
+        let mut conn = self.conn.state.lock("RecvStream::drop");
         if self.all_data_read {
             debug_assert!(
-                !self
-                    .conn
-                    .state
-                    .lock("RecvStream:drop")
-                    .blocked_readers
-                    .contains_key(&self.stream),
+                !conn.blocked_readers.contains_key(&self.stream),
                 "Stream {} should not have a blocked reader when all data read is true",
                 &self.stream
             );
-            return;
+        } else {
+            // clean up any previously registered wakers
+            conn.blocked_readers.remove(&self.stream);
         }
-        let mut conn = self.conn.state.lock("RecvStream::drop");
-
-        // clean up any previously registered wakers
-        conn.blocked_readers.remove(&self.stream);
 
         if conn.error.is_some() || (self.is_0rtt && conn.check_0rtt().is_err()) {
             return;
         }
 
-        // Ignore ClosedStream errors
-        let _ = conn.inner.recv_stream(self.stream).stop(0u32.into());
+        let mut stream = conn.inner.recv_stream(self.stream);
+        if !self.all_data_read {
+            // Ignore ClosedStream errors
+            let _ = stream.stop(0u32.into());
+        }
+        // May allow the peer to open another stream
+        stream.handled();
         conn.wake();
     }
 }
@@ -0,0 +1,82 @@
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::{ConnectionError, Duration, ReadToEndError, SendStream, WriteError};
+
+/// Parameters governing [`Connection::request_with`](crate::Connection::request_with)
+#[derive(Debug, Clone)]
+pub struct RequestConfig {
+    pub(crate) max_response_size: usize,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl RequestConfig {
+    /// Maximum size of the response in bytes
+    ///
+    /// Responses exceeding this fail with [`RequestError::Read`] carrying
+    /// [`ReadToEndError::TooLong`], and the response stream is stopped.
+    pub fn max_response_size(&mut self, value: usize) -> &mut Self {
+        self.max_response_size = value;
+        self
+    }
+
+    /// Maximum time from opening the stream until the whole response has been read
+    ///
+    /// When `None`, the request only fails if the connection is lost, which may take as long as
+    /// the idle timeout if the peer stops responding, or forever if the peer keeps the connection
+    /// alive without answering.
+    pub fn timeout(&mut self, value: Option<Duration>) -> &mut Self {
+        self.timeout = value;
+        self
+    }
+}
+
+impl Default for RequestConfig {
+    fn default() -> Self {
+        Self {
+            max_response_size: 64 * 1024,
+            timeout: None,
+        }
+    }
+}
+
+/// Errors from [`Connection::request`](crate::Connection::request)
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// The connection was lost before a stream could be opened
+    #[error("connection lost")]
+    ConnectionLost(#[from] ConnectionError),
+    /// Sending the request failed
+    #[error("write error: {0}")]
+    Write(#[from] WriteError),
+    /// Receiving the response failed, or it exceeded [`RequestConfig::max_response_size`]
+    #[error("read error: {0}")]
+    Read(#[from] ReadToEndError),
+    /// The response wasn't received within [`RequestConfig::timeout`]
+    #[error("timed out")]
+    TimedOut,
+}
+
+/// Write `payload` to `stream` and finish it
+///
+/// Dropping a [`SendStream`] finishes it, which would let the peer mistake a partially written
+/// request for a complete one, so the stream is reset instead if this is interrupted.
+pub(crate) async fn write_request(stream: SendStream, payload: Bytes) -> Result<(), WriteError> {
+    let mut guard = ResetOnDrop(Some(stream));
+    let stream = guard.0.as_mut().unwrap();
+    stream.write_chunk(payload).await?;
+    stream.finish()?;
+    guard.0 = None;
+    Ok(())
+}
+
+struct ResetOnDrop(Option<SendStream>);
+
+impl Drop for ResetOnDrop {
+    fn drop(&mut self) {
+        if let Some(mut stream) = self.0.take() {
+            // Ignore ClosedStream errors
+            let _ = stream.reset(0u32.into());
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::{
     future::Future,
     io::{self, IoSliceMut},
     net::SocketAddr,
+    ops::DerefMut,
     pin::Pin,
     task::{Context, Poll},
 };
@@ -39,6 +40,20 @@ pub trait Runtime: Send + Sync + Debug + 'static {
     fn now(&self) -> Instant {
         Instant::now()
     }
+    /// Allocate the memory an endpoint receives UDP datagrams into
+    ///
+    /// Called once when an endpoint is constructed. The buffer is split into
+    /// [`BATCH_SIZE`](udp::BATCH_SIZE) equal slices, one per datagram read in a single system call,
+    /// and each slice must hold [`AsyncUdpSocket::max_receive_segments`] datagrams of the maximum
+    /// UDP payload size (capped at 64 KiB), since GRO may coalesce that many into one read.
+    /// `len` is the product of these, and the returned buffer must be at least that long; a
+    /// shorter one is replaced by a default allocation.
+    ///
+    /// Allows e.g. aligned, huge-page backed or NUMA-local memory to be used. Defaults to a
+    /// zeroed heap allocation.
+    fn alloc_recv_buffer(&self, len: usize) -> Box<dyn DerefMut<Target = [u8]> + Send> {
+        Box::new(vec![0; len].into_boxed_slice())
+    }
 }
 
 /// Abstract implementation of an async timer for runtime independence
@@ -86,6 +101,32 @@ pub trait AsyncUdpSocket: Send + Sync + Debug + 'static {
     fn may_fragment(&self) -> bool {
         true
     }
+
+    /// Read an ICMP error reported for a datagram sent on this socket, if one is queued
+    ///
+    /// The quoted UDP payload of the offending datagram is written to `buf`. Must not block. The
+    /// default implementation never reports any errors. See
+    /// [`UdpSocketStateConfig::recv_icmp_errors`](udp::UdpSocketStateConfig::recv_icmp_errors).
+    fn try_recv_icmp_error(&self, buf: &mut [u8]) -> io::Result<Option<udp::IcmpError>> {
+        let _ = buf;
+        Ok(None)
+    }
+
+    /// The file descriptor of the OS socket backing this object, if any
+    ///
+    /// The default implementation returns `None`, for sockets not backed by a single descriptor.
+    #[cfg(unix)]
+    fn as_raw_fd(&self) -> Option<std::os::fd::RawFd> {
+        None
+    }
+
+    /// The handle of the OS socket backing this object, if any
+    ///
+    /// The default implementation returns `None`, for sockets not backed by a single handle.
+    #[cfg(windows)]
+    fn as_raw_socket(&self) -> Option<std::os::windows::io::RawSocket> {
+        None
+    }
 }
 
 /// An object for asynchronously writing to an associated [`AsyncUdpSocket`].
@@ -249,6 +290,9 @@ pub fn default_runtime() -> Option<Arc<dyn Runtime>> {
     None
 }
 
+mod virtual_time;
+pub use virtual_time::{VirtualClock, VirtualTimeRuntime};
+
 #[cfg(feature = "runtime-tokio")]
 mod tokio;
 #[cfg(feature = "runtime-tokio")]
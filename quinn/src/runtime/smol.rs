@@ -106,4 +106,20 @@ impl AsyncUdpSocket for UdpSocket {
     fn max_receive_segments(&self) -> usize {
         self.inner.gro_segments()
     }
+
+    fn try_recv_icmp_error(&self, buf: &mut [u8]) -> io::Result<Option<udp::IcmpError>> {
+        self.inner.recv_icmp_error((&self.io).into(), buf)
+    }
+
+    #[cfg(unix)]
+    fn as_raw_fd(&self) -> Option<std::os::fd::RawFd> {
+        Some(std::os::fd::AsRawFd::as_raw_fd(self.io.as_ref().as_ref()))
+    }
+
+    #[cfg(windows)]
+    fn as_raw_socket(&self) -> Option<std::os::windows::io::RawSocket> {
+        Some(std::os::windows::io::AsRawSocket::as_raw_socket(
+            self.io.as_ref().as_ref(),
+        ))
+    }
 }
@@ -0,0 +1,169 @@
+use std::{
+    fmt,
+    future::Future,
+    io,
+    ops::DerefMut,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use rustc_hash::FxHashMap;
+
+use super::{AsyncTimer, AsyncUdpSocket, Runtime};
+use crate::{Duration, Instant};
+
+/// A [`Runtime`] whose clock only moves when told to
+///
+/// Wraps another runtime, which still spawns tasks and performs I/O, but substitutes a
+/// [`VirtualClock`] for its sense of time: [`Runtime::now`] reports the virtual time, and timers
+/// only expire once the virtual time reaches their deadline. This allows testing timeouts,
+/// retransmissions and other time-dependent behavior of endpoints and connections
+/// deterministically and without actually waiting.
+///
+/// Time stands still until [`VirtualClock::advance`] is called on the handle returned by
+/// [`clock()`](Self::clock), which wakes any tasks whose timers have expired. Use the same runtime
+/// for every endpoint that should share a clock.
+#[derive(Debug)]
+pub struct VirtualTimeRuntime {
+    inner: Arc<dyn Runtime>,
+    clock: VirtualClock,
+}
+
+impl VirtualTimeRuntime {
+    /// Wrap `inner`, starting the virtual clock at the current time
+    pub fn new(inner: Arc<dyn Runtime>) -> Self {
+        let clock = VirtualClock::new(inner.now());
+        Self { inner, clock }
+    }
+
+    /// Handle for reading and advancing the virtual time
+    pub fn clock(&self) -> VirtualClock {
+        self.clock.clone()
+    }
+}
+
+impl Runtime for VirtualTimeRuntime {
+    fn new_timer(&self, i: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        let id = {
+            let mut state = self.clock.0.lock().unwrap();
+            state.next_timer_id += 1;
+            state.next_timer_id
+        };
+        Box::pin(VirtualTimer {
+            clock: self.clock.clone(),
+            deadline: i,
+            id,
+        })
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        self.inner.spawn(future);
+    }
+
+    #[cfg(not(wasm_browser))]
+    fn wrap_udp_socket(&self, t: std::net::UdpSocket) -> io::Result<Box<dyn AsyncUdpSocket>> {
+        self.inner.wrap_udp_socket(t)
+    }
+
+    #[cfg(not(wasm_browser))]
+    fn wrap_udp_socket_with_config(
+        &self,
+        t: std::net::UdpSocket,
+        config: udp::UdpSocketStateConfig,
+    ) -> io::Result<Box<dyn AsyncUdpSocket>> {
+        self.inner.wrap_udp_socket_with_config(t, config)
+    }
+
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn alloc_recv_buffer(&self, len: usize) -> Box<dyn DerefMut<Target = [u8]> + Send> {
+        self.inner.alloc_recv_buffer(len)
+    }
+}
+
+/// The clock of a [`VirtualTimeRuntime`]
+///
+/// Cheap to clone; clones refer to the same clock.
+#[derive(Clone)]
+pub struct VirtualClock(Arc<Mutex<ClockState>>);
+
+impl VirtualClock {
+    fn new(now: Instant) -> Self {
+        Self(Arc::new(Mutex::new(ClockState {
+            now,
+            next_timer_id: 0,
+            waiting: FxHashMap::default(),
+        })))
+    }
+
+    /// The current virtual time
+    pub fn now(&self) -> Instant {
+        self.0.lock().unwrap().now
+    }
+
+    /// Move the virtual time forward by `duration`
+    ///
+    /// Wakes every task waiting on a timer, so that those whose deadlines have passed can run. The
+    /// woken tasks run concurrently with the caller, so after advancing, a test should yield to
+    /// them (e.g. by awaiting the outcome it expects) before advancing again. Jumping far ahead at
+    /// once fires all timers that expired along the way together, at the new time.
+    pub fn advance(&self, duration: Duration) {
+        let waiting = {
+            let mut state = self.0.lock().unwrap();
+            state.now += duration;
+            std::mem::take(&mut state.waiting)
+        };
+        for waker in waiting.into_values() {
+            waker.wake();
+        }
+    }
+}
+
+impl fmt::Debug for VirtualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VirtualClock")
+            .field("now", &self.now())
+            .finish_non_exhaustive()
+    }
+}
+
+struct ClockState {
+    now: Instant,
+    next_timer_id: u64,
+    /// Tasks waiting for a timer to expire, by timer
+    waiting: FxHashMap<u64, Waker>,
+}
+
+#[derive(Debug)]
+struct VirtualTimer {
+    clock: VirtualClock,
+    deadline: Instant,
+    id: u64,
+}
+
+impl AsyncTimer for VirtualTimer {
+    fn reset(self: Pin<&mut Self>, i: Instant) {
+        self.get_mut().deadline = i;
+    }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.clock.0.lock().unwrap();
+        if state.now >= self.deadline {
+            state.waiting.remove(&self.id);
+            return Poll::Ready(());
+        }
+        state.waiting.insert(self.id, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for VirtualTimer {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.clock.0.lock() {
+            state.waiting.remove(&self.id);
+        }
+    }
+}
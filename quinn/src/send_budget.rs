@@ -0,0 +1,76 @@
+use std::sync::Mutex;
+
+use crate::{Duration, Instant};
+
+/// Amount of budget which may be spent ahead of schedule
+const MAX_BURST: Duration = Duration::from_millis(2);
+
+/// Limits the aggregate rate at which the connections of an endpoint send
+///
+/// Each transmit reserves the next free slot in a schedule shared by all connections, so the
+/// budget is allocated in the order it's requested. See
+/// [`EndpointConfig::max_aggregate_send_rate`](proto::EndpointConfig::max_aggregate_send_rate).
+#[derive(Debug)]
+pub(crate) struct SendBudget {
+    /// Permitted sending rate in bytes per second
+    rate: u64,
+    /// Time at which the budget reserved so far is used up
+    ///
+    /// `None` if nothing was reserved yet.
+    reserved_until: Mutex<Option<Instant>>,
+}
+
+impl SendBudget {
+    pub(crate) fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            reserved_until: Mutex::new(None),
+        }
+    }
+
+    /// Reserve budget for sending `bytes`, returning the time at which they may be sent
+    pub(crate) fn reserve(&self, now: Instant, bytes: usize) -> Instant {
+        let mut reserved_until = self.reserved_until.lock().unwrap();
+        let start = match *reserved_until {
+            Some(t) if t > now => t,
+            _ => now,
+        };
+        let cost = Duration::from_nanos((bytes as u64).saturating_mul(1_000_000_000) / self.rate);
+        *reserved_until = Some(start + cost);
+        start.checked_sub(MAX_BURST).map_or(now, |t| t.max(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_then_paced() {
+        let budget = SendBudget::new(1_000_000);
+        let now = Instant::now();
+        // 2ms worth of budget is available immediately
+        for _ in 0..3 {
+            assert_eq!(budget.reserve(now, 1000), now);
+        }
+        // Further transmits are scheduled one after the other
+        assert_eq!(budget.reserve(now, 1000), now + Duration::from_millis(1));
+        assert_eq!(budget.reserve(now, 1000), now + Duration::from_millis(2));
+    }
+
+    #[test]
+    fn unused_budget_expires() {
+        let budget = SendBudget::new(1_000_000);
+        let now = Instant::now();
+        assert_eq!(budget.reserve(now, 10_000), now);
+        // Idle time doesn't accumulate budget beyond the permitted burst
+        let later = now + Duration::from_secs(1);
+        for _ in 0..3 {
+            assert_eq!(budget.reserve(later, 1000), later);
+        }
+        assert_eq!(
+            budget.reserve(later, 1000),
+            later + Duration::from_millis(1)
+        );
+    }
+}
@@ -2,15 +2,19 @@ use std::{
     future::{Future, poll_fn},
     io,
     pin::{Pin, pin},
+    sync::Arc,
     task::{Context, Poll},
 };
 
 use bytes::Bytes;
-use proto::{ClosedStream, ConnectionError, FinishError, StreamId, Written};
+use proto::{
+    ClosedStream, ConnectionError, FinishError, PartialReliability, StreamId, WriteStallReason,
+    Written,
+};
 use thiserror::Error;
 
 use crate::{
-    VarInt,
+    Instant, VarInt,
     connection::{ConnectionRef, State},
 };
 
@@ -60,6 +64,26 @@ impl SendStream {
         poll_fn(|cx| self.execute_poll(cx, |s| s.write(buf))).await
     }
 
+    /// Write bytes to the stream, resetting it if they aren't acknowledged by `deadline`
+    ///
+    /// Like [`write`](Self::write), but if the written bytes and everything before them haven't
+    /// been acknowledged by the peer when `deadline` passes, the stream is reset with `error_code`
+    /// instead of retransmitting further. This sacrifices the stream's reliability for timeliness;
+    /// see [`proto::SendStream::write_with_deadline`].
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancellation safe. If this does not resolve, no bytes were written.
+    pub async fn write_with_deadline(
+        &mut self,
+        buf: &[u8],
+        deadline: Instant,
+        error_code: VarInt,
+    ) -> Result<usize, WriteError> {
+        poll_fn(|cx| self.execute_poll(cx, |s| s.write_with_deadline(buf, deadline, error_code)))
+            .await
+    }
+
     /// Write a buffer into this stream in its entirety
     ///
     /// This method repeatedly calls [`write`](Self::write) until all bytes are written, or an
@@ -100,6 +124,21 @@ impl SendStream {
         poll_fn(|cx| self.execute_poll(cx, |s| s.write_chunks(bufs))).await
     }
 
+    /// Write a [`Bytes`] into this stream without copying it, returning how much was written
+    ///
+    /// Unless this method errors, it waits until some amount of `buf` can be written into this
+    /// stream, and then writes as much as it can without waiting again. The stream retains a
+    /// reference to `buf`'s underlying buffer rather than copying from it, and releases it once
+    /// the peer has acknowledged all the data it covers, so the entire allocation stays alive
+    /// until then. The unwritten remainder, if any, is `buf.slice(n..)`.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancellation safe. If this does not resolve, no bytes were written.
+    pub async fn write_owned(&mut self, buf: Bytes) -> Result<usize, WriteError> {
+        poll_fn(|cx| self.execute_poll(cx, |s| s.write_owned(buf.clone()))).await
+    }
+
     /// Write a single [`Bytes`] into this stream in its entirety
     ///
     /// Bytes to write are provided to this method as an single cheaply cloneable chunk. This
@@ -205,34 +244,149 @@ impl SendStream {
     /// called. This error is harmless and serves only to indicate that the caller may have
     /// incorrect assumptions about the stream's state.
     pub fn reset(&mut self, error_code: VarInt) -> Result<(), ClosedStream> {
-        let mut conn = self.conn.state.lock("SendStream::reset");
-        if self.is_0rtt && conn.check_0rtt().is_err() {
-            return Ok(());
-        }
-        conn.inner.send_stream(self.stream).reset(error_code)?;
-        conn.wake();
-        Ok(())
+        reset_send_stream(&self.conn, self.stream, self.is_0rtt, error_code)
     }
 
     /// Set the priority of the send stream
     ///
-    /// Every send stream has an initial priority of 0. Locally buffered data from streams with
-    /// higher priority will be transmitted before data from streams with lower priority. Changing
-    /// the priority of a stream with pending data may only take effect after that data has been
-    /// transmitted. Using many different priority levels per connection may have a negative
-    /// impact on performance.
+    /// Every send stream has an initial priority of 0, and any `i32` is valid. Locally buffered
+    /// data from streams with higher priority will be transmitted before data from streams with
+    /// lower priority. Streams of equal priority take turns in round-robin order, unless
+    /// [`TransportConfig::send_fairness`](crate::TransportConfig::send_fairness) is disabled.
+    /// Changing the priority of a stream with pending data may only take effect after that data
+    /// has been transmitted. Using many different priority levels per connection may have a
+    /// negative impact on performance.
+    ///
+    /// With [`TransportConfig::weighted_priorities`](crate::TransportConfig::weighted_priorities)
+    /// enabled, priorities are instead weights which determine each stream's share of the
+    /// connection.
     pub fn set_priority(&self, priority: i32) -> Result<(), ClosedStream> {
         let mut conn = self.conn.state.lock("SendStream::set_priority");
         conn.inner.send_stream(self.stream).set_priority(priority)?;
         Ok(())
     }
 
+    /// Coalesce small writes, holding back written data until `threshold` bytes have accumulated
+    ///
+    /// Useful for protocols making many small writes in quick succession, each of which would
+    /// otherwise likely be sent in a separate STREAM frame. While coalescing, written data is only
+    /// sent once at least `threshold` bytes are waiting, or when [`flush_writes()`] or
+    /// [`finish()`](Self::finish) is called. Passing `None` sends any held back data and restores
+    /// the default behavior of sending data as soon as possible.
+    ///
+    /// Coalescing reduces framing overhead at the cost of latency: held back data isn't sent until
+    /// the stream is flushed, however long that takes. Flushing through [`AsyncWrite`] also calls
+    /// [`flush_writes()`]. See [`proto::SendStream::set_write_coalescing`] for details.
+    ///
+    /// [`flush_writes()`]: Self::flush_writes
+    /// [`AsyncWrite`]: tokio::io::AsyncWrite
+    pub fn set_write_coalescing(&self, threshold: Option<u64>) -> Result<(), ClosedStream> {
+        let mut conn = self.conn.state.lock("SendStream::set_write_coalescing");
+        conn.inner
+            .send_stream(self.stream)
+            .set_write_coalescing(threshold)?;
+        conn.wake();
+        Ok(())
+    }
+
+    /// Make the stream partially reliable, abandoning stale data rather than retransmitting it
+    ///
+    /// Once data is stale according to `limit`, it's no longer sent or retransmitted, and the peer
+    /// skips over it, so that it can keep reading newer data. The peer sees the skipped ranges as
+    /// gaps in the offsets of chunks from [`RecvStream::read_chunk`](crate::RecvStream::read_chunk).
+    /// Passing `None` keeps all data written from now on until it's acknowledged.
+    ///
+    /// This is a Quinn extension, which only takes effect if the peer enabled
+    /// [`TransportConfig::partial_reliability`](crate::TransportConfig::partial_reliability); see
+    /// [`proto::SendStream::set_partial_reliability`].
+    pub fn set_partial_reliability(
+        &self,
+        limit: Option<PartialReliability>,
+    ) -> Result<(), ClosedStream> {
+        let mut conn = self.conn.state.lock("SendStream::set_partial_reliability");
+        conn.inner
+            .send_stream(self.stream)
+            .set_partial_reliability(limit)
+    }
+
+    /// Send all data held back by [`set_write_coalescing()`](Self::set_write_coalescing)
+    ///
+    /// Has no effect if writes aren't being coalesced.
+    pub fn flush_writes(&self) -> Result<(), ClosedStream> {
+        let mut conn = self.conn.state.lock("SendStream::flush_writes");
+        conn.inner.send_stream(self.stream).flush()?;
+        conn.wake();
+        Ok(())
+    }
+
     /// Get the priority of the send stream
+    ///
+    /// Returns the value most recently passed to [`set_priority()`](Self::set_priority), or 0.
     pub fn priority(&self) -> Result<i32, ClosedStream> {
         let mut conn = self.conn.state.lock("SendStream::priority");
         conn.inner.send_stream(self.stream).priority()
     }
 
+    /// Number of bytes of stream data transmitted at least once
+    ///
+    /// Counts application data, excluding framing and packet overhead. Retransmissions of lost
+    /// data aren't counted again. Fails with [`ClosedStream`] once the stream's state has been
+    /// discarded, i.e. after a reset or once finished and fully acknowledged.
+    pub fn bytes_sent(&self) -> Result<u64, ClosedStream> {
+        let mut conn = self.conn.state.lock("SendStream::bytes_sent");
+        conn.inner.send_stream(self.stream).bytes_sent()
+    }
+
+    /// Number of bytes of stream data acknowledged by the peer
+    ///
+    /// Counts application data, excluding framing and packet overhead, with each byte counted
+    /// once however many times it was transmitted. Fails with [`ClosedStream`] under the same
+    /// conditions as [`bytes_sent()`](Self::bytes_sent).
+    pub fn bytes_acked(&self) -> Result<u64, ClosedStream> {
+        let mut conn = self.conn.state.lock("SendStream::bytes_acked");
+        conn.inner.send_stream(self.stream).bytes_acked()
+    }
+
+    /// The limit that currently prevents writing to this stream, if any
+    ///
+    /// Explains why [`write()`](Self::write) is waiting, distinguishing flow control imposed by
+    /// the peer from a send window full of unacknowledged data. The result is a snapshot, which
+    /// may be out of date by the time it's inspected; see
+    /// [`proto::SendStream::write_stall_reason`].
+    pub fn write_stall_reason(&self) -> Option<WriteStallReason> {
+        let mut conn = self.conn.state.lock("SendStream::write_stall_reason");
+        conn.inner.send_stream(self.stream).write_stall_reason()
+    }
+
+    /// Set thresholds on the amount of data written to the stream but not yet acknowledged
+    ///
+    /// Once at least `high` bytes are unacknowledged, [`writable()`](Self::writable) waits until
+    /// no more than `low` bytes are, letting a producer pause generating data without busy-polling
+    /// [`write()`](Self::write). The watermarks don't otherwise affect writes, which remain subject
+    /// to flow and congestion control; see [`proto::SendStream::set_watermarks`].
+    ///
+    /// # Panics
+    /// - when `low` is greater than `high`
+    pub fn set_watermarks(&self, low: u64, high: u64) -> Result<(), ClosedStream> {
+        let mut conn = self.conn.state.lock("SendStream::set_watermarks");
+        conn.inner
+            .send_stream(self.stream)
+            .set_watermarks(low, high)
+    }
+
+    /// Completes when the producer should resume writing, according to the watermarks
+    ///
+    /// Resolves immediately unless the high watermark set by
+    /// [`set_watermarks()`](Self::set_watermarks) has been reached, in which case it waits for the
+    /// unacknowledged data to fall to the low watermark. Fails if writing to the stream would.
+    ///
+    /// # Cancel safety
+    ///
+    /// This method is cancellation safe.
+    pub async fn writable(&mut self) -> Result<(), WriteError> {
+        poll_fn(|cx| self.execute_poll(cx, |s| s.writable())).await
+    }
+
     /// Completes when the peer stops the stream or reads the stream to completion
     ///
     /// Yields `Some` with the stop error code if the peer stops the stream. Yields `None` if the
@@ -246,30 +400,7 @@ impl SendStream {
     pub fn stopped(
         &self,
     ) -> impl Future<Output = Result<Option<VarInt>, StoppedError>> + Send + Sync + 'static {
-        let conn = self.conn.clone();
-        let stream = self.stream;
-        let is_0rtt = self.is_0rtt;
-        async move {
-            loop {
-                // The `Notify::notified` future needs to be created while the lock is being held,
-                // otherwise a wakeup could be missed if triggered inbetween releasing the lock
-                // and creating the future.
-                // The lock may only be held in a block without `await`s, otherwise the future
-                // becomes `!Send`. `Notify::notified` is lifetime-bound to `Notify`, therefore
-                // we need to declare `notify` outside of the block, and initialize it inside.
-                let notify;
-                {
-                    let mut conn = conn.state.lock("SendStream::stopped");
-                    if let Some(output) = send_stream_stopped(&mut conn, stream, is_0rtt) {
-                        return output;
-                    }
-
-                    notify = conn.stopped.entry(stream).or_default().clone();
-                    notify.notified()
-                }
-                .await
-            }
-        }
+        stopped(self.conn.clone(), self.stream, self.is_0rtt)
     }
 
     /// Get the identity of this stream
@@ -277,6 +408,18 @@ impl SendStream {
         self.stream
     }
 
+    /// Convert into a handle that can be cloned and written to from multiple tasks
+    ///
+    /// See [`SharedSendStream`] for the ordering guarantees.
+    pub fn into_shared(self) -> SharedSendStream {
+        SharedSendStream {
+            conn: self.conn.clone(),
+            stream: self.stream,
+            is_0rtt: self.is_0rtt,
+            writer: Arc::new(tokio::sync::Mutex::new(self)),
+        }
+    }
+
     /// Attempt to write bytes from buf into the stream.
     ///
     /// On success, returns Poll::Ready(Ok(num_bytes_written)).
@@ -293,6 +436,52 @@ impl SendStream {
     }
 }
 
+/// Reset a send stream, waking any task blocked writing to it
+fn reset_send_stream(
+    conn: &ConnectionRef,
+    stream: StreamId,
+    is_0rtt: bool,
+    error_code: VarInt,
+) -> Result<(), ClosedStream> {
+    let mut conn = conn.state.lock("SendStream::reset");
+    if is_0rtt && conn.check_0rtt().is_err() {
+        return Ok(());
+    }
+    conn.inner.send_stream(stream).reset(error_code)?;
+    if let Some(waker) = conn.blocked_writers.remove(&stream) {
+        waker.wake();
+    }
+    conn.wake();
+    Ok(())
+}
+
+/// Completes when the stream is stopped or fully acknowledged, see [`SendStream::stopped`]
+async fn stopped(
+    conn: ConnectionRef,
+    stream: StreamId,
+    is_0rtt: bool,
+) -> Result<Option<VarInt>, StoppedError> {
+    loop {
+        // The `Notify::notified` future needs to be created while the lock is being held,
+        // otherwise a wakeup could be missed if triggered inbetween releasing the lock
+        // and creating the future.
+        // The lock may only be held in a block without `await`s, otherwise the future
+        // becomes `!Send`. `Notify::notified` is lifetime-bound to `Notify`, therefore
+        // we need to declare `notify` outside of the block, and initialize it inside.
+        let notify;
+        {
+            let mut conn = conn.state.lock("SendStream::stopped");
+            if let Some(output) = send_stream_stopped(&mut conn, stream, is_0rtt) {
+                return output;
+            }
+
+            notify = conn.stopped.entry(stream).or_default().clone();
+            notify.notified()
+        }
+        .await
+    }
+}
+
 /// Check if a send stream is stopped.
 ///
 /// Returns `Some` if the stream is stopped or the connection is closed.
@@ -323,6 +512,8 @@ impl futures_io::AsyncWrite for SendStream {
     }
 
     fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Flushing a closed stream is harmless
+        let _ = self.flush_writes();
         Poll::Ready(Ok(()))
     }
 
@@ -341,6 +532,8 @@ impl tokio::io::AsyncWrite for SendStream {
     }
 
     fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Flushing a closed stream is harmless
+        let _ = self.flush_writes();
         Poll::Ready(Ok(()))
     }
 
@@ -372,6 +565,121 @@ impl Drop for SendStream {
     }
 }
 
+/// A [`SendStream`] that can be written to from multiple tasks
+///
+/// Obtained from [`SendStream::into_shared`]. Clones refer to the same stream, and each of the
+/// `write` methods appends its entire input as one contiguous run of bytes: concurrent writes are
+/// serialized in the order they started waiting, rather than interleaved. This makes it possible
+/// for several producers to send self-delimiting messages on a single stream without further
+/// coordination. No ordering is guaranteed between writes of different tasks beyond that.
+///
+/// The stream is implicitly [`finish()`](Self::finish)ed once all clones are dropped, like a
+/// [`SendStream`].
+///
+/// # Cancellation
+///
+/// If a write future is dropped after writing part of its input, the rest of the stream could
+/// no longer be parsed by the peer, so the stream is [`reset()`](Self::reset) with error code 0.
+/// Dropping a write future before any of its input was written has no effect.
+#[derive(Debug, Clone)]
+pub struct SharedSendStream {
+    conn: ConnectionRef,
+    stream: StreamId,
+    is_0rtt: bool,
+    writer: Arc<tokio::sync::Mutex<SendStream>>,
+}
+
+impl SharedSendStream {
+    /// Write a buffer into this stream in its entirety, without interleaving other writes
+    ///
+    /// Waits for writes from other tasks to complete first.
+    pub async fn write_all(&self, mut buf: &[u8]) -> Result<(), WriteError> {
+        let mut stream = self.writer.lock().await;
+        let mut guard = PartialWriteGuard {
+            stream: &mut stream,
+            partial: false,
+        };
+        while !buf.is_empty() {
+            let written = guard.stream.write(buf).await?;
+            buf = &buf[written..];
+            guard.partial = !buf.is_empty();
+        }
+        Ok(())
+    }
+
+    /// Write a single [`Bytes`] into this stream in its entirety, without interleaving other
+    /// writes
+    ///
+    /// Waits for writes from other tasks to complete first.
+    pub async fn write_chunk(&self, buf: Bytes) -> Result<(), WriteError> {
+        self.write_all_chunks(&mut [buf]).await
+    }
+
+    /// Write a slice of [`Bytes`] into this stream in its entirety, without interleaving other
+    /// writes
+    ///
+    /// Waits for writes from other tasks to complete first. The chunks are written back to back,
+    /// and `bufs` is mutated as by [`SendStream::write_all_chunks`].
+    pub async fn write_all_chunks(&self, mut bufs: &mut [Bytes]) -> Result<(), WriteError> {
+        let mut stream = self.writer.lock().await;
+        let mut guard = PartialWriteGuard {
+            stream: &mut stream,
+            partial: false,
+        };
+        while !bufs.is_empty() {
+            let written = guard.stream.write_chunks(bufs).await?;
+            bufs = &mut bufs[written.chunks..];
+            guard.partial = !bufs.is_empty();
+        }
+        Ok(())
+    }
+
+    /// Notify the peer that no more data will ever be written to this stream
+    ///
+    /// Waits for writes from other tasks to complete first, then behaves like
+    /// [`SendStream::finish`]. Subsequent writes from any clone fail.
+    pub async fn finish(&self) -> Result<(), ClosedStream> {
+        self.writer.lock().await.finish()
+    }
+
+    /// Close the send stream immediately
+    ///
+    /// Unlike [`finish()`](Self::finish), this doesn't wait for other writes to complete: writes
+    /// in progress fail with [`WriteError::ClosedStream`]. See [`SendStream::reset`].
+    pub fn reset(&self, error_code: VarInt) -> Result<(), ClosedStream> {
+        reset_send_stream(&self.conn, self.stream, self.is_0rtt, error_code)
+    }
+
+    /// Completes when the peer stops the stream or reads the stream to completion
+    ///
+    /// See [`SendStream::stopped`].
+    pub fn stopped(
+        &self,
+    ) -> impl Future<Output = Result<Option<VarInt>, StoppedError>> + Send + Sync + 'static {
+        stopped(self.conn.clone(), self.stream, self.is_0rtt)
+    }
+
+    /// Get the identity of this stream
+    pub fn id(&self) -> StreamId {
+        self.stream
+    }
+}
+
+/// Resets the stream if dropped while a write has only been partially completed
+struct PartialWriteGuard<'a> {
+    stream: &'a mut SendStream,
+    partial: bool,
+}
+
+impl Drop for PartialWriteGuard<'_> {
+    fn drop(&mut self) {
+        if self.partial {
+            // Ignore ClosedStream errors
+            let _ = self.stream.reset(0u32.into());
+        }
+    }
+}
+
 /// Errors that arise from writing to a stream
 #[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum WriteError {
@@ -0,0 +1,333 @@
+//! Transport through a SOCKS5 proxy's UDP relay, see [`Socks5UdpSocket`]
+
+use std::{
+    fmt,
+    io::{self, IoSliceMut, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use tracing::trace;
+use udp::{RecvMeta, Transmit};
+
+use crate::{AsyncUdpSocket, UdpSender};
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const UDP_ASSOCIATE: u8 = 3;
+const ATYP_IPV4: u8 = 1;
+const ATYP_IPV6: u8 = 4;
+
+/// An [`AsyncUdpSocket`] sending and receiving all datagrams through a SOCKS5 proxy
+///
+/// Datagrams are exchanged with the proxy's UDP relay, established by a `UDP ASSOCIATE` request
+/// (RFC 1928 §7). Each datagram is prefixed with a header carrying the address of the peer it's
+/// destined to or received from, so connections traverse the proxy transparently and see the
+/// address of the peer rather than the relay. Use it with
+/// [`Endpoint::new_with_abstract_socket`](crate::Endpoint::new_with_abstract_socket).
+///
+/// Limitations:
+/// - Only proxies accepting clients without authentication are supported.
+/// - The header must be added to every datagram, so GSO is never used, and datagrams the kernel
+///   coalesced with GRO are delivered only as long as their headers match.
+/// - Fragmentation as defined by SOCKS5 isn't supported. Datagrams are always sent whole, and
+///   fragments received from the relay are dropped, as RFC 1928 requires of such implementations.
+/// - The header grows datagrams by up to 22 bytes between the host and the relay, and the relay's
+///   socket options are unknown, so MTU discovery is disabled.
+/// - Datagrams from peers the relay identifies by domain name rather than by IP address, and
+///   datagrams from addresses other than the relay, are dropped.
+/// - ICMP errors concerning the path beyond the relay aren't reported.
+pub struct Socks5UdpSocket {
+    inner: Box<dyn AsyncUdpSocket>,
+    relay: SocketAddr,
+    ipv6: bool,
+    /// The UDP association is terminated when this connection closes
+    _control: TcpStream,
+}
+
+impl Socks5UdpSocket {
+    /// Associate `inner` with the UDP relay of the proxy connected to by `control`
+    ///
+    /// Performs the SOCKS5 handshake on `control`, blocking the current thread until the proxy
+    /// answers, so consider calling this from a blocking context. The proxy keeps the association
+    /// open as long as `control` is, which is hence owned by the returned socket. If the proxy
+    /// reports an unspecified relay IP address, as is common, the proxy's IP address is used.
+    pub fn new(inner: Box<dyn AsyncUdpSocket>, control: TcpStream) -> io::Result<Self> {
+        let local = inner.local_addr()?;
+        let mut relay = associate(&control, local)?;
+        if relay.ip().is_unspecified() {
+            relay.set_ip(control.peer_addr()?.ip());
+        }
+        let ipv6 = local.is_ipv6();
+        let relay = match (relay, ipv6) {
+            (SocketAddr::V4(relay), true) => {
+                SocketAddr::new(relay.ip().to_ipv6_mapped().into(), relay.port())
+            }
+            (SocketAddr::V6(_), false) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "IPv6 relay unreachable from IPv4 socket",
+                ));
+            }
+            (relay, _) => relay,
+        };
+        trace!(%relay, "SOCKS5 UDP association established");
+        Ok(Self {
+            inner,
+            relay,
+            ipv6,
+            _control: control,
+        })
+    }
+
+    /// Address of the proxy's UDP relay, which all datagrams are sent to
+    pub fn relay_addr(&self) -> SocketAddr {
+        self.relay
+    }
+
+    /// Strip the headers of the datagrams in `buf`, as described by `meta`
+    ///
+    /// Returns `false` if no datagram is left.
+    fn decapsulate(&self, buf: &mut [u8], meta: &mut RecvMeta) -> bool {
+        if meta.addr.ip().to_canonical() != self.relay.ip().to_canonical()
+            || meta.addr.port() != self.relay.port()
+        {
+            trace!(addr = %meta.addr, "dropping datagram not from the SOCKS5 relay");
+            return false;
+        }
+
+        // Datagrams coalesced by GRO all have the same length, except for the last one, and must
+        // keep doing so once their headers are removed
+        let stride = meta.stride.max(1);
+        let mut peer = None;
+        let mut payload_stride = 0;
+        let mut len = 0;
+        for start in (0..meta.len).step_by(stride) {
+            let end = (start + stride).min(meta.len);
+            let Some((addr, header_len)) = decode_header(&buf[start..end]) else {
+                trace!("dropping malformed or fragmented SOCKS5 datagram");
+                break;
+            };
+            let payload_len = end - start - header_len;
+            match peer {
+                None => {
+                    peer = Some(addr);
+                    payload_stride = payload_len;
+                }
+                Some(peer) if peer != addr || payload_len > payload_stride => {
+                    trace!("dropping coalesced SOCKS5 datagrams from distinct peers");
+                    break;
+                }
+                Some(_) => {}
+            }
+            buf.copy_within(start + header_len..end, len);
+            len += payload_len;
+            if payload_len < payload_stride {
+                // Only the last datagram may be shorter
+                break;
+            }
+        }
+
+        let Some(peer) = peer.filter(|_| len > 0) else {
+            return false;
+        };
+        meta.addr = match (peer, self.ipv6) {
+            (SocketAddr::V4(peer), true) => {
+                SocketAddr::new(peer.ip().to_ipv6_mapped().into(), peer.port())
+            }
+            (peer, _) => peer,
+        };
+        meta.len = len;
+        meta.stride = payload_stride;
+        true
+    }
+}
+
+impl AsyncUdpSocket for Socks5UdpSocket {
+    fn create_sender(&self) -> Pin<Box<dyn UdpSender>> {
+        Box::pin(Socks5Sender {
+            inner: self.inner.create_sender(),
+            relay: self.relay,
+            buf: Vec::new(),
+        })
+    }
+
+    fn poll_recv(
+        &mut self,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let n = ready!(self.inner.poll_recv(cx, bufs, meta))?;
+            let mut any = false;
+            for (buf, meta) in bufs.iter_mut().zip(meta.iter_mut()).take(n) {
+                if self.decapsulate(buf, meta) {
+                    any = true;
+                } else {
+                    // Empty buffers are skipped by the endpoint, which saves moving the remaining
+                    // datagrams
+                    meta.len = 0;
+                }
+            }
+            if any {
+                return Poll::Ready(Ok(n));
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn max_receive_segments(&self) -> usize {
+        self.inner.max_receive_segments()
+    }
+
+    fn may_fragment(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Debug for Socks5UdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socks5UdpSocket")
+            .field("inner", &self.inner)
+            .field("relay", &self.relay)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Socks5Sender {
+    inner: Pin<Box<dyn UdpSender>>,
+    relay: SocketAddr,
+    /// The datagram being sent, including its header
+    buf: Vec<u8>,
+}
+
+impl UdpSender for Socks5Sender {
+    fn poll_send(
+        self: Pin<&mut Self>,
+        transmit: &Transmit<'_>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        debug_assert!(
+            transmit
+                .segment_size
+                .is_none_or(|size| size >= transmit.contents.len()),
+            "GSO through a SOCKS5 relay"
+        );
+        let this = self.get_mut();
+        this.buf.clear();
+        // Reserved bytes and fragment number
+        this.buf.extend_from_slice(&[0, 0, 0]);
+        encode_addr(&mut this.buf, transmit.destination);
+        this.buf.extend_from_slice(transmit.contents);
+        this.inner.as_mut().poll_send(
+            &Transmit {
+                destination: this.relay,
+                ecn: transmit.ecn,
+                contents: &this.buf,
+                segment_size: None,
+                src_ip: transmit.src_ip,
+            },
+            cx,
+        )
+    }
+}
+
+/// Request a UDP association for datagrams sent from `local`, returning the relay's address
+fn associate(control: &TcpStream, local: SocketAddr) -> io::Result<SocketAddr> {
+    let mut control = control;
+    control.write_all(&[VERSION, 1, NO_AUTHENTICATION])?;
+    let mut reply = [0; 2];
+    control.read_exact(&mut reply)?;
+    if reply != [VERSION, NO_AUTHENTICATION] {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "SOCKS5 proxy requires authentication",
+        ));
+    }
+
+    let mut request = vec![VERSION, UDP_ASSOCIATE, 0];
+    encode_addr(&mut request, local);
+    control.write_all(&request)?;
+
+    let mut reply = [0; 4];
+    control.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid SOCKS5 reply",
+        ));
+    }
+    if reply[1] != 0 {
+        return Err(io::Error::other(format!(
+            "SOCKS5 UDP association failed with code {}",
+            reply[1]
+        )));
+    }
+    let ip = match reply[3] {
+        ATYP_IPV4 => {
+            let mut ip = [0; 4];
+            control.read_exact(&mut ip)?;
+            IpAddr::from(ip)
+        }
+        ATYP_IPV6 => {
+            let mut ip = [0; 16];
+            control.read_exact(&mut ip)?;
+            IpAddr::from(ip)
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "SOCKS5 relay not identified by IP address",
+            ));
+        }
+    };
+    let mut port = [0; 2];
+    control.read_exact(&mut port)?;
+    Ok(SocketAddr::new(ip, u16::from_be_bytes(port)))
+}
+
+/// Write `addr` in the format shared by requests and the headers of datagrams
+fn encode_addr(buf: &mut Vec<u8>, addr: SocketAddr) {
+    match addr.ip().to_canonical() {
+        IpAddr::V4(ip) => {
+            buf.push(ATYP_IPV4);
+            buf.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            buf.push(ATYP_IPV6);
+            buf.extend_from_slice(&ip.octets());
+        }
+    }
+    buf.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+/// Parse the header of a datagram, returning the peer's address and the header's length
+///
+/// Returns `None` for fragments, which aren't supported, and peers identified by domain name.
+fn decode_header(datagram: &[u8]) -> Option<(SocketAddr, usize)> {
+    let (&frag, rest) = datagram.get(2..)?.split_first()?;
+    if frag != 0 {
+        return None;
+    }
+    let (&atyp, rest) = rest.split_first()?;
+    let (ip, rest) = match atyp {
+        ATYP_IPV4 => {
+            let (ip, rest) = rest.split_first_chunk::<4>()?;
+            (IpAddr::from(Ipv4Addr::from(*ip)), rest)
+        }
+        ATYP_IPV6 => {
+            let (ip, rest) = rest.split_first_chunk::<16>()?;
+            (IpAddr::from(Ipv6Addr::from(*ip)), rest)
+        }
+        _ => return None,
+    };
+    let (port, rest) = rest.split_first_chunk::<2>()?;
+    let addr = SocketAddr::new(ip, u16::from_be_bytes(*port));
+    Some((addr, datagram.len() - rest.len()))
+}
@@ -10,15 +10,17 @@ use std::{
     future::Future,
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    ops::{Deref, DerefMut},
+    pin::Pin,
     str,
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
     task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
 };
 
-use crate::runtime::TokioRuntime;
+use crate::runtime::{AsyncTimer, AsyncUdpSocket, TokioRuntime, VirtualTimeRuntime};
 use crate::{Duration, Instant};
 use bytes::Bytes;
 use proto::{RandomConnectionIdGenerator, crypto::rustls::QuicClientConfig};
@@ -33,7 +35,9 @@ use tracing::{error_span, info};
 use tracing_futures::Instrument as _;
 use tracing_subscriber::EnvFilter;
 
-use super::{ClientConfig, Endpoint, EndpointConfig, RecvStream, SendStream, TransportConfig};
+use super::{
+    ClientConfig, Endpoint, EndpointConfig, RecvStream, SendStream, Side, TransportConfig, VarInt,
+};
 
 #[test]
 fn handshake_timeout() {
@@ -133,6 +137,36 @@ fn local_addr() {
     );
 }
 
+#[test]
+#[cfg(unix)]
+fn socket_fd() {
+    use std::os::fd::BorrowedFd;
+
+    // Look up the address of the socket behind `fd` with `getsockname`
+    fn sockname(fd: std::os::fd::RawFd) -> SocketAddr {
+        // Safety: the endpoint keeps the socket open while we use it
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        socket2::SockRef::from(&fd)
+            .local_addr()
+            .unwrap()
+            .as_socket()
+            .unwrap()
+    }
+
+    let runtime = rt_basic();
+    let _guard = runtime.enter();
+    let socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+    let addr = socket.local_addr().unwrap();
+    let ep = Endpoint::new(Default::default(), None, socket, Arc::new(TokioRuntime)).unwrap();
+    assert_eq!(sockname(ep.socket_fd().unwrap()), addr);
+
+    // The descriptor follows rebinds
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = socket.local_addr().unwrap();
+    ep.rebind(socket).unwrap();
+    assert_eq!(sockname(ep.socket_fd().unwrap()), addr);
+}
+
 #[test]
 fn read_after_close() {
     let _guard = subscribe();
@@ -259,6 +293,90 @@ async fn ip_blocking() {
     server_task.abort();
 }
 
+#[tokio::test]
+async fn inject_datagram_initial() {
+    let _guard = subscribe();
+    let endpoint_factory = EndpointFactory::new();
+    let server = endpoint_factory.endpoint();
+    let server_addr = server.local_addr().unwrap();
+    let peer = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+    let peer_addr = peer.local_addr().unwrap();
+
+    // Produce a client's first flight without a quinn endpoint
+    let mut roots = RootCertStore::empty();
+    roots.add(endpoint_factory.cert.cert.der().clone()).unwrap();
+    let client_config = ClientConfig::with_root_certificates(Arc::new(roots)).unwrap();
+    let mut client = proto::Endpoint::new(Arc::new(EndpointConfig::default()), None, true);
+    let now = Instant::now();
+    let (_, mut conn) = client
+        .connect(now, client_config, server_addr, "localhost")
+        .unwrap();
+    let mut buf = Vec::new();
+    let transmit = conn.poll_transmit(now, 1, &mut buf).unwrap();
+    let initial = Bytes::copy_from_slice(&buf[..transmit.size]);
+
+    // Client Initials must be padded to 1200 bytes, so a truncated one is ignored
+    server.inject_datagram(peer_addr, initial.slice(..1000));
+    assert!(
+        timeout(Duration::from_millis(100), server.accept())
+            .await
+            .is_err()
+    );
+
+    server.inject_datagram(peer_addr, initial);
+    let incoming = server.accept().await.unwrap();
+    assert_eq!(incoming.remote_address(), peer_addr);
+    let _connecting = incoming.accept().unwrap();
+
+    // The server's response goes out through the socket, addressed to the injected source
+    let mut response = [0; 1500];
+    let (len, from) = timeout(Duration::from_secs(5), peer.recv_from(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(from, server_addr);
+    assert!(len >= 1200);
+    assert_ne!(response[0] & 0x80, 0, "expected a long header packet");
+}
+
+#[tokio::test]
+async fn inject_datagram_unknown_version() {
+    let _guard = subscribe();
+    let server = endpoint();
+    let peer = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+
+    // A long header with a reserved version, an 8-byte destination CID and no source CID
+    let mut packet = vec![0xc0, 0x0a, 0x1a, 0x2a, 0x3a, 8];
+    packet.extend_from_slice(&[0x42; 8]);
+    packet.push(0);
+    packet.resize(1200, 0);
+    let packet = Bytes::from(packet);
+
+    // Stateless responses are dropped if the socket isn't ready, so retry like a real peer would
+    let mut response = [0; 1500];
+    let len = timeout(Duration::from_secs(5), async {
+        loop {
+            server.inject_datagram(peer.local_addr().unwrap(), packet.clone());
+            let recv = timeout(Duration::from_millis(100), peer.recv_from(&mut response));
+            if let Ok(result) = recv.await {
+                break result.unwrap().0;
+            }
+        }
+    })
+    .await
+    .expect("no Version Negotiation packet received");
+    // Version Negotiation packets carry version 0, followed by the CIDs swapped around and the
+    // supported versions
+    assert_eq!(response[1..5], [0; 4]);
+    assert_eq!(response[5..7], [0, 8]);
+    assert_eq!(response[7..15], [0x42; 8]);
+    assert!(len > 15 && (len - 15) % 4 == 0);
+}
+
 /// Construct an endpoint suitable for connecting to itself
 fn endpoint() -> Endpoint {
     EndpointFactory::new().endpoint()
@@ -272,6 +390,7 @@ fn endpoint_with_config(transport_config: TransportConfig) -> Endpoint {
 struct EndpointFactory {
     cert: rcgen::CertifiedKey<rcgen::KeyPair>,
     endpoint_config: EndpointConfig,
+    runtime: Arc<dyn crate::Runtime>,
 }
 
 impl EndpointFactory {
@@ -279,6 +398,7 @@ impl EndpointFactory {
         Self {
             cert: rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap(),
             endpoint_config: EndpointConfig::default(),
+            runtime: Arc::new(TokioRuntime),
         }
     }
 
@@ -299,7 +419,7 @@ impl EndpointFactory {
             self.endpoint_config.clone(),
             Some(server_config),
             UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap(),
-            Arc::new(TokioRuntime),
+            self.runtime.clone(),
         )
         .unwrap();
         let mut client_config = ClientConfig::with_root_certificates(Arc::new(roots)).unwrap();
@@ -310,6 +430,56 @@ impl EndpointFactory {
     }
 }
 
+#[tokio::test]
+async fn shared_rustls_server_config() {
+    let _guard = subscribe();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(cert.signing_key.serialize_der().into());
+    let mut crypto = rustls::ServerConfig::builder_with_provider(default_provider().into())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)
+        .unwrap();
+    crypto.max_early_data_size = u32::MAX;
+    let crypto = Arc::new(crypto);
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let client = Endpoint::client(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    client
+        .set_default_client_config(ClientConfig::with_root_certificates(Arc::new(roots)).unwrap());
+
+    // Each endpoint keeps its own transport parameters despite sharing the TLS configuration
+    for datagrams in [false, true] {
+        let mut server_config = crate::ServerConfig::with_rustls(crypto.clone()).unwrap();
+        let mut transport = TransportConfig::default();
+        if !datagrams {
+            transport.datagram_receive_buffer_size(None);
+        }
+        server_config.transport_config(Arc::new(transport));
+        let server = Endpoint::server(
+            server_config,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+        )
+        .unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let server_task = tokio::spawn(async move {
+            let connection = server.accept().await.unwrap().await.unwrap();
+            connection.closed().await;
+        });
+        let connection = client
+            .connect(server_addr, "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(connection.max_datagram_size().is_some(), datagrams);
+        connection.close(0u32.into(), b"done");
+        server_task.await.unwrap();
+    }
+}
+
 #[tokio::test]
 async fn zero_rtt() {
     let _guard = subscribe();
@@ -342,6 +512,7 @@ async fn zero_rtt() {
         }
     });
 
+    assert!(!endpoint.can_resume_0rtt("localhost"));
     let connection = endpoint
         .connect(endpoint.local_addr().unwrap(), "localhost")
         .unwrap()
@@ -364,6 +535,8 @@ async fn zero_rtt() {
     }
 
     info!("initial connection complete");
+    assert!(endpoint.can_resume_0rtt("localhost"));
+    assert!(!endpoint.can_resume_0rtt("example.com"));
 
     let (connection, zero_rtt) = endpoint
         .connect(endpoint.local_addr().unwrap(), "localhost")
@@ -389,6 +562,406 @@ async fn zero_rtt() {
     endpoint.wait_idle().await;
 }
 
+#[tokio::test]
+async fn connect_bi() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+    let addr = endpoint.local_addr().unwrap();
+    let config = endpoint.default_client_config().unwrap();
+
+    const REQUEST: &[u8] = b"request";
+    const RESPONSE: &[u8] = b"response";
+    let endpoint2 = endpoint.clone();
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint2.accept().await {
+            let incoming = incoming.accept().unwrap();
+            let (connection, _) = incoming.into_0rtt().unwrap_or_else(|_| unreachable!());
+            tokio::spawn(async move {
+                while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                    let msg = recv.read_to_end(usize::MAX).await.unwrap();
+                    assert_eq!(msg, REQUEST);
+                    send.write_all(RESPONSE).await.unwrap();
+                    send.finish().unwrap();
+                }
+            });
+        }
+    });
+
+    async fn request(send: &mut SendStream, recv: &mut RecvStream) {
+        send.write_all(REQUEST).await.unwrap();
+        send.finish().unwrap();
+        let msg = recv.read_to_end(usize::MAX).await.unwrap();
+        assert_eq!(msg, RESPONSE);
+    }
+
+    // Without session keys, the handshake has to complete first
+    let (connection, (mut send, mut recv)) = endpoint
+        .connect_bi(config.clone(), addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    request(&mut send, &mut recv).await;
+    assert!(!connection.stats().used_0rtt);
+    connection.close(0u32.into(), b"done");
+
+    // The manual sequence waits for the handshake before it can open a stream
+    let start = Instant::now();
+    let connection = endpoint
+        .connect_with(config.clone(), addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    request(&mut send, &mut recv).await;
+    let manual = start.elapsed();
+    connection.close(0u32.into(), b"done");
+
+    // Resuming the session, the request goes out in 0-RTT alongside the handshake
+    let start = Instant::now();
+    let (connection, (mut send, mut recv)) = endpoint
+        .connect_bi(config, addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    request(&mut send, &mut recv).await;
+    let combined = start.elapsed();
+    // Loopback RTTs are too small to compare the timings reliably, so check the round trip was
+    // actually saved instead
+    info!(?manual, ?combined, "request latency");
+    let stats = connection.stats();
+    assert!(stats.used_0rtt);
+    assert!(stats.zero_rtt_accepted);
+    connection.close(0u32.into(), b"done");
+
+    endpoint.wait_idle().await;
+}
+
+#[tokio::test]
+async fn negotiated_version() {
+    let _guard = subscribe();
+    let mut factory = EndpointFactory::new();
+    factory.endpoint_config.supported_versions(vec![1]);
+    let server = factory.endpoint();
+    factory
+        .endpoint_config
+        .supported_versions(vec![0xff00_001d, 1]);
+    let client = factory.endpoint();
+
+    let mut config = client.default_client_config().unwrap();
+    config.version(0xff00_001d);
+    for (server, expected) in [(&client, 0xff00_001d), (&server, 1)] {
+        // The client prefers a draft version, but falls back to QUIC v1 if the server only
+        // supports that
+        let (client_conn, server_conn) = tokio::join!(
+            async {
+                client
+                    .connect_with(config.clone(), server.local_addr().unwrap(), "localhost")
+                    .unwrap()
+                    .await
+                    .unwrap()
+            },
+            async { server.accept().await.unwrap().await.unwrap() }
+        );
+        assert_eq!(client_conn.version(), expected);
+        assert_eq!(server_conn.version(), expected);
+    }
+}
+
+#[tokio::test]
+async fn alpn() {
+    let _guard = subscribe();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(cert.signing_key.serialize_der().into());
+    let mut crypto = rustls::ServerConfig::builder_with_provider(default_provider().into())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)
+        .unwrap();
+    crypto.alpn_protocols = vec![b"foo".to_vec(), b"bar".to_vec()];
+    let server = Endpoint::server(
+        crate::ServerConfig::with_rustls(Arc::new(crypto)).unwrap(),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+    )
+    .unwrap();
+    let server_addr = server.local_addr().unwrap();
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let roots = Arc::new(roots);
+    let client = Endpoint::client(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+
+    // The same server negotiates whichever of its protocols each client asks for
+    for (offered, expected) in [
+        (vec![b"bar".to_vec()], &b"bar"[..]),
+        (vec![b"baz".to_vec(), b"foo".to_vec()], &b"foo"[..]),
+    ] {
+        let mut crypto = rustls::ClientConfig::builder_with_provider(default_provider().into())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots.clone())
+            .with_no_client_auth();
+        crypto.alpn_protocols = offered;
+        let config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(crypto).unwrap()));
+        let (client_conn, server_conn) = tokio::join!(
+            async {
+                client
+                    .connect_with(config, server_addr, "localhost")
+                    .unwrap()
+                    .await
+                    .unwrap()
+            },
+            async { server.accept().await.unwrap().await.unwrap() }
+        );
+        assert_eq!(client_conn.alpn().unwrap(), expected);
+        assert_eq!(server_conn.alpn().unwrap(), expected);
+    }
+}
+
+#[tokio::test]
+async fn connection_side() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+    let (client_conn, server_conn) = tokio::join!(
+        async {
+            endpoint
+                .connect(endpoint.local_addr().unwrap(), "localhost")
+                .unwrap()
+                .await
+                .unwrap()
+        },
+        async { endpoint.accept().await.unwrap().await.unwrap() }
+    );
+    assert_eq!(client_conn.side(), Side::Client);
+    assert_eq!(server_conn.side(), Side::Server);
+}
+
+#[tokio::test]
+async fn survives_icmp_errors() {
+    use crate::runtime::{Runtime as _, UdpSender};
+
+    /// Fails receives with the errors reported for queued ICMP messages, before receiving anything
+    #[derive(Debug)]
+    struct IcmpErrorSocket {
+        inner: Box<dyn AsyncUdpSocket>,
+        errors: Vec<io::Error>,
+    }
+
+    impl AsyncUdpSocket for IcmpErrorSocket {
+        fn create_sender(&self) -> Pin<Box<dyn UdpSender>> {
+            self.inner.create_sender()
+        }
+
+        fn poll_recv(
+            &mut self,
+            cx: &mut Context<'_>,
+            bufs: &mut [io::IoSliceMut<'_>],
+            meta: &mut [udp::RecvMeta],
+        ) -> Poll<io::Result<usize>> {
+            match self.errors.pop() {
+                Some(e) => Poll::Ready(Err(e)),
+                None => self.inner.poll_recv(cx, bufs, meta),
+            }
+        }
+
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.inner.local_addr()
+        }
+    }
+
+    let _guard = subscribe();
+    let factory = EndpointFactory::new();
+    let endpoint = factory.endpoint();
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let mut errors = vec![
+        io::ErrorKind::ConnectionReset.into(),
+        io::ErrorKind::ConnectionRefused.into(),
+        io::ErrorKind::HostUnreachable.into(),
+        io::ErrorKind::NetworkUnreachable.into(),
+    ];
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    errors.push(io::Error::from_raw_os_error(libc::EMSGSIZE));
+    endpoint
+        .rebind_abstract(Box::new(IcmpErrorSocket {
+            inner: TokioRuntime.wrap_udp_socket(socket).unwrap(),
+            errors,
+        }))
+        .unwrap();
+
+    // Anyone can spoof ICMP messages, so they mustn't bring the endpoint down
+    let (client_conn, server_conn) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    client_conn.unwrap();
+    server_conn.unwrap();
+}
+
+#[tokio::test]
+async fn client_and_server_roles() {
+    let _guard = subscribe();
+    let factory = EndpointFactory::new();
+    let a = factory.endpoint();
+    let b = factory.endpoint();
+    let a_addr = a.local_addr().unwrap();
+    let b_addr = b.local_addr().unwrap();
+
+    // Each endpoint connects to the other while accepting the other's connection
+    let (a_out, b_out, a_in, b_in) = tokio::join!(
+        a.connect(b_addr, "localhost").unwrap(),
+        b.connect(a_addr, "localhost").unwrap(),
+        async { a.accept().await.unwrap().await },
+        async { b.accept().await.unwrap().await },
+    );
+    let (a_out, b_out, a_in, b_in) = (a_out.unwrap(), b_out.unwrap(), a_in.unwrap(), b_in.unwrap());
+    assert_eq!(a_out.side(), Side::Client);
+    assert_eq!(a_in.side(), Side::Server);
+    assert_eq!(a_out.remote_address(), b_addr);
+    assert_eq!(a_in.remote_address(), b_addr);
+    assert_eq!(b_out.side(), Side::Client);
+    assert_eq!(b_in.side(), Side::Server);
+
+    // Without a server configuration, connection attempts are ignored, while connecting out and
+    // existing connections keep working
+    a.set_server_config(None);
+    let mut transport = TransportConfig::default();
+    transport.max_idle_timeout(Some(Duration::from_millis(200).try_into().unwrap()));
+    let mut config = b.default_client_config().unwrap();
+    config.transport_config(Arc::new(transport));
+    let error = b
+        .connect_with(config, a_addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap_err();
+    assert!(matches!(error, crate::ConnectionError::TimedOut));
+    // Not even a stateless reset is sent back
+    let peer = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+    let mut client = proto::Endpoint::new(Arc::new(EndpointConfig::default()), None, true);
+    let now = Instant::now();
+    let (_, mut conn) = client
+        .connect(now, b.default_client_config().unwrap(), a_addr, "localhost")
+        .unwrap();
+    let mut buf = Vec::new();
+    let transmit = conn.poll_transmit(now, 1, &mut buf).unwrap();
+    peer.send_to(&buf[..transmit.size], a_addr).await.unwrap();
+    let mut response = [0; 1500];
+    assert!(
+        timeout(Duration::from_millis(200), peer.recv_from(&mut response))
+            .await
+            .is_err()
+    );
+    let (a_out, b_in) = tokio::join!(a.connect(b_addr, "localhost").unwrap(), async {
+        b.accept().await.unwrap().await
+    });
+    let (a_out, b_in) = (a_out.unwrap(), b_in.unwrap());
+    const MSG: &[u8] = b"hello";
+    let mut send = a_out.open_uni().await.unwrap();
+    send.write_all(MSG).await.unwrap();
+    send.finish().unwrap();
+    let mut recv = b_in.accept_uni().await.unwrap();
+    assert_eq!(recv.read_to_end(MSG.len()).await.unwrap(), MSG);
+
+    // Accepting resumes once a server configuration is set again
+    let key = PrivateKeyDer::Pkcs8(factory.cert.signing_key.serialize_der().into());
+    let server_config =
+        crate::ServerConfig::with_single_cert(vec![factory.cert.cert.der().clone()], key).unwrap();
+    a.set_server_config(Some(server_config));
+    let (b_out, a_in) = tokio::join!(b.connect(a_addr, "localhost").unwrap(), async {
+        a.accept().await.unwrap().await
+    });
+    assert_eq!(b_out.unwrap().remote_address(), a_addr);
+    assert_eq!(a_in.unwrap().side(), Side::Server);
+}
+
+#[tokio::test]
+async fn custom_recv_buffer() {
+    let _guard = subscribe();
+    let runtime = Arc::new(RecvBufferRuntime::default());
+    let mut factory = EndpointFactory::new();
+    factory.runtime = runtime.clone();
+    let endpoint = factory.endpoint();
+    assert_eq!(runtime.allocated.load(Ordering::Relaxed), 1);
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+    let mut send = client.open_uni().await.unwrap();
+    send.write_all(b"hello").await.unwrap();
+    send.finish().unwrap();
+    let mut recv = server.accept_uni().await.unwrap();
+    assert_eq!(recv.read_to_end(usize::MAX).await.unwrap(), b"hello");
+
+    // The endpoint borrows the buffer again for each read, so this is visible once the close
+    // has been exchanged
+    client.close(0u32.into(), b"done");
+    endpoint.wait_idle().await;
+    assert!(runtime.used.load(Ordering::Relaxed));
+}
+
+/// Delegates to Tokio, but allocates receive buffers that report whether datagrams were read into
+/// them
+#[derive(Debug, Default)]
+struct RecvBufferRuntime {
+    allocated: AtomicUsize,
+    used: Arc<AtomicBool>,
+}
+
+impl crate::Runtime for RecvBufferRuntime {
+    fn new_timer(&self, i: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        TokioRuntime.new_timer(i)
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        TokioRuntime.spawn(future)
+    }
+
+    fn wrap_udp_socket(&self, t: UdpSocket) -> io::Result<Box<dyn AsyncUdpSocket>> {
+        TokioRuntime.wrap_udp_socket(t)
+    }
+
+    fn alloc_recv_buffer(&self, len: usize) -> Box<dyn DerefMut<Target = [u8]> + Send> {
+        self.allocated.fetch_add(1, Ordering::Relaxed);
+        Box::new(TrackedBuffer {
+            data: vec![TrackedBuffer::FILL; len].into_boxed_slice(),
+            used: self.used.clone(),
+        })
+    }
+}
+
+struct TrackedBuffer {
+    data: Box<[u8]>,
+    used: Arc<AtomicBool>,
+}
+
+impl TrackedBuffer {
+    const FILL: u8 = 0xa5;
+}
+
+impl Deref for TrackedBuffer {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for TrackedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        if !self.used.load(Ordering::Relaxed) && self.data.iter().any(|&b| b != Self::FILL) {
+            self.used.store(true, Ordering::Relaxed);
+        }
+        &mut self.data
+    }
+}
+
 #[test]
 #[cfg_attr(
     any(target_os = "solaris", target_os = "illumos"),
@@ -668,6 +1241,94 @@ impl std::io::Write for TestWriter {
     }
 }
 
+#[test]
+#[cfg(feature = "metrics")]
+fn metrics() {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let _guard = subscribe();
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    // The recorder is only installed on this thread, which runs all tasks of a basic runtime
+    let runtime = rt_basic();
+    metrics::with_local_recorder(&recorder, || {
+        runtime.block_on(async {
+            let endpoint = endpoint();
+            let (client, server) = tokio::join!(
+                endpoint
+                    .connect(endpoint.local_addr().unwrap(), "localhost")
+                    .unwrap(),
+                async { endpoint.accept().await.unwrap().await }
+            );
+            let client = client.unwrap();
+            let server = server.unwrap();
+
+            let mut send = client.open_uni().await.unwrap();
+            send.write_all(&[0xAB; 100_000]).await.unwrap();
+            send.finish().unwrap();
+            let mut recv = server.accept_uni().await.unwrap();
+            recv.read_to_end(usize::MAX).await.unwrap();
+
+            client.close(0u32.into(), b"done");
+            drop((client, server));
+            endpoint.wait_idle().await;
+        })
+    });
+
+    let snapshot = snapshotter.snapshot().into_vec();
+    let value = |name: &str, side: &str, reason: Option<&str>| {
+        snapshot
+            .iter()
+            .find(|(key, ..)| {
+                let key = key.key();
+                key.name() == name
+                    && key.labels().any(|l| l.key() == "side" && l.value() == side)
+                    && reason.is_none_or(|reason| {
+                        key.labels()
+                            .any(|l| l.key() == "reason" && l.value() == reason)
+                    })
+            })
+            .map(|(.., value)| value)
+            .unwrap_or_else(|| panic!("{name} not reported for {side}"))
+    };
+    let counter = |name: &str, side: &str| match value(name, side, None) {
+        DebugValue::Counter(x) => *x,
+        x => panic!("{name} is {x:?}"),
+    };
+
+    for side in ["client", "server"] {
+        assert_eq!(
+            value(crate::metrics::CONNECTIONS_ACTIVE, side, None),
+            &DebugValue::Gauge(0.0.into())
+        );
+        assert_eq!(counter(crate::metrics::HANDSHAKES_COMPLETED, side), 1);
+        assert!(counter(crate::metrics::DATAGRAMS_SENT, side) > 0);
+        assert!(counter(crate::metrics::DATAGRAMS_RECEIVED, side) > 0);
+        match value(crate::metrics::RTT, side, None) {
+            DebugValue::Histogram(samples) => assert!(!samples.is_empty()),
+            x => panic!("RTT is {x:?}"),
+        }
+    }
+    assert!(counter(crate::metrics::BYTES_SENT, "client") > 100_000);
+    assert!(counter(crate::metrics::BYTES_RECEIVED, "server") > 100_000);
+    assert_eq!(
+        value(
+            crate::metrics::CONNECTIONS_CLOSED,
+            "client",
+            Some("locally_closed")
+        ),
+        &DebugValue::Counter(1)
+    );
+    assert_eq!(
+        value(
+            crate::metrics::CONNECTIONS_CLOSED,
+            "server",
+            Some("application_closed")
+        ),
+        &DebugValue::Counter(1)
+    );
+}
+
 fn rt_basic() -> Runtime {
     Builder::new_current_thread().enable_all().build().unwrap()
 }
@@ -747,20 +1408,183 @@ async fn rebind_recv() {
 }
 
 #[tokio::test]
-async fn stream_id_flow_control() {
+async fn set_local_socket() {
+    use crate::runtime::Runtime as _;
     let _guard = subscribe();
-    let mut cfg = TransportConfig::default();
-    cfg.max_concurrent_uni_streams(1u32.into());
-    let endpoint = endpoint_with_config(cfg);
+    let factory = EndpointFactory::new();
+    let server = factory.endpoint();
+    let server_addr = server.local_addr().unwrap();
+    let client = factory.endpoint();
 
-    let (client, server) = tokio::join!(
-        endpoint
-            .connect(endpoint.local_addr().unwrap(), "localhost")
-            .unwrap(),
-        async { endpoint.accept().await.unwrap().await }
-    );
-    let client = client.unwrap();
-    let server = server.unwrap();
+    let connect = || async {
+        let (client_conn, server_conn) =
+            tokio::join!(client.connect(server_addr, "localhost").unwrap(), async {
+                server.accept().await.unwrap().await
+            });
+        (client_conn.unwrap(), server_conn.unwrap())
+    };
+    // Check that data flows over a connection, and return the client address seen by the server
+    async fn round_trip(
+        client_conn: &crate::Connection,
+        server_conn: &crate::Connection,
+    ) -> SocketAddr {
+        const MSG: &[u8] = b"hello";
+        let mut send = client_conn.open_uni().await.unwrap();
+        send.write_all(MSG).await.unwrap();
+        send.finish().unwrap();
+        let mut recv = server_conn.accept_uni().await.unwrap();
+        assert_eq!(recv.read_to_end(MSG.len()).await.unwrap(), MSG);
+        server_conn.remote_address()
+    }
+
+    let (moved_client, moved_server) = connect().await;
+    let (shared_client, shared_server) = connect().await;
+
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let socket = TokioRuntime.wrap_udp_socket(socket).unwrap();
+    let socket_addr = socket.local_addr().unwrap();
+    moved_client.set_local_socket(socket).unwrap();
+    assert_eq!(round_trip(&moved_client, &moved_server).await, socket_addr);
+    let client_addr = client.local_addr().unwrap();
+    assert_eq!(
+        round_trip(&shared_client, &shared_server).await,
+        client_addr
+    );
+
+    // Rebinding the endpoint leaves the moved connection on its dedicated socket
+    client
+        .rebind(UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap())
+        .unwrap();
+    let client_addr = client.local_addr().unwrap();
+    assert_eq!(
+        round_trip(&shared_client, &shared_server).await,
+        client_addr
+    );
+    assert_eq!(round_trip(&moved_client, &moved_server).await, socket_addr);
+
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let socket = TokioRuntime.wrap_udp_socket(socket).unwrap();
+    let err = moved_server.set_local_socket(socket).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
+
+#[tokio::test]
+#[cfg(feature = "socks5")]
+async fn socks5_relay() {
+    use crate::{Socks5UdpSocket, runtime::Runtime as _};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+    let _guard = subscribe();
+    let factory = EndpointFactory::new();
+    let server = factory.endpoint();
+    let server_addr = server.local_addr().unwrap();
+
+    let client_socket =
+        UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let client_socket_addr = client_socket.local_addr().unwrap();
+    let relay = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    let proxy = TcpListener::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+
+    // A minimal proxy, answering with an unspecified relay address as many proxies do
+    std::thread::spawn(move || {
+        let (mut control, _) = proxy.accept().unwrap();
+        let mut greeting = [0; 3];
+        control.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        control.write_all(&[5, 0]).unwrap();
+        let mut request = [0; 10];
+        control.read_exact(&mut request).unwrap();
+        assert_eq!(request[..4], [5, 3, 0, 1]);
+        let mut reply = vec![5, 0, 0, 1, 0, 0, 0, 0];
+        reply.extend_from_slice(&relay_addr.port().to_be_bytes());
+        control.write_all(&reply).unwrap();
+        // Keep the association alive until the client goes away
+        let _ = control.read(&mut [0]);
+    });
+    std::thread::spawn(move || {
+        let mut buf = [0; 65535];
+        loop {
+            let (len, from) = relay.recv_from(&mut buf).unwrap();
+            if from == client_socket_addr {
+                // Only IPv4 peers are used here
+                assert_eq!(buf[..4], [0, 0, 0, 1]);
+                let ip = Ipv4Addr::new(buf[4], buf[5], buf[6], buf[7]);
+                let port = u16::from_be_bytes([buf[8], buf[9]]);
+                relay
+                    .send_to(&buf[10..len], SocketAddr::new(ip.into(), port))
+                    .unwrap();
+            } else {
+                let SocketAddr::V4(from) = from else {
+                    unreachable!()
+                };
+                let mut datagram = vec![0, 0, 0, 1];
+                datagram.extend_from_slice(&from.ip().octets());
+                datagram.extend_from_slice(&from.port().to_be_bytes());
+                datagram.extend_from_slice(&buf[..len]);
+                relay.send_to(&datagram, client_socket_addr).unwrap();
+            }
+        }
+    });
+
+    let socket = Socks5UdpSocket::new(
+        TokioRuntime.wrap_udp_socket(client_socket).unwrap(),
+        TcpStream::connect(proxy_addr).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(socket.relay_addr(), relay_addr);
+    let client = Endpoint::new_with_abstract_socket(
+        EndpointConfig::default(),
+        None,
+        Box::new(socket),
+        Arc::new(TokioRuntime),
+    )
+    .unwrap();
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(factory.cert.cert.der().clone()).unwrap();
+    client
+        .set_default_client_config(ClientConfig::with_root_certificates(Arc::new(roots)).unwrap());
+
+    let (client_conn, server_conn) =
+        tokio::join!(client.connect(server_addr, "localhost").unwrap(), async {
+            server.accept().await.unwrap().await
+        });
+    let (client_conn, server_conn) = (client_conn.unwrap(), server_conn.unwrap());
+    // Each side sees the other through the relay
+    assert_eq!(client_conn.remote_address(), server_addr);
+    assert_eq!(server_conn.remote_address(), relay_addr);
+
+    const MSG: &[u8] = b"hello";
+    let mut send = client_conn.open_uni().await.unwrap();
+    send.write_all(MSG).await.unwrap();
+    send.finish().unwrap();
+    let mut recv = server_conn.accept_uni().await.unwrap();
+    assert_eq!(recv.read_to_end(MSG.len()).await.unwrap(), MSG);
+    let mut send = server_conn.open_uni().await.unwrap();
+    send.write_all(MSG).await.unwrap();
+    send.finish().unwrap();
+    let mut recv = client_conn.accept_uni().await.unwrap();
+    assert_eq!(recv.read_to_end(MSG.len()).await.unwrap(), MSG);
+}
+
+#[tokio::test]
+async fn stream_id_flow_control() {
+    let _guard = subscribe();
+    let mut cfg = TransportConfig::default();
+    cfg.max_concurrent_uni_streams(1u32.into());
+    let endpoint = endpoint_with_config(cfg);
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
 
     // If `open_uni` doesn't get unblocked when the previous stream is dropped, this will time out.
     tokio::join!(
@@ -816,6 +1640,267 @@ async fn two_datagram_readers() {
     assert!(*a == *b"two" || *b == *b"two");
 }
 
+#[tokio::test]
+async fn datagram_recv_backpressure() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    // A polling consumer is woken once a datagram arrives
+    let (waker, wake_counter) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    assert!(server.poll_read_datagram(&mut cx).is_pending());
+    client.send_datagram(b"one"[..].into()).unwrap();
+    // Datagrams are sent before the PING, so they've been received once it's acknowledged
+    client.ping().await.unwrap();
+    assert_eq!(wake_counter.wakes(), 1);
+    assert!(matches!(
+        server.poll_read_datagram(&mut cx),
+        Poll::Ready(Ok(x)) if x == b"one"[..]
+    ));
+
+    // Datagrams arriving while paused are dropped and accounted for, without waking the consumer
+    assert!(server.poll_read_datagram(&mut cx).is_pending());
+    server.pause_datagram_recv(crate::DatagramBackpressure::Drop);
+    for _ in 0..5 {
+        client.send_datagram_wait(b"two"[..].into()).await.unwrap();
+    }
+    client.ping().await.unwrap();
+    assert_eq!(wake_counter.wakes(), 1);
+    let drops = server.stats().datagram_drops;
+    assert_eq!(drops.paused, 5);
+    assert_eq!(drops.buffer_full, 0);
+    assert_eq!(drops.bytes, 5 * 3);
+
+    server.resume_datagram_recv();
+    client.send_datagram(b"three"[..].into()).unwrap();
+    client.ping().await.unwrap();
+    assert_eq!(wake_counter.wakes(), 2);
+    let x = std::future::poll_fn(|cx| server.poll_read_datagram(cx))
+        .await
+        .unwrap();
+    assert_eq!(x, &b"three"[..]);
+}
+
+#[tokio::test]
+async fn send_datagrams_batch() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.datagram_send_buffer_size(1000);
+    let endpoint = endpoint_with_config(transport);
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    // Only three datagrams fit in the send buffer at once
+    let mut batch = (0..10u8)
+        .map(|i| Bytes::from(vec![i; 300]))
+        .collect::<Vec<_>>();
+    assert_eq!(client.send_datagrams(&mut batch).unwrap(), 3);
+    assert_eq!(batch.len(), 7);
+    assert_eq!(batch[0][0], 3);
+    for i in 0..3 {
+        assert_eq!(server.read_datagram().await.unwrap()[0], i);
+    }
+
+    // An oversized datagram stops the batch, and is reported once it reaches the front
+    let max = client.max_datagram_size().unwrap();
+    let mut batch = vec![Bytes::from_static(b"small"), Bytes::from(vec![0; max + 1])];
+    assert_eq!(client.send_datagrams(&mut batch).unwrap(), 1);
+    assert_eq!(batch.len(), 1);
+    assert_eq!(
+        client.send_datagrams(&mut batch),
+        Err(crate::SendDatagramError::TooLarge)
+    );
+    assert_eq!(batch.len(), 1);
+    assert_eq!(server.read_datagram().await.unwrap(), &b"small"[..]);
+}
+
+#[tokio::test]
+async fn datagram_flows() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    // Identifiers of different encoded lengths
+    const AUDIO: VarInt = VarInt::from_u32(3);
+    const VIDEO: VarInt = VarInt::from_u32(20_000);
+    for i in 0..3u8 {
+        client
+            .send_datagram_on_flow(AUDIO, Bytes::from(vec![i; 10]))
+            .unwrap();
+        client
+            .send_datagram_on_flow(VIDEO, Bytes::from(vec![i; 100]))
+            .unwrap();
+    }
+    let mut audio = Vec::new();
+    let mut video = Vec::new();
+    for _ in 0..6 {
+        let (flow_id, data) = server.read_datagram_flow().await.unwrap();
+        match flow_id {
+            AUDIO => audio.push(data),
+            VIDEO => video.push(data),
+            _ => panic!("unexpected flow {flow_id}"),
+        }
+    }
+    audio.sort();
+    video.sort();
+    assert_eq!(audio, (0..3u8).map(|i| vec![i; 10]).collect::<Vec<_>>());
+    assert_eq!(video, (0..3u8).map(|i| vec![i; 100]).collect::<Vec<_>>());
+
+    // The identifier counts towards the size limit
+    let max = client.max_datagram_size().unwrap();
+    assert_eq!(client.max_datagram_size_on_flow(AUDIO), Some(max - 1));
+    assert_eq!(client.max_datagram_size_on_flow(VIDEO), Some(max - 4));
+    client
+        .send_datagram_on_flow(VIDEO, Bytes::from(vec![0; max - 4]))
+        .unwrap();
+    assert_eq!(
+        client.send_datagram_on_flow(VIDEO, Bytes::from(vec![0; max - 3])),
+        Err(crate::SendDatagramError::TooLarge)
+    );
+    let (flow_id, data) = server.read_datagram_flow().await.unwrap();
+    assert_eq!(flow_id, VIDEO);
+    assert_eq!(data.len(), max - 4);
+}
+
+#[tokio::test]
+async fn ping_measures_rtt() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let _server = server.unwrap();
+
+    let rtt = client.ping().await.unwrap();
+    assert!(
+        rtt < Duration::from_secs(1),
+        "implausible loopback RTT {rtt:?}"
+    );
+
+    let (a, b) = tokio::join!(client.ping(), client.ping());
+    a.unwrap();
+    b.unwrap();
+
+    // A ping that is only polled after a later one was answered still measures its own answer
+    let early = client.ping();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    client.ping().await.unwrap();
+    let rtt = early.await.unwrap();
+    assert!(
+        rtt < Duration::from_millis(200),
+        "late poll skewed RTT {rtt:?}"
+    );
+
+    client.close(0u32.into(), b"done");
+    assert!(matches!(
+        client.ping().await,
+        Err(crate::ConnectionError::LocallyClosed)
+    ));
+}
+
+#[tokio::test]
+async fn aggregate_send_rate() {
+    let _guard = subscribe();
+    const RATE: u64 = 1_000_000;
+    const CLIENTS: usize = 3;
+    const SIZE: usize = 200_000;
+
+    // Measure the rate in virtual time, so that it doesn't depend on how quickly tasks get to run
+    let runtime = VirtualTimeRuntime::new(Arc::new(TokioRuntime));
+    let clock = runtime.clock();
+    let mut factory = EndpointFactory::new();
+    factory.runtime = Arc::new(runtime);
+    factory
+        .endpoint_config
+        .max_aggregate_send_rate(Some(RATE))
+        .unwrap();
+    let server = factory.endpoint();
+    let server_addr = server.local_addr().unwrap();
+
+    let start = clock.now();
+    let clients = (0..CLIENTS)
+        .map(|_| {
+            let endpoint = factory.endpoint();
+            tokio::spawn(async move {
+                let conn = endpoint
+                    .connect(server_addr, "localhost")
+                    .unwrap()
+                    .await
+                    .unwrap();
+                let mut stream = conn.accept_uni().await.unwrap();
+                assert_eq!(stream.read_to_end(SIZE).await.unwrap().len(), SIZE);
+                conn.close(0u32.into(), b"done");
+                endpoint.wait_idle().await;
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let server_task = tokio::spawn(async move {
+        let mut conns = Vec::new();
+        for _ in 0..CLIENTS {
+            let conn = server.accept().await.unwrap().await.unwrap();
+            let mut stream = conn.open_uni().await.unwrap();
+            tokio::spawn(async move {
+                stream.write_all(&vec![0xab; SIZE]).await.unwrap();
+                stream.finish().unwrap();
+            });
+            conns.push(conn);
+        }
+        conns
+    });
+
+    while !clients.iter().all(|client| client.is_finished()) {
+        clock.advance(Duration::from_millis(1));
+        tokio::task::yield_now().await;
+    }
+    for client in clients {
+        client.await.unwrap();
+    }
+    let elapsed = clock.now() - start;
+    let sent = server_task
+        .await
+        .unwrap()
+        .iter()
+        .map(|conn| conn.stats().udp_tx.bytes)
+        .sum::<u64>();
+
+    assert!(sent >= (CLIENTS * SIZE) as u64);
+    let rate = sent as f64 / elapsed.as_secs_f64();
+    assert!(
+        rate <= RATE as f64 * 1.05,
+        "sent {sent} bytes in {elapsed:?}"
+    );
+}
+
 #[tokio::test]
 async fn multiple_conns_with_zero_length_cids() {
     let _guard = subscribe();
@@ -867,6 +1952,340 @@ async fn multiple_conns_with_zero_length_cids() {
     tokio::join!(client1, client2, server);
 }
 
+#[tokio::test]
+async fn all_streams_finished() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    // No streams yet, so the barrier is already satisfied
+    client.all_streams_finished().await.unwrap();
+
+    let mut s1 = client.open_uni().await.unwrap();
+    let mut s2 = client.open_uni().await.unwrap();
+    s1.write_all(b"one").await.unwrap();
+    s2.write_all(b"two").await.unwrap();
+    s1.finish().unwrap();
+
+    // `s2` is still open, so the barrier must not resolve
+    let barrier = client.all_streams_finished();
+    tokio::pin!(barrier);
+    assert!(
+        timeout(Duration::from_millis(50), &mut barrier)
+            .await
+            .is_err()
+    );
+
+    s2.finish().unwrap();
+    barrier.await.unwrap();
+
+    // Resolution implies the peer acknowledged everything we sent
+    assert_eq!(s1.stopped().await, Ok(None));
+    assert_eq!(s2.stopped().await, Ok(None));
+    for _ in 0..2 {
+        let mut recv = server.accept_uni().await.unwrap();
+        let data = recv.read_to_end(usize::MAX).await.unwrap();
+        assert!(data == b"one" || data == b"two");
+    }
+
+    client.close(0u32.into(), b"done");
+    assert!(matches!(
+        client.all_streams_finished().await,
+        Err(crate::ConnectionError::LocallyClosed)
+    ));
+}
+
+#[tokio::test]
+async fn finish_and_close() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    const RESPONSE_LEN: usize = 1024 * 1024;
+    let server_task = tokio::spawn(async move {
+        let (mut send, mut recv) = server.accept_bi().await.unwrap();
+        assert_eq!(recv.read_to_end(usize::MAX).await.unwrap(), b"request");
+        send.write_all(&vec![42; RESPONSE_LEN]).await.unwrap();
+        // The response stream is left open for `finish_and_close` to finish
+        server.finish_and_close(7u32.into(), b"bye").await.unwrap();
+        assert!(matches!(
+            server.close_reason(),
+            Some(crate::ConnectionError::LocallyClosed)
+        ));
+    });
+
+    let (mut send, mut recv) = client.open_bi().await.unwrap();
+    send.write_all(b"request").await.unwrap();
+    send.finish().unwrap();
+    server_task.await.unwrap();
+
+    // The close may already have arrived, yet the response was acknowledged before it was sent
+    let response = recv.read_to_end(usize::MAX).await.unwrap();
+    assert_eq!(response.len(), RESPONSE_LEN);
+    assert!(response.iter().all(|&b| b == 42));
+    match client.closed().await {
+        crate::ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(close.error_code, 7u32.into());
+            assert_eq!(&close.reason[..], b"bye");
+        }
+        e => panic!("unexpected close reason: {e}"),
+    }
+}
+
+#[tokio::test]
+async fn request_response() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut send, mut recv)) = server.accept_bi().await {
+            tokio::spawn(async move {
+                let request = recv.read_to_end(usize::MAX).await.unwrap();
+                if request == b"ignore me" {
+                    // Hold the stream open without ever responding
+                    std::future::pending::<()>().await;
+                }
+                // The client stops the stream when the response is too long
+                let _ = send.write_all(&request).await;
+                let _ = send.finish();
+            });
+        }
+    });
+
+    let response = client.request(Bytes::from_static(b"hello")).await.unwrap();
+    assert_eq!(&response[..], b"hello");
+
+    let payload = Bytes::from(gen_data(256 * 1024, 42));
+    let mut config = crate::RequestConfig::default();
+    config.max_response_size(payload.len());
+    let response = client.request_with(payload.clone(), &config).await.unwrap();
+    assert_eq!(response, payload);
+
+    // Responses exceeding the limit are rejected
+    config.max_response_size(payload.len() - 1);
+    assert_eq!(
+        client.request_with(payload, &config).await,
+        Err(crate::RequestError::Read(crate::ReadToEndError::TooLong))
+    );
+
+    // A peer that never responds runs into the timeout
+    config.timeout(Some(Duration::from_millis(100)));
+    assert_eq!(
+        client
+            .request_with(Bytes::from_static(b"ignore me"), &config)
+            .await,
+        Err(crate::RequestError::TimedOut)
+    );
+
+    // The connection remains usable
+    let response = client.request(Bytes::from_static(b"bye")).await.unwrap();
+    assert_eq!(&response[..], b"bye");
+}
+
+#[tokio::test]
+async fn shared_send_stream() {
+    const TASKS: u8 = 4;
+    const MESSAGES: usize = 16;
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    let send = client.open_uni().await.unwrap().into_shared();
+    let writers = (0..TASKS)
+        .map(|task| {
+            let send = send.clone();
+            tokio::spawn(async move {
+                for i in 0..MESSAGES {
+                    // Large enough that writes are regularly blocked by flow control
+                    let len = 32 * 1024 + i * 1024;
+                    let mut message = Vec::with_capacity(4 + len);
+                    message.extend_from_slice(&(len as u32).to_be_bytes());
+                    message.resize(4 + len, task);
+                    if i % 2 == 0 {
+                        send.write_all(&message).await.unwrap();
+                    } else {
+                        send.write_chunk(message.into()).await.unwrap();
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(send);
+
+    let mut recv = server.accept_uni().await.unwrap();
+    let mut counts = [0; TASKS as usize];
+    let mut len = [0; 4];
+    loop {
+        match recv.read_exact(&mut len).await {
+            Ok(()) => {}
+            Err(crate::ReadExactError::FinishedEarly(0)) => break,
+            Err(e) => panic!("{e}"),
+        }
+        let mut message = vec![0; u32::from_be_bytes(len) as usize];
+        recv.read_exact(&mut message).await.unwrap();
+        // Each message is written by a single task, uninterrupted
+        let task = message[0];
+        assert!(message.iter().all(|&x| x == task));
+        counts[task as usize] += 1;
+    }
+    assert_eq!(counts, [MESSAGES; TASKS as usize]);
+    for writer in writers {
+        writer.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn closed_multiple_waiters() {
+    let _guard = subscribe();
+    let endpoint = endpoint();
+
+    let (client, server) = tokio::join!(
+        endpoint
+            .connect(endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { endpoint.accept().await.unwrap().await }
+    );
+    let client = client.unwrap();
+    let server = server.unwrap();
+
+    let waiters = (0..3)
+        .map(|_| {
+            let conn = client.clone();
+            tokio::spawn(async move { conn.closed().await })
+        })
+        .collect::<Vec<_>>();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(waiters.iter().all(|w| !w.is_finished()));
+
+    server.close(42u32.into(), b"bye");
+    for waiter in waiters {
+        let error = timeout(Duration::from_secs(5), waiter)
+            .await
+            .expect("waiter not woken")
+            .unwrap();
+        assert!(matches!(
+            error,
+            crate::ConnectionError::ApplicationClosed(ref close)
+                if close.error_code == 42u32.into() && close.reason == b"bye"[..]
+        ));
+    }
+
+    // Already closed, so this resolves immediately with the same reason
+    let error = timeout(Duration::ZERO, client.closed()).await.unwrap();
+    assert_eq!(Some(error), client.close_reason());
+}
+
+#[tokio::test]
+async fn close_and_flush() {
+    let _guard = subscribe();
+    let factory = EndpointFactory::new();
+    let server = factory.endpoint();
+    let client = factory.endpoint();
+
+    let (conn, server_conn) = tokio::join!(
+        client
+            .connect(server.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { server.accept().await.unwrap().await.unwrap() }
+    );
+    let conn = conn.unwrap();
+
+    assert!(
+        server
+            .close_and_flush(42u32.into(), b"bye", Duration::from_secs(1))
+            .await
+    );
+    // The close must already be on the wire, so tearing down the endpoint doesn't lose it
+    drop(server_conn);
+    drop(server);
+
+    // Well within the default idle timeout
+    let error = timeout(Duration::from_secs(1), conn.closed())
+        .await
+        .expect("peer not notified");
+    assert!(matches!(
+        error,
+        crate::ConnectionError::ApplicationClosed(ref close)
+            if close.error_code == 42u32.into() && close.reason == b"bye"[..]
+    ));
+}
+
+#[tokio::test]
+async fn virtual_time_idle_timeout() {
+    let _guard = subscribe();
+    let runtime = VirtualTimeRuntime::new(Arc::new(TokioRuntime));
+    let clock = runtime.clock();
+    let mut factory = EndpointFactory::new();
+    factory.runtime = Arc::new(runtime);
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+    let endpoint = |factory: &EndpointFactory| {
+        let mut transport = TransportConfig::default();
+        transport.max_idle_timeout(Some(IDLE_TIMEOUT.try_into().unwrap()));
+        factory.endpoint_with_config(transport)
+    };
+    let server = endpoint(&factory);
+    let client = endpoint(&factory);
+
+    let start = Instant::now();
+    let (conn, server_conn) = tokio::join!(
+        client
+            .connect(server.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { server.accept().await.unwrap().await.unwrap() }
+    );
+    let conn = conn.unwrap();
+
+    // Let delayed ACKs through, so neither side has anything left to retransmit that would reach
+    // the other after it timed out
+    while conn.stats().path.bytes_in_flight + server_conn.stats().path.bytes_in_flight > 0 {
+        clock.advance(Duration::from_millis(10));
+        tokio::task::yield_now().await;
+    }
+
+    clock.advance(IDLE_TIMEOUT);
+    for conn in [conn, server_conn] {
+        let error = timeout(Duration::from_secs(5), conn.closed())
+            .await
+            .expect("idle timeout didn't fire");
+        assert!(matches!(error, crate::ConnectionError::TimedOut));
+    }
+    // No actual waiting involved
+    assert!(start.elapsed() < IDLE_TIMEOUT);
+}
+
 #[tokio::test]
 async fn stream_stopped() {
     let _guard = subscribe();
@@ -950,6 +2369,172 @@ async fn stream_stopped_2() {
     assert_eq!(res, Ok(Ok(None)));
 }
 
+#[tokio::test]
+async fn send_watermarks() {
+    let _guard = subscribe();
+    let factory = EndpointFactory::new();
+    let server = {
+        let _guard = error_span!("server").entered();
+        factory.endpoint()
+    };
+    let server_addr = server.local_addr().unwrap();
+    let client = {
+        let _guard = error_span!("client").entered();
+        factory.endpoint()
+    };
+
+    const CHUNK: usize = 4 * 1024;
+    const CHUNKS: usize = 256;
+    let client = async move {
+        let conn = client
+            .connect(server_addr, "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let mut stream = conn.open_uni().await.unwrap();
+        stream.set_watermarks(16 * 1024, 64 * 1024).unwrap();
+
+        // Only produce more data while the stream asks for it
+        let mut pauses = 0;
+        for i in 0..CHUNKS {
+            {
+                let mut writable = std::pin::pin!(stream.writable());
+                let poll = std::future::poll_fn(|cx| Poll::Ready(writable.as_mut().poll(cx)));
+                match poll.await {
+                    Poll::Ready(result) => result.unwrap(),
+                    Poll::Pending => {
+                        pauses += 1;
+                        writable.await.unwrap();
+                    }
+                }
+            }
+            stream.write_all(&[i as u8; CHUNK]).await.unwrap();
+        }
+        stream.finish().unwrap();
+        stream.stopped().await.unwrap();
+        pauses
+    }
+    .instrument(error_span!("client"));
+    let server = async move {
+        let conn = server.accept().await.unwrap().await.unwrap();
+        let mut stream = conn.accept_uni().await.unwrap();
+        let data = stream.read_to_end(usize::MAX).await.unwrap();
+        assert_eq!(data.len(), CHUNK * CHUNKS);
+        assert!(
+            data.chunks(CHUNK)
+                .enumerate()
+                .all(|(i, chunk)| chunk.iter().all(|&b| b == i as u8))
+        );
+        conn
+    }
+    .instrument(error_span!("server"));
+    let (pauses, _conn) = timeout(Duration::from_secs(10), async {
+        tokio::join!(client, server)
+    })
+    .await
+    .expect("timeout");
+    assert!(pauses > 0);
+}
+
+#[tokio::test]
+async fn read_to_end_too_long() {
+    let _guard = subscribe();
+    const LIMIT: usize = 64 * 1024;
+    const WINDOW: u32 = 16 * 1024;
+    let factory = EndpointFactory::new();
+    let mut transport = TransportConfig::default();
+    transport.stream_receive_window(WINDOW.into());
+    let server = {
+        let _guard = error_span!("server").entered();
+        factory.endpoint_with_config(transport)
+    };
+    let server_addr = server.local_addr().unwrap();
+    let client = {
+        let _guard = error_span!("client").entered();
+        // MTU probes would muddy the count of bytes sent
+        let mut transport = TransportConfig::default();
+        transport.mtu_discovery_config(None);
+        factory.endpoint_with_config(transport)
+    };
+
+    let (conn, server_conn) = tokio::join!(
+        async { client.connect(server_addr, "localhost").unwrap().await },
+        async { server.accept().await.unwrap().await },
+    );
+    let (conn, server_conn) = (conn.unwrap(), server_conn.unwrap());
+    let handshake_bytes = conn.stats().udp_tx.bytes;
+    let mut send = conn.open_uni().await.unwrap();
+    let writer = tokio::spawn(async move {
+        // Far more than the reader is willing to accept
+        send.write_all(&[0; 16 * LIMIT]).await
+    });
+
+    let mut recv = server_conn.accept_uni().await.unwrap();
+    assert_eq!(
+        recv.read_to_end(LIMIT).await,
+        Err(crate::ReadToEndError::TooLong)
+    );
+
+    // No credit is issued past the limit, so the writer stalls within a window of it. Allow some
+    // slack for packet overhead.
+    sleep(Duration::from_millis(100)).await;
+    assert!(!writer.is_finished());
+    let sent = conn.stats().udp_tx.bytes - handshake_bytes;
+    assert!(
+        sent < (LIMIT + WINDOW as usize + 4 * 1024) as u64,
+        "peer sent {sent} bytes"
+    );
+    writer.abort();
+    drop(server_conn);
+}
+
+#[tokio::test]
+async fn per_connection_congestion_controller() {
+    let _guard = subscribe();
+    let factory = EndpointFactory::new();
+    let server = {
+        let _guard = error_span!("server").entered();
+        factory.endpoint()
+    };
+    let server_addr = server.local_addr().unwrap();
+    let client = {
+        let _guard = error_span!("client").entered();
+        factory.endpoint()
+    };
+
+    let mut config = client.default_client_config().unwrap();
+    config
+        .transport_config_mut()
+        .congestion_controller_factory(Arc::new(crate::congestion::NewRenoConfig::default()));
+
+    let server_task = tokio::spawn(async move {
+        let mut conns = Vec::new();
+        for _ in 0..3 {
+            conns.push(server.accept().await.unwrap().await.unwrap());
+        }
+        conns
+    });
+    let mut conns = Vec::new();
+    for config in [None, Some(config), None] {
+        let connecting = match config {
+            Some(config) => client.connect_with(config, server_addr, "localhost"),
+            None => client.connect(server_addr, "localhost"),
+        };
+        conns.push(connecting.unwrap().await.unwrap());
+    }
+    server_task.await.unwrap();
+
+    let is_new_reno = |conn: &crate::Connection| {
+        conn.congestion_state()
+            .into_any()
+            .is::<crate::congestion::NewReno>()
+    };
+    assert!(!is_new_reno(&conns[0]));
+    assert!(is_new_reno(&conns[1]));
+    // The override doesn't leak into the endpoint's default configuration
+    assert!(!is_new_reno(&conns[2]));
+}
+
 #[tokio::test]
 async fn stream_drop_removes_blocked_reader() {
     let _guard = subscribe();
@@ -1064,3 +2649,92 @@ unsafe fn drop_waker(data: *const ()) {
         drop(Arc::<WakeCounter>::from_raw(data as *const WakeCounter));
     }
 }
+
+#[test]
+#[cfg(feature = "native-certs")]
+fn native_root_store() {
+    let roots = crate::endpoint::native_root_store().unwrap();
+    assert!(!roots.is_empty());
+}
+
+#[tokio::test]
+#[cfg(all(feature = "native-certs", feature = "runtime-tokio"))]
+async fn native_roots_client_config() {
+    let _guard = subscribe();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(cert.signing_key.serialize_der().into());
+    let mut crypto = rustls::ServerConfig::builder_with_provider(default_provider().into())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)
+        .unwrap();
+    crypto.alpn_protocols = vec![b"h3".to_vec()];
+    let server = Endpoint::server(
+        crate::ServerConfig::with_rustls(Arc::new(crypto)).unwrap(),
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+    )
+    .unwrap();
+    let server_addr = server.local_addr().unwrap();
+
+    // The default configuration offers the given protocols and verifies the server's certificate
+    // against the given roots
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let config = crate::endpoint::native_roots_client_config(roots, vec![b"h3".to_vec()]).unwrap();
+    let client = Endpoint::client(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    client.set_default_client_config(config);
+    let (client_conn, server_conn) = tokio::join!(
+        async {
+            client
+                .connect(server_addr, "localhost")
+                .unwrap()
+                .await
+                .unwrap()
+        },
+        async { server.accept().await.unwrap().await.unwrap() }
+    );
+    assert_eq!(client_conn.alpn().unwrap(), b"h3");
+    assert_eq!(server_conn.alpn().unwrap(), b"h3");
+
+    // The platform's roots don't include the self-signed certificate
+    let client = Endpoint::client_with_native_roots(
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+        vec![b"h3".to_vec()],
+    )
+    .unwrap();
+    let (client_result, _) =
+        tokio::join!(client.connect(server_addr, "localhost").unwrap(), async {
+            server.accept().await.unwrap().await
+        });
+    assert!(matches!(
+        client_result,
+        Err(crate::ConnectionError::TransportError(_))
+    ));
+}
+
+#[tokio::test]
+#[cfg(all(feature = "native-certs", feature = "runtime-tokio"))]
+#[ignore = "requires network access"]
+async fn native_roots_public_endpoint() {
+    let _guard = subscribe();
+    // Public HTTP/3 servers refuse handshakes that don't negotiate an application protocol
+    let endpoint =
+        Endpoint::client_with_native_roots((Ipv6Addr::UNSPECIFIED, 0).into(), vec![b"h3".to_vec()])
+            .unwrap();
+
+    let addr = tokio::net::lookup_host("cloudflare-quic.com:443")
+        .await
+        .unwrap()
+        .next()
+        .unwrap();
+    let conn = timeout(
+        Duration::from_secs(10),
+        endpoint.connect(addr, "cloudflare-quic.com").unwrap(),
+    )
+    .await
+    .expect("timeout")
+    .unwrap();
+    conn.close(0u32.into(), b"done");
+    endpoint.wait_idle().await;
+}